@@ -6,6 +6,7 @@ glslc sprite.frag -o sprite.frag.spv
 
 glslc mesh.vert -o mesh.vert.spv
 glslc mesh.frag -o mesh.frag.spv
+glslc mesh_shadow_map.vert -o mesh_shadow_map.vert.spv
 
 glslc debug.vert -o debug.vert.spv
 glslc debug.frag -o debug.frag.spv
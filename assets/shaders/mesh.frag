@@ -44,17 +44,30 @@ struct SpotLight {
     float intensity;
 };
 
+// Transforms view space positions of the main view into the clip space of one shadow map cascade
+struct ShadowMapCascade {
+    mat4 view_to_shadow_map;
+    float split_depth_vs;
+};
+
+const uint MAX_SHADOW_MAP_CASCADE_COUNT = 4;
+
 layout (set = 0, binding = 0) uniform PerFrameData {
     vec4 ambient_light;
     uint point_light_count;
     uint directional_light_count;
     uint spot_light_count;
+    // Only the first directional light casts shadows
+    uint shadow_map_cascade_count;
     PointLight point_lights[16];
     DirectionalLight directional_lights[16];
     SpotLight spot_lights[16];
+    ShadowMapCascade shadow_map_cascades[MAX_SHADOW_MAP_CASCADE_COUNT];
 } per_frame_data;
 
 layout (set = 0, binding = 1) uniform sampler smp;
+layout (set = 0, binding = 2) uniform texture2DArray shadow_map_image;
+layout (set = 0, binding = 3) uniform sampler smp_depth;
 
 //
 // Per-Material Bindings
@@ -267,6 +280,44 @@ vec4 directional_light(
     return shade_diffuse_specular(surface_to_light_dir, surface_to_eye_dir_vs, normal_vs, light.color, light.intensity);
 }
 
+// Returns 0 if the surface is entirely in shadow and 1 if it is entirely lit
+float calculate_shadow_map_visibility(
+    vec3 surface_position_vs
+) {
+    // Pick the first cascade that covers the surface's depth
+    float depth_vs = -surface_position_vs.z;
+    uint cascade_index = 0;
+    while (cascade_index < per_frame_data.shadow_map_cascade_count &&
+           depth_vs > per_frame_data.shadow_map_cascades[cascade_index].split_depth_vs) {
+        ++cascade_index;
+    }
+
+    if (cascade_index >= per_frame_data.shadow_map_cascade_count) {
+        return 1.0;
+    }
+
+    vec4 shadow_map_pos = per_frame_data.shadow_map_cascades[cascade_index].view_to_shadow_map * vec4(surface_position_vs, 1.0);
+    shadow_map_pos.xyz /= shadow_map_pos.w;
+    vec2 uv = shadow_map_pos.xy * 0.5 + 0.5;
+    if (shadow_map_pos.z > 1.0 || any(lessThan(uv, vec2(0.0))) || any(greaterThan(uv, vec2(1.0)))) {
+        return 1.0;
+    }
+
+    // 3x3 PCF, each tap is also bilinearly filtered by the comparison sampler
+    vec2 texel_size = 1.0 / vec2(textureSize(sampler2DArrayShadow(shadow_map_image, smp_depth), 0).xy);
+    float visibility = 0.0;
+    for (int y = -1; y <= 1; ++y) {
+        for (int x = -1; x <= 1; ++x) {
+            visibility += texture(
+                sampler2DArrayShadow(shadow_map_image, smp_depth),
+                vec4(uv + vec2(x, y) * texel_size, cascade_index, shadow_map_pos.z)
+            );
+        }
+    }
+
+    return visibility / 9.0;
+}
+
 //
 // Normal distribution function approximates the relative surface area where microfacets are aligned to the halfway
 // vector, producing specular-like results. (GGX/Trowbridge-Reitz)
@@ -496,13 +547,20 @@ vec4 non_pbr_path(
     }
 
     // directional Lights
+    float shadow_map_visibility = calculate_shadow_map_visibility(in_position_vs);
     for (uint i = 0; i < per_frame_data.directional_light_count; ++i) {
-        total_light += directional_light(
+        vec3 light = directional_light(
             per_frame_data.directional_lights[i],
             surface_to_eye_vs,
             in_position_vs,
             normal_vs
         ).rgb;
+
+        if (i == 0) {
+            light *= shadow_map_visibility;
+        }
+
+        total_light += light;
     }
 
     vec3 rgb_color = base_color.rgb;
@@ -555,8 +613,9 @@ vec4 pbr_path(
     }
 
     // directional Lights
+    float shadow_map_visibility = calculate_shadow_map_visibility(in_position_vs);
     for (uint i = 0; i < per_frame_data.directional_light_count; ++i) {
-        total_light += directional_light_pbr(
+        vec3 light = directional_light_pbr(
             per_frame_data.directional_lights[i],
             surface_to_eye_vs,
             in_position_vs,
@@ -566,6 +625,12 @@ vec4 pbr_path(
             roughness,
             metalness
         );
+
+        if (i == 0) {
+            light *= shadow_map_visibility;
+        }
+
+        total_light += light;
     }
 
     //
@@ -0,0 +1,15 @@
+#version 450
+#extension GL_ARB_separate_shader_objects : enable
+#extension GL_ARB_shading_language_420pack : enable
+
+// Same layout as the per-object data in mesh.vert, but only model_view_proj is used
+layout(set = 0, binding = 0) uniform PerObjectData {
+    mat4 model_view;
+    mat4 model_view_proj;
+} per_object_data;
+
+layout (location = 0) in vec3 in_pos;
+
+void main() {
+    gl_Position = per_object_data.model_view_proj * vec4(in_pos, 1.0);
+}
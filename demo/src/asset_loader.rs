@@ -34,7 +34,8 @@ where
             bincode::deserialize::<AssetDataT>(data)
         })?;
 
-        let result = self.0.update_asset(load_handle, load_op, asset);
+        let asset_uuid = loader_info.get_asset_id(load_handle).unwrap();
+        let result = self.0.update_asset(load_handle, asset_uuid, load_op, asset);
         Ok(UpdateAssetResult::AsyncResult(result.result_rx))
     }
 
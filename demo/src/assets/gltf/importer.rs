@@ -11,7 +11,7 @@ use atelier_assets::loader::handle::Handle;
 use crate::assets::gltf::{
     GltfMaterialAsset, MeshAssetData, MeshPart, MeshVertex, GltfMaterialDataShaderParam,
 };
-use renderer::assets::assets::{ImageAssetData, ColorSpace};
+use renderer::assets::assets::{ImageAssetData, ColorSpace, ImageImporterOptions, process_image};
 use renderer::assets::assets::BufferAssetData;
 use renderer::assets::push_buffer::PushBuffer;
 use atelier_assets::loader::handle::SerdeContext;
@@ -101,7 +101,7 @@ impl Importer for GltfImporter {
     where
         Self: Sized,
     {
-        23
+        26
     }
 
     fn version(&self) -> u32 {
@@ -566,12 +566,13 @@ fn extract_images_to_import(
             image.index()
         );
 
-        let asset = ImageAssetData {
-            data: converted_image.to_vec(),
-            width: image_data.width,
-            height: image_data.height,
-            color_space,
-        };
+        let asset = process_image(
+            image_data.width,
+            image_data.height,
+            converted_image.to_vec(),
+            &ImageImporterOptions::with_color_space(color_space),
+        )
+        .expect("Single layer images always match their layout");
         let id = image
             .name()
             .map(|s| GltfObjectId::Name(s.to_string()))
@@ -4,6 +4,7 @@ use crate::features::debug3d::{
 use crate::render_contexts::{RenderJobExtractContext, RenderJobWriteContext, RenderJobPrepareContext};
 use renderer::nodes::{
     FramePacket, RenderView, PrepareJob, RenderFeatureIndex, RenderFeature, ExtractJob,
+    RenderJobExecutor,
 };
 use crate::features::debug3d::prepare::Debug3dPrepareJobImpl;
 use renderer::vulkan::VkDeviceContext;
@@ -42,6 +43,7 @@ impl ExtractJob<RenderJobExtractContext, RenderJobPrepareContext, RenderJobWrite
         extract_context: &RenderJobExtractContext,
         _frame_packet: &FramePacket,
         views: &[&RenderView],
+        _job_executor: &dyn RenderJobExecutor,
     ) -> Box<dyn PrepareJob<RenderJobPrepareContext, RenderJobWriteContext>> {
         let dyn_resource_allocator = extract_context
             .resource_manager
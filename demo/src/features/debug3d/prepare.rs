@@ -1,6 +1,6 @@
 use renderer::nodes::{
     RenderView, ViewSubmitNodes, FeatureSubmitNodes, FeatureCommandWriter, RenderFeatureIndex,
-    FramePacket, RenderFeature, PrepareJob,
+    FramePacket, RenderFeature, PrepareJob, RenderJobExecutor,
 };
 use crate::features::debug3d::{
     Debug3dRenderFeature, ExtractedDebug3dData, Debug3dDrawCall, Debug3dVertex,
@@ -44,6 +44,7 @@ impl PrepareJob<RenderJobPrepareContext, RenderJobWriteContext> for Debug3dPrepa
         _prepare_context: &RenderJobPrepareContext,
         _frame_packet: &FramePacket,
         views: &[&RenderView],
+        _job_executor: &dyn RenderJobExecutor,
     ) -> (
         Box<dyn FeatureCommandWriter<RenderJobWriteContext>>,
         FeatureSubmitNodes,
@@ -2,6 +2,7 @@ use crate::features::imgui::{ExtractedImGuiData, ImGuiRenderFeature, ImGuiUnifor
 use crate::render_contexts::{RenderJobExtractContext, RenderJobWriteContext, RenderJobPrepareContext};
 use renderer::nodes::{
     FramePacket, RenderView, PrepareJob, RenderFeatureIndex, RenderFeature, ExtractJob,
+    RenderJobExecutor,
 };
 use crate::features::imgui::prepare::ImGuiPrepareJobImpl;
 use renderer::vulkan::VkDeviceContext;
@@ -74,6 +75,7 @@ impl ExtractJob<RenderJobExtractContext, RenderJobPrepareContext, RenderJobWrite
         extract_context: &RenderJobExtractContext,
         _frame_packet: &FramePacket,
         _views: &[&RenderView],
+        _job_executor: &dyn RenderJobExecutor,
     ) -> Box<dyn PrepareJob<RenderJobPrepareContext, RenderJobWriteContext>> {
         let imgui_draw_data = extract_context
             .resources
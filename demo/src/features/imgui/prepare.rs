@@ -1,7 +1,7 @@
 use crate::phases::UiRenderPhase;
 use renderer::nodes::{
     RenderView, ViewSubmitNodes, FeatureSubmitNodes, FeatureCommandWriter, RenderFeatureIndex,
-    FramePacket, RenderFeature, PrepareJob,
+    FramePacket, RenderFeature, PrepareJob, RenderJobExecutor,
 };
 use crate::features::imgui::{ImGuiRenderFeature, ExtractedImGuiData};
 use super::write::ImGuiCommandWriter;
@@ -45,6 +45,7 @@ impl PrepareJob<RenderJobPrepareContext, RenderJobWriteContext> for ImGuiPrepare
         _prepare_context: &RenderJobPrepareContext,
         _frame_packet: &FramePacket,
         views: &[&RenderView],
+        _job_executor: &dyn RenderJobExecutor,
     ) -> (
         Box<dyn FeatureCommandWriter<RenderJobWriteContext>>,
         FeatureSubmitNodes,
@@ -1,6 +1,6 @@
 use crate::features::mesh::{
     ExtractedFrameNodeMeshData, MeshRenderNodeSet, MeshRenderFeature, MeshRenderNode, MeshDrawCall,
-    MeshPerObjectShaderParam, ExtractedViewNodeMeshData, MeshPerViewShaderParam,
+    MeshPerObjectShaderParam, ExtractedViewNodeMeshData, MeshPerViewShaderParam, MeshShadowMapData,
 };
 use crate::components::{
     PointLightComponent, SpotLightComponent, DirectionalLightComponent, PositionComponent,
@@ -12,36 +12,43 @@ use renderer::nodes::{
 };
 use renderer::base::slab::RawSlabKey;
 use crate::features::mesh::prepare::MeshPrepareJobImpl;
-use renderer::assets::resources::{PipelineSwapchainInfo, DescriptorSetAllocatorRef};
+use renderer::assets::resources::{
+    PipelineSwapchainInfo, DescriptorSetAllocatorRef, ResourceArc, DescriptorSetLayoutResource,
+};
 use atelier_assets::loader::handle::Handle;
 use renderer::assets::resources::DescriptorSetArc;
 use legion::prelude::*;
 use crate::components::MeshComponent;
 use crate::resource_manager::GameResourceManager;
 use renderer::assets::MaterialAsset;
+use crate::phases::{OpaqueRenderPhase, ShadowMapRenderPhase};
 
 pub struct MeshExtractJobImpl {
     descriptor_set_allocator: DescriptorSetAllocatorRef,
     pipeline_info: PipelineSwapchainInfo,
+    shadow_map_pipeline_info: PipelineSwapchainInfo,
     mesh_material: Handle<MaterialAsset>,
-    descriptor_sets_per_view: Vec<DescriptorSetArc>,
-    extracted_frame_node_mesh_data: Vec<Option<ExtractedFrameNodeMeshData>>,
-    extracted_view_node_mesh_data: Vec<Vec<Option<ExtractedViewNodeMeshData>>>,
+    shadow_map_data: MeshShadowMapData,
+    descriptor_sets_per_view: Vec<Option<DescriptorSetArc>>,
+    per_instance_descriptor_set_layouts: Vec<ResourceArc<DescriptorSetLayoutResource>>,
 }
 
 impl MeshExtractJobImpl {
     pub fn new(
         descriptor_set_allocator: DescriptorSetAllocatorRef,
         pipeline_info: PipelineSwapchainInfo,
+        shadow_map_pipeline_info: PipelineSwapchainInfo,
         mesh_material: &Handle<MaterialAsset>,
+        shadow_map_data: MeshShadowMapData,
     ) -> Self {
         MeshExtractJobImpl {
             descriptor_set_allocator,
             pipeline_info,
+            shadow_map_pipeline_info,
             mesh_material: mesh_material.clone(),
+            shadow_map_data,
             descriptor_sets_per_view: Default::default(),
-            extracted_frame_node_mesh_data: Default::default(),
-            extracted_view_node_mesh_data: Default::default(),
+            per_instance_descriptor_set_layouts: Default::default(),
         }
     }
 }
@@ -49,29 +56,43 @@ impl MeshExtractJobImpl {
 impl DefaultExtractJobImpl<RenderJobExtractContext, RenderJobPrepareContext, RenderJobWriteContext>
     for MeshExtractJobImpl
 {
+    type ExtractedFrameNodeData = Option<ExtractedFrameNodeMeshData>;
+    // View nodes are extracted in parallel, so their descriptor sets are created afterwards in
+    // extract_frame_finalize
+    type ExtractedViewNodeData = Option<MeshPerObjectShaderParam>;
+
     fn extract_begin(
         &mut self,
-        _extract_context: &RenderJobExtractContext,
-        frame_packet: &FramePacket,
+        extract_context: &RenderJobExtractContext,
+        _frame_packet: &FramePacket,
         views: &[&RenderView],
     ) {
-        self.extracted_frame_node_mesh_data
-            .reserve(frame_packet.frame_node_count(self.feature_index()) as usize);
+        self.descriptor_sets_per_view.reserve(views.len());
 
-        self.extracted_view_node_mesh_data.reserve(views.len());
+        // The shadow map pass only has the per-instance set, at index 0
         for view in views {
-            self.extracted_view_node_mesh_data.push(Vec::with_capacity(
-                frame_packet.view_node_count(view, self.feature_index()) as usize,
-            ));
+            let (pass_index, set_index) = if view.phase_is_relevant::<ShadowMapRenderPhase>() {
+                (1, 0)
+            } else {
+                (0, 2)
+            };
+
+            let layout = extract_context.resource_manager.get_descriptor_set_info(
+                &self.mesh_material,
+                pass_index,
+                set_index,
+            );
+            self.per_instance_descriptor_set_layouts
+                .push(layout.descriptor_set_layout);
         }
     }
 
     fn extract_frame_node(
-        &mut self,
+        &self,
         extract_context: &RenderJobExtractContext,
         frame_node: PerFrameNode,
         _frame_node_index: u32,
-    ) {
+    ) -> Option<ExtractedFrameNodeMeshData> {
         let render_node_index = frame_node.render_node_index();
         let render_node_handle = RawSlabKey::<MeshRenderNode>::new(render_node_index);
 
@@ -95,12 +116,7 @@ impl DefaultExtractJobImpl<RenderJobExtractContext, RenderJobPrepareContext, Ren
             .get::<GameResourceManager>()
             .unwrap();
 
-        let mesh_info = game_resource_manager.get_mesh_info(&mesh_component.mesh);
-        if mesh_info.is_none() {
-            self.extracted_frame_node_mesh_data.push(None);
-            return;
-        }
-        let mesh_info = mesh_info.unwrap();
+        let mesh_info = game_resource_manager.get_mesh_info(&mesh_component.mesh)?;
 
         let draw_calls: Vec<_> = mesh_info
             .mesh_asset
@@ -123,56 +139,32 @@ impl DefaultExtractJobImpl<RenderJobExtractContext, RenderJobPrepareContext, Ren
 
         let world_transform = glam::Mat4::from_translation(position_component.position);
 
-        self.extracted_frame_node_mesh_data
-            .push(Some(ExtractedFrameNodeMeshData {
-                world_transform,
-                vertex_buffer: mesh_info.vertex_buffer.clone(),
-                index_buffer: mesh_info.index_buffer.clone(),
-                draw_calls,
-            }));
+        Some(ExtractedFrameNodeMeshData {
+            world_transform,
+            vertex_buffer: mesh_info.vertex_buffer.clone(),
+            index_buffer: mesh_info.index_buffer.clone(),
+            draw_calls,
+        })
     }
 
     fn extract_view_node(
-        &mut self,
-        extract_context: &RenderJobExtractContext,
+        &self,
+        _extract_context: &RenderJobExtractContext,
         view: &RenderView,
         view_node: PerViewNode,
         _view_node_index: u32,
-    ) {
+        extracted_frame_node_data: &[Option<ExtractedFrameNodeMeshData>],
+    ) -> Option<MeshPerObjectShaderParam> {
         let frame_node_data =
-            &self.extracted_frame_node_mesh_data[view_node.frame_node_index() as usize];
-        if frame_node_data.is_none() {
-            self.extracted_view_node_mesh_data[view.view_index() as usize].push(None);
-            return;
-        }
-        let frame_node_data = frame_node_data.as_ref().unwrap();
+            extracted_frame_node_data[view_node.frame_node_index() as usize].as_ref()?;
 
         let model_view = view.view_matrix() * frame_node_data.world_transform;
         let model_view_proj = view.projection_matrix() * model_view;
 
-        let per_object_param = MeshPerObjectShaderParam {
+        Some(MeshPerObjectShaderParam {
             model_view,
             model_view_proj,
-        };
-
-        let layout =
-            extract_context
-                .resource_manager
-                .get_descriptor_set_info(&self.mesh_material, 0, 2);
-        let mut descriptor_set = self
-            .descriptor_set_allocator
-            .create_dyn_descriptor_set_uninitialized(&layout.descriptor_set_layout)
-            .unwrap();
-        descriptor_set.set_buffer_data(0, &per_object_param);
-        descriptor_set
-            .flush(&mut self.descriptor_set_allocator)
-            .unwrap();
-
-        self.extracted_view_node_mesh_data[view.view_index() as usize].push(Some(
-            ExtractedViewNodeMeshData {
-                per_instance_descriptor: descriptor_set.descriptor_set().clone(),
-            },
-        ))
+        })
     }
 
     fn extract_view_finalize(
@@ -180,6 +172,12 @@ impl DefaultExtractJobImpl<RenderJobExtractContext, RenderJobPrepareContext, Ren
         extract_context: &RenderJobExtractContext,
         view: &RenderView,
     ) {
+        // Shadow map views don't light anything, so they don't need per-view data
+        if !view.phase_is_relevant::<OpaqueRenderPhase>() {
+            self.descriptor_sets_per_view.push(None);
+            return;
+        }
+
         let mut per_view_data = MeshPerViewShaderParam::default();
 
         let query = <Read<DirectionalLightComponent>>::query();
@@ -206,6 +204,21 @@ impl DefaultExtractJobImpl<RenderJobExtractContext, RenderJobPrepareContext, Ren
             per_view_data.directional_light_count += 1;
         }
 
+        // The shadow map is rendered for the first directional light
+        if per_view_data.directional_light_count > 0 {
+            for (cascade, out) in self
+                .shadow_map_data
+                .cascades
+                .iter()
+                .zip(per_view_data.shadow_map_cascades.iter_mut())
+            {
+                out.view_to_shadow_map = cascade.view_to_shadow_map(view.view_matrix());
+                out.split_depth_vs = cascade.split_depth_vs;
+            }
+
+            per_view_data.shadow_map_cascade_count = self.shadow_map_data.cascades.len() as u32;
+        }
+
         let query = <(Read<PositionComponent>, Read<PointLightComponent>)>::query();
         for (position, light) in query.iter(extract_context.world) {
             let light_count = per_view_data.point_light_count as usize;
@@ -262,23 +275,51 @@ impl DefaultExtractJobImpl<RenderJobExtractContext, RenderJobPrepareContext, Ren
             .create_dyn_descriptor_set_uninitialized(&layout.descriptor_set_layout)
             .unwrap();
         descriptor_set.set_buffer_data(0, &per_view_data);
+        descriptor_set.set_image_raw(2, self.shadow_map_data.image_view);
         descriptor_set
             .flush(&mut self.descriptor_set_allocator)
             .unwrap();
 
         self.descriptor_sets_per_view
-            .push(descriptor_set.descriptor_set().clone());
+            .push(Some(descriptor_set.descriptor_set().clone()));
     }
 
     fn extract_frame_finalize(
-        self,
+        mut self,
         _extract_context: &RenderJobExtractContext,
+        extracted_frame_node_data: Vec<Option<ExtractedFrameNodeMeshData>>,
+        extracted_view_node_data: Vec<Vec<Option<MeshPerObjectShaderParam>>>,
     ) -> Box<dyn PrepareJob<RenderJobPrepareContext, RenderJobWriteContext>> {
+        let descriptor_set_allocator = &mut self.descriptor_set_allocator;
+        let mut extracted_view_node_mesh_data = Vec::with_capacity(extracted_view_node_data.len());
+        for (view_node_data, layout) in extracted_view_node_data
+            .into_iter()
+            .zip(&self.per_instance_descriptor_set_layouts)
+        {
+            let mut view_node_mesh_data = Vec::with_capacity(view_node_data.len());
+            for per_object_param in view_node_data {
+                view_node_mesh_data.push(per_object_param.map(|per_object_param| {
+                    let mut descriptor_set = descriptor_set_allocator
+                        .create_dyn_descriptor_set_uninitialized(layout)
+                        .unwrap();
+                    descriptor_set.set_buffer_data(0, &per_object_param);
+                    descriptor_set.flush(descriptor_set_allocator).unwrap();
+
+                    ExtractedViewNodeMeshData {
+                        per_instance_descriptor: descriptor_set.descriptor_set().clone(),
+                    }
+                }));
+            }
+
+            extracted_view_node_mesh_data.push(view_node_mesh_data);
+        }
+
         let prepare_impl = MeshPrepareJobImpl::new(
             self.pipeline_info,
+            self.shadow_map_pipeline_info,
             self.descriptor_sets_per_view,
-            self.extracted_frame_node_mesh_data,
-            self.extracted_view_node_mesh_data,
+            extracted_frame_node_data,
+            extracted_view_node_mesh_data,
         );
 
         Box::new(DefaultPrepareJob::new(prepare_impl))
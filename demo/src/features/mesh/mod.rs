@@ -21,6 +21,8 @@ use renderer::assets::resources::{
     PipelineSwapchainInfo, DescriptorSetArc, DescriptorSetAllocatorRef, ResourceArc,
 };
 use renderer::assets::MaterialAsset;
+use crate::game_renderer::{ShadowMapCascade, SHADOW_MAP_CASCADE_COUNT};
+use ash::vk;
 
 // Represents the data uploaded to the GPU to represent a single point light
 #[derive(Default, Copy, Clone)]
@@ -57,6 +59,14 @@ pub struct SpotLight {
     pub intensity: f32,            // +88
 } // 6*16 = 96 bytes
 
+// Represents the data uploaded to the GPU to sample a single shadow map cascade
+#[derive(Default, Copy, Clone)]
+#[repr(C)]
+pub struct ShadowMapCascadeShaderParam {
+    pub view_to_shadow_map: glam::Mat4, // +0
+    pub split_depth_vs: f32,            // +64
+} // 5*16 = 80 bytes
+
 // Represents the data uploaded to the GPU to provide all data necessary to render meshes
 //TODO: Remove view/proj, they aren't being used. Add ambient light constant
 #[derive(Default, Copy, Clone)]
@@ -66,10 +76,13 @@ pub struct MeshPerViewShaderParam {
     pub point_light_count: u32,                     // +16
     pub directional_light_count: u32,               // 20
     pub spot_light_count: u32,                      // +24
+    pub shadow_map_cascade_count: u32,              // +28
     pub point_lights: [PointLight; 16],             // +32 (64*16 = 1024),
     pub directional_lights: [DirectionalLight; 16], // +1056 (64*16 = 1024),
     pub spot_lights: [SpotLight; 16],               // +2080 (96*16 = 1536)
-} // 3616 bytes
+    // +3616 (80*4 = 320)
+    pub shadow_map_cascades: [ShadowMapCascadeShaderParam; SHADOW_MAP_CASCADE_COUNT],
+} // 3936 bytes
 
 #[derive(Default, Copy, Clone)]
 #[repr(C)]
@@ -78,15 +91,25 @@ pub struct MeshPerObjectShaderParam {
     pub model_view_proj: glam::Mat4, // +64
 } // 128 bytes
 
+// Everything the opaque pass needs to sample the shadow map rendered for the main view
+pub struct MeshShadowMapData {
+    pub cascades: [ShadowMapCascade; SHADOW_MAP_CASCADE_COUNT],
+    pub image_view: vk::ImageView,
+}
+
 pub fn create_mesh_extract_job(
     descriptor_set_allocator: DescriptorSetAllocatorRef,
     pipeline_info: PipelineSwapchainInfo,
+    shadow_map_pipeline_info: PipelineSwapchainInfo,
     mesh_material: &Handle<MaterialAsset>,
+    shadow_map_data: MeshShadowMapData,
 ) -> Box<dyn ExtractJob<RenderJobExtractContext, RenderJobPrepareContext, RenderJobWriteContext>> {
     Box::new(DefaultExtractJob::new(MeshExtractJobImpl::new(
         descriptor_set_allocator,
         pipeline_info,
+        shadow_map_pipeline_info,
         mesh_material,
+        shadow_map_data,
     )))
 }
 
@@ -190,12 +213,12 @@ pub struct MeshDrawCall {
 
 #[derive(Debug)]
 pub struct ExtractedViewNodeMeshData {
-    pub per_instance_descriptor: DescriptorSetArc, // set 2
+    pub per_instance_descriptor: DescriptorSetArc, // set 2 (set 0 in the shadow map pass)
 }
 
 #[derive(Debug)]
 pub struct PreparedViewNodeMeshData {
-    pub per_instance_descriptor: DescriptorSetArc, // set 2
+    pub per_instance_descriptor: DescriptorSetArc, // set 2 (set 0 in the shadow map pass)
     pub frame_node_index: FrameNodeIndex,
-    pub per_view_descriptor: DescriptorSetArc, // set 0
+    pub per_view_descriptor: Option<DescriptorSetArc>, // set 0, not used by the shadow map pass
 }
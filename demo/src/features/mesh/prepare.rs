@@ -1,44 +1,48 @@
 use renderer::nodes::{
     RenderView, ViewSubmitNodes, FeatureSubmitNodes, FeatureCommandWriter, RenderFeatureIndex,
-    FramePacket, DefaultPrepareJobImpl, PerFrameNode, PerViewNode, RenderFeature,
+    FramePacket, DefaultPrepareJobImpl, PerFrameNode, PerViewNode, RenderFeature, SubmitNodeId,
+    SubmitNodeSortKeyBuilder,
 };
 use crate::features::mesh::{
     MeshRenderFeature, ExtractedFrameNodeMeshData, ExtractedViewNodeMeshData,
     PreparedViewNodeMeshData,
 };
-use crate::phases::OpaqueRenderPhase;
-use glam::Vec3;
+use crate::phases::{OpaqueRenderPhase, ShadowMapRenderPhase};
 use super::MeshCommandWriter;
 use crate::render_contexts::{RenderJobWriteContext, RenderJobPrepareContext};
 use renderer::assets::resources::{PipelineSwapchainInfo, DescriptorSetArc};
+use ash::vk::Handle;
 
 pub struct MeshPrepareJobImpl {
     pipeline_info: PipelineSwapchainInfo,
-    descriptor_sets_per_view: Vec<DescriptorSetArc>,
+    shadow_map_pipeline_info: PipelineSwapchainInfo,
+    descriptor_sets_per_view: Vec<Option<DescriptorSetArc>>,
     extracted_frame_node_mesh_data: Vec<Option<ExtractedFrameNodeMeshData>>,
     extracted_view_node_mesh_data: Vec<Vec<Option<ExtractedViewNodeMeshData>>>,
-    prepared_view_node_mesh_data: Vec<PreparedViewNodeMeshData>,
 }
 
 impl MeshPrepareJobImpl {
     pub(super) fn new(
         pipeline_info: PipelineSwapchainInfo,
-        descriptor_sets_per_view: Vec<DescriptorSetArc>,
+        shadow_map_pipeline_info: PipelineSwapchainInfo,
+        descriptor_sets_per_view: Vec<Option<DescriptorSetArc>>,
         extracted_frame_node_mesh_data: Vec<Option<ExtractedFrameNodeMeshData>>,
         extracted_view_node_mesh_data: Vec<Vec<Option<ExtractedViewNodeMeshData>>>,
     ) -> Self {
-        let prepared_view_node_mesh_data = Vec::with_capacity(extracted_view_node_mesh_data.len());
         MeshPrepareJobImpl {
             pipeline_info,
+            shadow_map_pipeline_info,
             descriptor_sets_per_view,
             extracted_frame_node_mesh_data,
             extracted_view_node_mesh_data,
-            prepared_view_node_mesh_data,
         }
     }
 }
 
 impl DefaultPrepareJobImpl<RenderJobPrepareContext, RenderJobWriteContext> for MeshPrepareJobImpl {
+    type PreparedFrameNodeData = ();
+    type PreparedViewNodeData = Option<PreparedViewNodeMeshData>;
+
     fn prepare_begin(
         &mut self,
         _prepare_context: &RenderJobPrepareContext,
@@ -49,51 +53,65 @@ impl DefaultPrepareJobImpl<RenderJobPrepareContext, RenderJobWriteContext> for M
     }
 
     fn prepare_frame_node(
-        &mut self,
+        &self,
         _prepare_context: &RenderJobPrepareContext,
         _frame_node: PerFrameNode,
         _frame_node_index: u32,
-        _submit_nodes: &mut FeatureSubmitNodes,
     ) {
     }
 
     fn prepare_view_node(
-        &mut self,
+        &self,
         _prepare_context: &RenderJobPrepareContext,
         view: &RenderView,
         view_node: PerViewNode,
         view_node_index: u32,
+        _prepared_frame_node_data: &[()],
+        submit_node_id: SubmitNodeId,
         submit_nodes: &mut ViewSubmitNodes,
-    ) {
+    ) -> Option<PreparedViewNodeMeshData> {
         let frame_node_index = view_node.frame_node_index();
-        let extracted_frame_data = &self.extracted_frame_node_mesh_data[frame_node_index as usize];
+        let extracted_frame_data =
+            self.extracted_frame_node_mesh_data[frame_node_index as usize].as_ref()?;
 
-        if let Some(extracted_frame_data) = extracted_frame_data {
-            if let Some(extracted_view_data) = &self.extracted_view_node_mesh_data
-                [view.view_index() as usize][view_node_index as usize]
-            {
-                let submit_node_id = self.prepared_view_node_mesh_data.len() as u32;
-                self.prepared_view_node_mesh_data
-                    .push(PreparedViewNodeMeshData {
-                        per_view_descriptor: self.descriptor_sets_per_view
-                            [view.view_index() as usize]
-                            .clone(),
-                        frame_node_index,
-                        per_instance_descriptor: extracted_view_data
-                            .per_instance_descriptor
-                            .clone(),
-                    });
+        let extracted_view_data = self.extracted_view_node_mesh_data[view.view_index() as usize]
+            [view_node_index as usize]
+            .as_ref()?;
 
-                let distance_from_camera = Vec3::length(
-                    extracted_frame_data.world_transform.w_axis().truncate() - view.eye_position(),
-                );
-                submit_nodes.add_submit_node::<OpaqueRenderPhase>(
-                    submit_node_id,
-                    0,
-                    distance_from_camera,
-                );
-            }
-        }
+        // Meshes at similar depths that share a material end up next to each other. Only the first
+        // draw call's material is used, which is enough for the common single-material mesh.
+        let material = extracted_frame_data
+            .draw_calls
+            .first()
+            .map(|draw_call| draw_call.per_material_descriptor.get().as_raw())
+            .unwrap_or(0);
+
+        // Submit nodes for phases the view doesn't include are dropped
+        let opaque_sort_key = SubmitNodeSortKeyBuilder::new()
+            .pipeline(self.pipeline_info.pipeline.get_raw().pipelines[0].as_raw())
+            .material(material)
+            .build();
+        submit_nodes.add_submit_node::<OpaqueRenderPhase>(
+            submit_node_id,
+            opaque_sort_key,
+            view_node.distance_from_camera(),
+        );
+
+        // The shadow map pass doesn't bind materials
+        let shadow_map_sort_key = SubmitNodeSortKeyBuilder::new()
+            .pipeline(self.shadow_map_pipeline_info.pipeline.get_raw().pipelines[0].as_raw())
+            .build();
+        submit_nodes.add_submit_node::<ShadowMapRenderPhase>(
+            submit_node_id,
+            shadow_map_sort_key,
+            view_node.distance_from_camera(),
+        );
+
+        Some(PreparedViewNodeMeshData {
+            per_view_descriptor: self.descriptor_sets_per_view[view.view_index() as usize].clone(),
+            frame_node_index,
+            per_instance_descriptor: extracted_view_data.per_instance_descriptor.clone(),
+        })
     }
 
     fn prepare_view_finalize(
@@ -107,13 +125,16 @@ impl DefaultPrepareJobImpl<RenderJobPrepareContext, RenderJobWriteContext> for M
     fn prepare_frame_finalize(
         self,
         _prepare_context: &RenderJobPrepareContext,
+        _prepared_frame_node_data: Vec<()>,
+        prepared_view_node_data: Vec<Option<PreparedViewNodeMeshData>>,
         _submit_nodes: &mut FeatureSubmitNodes,
     ) -> Box<dyn FeatureCommandWriter<RenderJobWriteContext>> {
         Box::new(MeshCommandWriter {
             pipeline_info: self.pipeline_info,
+            shadow_map_pipeline_info: self.shadow_map_pipeline_info,
             descriptor_sets_per_view: self.descriptor_sets_per_view,
             extracted_frame_node_mesh_data: self.extracted_frame_node_mesh_data,
-            prepared_view_node_mesh_data: self.prepared_view_node_mesh_data,
+            prepared_view_node_mesh_data: prepared_view_node_data,
         })
     }
 
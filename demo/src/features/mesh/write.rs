@@ -1,65 +1,189 @@
 use crate::features::mesh::{MeshRenderFeature, ExtractedFrameNodeMeshData, PreparedViewNodeMeshData};
 use renderer::nodes::{
-    RenderFeatureIndex, RenderPhaseIndex, RenderFeature, SubmitNodeId, FeatureCommandWriter, RenderView,
+    RenderFeatureIndex, RenderPhaseIndex, RenderFeature, SubmitNodeId, FeatureCommandWriter,
+    RenderView, RenderElementState,
 };
 use crate::render_contexts::RenderJobWriteContext;
 use renderer::assets::resources::{PipelineSwapchainInfo, DescriptorSetArc};
 use ash::vk;
+use ash::vk::Handle;
 use ash::version::DeviceV1_0;
+use crate::phases::ShadowMapRenderPhase;
+use renderer::nodes::RenderPhase;
 
 pub struct MeshCommandWriter {
     pub pipeline_info: PipelineSwapchainInfo,
-    pub descriptor_sets_per_view: Vec<DescriptorSetArc>,
+    pub shadow_map_pipeline_info: PipelineSwapchainInfo,
+    pub descriptor_sets_per_view: Vec<Option<DescriptorSetArc>>,
     pub extracted_frame_node_mesh_data: Vec<Option<ExtractedFrameNodeMeshData>>,
-    pub prepared_view_node_mesh_data: Vec<PreparedViewNodeMeshData>,
+    // Indexed by submit node id. Entries are None for view nodes that didn't produce a submit node
+    pub prepared_view_node_mesh_data: Vec<Option<PreparedViewNodeMeshData>>,
 }
 
-impl FeatureCommandWriter<RenderJobWriteContext> for MeshCommandWriter {
-    fn apply_setup(
+impl MeshCommandWriter {
+    fn pipeline_info(
+        &self,
+        render_phase_index: RenderPhaseIndex,
+    ) -> &PipelineSwapchainInfo {
+        if render_phase_index == ShadowMapRenderPhase::render_phase_index() {
+            &self.shadow_map_pipeline_info
+        } else {
+            &self.pipeline_info
+        }
+    }
+
+    // Depth-only, so only the per-instance set and the vertex positions are needed
+    fn render_element_shadow_map(
         &self,
         write_context: &mut RenderJobWriteContext,
-        _view: &RenderView,
-        _render_phase_index: RenderPhaseIndex,
+        view_node_data: &PreparedViewNodeMeshData,
+        frame_node_data: &ExtractedFrameNodeMeshData,
     ) {
-        // println!("render");
         let logical_device = write_context.device_context.device();
         let command_buffer = write_context.command_buffer;
+
         unsafe {
-            logical_device.cmd_bind_pipeline(
+            logical_device.cmd_bind_descriptor_sets(
                 command_buffer,
                 vk::PipelineBindPoint::GRAPHICS,
-                self.pipeline_info.pipeline.get_raw().pipelines[0],
+                self.shadow_map_pipeline_info
+                    .pipeline_layout
+                    .get_raw()
+                    .pipeline_layout,
+                0,
+                &[view_node_data.per_instance_descriptor.get()],
+                &[],
             );
+
+            for draw_call in &frame_node_data.draw_calls {
+                logical_device.cmd_bind_vertex_buffers(
+                    command_buffer,
+                    0, // first binding
+                    &[frame_node_data.vertex_buffer.get_raw().buffer],
+                    &[draw_call.vertex_buffer_offset_in_bytes as u64], // offsets
+                );
+
+                logical_device.cmd_bind_index_buffer(
+                    command_buffer,
+                    frame_node_data.index_buffer.get_raw().buffer,
+                    draw_call.index_buffer_offset_in_bytes as u64, // offset
+                    vk::IndexType::UINT16,
+                );
+
+                logical_device.cmd_draw_indexed(
+                    command_buffer,
+                    draw_call.index_buffer_size_in_bytes / 2, //sizeof(u16)
+                    1,
+                    0,
+                    0,
+                    0,
+                );
+            }
         }
     }
+}
 
-    fn render_element(
+impl FeatureCommandWriter<RenderJobWriteContext> for MeshCommandWriter {
+    fn apply_setup(
         &self,
-        write_context: &mut RenderJobWriteContext,
+        _write_context: &mut RenderJobWriteContext,
         _view: &RenderView,
         _render_phase_index: RenderPhaseIndex,
+    ) {
+    }
+
+    // The pipeline and the per-view set are shared by every mesh in the view, so they are only
+    // bound once per run of mesh nodes
+    fn render_element_state(
+        &self,
+        _view: &RenderView,
+        render_phase_index: RenderPhaseIndex,
         index: SubmitNodeId,
+    ) -> Option<RenderElementState> {
+        let mut state = RenderElementState {
+            pipeline: self
+                .pipeline_info(render_phase_index)
+                .pipeline
+                .get_raw()
+                .pipelines[0]
+                .as_raw(),
+            ..Default::default()
+        };
+
+        if render_phase_index != ShadowMapRenderPhase::render_phase_index() {
+            let view_node_data = self.prepared_view_node_mesh_data[index as usize]
+                .as_ref()
+                .unwrap();
+            state.descriptor_sets[0] = view_node_data
+                .per_view_descriptor
+                .as_ref()
+                .unwrap()
+                .get()
+                .as_raw();
+        }
+
+        Some(state)
+    }
+
+    fn bind_render_element_state(
+        &self,
+        write_context: &mut RenderJobWriteContext,
+        _view: &RenderView,
+        render_phase_index: RenderPhaseIndex,
+        state: &RenderElementState,
+        previous_state: Option<&RenderElementState>,
     ) {
         let logical_device = write_context.device_context.device();
         let command_buffer = write_context.command_buffer;
+        let pipeline_info = self.pipeline_info(render_phase_index);
+        unsafe {
+            if state.pipeline_changed(previous_state) {
+                logical_device.cmd_bind_pipeline(
+                    command_buffer,
+                    vk::PipelineBindPoint::GRAPHICS,
+                    pipeline_info.pipeline.get_raw().pipelines[0],
+                );
+            }
+
+            // Bind per-pass data (UBO with view/proj matrix, sampler, shadow map)
+            if state.descriptor_sets[0] != 0 && state.descriptor_set_changed(0, previous_state) {
+                logical_device.cmd_bind_descriptor_sets(
+                    command_buffer,
+                    vk::PipelineBindPoint::GRAPHICS,
+                    pipeline_info.pipeline_layout.get_raw().pipeline_layout,
+                    0,
+                    &[vk::DescriptorSet::from_raw(state.descriptor_sets[0])],
+                    &[],
+                );
+            }
+        }
+    }
 
-        let view_node_data = &self.prepared_view_node_mesh_data[index as usize];
+    fn render_element(
+        &self,
+        write_context: &mut RenderJobWriteContext,
+        _view: &RenderView,
+        render_phase_index: RenderPhaseIndex,
+        index: SubmitNodeId,
+    ) {
+        let view_node_data = self.prepared_view_node_mesh_data[index as usize]
+            .as_ref()
+            .unwrap();
         let frame_node_data = self.extracted_frame_node_mesh_data
             [view_node_data.frame_node_index as usize]
             .as_ref()
             .unwrap();
 
-        unsafe {
-            // Bind per-pass data (UBO with view/proj matrix, sampler)
-            logical_device.cmd_bind_descriptor_sets(
-                command_buffer,
-                vk::PipelineBindPoint::GRAPHICS,
-                self.pipeline_info.pipeline_layout.get_raw().pipeline_layout,
-                0,
-                &[view_node_data.per_view_descriptor.get()],
-                &[],
-            );
+        if render_phase_index == ShadowMapRenderPhase::render_phase_index() {
+            self.render_element_shadow_map(write_context, view_node_data, frame_node_data);
+            return;
+        }
 
+        let logical_device = write_context.device_context.device();
+        let command_buffer = write_context.command_buffer;
+
+        // The pipeline and per-view data are bound by bind_render_element_state
+        unsafe {
             logical_device.cmd_bind_descriptor_sets(
                 command_buffer,
                 vk::PipelineBindPoint::GRAPHICS,
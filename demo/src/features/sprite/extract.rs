@@ -1,5 +1,6 @@
 use crate::features::sprite::{
     ExtractedSpriteData, SpriteRenderNodeSet, SpriteRenderFeature, SpriteRenderNode,
+    ExtractedSpriteFrameNodeData,
 };
 use crate::components::{PositionComponent, SpriteComponent};
 use crate::render_contexts::{RenderJobExtractContext, RenderJobWriteContext, RenderJobPrepareContext};
@@ -46,7 +47,6 @@ pub struct SpriteExtractJobImpl {
     descriptor_set_allocator: DescriptorSetAllocatorRef,
     pipeline_info: PipelineSwapchainInfo,
     sprite_material: Handle<MaterialAsset>,
-    extracted_frame_node_sprite_data: Vec<Option<ExtractedSpriteData>>,
     per_view_descriptors: Vec<DescriptorSetArc>,
 }
 
@@ -63,7 +63,6 @@ impl SpriteExtractJobImpl {
             pipeline_info,
             sprite_material: sprite_material.clone(),
             //descriptor_set_per_pass,
-            extracted_frame_node_sprite_data: Default::default(),
             per_view_descriptors: Default::default(),
         }
     }
@@ -72,15 +71,15 @@ impl SpriteExtractJobImpl {
 impl DefaultExtractJobImpl<RenderJobExtractContext, RenderJobPrepareContext, RenderJobWriteContext>
     for SpriteExtractJobImpl
 {
+    type ExtractedFrameNodeData = Option<ExtractedSpriteFrameNodeData>;
+    type ExtractedViewNodeData = ();
+
     fn extract_begin(
         &mut self,
         extract_context: &RenderJobExtractContext,
-        frame_packet: &FramePacket,
+        _frame_packet: &FramePacket,
         _views: &[&RenderView],
     ) {
-        self.extracted_frame_node_sprite_data
-            .reserve(frame_packet.frame_node_count(self.feature_index()) as usize);
-
         // for view in views {
         //     let layout = extract_context.resource_manager.get_descriptor_set_info(&self.sprite_material, 0, 0);
         //     let mut descriptor_set = self.descriptor_set_allocator.create_dyn_descriptor_set_uninitialized(&layout.descriptor_set_layout).unwrap();
@@ -129,11 +128,11 @@ impl DefaultExtractJobImpl<RenderJobExtractContext, RenderJobPrepareContext, Ren
     }
 
     fn extract_frame_node(
-        &mut self,
+        &self,
         extract_context: &RenderJobExtractContext,
         frame_node: PerFrameNode,
         _frame_node_index: u32,
-    ) {
+    ) -> Option<ExtractedSpriteFrameNodeData> {
         let render_node_index = frame_node.render_node_index();
         let render_node_handle = RawSlabKey::<SpriteRenderNode>::new(render_node_index);
 
@@ -154,45 +153,22 @@ impl DefaultExtractJobImpl<RenderJobExtractContext, RenderJobPrepareContext, Ren
 
         let image_info = extract_context
             .resource_manager
-            .get_image_info(&sprite_component.image);
-        if image_info.is_none() {
-            self.extracted_frame_node_sprite_data.push(None);
-            return;
-        }
-        let image_info = image_info.unwrap();
-
-        let descriptor_set_info =
-            extract_context
-                .resource_manager
-                .get_descriptor_set_info(&self.sprite_material, 0, 1);
-        let mut sprite_texture_descriptor = self
-            .descriptor_set_allocator
-            .create_dyn_descriptor_set_uninitialized(&descriptor_set_info.descriptor_set_layout)
-            .unwrap();
+            .get_image_info(&sprite_component.image)?;
 
-        sprite_texture_descriptor.set_image(0, image_info.image_view);
-        sprite_texture_descriptor
-            .flush(&mut self.descriptor_set_allocator)
-            .unwrap();
-        let texture_descriptor_set = sprite_texture_descriptor.descriptor_set().clone();
-
-        self.extracted_frame_node_sprite_data
-            .push(Some(ExtractedSpriteData {
-                position: position_component.position,
-                texture_size: glam::Vec2::new(50.0, 50.0),
-                scale: 1.0,
-                rotation: 0.0,
-                alpha: sprite_component.alpha,
-                texture_descriptor_set,
-            }));
+        Some(ExtractedSpriteFrameNodeData {
+            position: position_component.position,
+            alpha: sprite_component.alpha,
+            image_view: image_info.image_view,
+        })
     }
 
     fn extract_view_node(
-        &mut self,
+        &self,
         _extract_context: &RenderJobExtractContext,
         _view: &RenderView,
         _view_node: PerViewNode,
         _view_node_index: u32,
+        _extracted_frame_node_data: &[Option<ExtractedSpriteFrameNodeData>],
     ) {
     }
 
@@ -204,14 +180,49 @@ impl DefaultExtractJobImpl<RenderJobExtractContext, RenderJobPrepareContext, Ren
     }
 
     fn extract_frame_finalize(
-        self,
-        _extract_context: &RenderJobExtractContext,
+        mut self,
+        extract_context: &RenderJobExtractContext,
+        extracted_frame_node_data: Vec<Option<ExtractedSpriteFrameNodeData>>,
+        _extracted_view_node_data: Vec<Vec<()>>,
     ) -> Box<dyn PrepareJob<RenderJobPrepareContext, RenderJobWriteContext>> {
+        let descriptor_set_info =
+            extract_context
+                .resource_manager
+                .get_descriptor_set_info(&self.sprite_material, 0, 1);
+
+        let descriptor_set_allocator = &mut self.descriptor_set_allocator;
+        let extracted_sprite_data = extracted_frame_node_data
+            .into_iter()
+            .map(|frame_node_data| {
+                let frame_node_data = frame_node_data?;
+                let mut sprite_texture_descriptor = descriptor_set_allocator
+                    .create_dyn_descriptor_set_uninitialized(
+                        &descriptor_set_info.descriptor_set_layout,
+                    )
+                    .unwrap();
+
+                sprite_texture_descriptor.set_image(0, frame_node_data.image_view);
+                sprite_texture_descriptor
+                    .flush(descriptor_set_allocator)
+                    .unwrap();
+                let texture_descriptor_set = sprite_texture_descriptor.descriptor_set().clone();
+
+                Some(ExtractedSpriteData {
+                    position: frame_node_data.position,
+                    texture_size: glam::Vec2::new(50.0, 50.0),
+                    scale: 1.0,
+                    rotation: 0.0,
+                    alpha: frame_node_data.alpha,
+                    texture_descriptor_set,
+                })
+            })
+            .collect();
+
         let prepare_impl = SpritePrepareJobImpl::new(
             self.device_context,
             self.pipeline_info,
             self.per_view_descriptors.clone(),
-            self.extracted_frame_node_sprite_data,
+            extracted_sprite_data,
         );
 
         Box::new(DefaultPrepareJob::new(prepare_impl))
@@ -19,6 +19,7 @@ mod write;
 use write::SpriteCommandWriter;
 use renderer::vulkan::VkDeviceContext;
 use renderer::assets::resources::{PipelineSwapchainInfo, DescriptorSetArc, DescriptorSetAllocatorRef};
+use renderer::assets::resources::{ResourceArc, ImageViewResource};
 
 /// Per-pass "global" data
 #[derive(Clone, Debug, Copy)]
@@ -161,6 +162,15 @@ impl RenderFeature for SpriteRenderFeature {
     }
 }
 
+// Sprites are extracted in parallel, so the texture descriptor set is created afterwards in
+// extract_frame_finalize
+#[derive(Debug)]
+pub(self) struct ExtractedSpriteFrameNodeData {
+    position: glam::Vec3,
+    alpha: f32,
+    image_view: ResourceArc<ImageViewResource>,
+}
+
 #[derive(Debug)]
 pub(self) struct ExtractedSpriteData {
     position: glam::Vec3,
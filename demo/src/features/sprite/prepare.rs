@@ -1,18 +1,19 @@
 use crate::phases::TransparentRenderPhase;
 use renderer::nodes::{
     RenderView, ViewSubmitNodes, FeatureSubmitNodes, FeatureCommandWriter, RenderFeatureIndex,
-    FramePacket, DefaultPrepareJobImpl, PerFrameNode, PerViewNode, RenderFeature,
+    FramePacket, DefaultPrepareJobImpl, PerFrameNode, PerViewNode, RenderFeature, SubmitNodeId,
+    SubmitNodeSortKeyBuilder,
 };
 use crate::features::sprite::{
     SpriteRenderFeature, ExtractedSpriteData, QUAD_VERTEX_LIST, QUAD_INDEX_LIST, SpriteDrawCall,
     SpriteVertex,
 };
 use crate::phases::OpaqueRenderPhase;
-use glam::Vec3;
 use super::SpriteCommandWriter;
 use crate::render_contexts::{RenderJobWriteContext, RenderJobPrepareContext};
 use renderer::vulkan::{VkBuffer, VkDeviceContext};
 use ash::vk;
+use ash::vk::Handle;
 use renderer::assets::resources::{PipelineSwapchainInfo, DescriptorSetArc};
 
 pub struct SpritePrepareJobImpl {
@@ -49,6 +50,9 @@ impl SpritePrepareJobImpl {
 impl DefaultPrepareJobImpl<RenderJobPrepareContext, RenderJobWriteContext>
     for SpritePrepareJobImpl
 {
+    type PreparedFrameNodeData = ();
+    type PreparedViewNodeData = ();
+
     fn prepare_begin(
         &mut self,
         _prepare_context: &RenderJobPrepareContext,
@@ -98,20 +102,21 @@ impl DefaultPrepareJobImpl<RenderJobPrepareContext, RenderJobWriteContext>
     }
 
     fn prepare_frame_node(
-        &mut self,
+        &self,
         _prepare_context: &RenderJobPrepareContext,
         _frame_node: PerFrameNode,
         _frame_node_index: u32,
-        _submit_nodes: &mut FeatureSubmitNodes,
     ) {
     }
 
     fn prepare_view_node(
-        &mut self,
+        &self,
         _prepare_context: &RenderJobPrepareContext,
-        view: &RenderView,
+        _view: &RenderView,
         view_node: PerViewNode,
         _view_node_index: u32,
+        _prepared_frame_node_data: &[()],
+        _submit_node_id: SubmitNodeId,
         submit_nodes: &mut ViewSubmitNodes,
     ) {
         // Use the frame node index as the submit ID since we don't have any view-specific data
@@ -122,15 +127,18 @@ impl DefaultPrepareJobImpl<RenderJobPrepareContext, RenderJobWriteContext>
         if let Some(extracted_data) =
             &self.extracted_frame_node_sprite_data[frame_node_index as usize]
         {
+            // Group sprites by texture so the descriptor set is bound as rarely as possible
+            let sort_key = SubmitNodeSortKeyBuilder::new()
+                .material(extracted_data.texture_descriptor_set.get().as_raw())
+                .build();
+
             if extracted_data.alpha >= 1.0 {
-                submit_nodes.add_submit_node::<OpaqueRenderPhase>(frame_node_index, 0, 0.0);
+                submit_nodes.add_submit_node::<OpaqueRenderPhase>(frame_node_index, sort_key, 0.0);
             } else {
-                let distance_from_camera =
-                    Vec3::length(extracted_data.position - view.eye_position());
                 submit_nodes.add_submit_node::<TransparentRenderPhase>(
                     frame_node_index,
-                    0,
-                    distance_from_camera,
+                    sort_key,
+                    view_node.distance_from_camera(),
                 );
             }
         }
@@ -147,6 +155,8 @@ impl DefaultPrepareJobImpl<RenderJobPrepareContext, RenderJobWriteContext>
     fn prepare_frame_finalize(
         self,
         prepare_context: &RenderJobPrepareContext,
+        _prepared_frame_node_data: Vec<()>,
+        _prepared_view_node_data: Vec<()>,
         _submit_nodes: &mut FeatureSubmitNodes,
     ) -> Box<dyn FeatureCommandWriter<RenderJobWriteContext>> {
         //TODO: indexes are u16 so we may need to produce more than one set of buffers
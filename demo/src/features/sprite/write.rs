@@ -1,11 +1,13 @@
 use crate::features::sprite::{SpriteRenderFeature, SpriteDrawCall};
 use renderer::nodes::{
-    RenderFeatureIndex, RenderPhaseIndex, RenderFeature, SubmitNodeId, FeatureCommandWriter, RenderView,
+    RenderFeatureIndex, RenderPhaseIndex, RenderFeature, SubmitNodeId, FeatureCommandWriter,
+    RenderView, RenderElementState,
 };
 use crate::render_contexts::RenderJobWriteContext;
 use renderer::vulkan::VkBufferRaw;
 use renderer::assets::resources::{ResourceArc, PipelineSwapchainInfo, DescriptorSetArc};
 use ash::vk;
+use ash::vk::Handle;
 use ash::version::DeviceV1_0;
 
 pub struct SpriteCommandWriter {
@@ -59,18 +61,35 @@ impl FeatureCommandWriter<RenderJobWriteContext> for SpriteCommandWriter {
         }
     }
 
-    fn render_element(
+    // Consecutive sprites that share a texture don't need to rebind it
+    fn render_element_state(
         &self,
-        write_context: &mut RenderJobWriteContext,
         _view: &RenderView,
         _render_phase_index: RenderPhaseIndex,
         index: SubmitNodeId,
+    ) -> Option<RenderElementState> {
+        let mut state = RenderElementState::default();
+        state.descriptor_sets[1] = self.draw_calls[index as usize]
+            .texture_descriptor_set
+            .get()
+            .as_raw();
+        Some(state)
+    }
+
+    fn bind_render_element_state(
+        &self,
+        write_context: &mut RenderJobWriteContext,
+        _view: &RenderView,
+        _render_phase_index: RenderPhaseIndex,
+        state: &RenderElementState,
+        previous_state: Option<&RenderElementState>,
     ) {
-        // //println!("render");
+        if !state.descriptor_set_changed(1, previous_state) {
+            return;
+        }
+
         let logical_device = write_context.device_context.device();
         let command_buffer = write_context.command_buffer;
-        let draw_call = &self.draw_calls[index as usize];
-
         unsafe {
             // Bind per-draw-call data (i.e. texture)
             logical_device.cmd_bind_descriptor_sets(
@@ -78,10 +97,25 @@ impl FeatureCommandWriter<RenderJobWriteContext> for SpriteCommandWriter {
                 vk::PipelineBindPoint::GRAPHICS,
                 self.pipeline_info.pipeline_layout.get_raw().pipeline_layout,
                 1,
-                &[draw_call.texture_descriptor_set.get()],
+                &[vk::DescriptorSet::from_raw(state.descriptor_sets[1])],
                 &[],
             );
+        }
+    }
+
+    fn render_element(
+        &self,
+        write_context: &mut RenderJobWriteContext,
+        _view: &RenderView,
+        _render_phase_index: RenderPhaseIndex,
+        index: SubmitNodeId,
+    ) {
+        // //println!("render");
+        let logical_device = write_context.device_context.device();
+        let command_buffer = write_context.command_buffer;
+        let draw_call = &self.draw_calls[index as usize];
 
+        unsafe {
             logical_device.cmd_draw_indexed(
                 command_buffer,
                 draw_call.index_buffer_count as u32,
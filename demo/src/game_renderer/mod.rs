@@ -1,5 +1,7 @@
 use crate::imgui_support::Sdl2ImguiManager;
-use renderer::vulkan::{VkSurface, Window, VkDeviceContext, VkContext, FrameInFlight};
+use renderer::vulkan::{
+    VkSurface, Window, VkDeviceContext, VkContext, FrameInFlight, VkGpuProfileFrame,
+};
 use ash::prelude::VkResult;
 use std::mem::ManuallyDrop;
 use ash::vk;
@@ -8,16 +10,17 @@ use crate::asset_resource::AssetResource;
 use renderer::assets::resources::{ResourceManager, ResourceArc, ImageViewResource};
 use crate::features::debug3d::create_debug3d_extract_job;
 use crate::features::sprite::{SpriteRenderNodeSet, create_sprite_extract_job};
-use renderer::visibility::{StaticVisibilityNodeSet, DynamicVisibilityNodeSet};
+use renderer::visibility::{StaticVisibilityNodeSet, DynamicVisibilityNodeSet, OccluderSet};
 use renderer::nodes::{
     RenderPhaseMaskBuilder, RenderPhaseMask, RenderRegistry, RenderViewSet, AllRenderNodes,
     FramePacketBuilder, ExtractJobSet,
 };
-use crate::phases::{OpaqueRenderPhase, UiRenderPhase};
+use crate::phases::{OpaqueRenderPhase, UiRenderPhase, ShadowMapRenderPhase};
 use crate::phases::TransparentRenderPhase;
 use legion::prelude::*;
 use crate::render_contexts::{RenderJobExtractContext};
-use crate::features::mesh::{create_mesh_extract_job, MeshRenderNodeSet};
+use crate::features::mesh::{create_mesh_extract_job, MeshRenderNodeSet, MeshShadowMapData};
+use crate::components::DirectionalLightComponent;
 use std::sync::{Arc, Mutex};
 
 mod static_resources;
@@ -32,6 +35,13 @@ use swapchain_resources::SwapchainResources;
 mod render_frame_job;
 use render_frame_job::RenderFrameJob;
 
+mod shadow_map_cascades;
+pub use shadow_map_cascades::ShadowMapCascade;
+pub use shadow_map_cascades::ShadowMapCameraParams;
+pub use shadow_map_cascades::SHADOW_MAP_CASCADE_COUNT;
+pub use shadow_map_cascades::SHADOW_MAP_RESOLUTION;
+use shadow_map_cascades::calculate_shadow_map_cascades;
+
 //TODO: Find a way to not expose this
 mod swapchain_handling;
 pub use swapchain_handling::SwapchainLifetimeListener;
@@ -45,6 +55,7 @@ pub struct GameRendererInner {
     swapchain_resources: Option<SwapchainResources>,
 
     main_camera_render_phase_mask: RenderPhaseMask,
+    shadow_map_render_phase_mask: RenderPhaseMask,
 
     previous_frame_result: Option<VkResult<()>>,
 
@@ -54,6 +65,9 @@ pub struct GameRendererInner {
 #[derive(Clone)]
 pub struct GameRenderer {
     inner: Arc<Mutex<GameRendererInner>>,
+
+    // Kept outside of inner so that reading it doesn't wait on the render thread
+    gpu_profile_results: Arc<Mutex<Option<VkGpuProfileFrame>>>,
 }
 
 impl GameRenderer {
@@ -82,6 +96,10 @@ impl GameRenderer {
             .add_render_phase::<UiRenderPhase>()
             .build();
 
+        let shadow_map_render_phase_mask = RenderPhaseMaskBuilder::default()
+            .add_render_phase::<ShadowMapRenderPhase>()
+            .build();
+
         log::info!("all waits complete");
         let game_renderer_resources =
             GameRendererStaticResources::new(asset_resource, resource_manager)?;
@@ -94,6 +112,7 @@ impl GameRenderer {
             swapchain_resources: None,
 
             main_camera_render_phase_mask,
+            shadow_map_render_phase_mask,
 
             render_thread,
 
@@ -102,9 +121,15 @@ impl GameRenderer {
 
         Ok(GameRenderer {
             inner: Arc::new(Mutex::new(renderer)),
+            gpu_profile_results: Default::default(),
         })
     }
 
+    /// GPU timings of a recent frame. These lag a few frames behind.
+    pub fn gpu_profile_results(&self) -> Option<VkGpuProfileFrame> {
+        self.gpu_profile_results.lock().unwrap().clone()
+    }
+
     fn create_font_atlas_image_view(
         device_context: &VkDeviceContext,
         resource_manager: &mut ResourceManager,
@@ -121,6 +146,9 @@ impl GameRenderer {
             height: imgui_font_atlas.height,
             data: imgui_font_atlas.data,
             color_space: renderer::assets::image_utils::ColorSpace::Linear,
+            format: renderer::assets::image_utils::DecodedTextureFormat::RGBA8,
+            layer_count: 1,
+            is_cube_map: false,
             mips: renderer::assets::image_utils::default_mip_settings_for_image(
                 imgui_font_atlas.width,
                 imgui_font_atlas.height,
@@ -143,6 +171,7 @@ impl GameRenderer {
         let dyn_resource_allocator = resource_manager.create_dyn_resource_allocator_set();
         let imgui_font_atlas_image = dyn_resource_allocator
             .insert_image(unsafe { ManuallyDrop::take(&mut imgui_font_atlas_image[0]) });
+        dyn_resource_allocator.set_debug_name(&imgui_font_atlas_image, "imgui font atlas");
 
         let subresource_range = vk::ImageSubresourceRange::builder()
             .aspect_mask(vk::ImageAspectFlags::COLOR)
@@ -165,6 +194,7 @@ impl GameRenderer {
 
         let imgui_font_atlas_image_view = dyn_resource_allocator
             .insert_image_view(imgui_font_atlas_image, imgui_font_atlas_image_view);
+        dyn_resource_allocator.set_debug_name(&imgui_font_atlas_image_view, "imgui font atlas");
 
         Ok(imgui_font_atlas_image_view)
     }
@@ -271,6 +301,9 @@ impl GameRenderer {
             resources.get::<DynamicVisibilityNodeSet>().unwrap();
         let dynamic_visibility_node_set = &*dynamic_visibility_node_set_fetch;
 
+        let occluder_set_fetch = resources.get::<OccluderSet>().unwrap();
+        let occluder_set = &*occluder_set_fetch;
+
         // let mut debug_draw_3d_line_lists = resources
         //     .get_mut::<DebugDraw3DResource>()
         //     .unwrap()
@@ -287,8 +320,12 @@ impl GameRenderer {
 
         let mut guard = game_renderer.inner.lock().unwrap();
         let main_camera_render_phase_mask = guard.main_camera_render_phase_mask.clone();
+        let shadow_map_render_phase_mask = guard.shadow_map_render_phase_mask.clone();
         let swapchain_resources = guard.swapchain_resources.as_mut().unwrap();
         let swapchain_surface_info = swapchain_resources.swapchain_surface_info.clone();
+        let shadow_map_image_view = swapchain_resources
+            .shadow_map_renderpass
+            .shadow_map_image_view;
 
         //
         // View Management
@@ -307,17 +344,25 @@ impl GameRenderer {
         let aspect_ratio = extents_width as f32 / extents_height as f32;
 
         let render_view_set = RenderViewSet::default();
-        let (main_view, view_proj) = {
-            let view = glam::Mat4::look_at_rh(
+        let main_camera_params = ShadowMapCameraParams {
+            view: glam::Mat4::look_at_rh(
                 eye,
                 glam::Vec3::new(0.0, 0.0, 0.0),
                 glam::Vec3::new(0.0, 0.0, 1.0),
-            );
+            ),
+            fov_y: std::f32::consts::FRAC_PI_4,
+            aspect_ratio,
+            near: 0.01,
+            far: 20.0,
+        };
+
+        let (main_view, view_proj) = {
+            let view = main_camera_params.view;
             let proj = glam::Mat4::perspective_rh_gl(
-                std::f32::consts::FRAC_PI_4,
-                aspect_ratio,
-                0.01,
-                20.0,
+                main_camera_params.fov_y,
+                main_camera_params.aspect_ratio,
+                main_camera_params.near,
+                main_camera_params.far,
             );
             let proj = glam::Mat4::from_scale(glam::Vec3::new(1.0, -1.0, 1.0)) * proj;
             let view_proj = proj * view;
@@ -333,24 +378,76 @@ impl GameRenderer {
             (main_view, view_proj)
         };
 
+        // Only the first directional light casts shadows. The cascades are created even if there
+        // is no light so that the shadow map renderpass always has a view per cascade.
+        let shadow_map_light_direction = <Read<DirectionalLightComponent>>::query()
+            .iter(world)
+            .next()
+            .map(|light| light.direction)
+            .unwrap_or_else(|| glam::Vec3::new(0.0, 0.0, -1.0));
+
+        let shadow_map_cascades =
+            calculate_shadow_map_cascades(&main_camera_params, shadow_map_light_direction);
+        let shadow_map_views: Vec<_> = shadow_map_cascades
+            .iter()
+            .enumerate()
+            .map(|(cascade_index, cascade)| {
+                render_view_set.create_view(
+                    cascade.eye_position,
+                    cascade.view,
+                    cascade.proj,
+                    shadow_map_render_phase_mask,
+                    format!("shadow_map_cascade_{}", cascade_index),
+                )
+            })
+            .collect();
+
         //
         // Visibility
         //
-        let main_view_static_visibility_result =
-            static_visibility_node_set.calculate_static_visibility(&main_view);
-        let main_view_dynamic_visibility_result =
-            dynamic_visibility_node_set.calculate_dynamic_visibility(&main_view);
+        let (main_view_static_visibility_result, main_view_dynamic_visibility_result) =
+            if occluder_set.occluder_count() > 0 {
+                let main_view_occlusion_buffer = occluder_set.build_occlusion_buffer(&main_view);
+                (
+                    static_visibility_node_set.calculate_static_visibility_with_occlusion(
+                        &main_view,
+                        &main_view_occlusion_buffer,
+                    ),
+                    dynamic_visibility_node_set.calculate_dynamic_visibility_with_occlusion(
+                        &main_view,
+                        &main_view_occlusion_buffer,
+                    ),
+                )
+            } else {
+                // Nothing can be occluded, so skip rasterizing and testing against an empty buffer
+                (
+                    static_visibility_node_set.calculate_static_visibility(&main_view),
+                    dynamic_visibility_node_set.calculate_dynamic_visibility(&main_view),
+                )
+            };
 
         log::trace!(
             "main view static node count: {}",
-            main_view_static_visibility_result.handles.len()
+            main_view_static_visibility_result.visible_nodes.len()
         );
 
         log::trace!(
             "main view dynamic node count: {}",
-            main_view_dynamic_visibility_result.handles.len()
+            main_view_dynamic_visibility_result.visible_nodes.len()
         );
 
+        // Shadow casters outside the main view can still cast into it, so these views don't
+        // use the main view's occlusion buffer
+        let shadow_map_visibility_results: Vec<_> = shadow_map_views
+            .iter()
+            .map(|shadow_map_view| {
+                [
+                    static_visibility_node_set.calculate_static_visibility(shadow_map_view),
+                    dynamic_visibility_node_set.calculate_dynamic_visibility(shadow_map_view),
+                ]
+            })
+            .collect();
+
         let sprite_render_nodes = resources.get::<SpriteRenderNodeSet>().unwrap();
         let mesh_render_nodes = resources.get::<MeshRenderNodeSet>().unwrap();
         let mut all_render_nodes = AllRenderNodes::new();
@@ -368,6 +465,13 @@ impl GameRenderer {
             ],
         );
 
+        for (shadow_map_view, visibility_results) in shadow_map_views
+            .iter()
+            .zip(shadow_map_visibility_results)
+        {
+            frame_packet_builder.add_view(shadow_map_view, &visibility_results);
+        }
+
         let mut descriptor_set_allocator = resource_manager.create_descriptor_set_allocator();
         swapchain_resources
             .debug_material_per_frame_data
@@ -386,6 +490,14 @@ impl GameRenderer {
         // Extract Jobs
         //
         let frame_packet = frame_packet_builder.build();
+
+        // Used by the mesh extract job and by the shadow map pass
+        let shadow_map_pipeline_info = resource_manager.get_pipeline_info(
+            &guard.static_resources.mesh_material,
+            &swapchain_surface_info,
+            1,
+        );
+
         let extract_job_set = {
             let sprite_pipeline_info = resource_manager.get_pipeline_info(
                 &guard.static_resources.sprite_material,
@@ -425,7 +537,12 @@ impl GameRenderer {
             extract_job_set.add_job(create_mesh_extract_job(
                 resource_manager.create_descriptor_set_allocator(),
                 mesh_pipeline_info,
+                shadow_map_pipeline_info.clone(),
                 &guard.static_resources.mesh_material,
+                MeshShadowMapData {
+                    cascades: shadow_map_cascades,
+                    image_view: shadow_map_image_view,
+                },
             ));
 
             // Debug 3D
@@ -451,8 +568,9 @@ impl GameRenderer {
 
         let mut extract_context =
             RenderJobExtractContext::new(&world, &resources, resource_manager);
-        let prepare_job_set =
-            extract_job_set.extract(&mut extract_context, &frame_packet, &[&main_view]);
+        let mut views = vec![&main_view];
+        views.extend(shadow_map_views.iter());
+        let prepare_job_set = extract_job_set.extract(&mut extract_context, &frame_packet, &views);
 
         let opaque_pipeline_info = resource_manager.get_pipeline_info(
             &guard.static_resources.sprite_material,
@@ -482,8 +600,10 @@ impl GameRenderer {
             dyn_resource_allocator_set,
             frame_packet,
             main_view,
+            shadow_map_views,
             render_registry: render_registry.clone(),
             device_context: device_context.clone(),
+            shadow_map_pipeline_info,
             opaque_pipeline_info,
             imgui_pipeline_info,
             frame_in_flight,
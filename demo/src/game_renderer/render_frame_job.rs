@@ -4,8 +4,8 @@ use crate::render_contexts::{
     RenderJobPrepareContext, RenderJobWriteContext, RenderJobWriteContextFactory,
 };
 use renderer::assets::resources::{DynResourceAllocatorSet, PipelineSwapchainInfo};
-use renderer::vulkan::{VkDeviceContext, FrameInFlight};
-use std::sync::MutexGuard;
+use renderer::vulkan::{VkDeviceContext, FrameInFlight, VkGpuProfileFrame};
+use std::sync::{Mutex, MutexGuard};
 use ash::prelude::VkResult;
 use ash::vk;
 
@@ -15,8 +15,10 @@ pub struct RenderFrameJob {
     pub dyn_resource_allocator_set: DynResourceAllocatorSet,
     pub frame_packet: FramePacket,
     pub main_view: RenderView,
+    pub shadow_map_views: Vec<RenderView>,
     pub render_registry: RenderRegistry,
     pub device_context: VkDeviceContext,
+    pub shadow_map_pipeline_info: PipelineSwapchainInfo,
     pub opaque_pipeline_info: PipelineSwapchainInfo,
     pub imgui_pipeline_info: PipelineSwapchainInfo,
     pub frame_in_flight: FrameInFlight,
@@ -29,12 +31,15 @@ impl RenderFrameJob {
 
         let result = Self::do_render_async(
             guard,
+            &self.game_renderer.gpu_profile_results,
             self.prepare_job_set,
             self.dyn_resource_allocator_set,
             self.frame_packet,
             self.main_view,
+            self.shadow_map_views,
             self.render_registry,
             self.device_context,
+            self.shadow_map_pipeline_info,
             self.opaque_pipeline_info,
             self.imgui_pipeline_info,
             self.frame_in_flight.present_index() as usize,
@@ -64,12 +69,15 @@ impl RenderFrameJob {
 
     fn do_render_async(
         mut guard: MutexGuard<GameRendererInner>,
+        gpu_profile_results: &Mutex<Option<VkGpuProfileFrame>>,
         prepare_job_set: PrepareJobSet<RenderJobPrepareContext, RenderJobWriteContext>,
         dyn_resource_allocator_set: DynResourceAllocatorSet,
         frame_packet: FramePacket,
         main_view: RenderView,
+        shadow_map_views: Vec<RenderView>,
         render_registry: RenderRegistry,
         device_context: VkDeviceContext,
+        shadow_map_pipeline_info: PipelineSwapchainInfo,
         opaque_pipeline_info: PipelineSwapchainInfo,
         imgui_pipeline_info: PipelineSwapchainInfo,
         present_index: usize,
@@ -80,16 +88,22 @@ impl RenderFrameJob {
 
         let mut command_buffers = vec![];
 
+        // Also reads back the timings of the last frame that used this swapchain image
+        let gpu_profiler = &mut swapchain_resources.gpu_profiler;
+        command_buffers.push(gpu_profiler.begin_frame(present_index)?);
+        if let Some(results) = gpu_profiler.latest_results() {
+            *gpu_profile_results.lock().unwrap() = Some(results.clone());
+        }
+
         //
         // Prepare Jobs - everything beyond this point could be done in parallel with the main thread
         //
         let prepare_context = RenderJobPrepareContext::new(dyn_resource_allocator_set);
-        let prepared_render_data = prepare_job_set.prepare(
-            &prepare_context,
-            &frame_packet,
-            &[&main_view],
-            &render_registry,
-        );
+        let shadow_map_view_refs: Vec<_> = shadow_map_views.iter().collect();
+        let mut views = vec![&main_view];
+        views.extend_from_slice(&shadow_map_view_refs);
+        let prepared_render_data =
+            prepare_job_set.prepare(&prepare_context, &frame_packet, &views, &render_registry);
         let t1 = std::time::Instant::now();
         log::trace!(
             "[async] render prepare took {} ms",
@@ -104,6 +118,21 @@ impl RenderFrameJob {
             prepare_context.dyn_resource_lookups,
         );
 
+        //
+        // Shadow map renderpass
+        //
+        log::trace!("shadow_map_renderpass update");
+        swapchain_resources.shadow_map_renderpass.update(
+            &shadow_map_pipeline_info,
+            present_index,
+            &*prepared_render_data,
+            &shadow_map_view_refs,
+            &write_context_factory,
+            &mut swapchain_resources.gpu_profiler,
+        )?;
+        command_buffers
+            .push(swapchain_resources.shadow_map_renderpass.command_buffers[present_index].clone());
+
         //
         // Opaque renderpass
         //
@@ -114,6 +143,7 @@ impl RenderFrameJob {
             &*prepared_render_data,
             &main_view,
             &write_context_factory,
+            &mut swapchain_resources.gpu_profiler,
         )?;
         command_buffers
             .push(swapchain_resources.opaque_renderpass.command_buffers[present_index].clone());
@@ -130,6 +160,7 @@ impl RenderFrameJob {
         swapchain_resources.msaa_renderpass.update(
             present_index,
             descriptor_set_per_pass,
+            &mut swapchain_resources.gpu_profiler,
             //debug_draw_3d_line_lists,
         )?;
         command_buffers
@@ -144,9 +175,11 @@ impl RenderFrameJob {
             .get();
         log::trace!("bloom_extract_renderpass update");
 
-        swapchain_resources
-            .bloom_extract_renderpass
-            .update(present_index, descriptor_set_per_pass)?;
+        swapchain_resources.bloom_extract_renderpass.update(
+            present_index,
+            descriptor_set_per_pass,
+            &mut swapchain_resources.gpu_profiler,
+        )?;
         command_buffers.push(
             swapchain_resources.bloom_extract_renderpass.command_buffers[present_index].clone(),
         );
@@ -154,7 +187,10 @@ impl RenderFrameJob {
         //
         // bloom blur
         //
+        // The blur command buffers are recorded once and reused, so the profiler can't write into
+        // them. Measure them with command buffers submitted around them instead.
         log::trace!("bloom_blur_renderpass update");
+        command_buffers.push(swapchain_resources.gpu_profiler.begin_scope("bloom_blur")?);
         command_buffers.push(swapchain_resources.bloom_blur_renderpass.command_buffers[0].clone());
         command_buffers.push(swapchain_resources.bloom_blur_renderpass.command_buffers[1].clone());
         command_buffers.push(swapchain_resources.bloom_blur_renderpass.command_buffers[0].clone());
@@ -165,6 +201,7 @@ impl RenderFrameJob {
         command_buffers.push(swapchain_resources.bloom_blur_renderpass.command_buffers[1].clone());
         command_buffers.push(swapchain_resources.bloom_blur_renderpass.command_buffers[0].clone());
         command_buffers.push(swapchain_resources.bloom_blur_renderpass.command_buffers[1].clone());
+        command_buffers.push(swapchain_resources.gpu_profiler.end_scope()?);
 
         //
         // bloom combine
@@ -175,9 +212,11 @@ impl RenderFrameJob {
             .get();
         log::trace!("bloom_combine_renderpass update");
 
-        swapchain_resources
-            .bloom_combine_renderpass
-            .update(present_index, descriptor_set_per_pass)?;
+        swapchain_resources.bloom_combine_renderpass.update(
+            present_index,
+            descriptor_set_per_pass,
+            &mut swapchain_resources.gpu_profiler,
+        )?;
         command_buffers.push(
             swapchain_resources.bloom_combine_renderpass.command_buffers[present_index].clone(),
         );
@@ -191,10 +230,13 @@ impl RenderFrameJob {
             &*prepared_render_data,
             &main_view,
             &write_context_factory,
+            &mut swapchain_resources.gpu_profiler,
         )?;
         command_buffers
             .push(swapchain_resources.ui_renderpass.command_buffers[present_index].clone());
 
+        command_buffers.push(swapchain_resources.gpu_profiler.end_frame()?);
+
         let t2 = std::time::Instant::now();
         log::trace!(
             "[async] render write took {} ms",
@@ -0,0 +1,146 @@
+use glam::{Mat4, Vec3, Vec4};
+
+pub const SHADOW_MAP_CASCADE_COUNT: usize = 4;
+
+// Must match the viewport in mesh_shadow_map.pipeline
+pub const SHADOW_MAP_RESOLUTION: u32 = 2048;
+
+// Blends between uniform (0.0) and logarithmic (1.0) split distances
+const CASCADE_SPLIT_LAMBDA: f32 = 0.75;
+
+/// The light-space view and projection for one slice of the main camera's frustum
+#[derive(Copy, Clone, Debug)]
+pub struct ShadowMapCascade {
+    pub eye_position: Vec3,
+    pub view: Mat4,
+    pub proj: Mat4,
+
+    // Distance along the main camera's view direction where this cascade ends
+    pub split_depth_vs: f32,
+}
+
+impl ShadowMapCascade {
+    /// Transforms view space positions of the camera the cascade was fit to into the cascade's
+    /// clip space
+    pub fn view_to_shadow_map(
+        &self,
+        camera_view: Mat4,
+    ) -> Mat4 {
+        self.proj * self.view * camera_view.inverse()
+    }
+}
+
+/// Perspective camera parameters needed to split its frustum
+#[derive(Copy, Clone, Debug)]
+pub struct ShadowMapCameraParams {
+    pub view: Mat4,
+    pub fov_y: f32,
+    pub aspect_ratio: f32,
+    pub near: f32,
+    pub far: f32,
+}
+
+/// Splits the camera frustum into `SHADOW_MAP_CASCADE_COUNT` slices and fits an orthographic
+/// projection looking down `light_direction` around each one
+pub fn calculate_shadow_map_cascades(
+    camera: &ShadowMapCameraParams,
+    light_direction: Vec3,
+) -> [ShadowMapCascade; SHADOW_MAP_CASCADE_COUNT] {
+    let light_direction = light_direction.normalize();
+    let camera_view_inverse = camera.view.inverse();
+    let tan_half_fov_y = (camera.fov_y * 0.5).tan();
+
+    let mut split_near = camera.near;
+    let mut cascades = [ShadowMapCascade {
+        eye_position: Vec3::zero(),
+        view: Mat4::identity(),
+        proj: Mat4::identity(),
+        split_depth_vs: 0.0,
+    }; SHADOW_MAP_CASCADE_COUNT];
+
+    for (cascade_index, cascade) in cascades.iter_mut().enumerate() {
+        let split_far = split_depth(camera, cascade_index + 1);
+
+        // Bounding sphere of the slice's corners. A sphere keeps the projection the same size
+        // as the camera rotates, which avoids shimmering at shadow edges.
+        let mut corners = [Vec3::zero(); 8];
+        for (corner_index, corner) in corners.iter_mut().enumerate() {
+            let depth = if corner_index & 4 == 0 {
+                split_near
+            } else {
+                split_far
+            };
+            let half_height = depth * tan_half_fov_y;
+            let half_width = half_height * camera.aspect_ratio;
+            let x = if corner_index & 1 == 0 {
+                -half_width
+            } else {
+                half_width
+            };
+            let y = if corner_index & 2 == 0 {
+                -half_height
+            } else {
+                half_height
+            };
+            *corner = camera_view_inverse.transform_point3(Vec3::new(x, y, -depth));
+        }
+
+        let center = corners
+            .iter()
+            .fold(Vec3::zero(), |sum, corner| sum + *corner)
+            / 8.0;
+        let radius = corners
+            .iter()
+            .map(|corner| (*corner - center).length())
+            .fold(0.0, f32::max);
+
+        // Round up so small changes in the camera don't change the texel size
+        let radius = (radius * 16.0).ceil() / 16.0;
+
+        // Back the light away from the slice so that casters between the light and the slice
+        // are still drawn into the shadow map
+        let eye_position = center - light_direction * (radius * 2.0);
+        let up = if light_direction.z().abs() > 0.99 {
+            Vec3::unit_y()
+        } else {
+            Vec3::unit_z()
+        };
+        let view = Mat4::look_at_rh(eye_position, center, up);
+        let proj = Mat4::orthographic_rh(-radius, radius, -radius, radius, 0.0, radius * 3.0);
+        let proj = snap_to_texels(proj * view) * proj;
+
+        *cascade = ShadowMapCascade {
+            eye_position,
+            view,
+            proj,
+            split_depth_vs: split_far,
+        };
+
+        split_near = split_far;
+    }
+
+    cascades
+}
+
+// Practical split scheme: blend of logarithmic and uniform distribution
+fn split_depth(
+    camera: &ShadowMapCameraParams,
+    split_index: usize,
+) -> f32 {
+    let fraction = split_index as f32 / SHADOW_MAP_CASCADE_COUNT as f32;
+    let log_split = camera.near * (camera.far / camera.near).powf(fraction);
+    let uniform_split = camera.near + (camera.far - camera.near) * fraction;
+    CASCADE_SPLIT_LAMBDA * log_split + (1.0 - CASCADE_SPLIT_LAMBDA) * uniform_split
+}
+
+// Returns a clip space translation that moves the world origin onto a texel boundary, so that
+// moving the camera slides the projection in whole texel increments
+fn snap_to_texels(view_proj: Mat4) -> Mat4 {
+    let half_resolution = SHADOW_MAP_RESOLUTION as f32 * 0.5;
+    let origin = view_proj * Vec4::new(0.0, 0.0, 0.0, 1.0);
+    let origin_texels_x = origin.x() * half_resolution;
+    let origin_texels_y = origin.y() * half_resolution;
+    let offset_x = (origin_texels_x.round() - origin_texels_x) / half_resolution;
+    let offset_y = (origin_texels_y.round() - origin_texels_y) / half_resolution;
+    Mat4::from_translation(Vec3::new(offset_x, offset_y, 0.0))
+}
@@ -1,12 +1,15 @@
 use crate::renderpass::{
     VkOpaqueRenderPass, VkMsaaRenderPass, VkBloomRenderPassResources, VkBloomExtractRenderPass,
-    VkBloomBlurRenderPass, VkBloomCombineRenderPass, VkUiRenderPass,
+    VkBloomBlurRenderPass, VkBloomCombineRenderPass, VkUiRenderPass, VkShadowMapRenderPass,
 };
-use renderer::vulkan::{VkDeviceContext, VkSwapchain};
+use renderer::vulkan::{VkDeviceContext, VkSwapchain, VkGpuProfiler};
 use crate::game_renderer::GameRendererInner;
 use renderer::assets::resources::{ResourceManager, DynDescriptorSet};
 use renderer::assets::vk_description::SwapchainSurfaceInfo;
 use ash::prelude::VkResult;
+use ash::vk;
+
+const MAX_GPU_PROFILER_SCOPES: u32 = 32;
 
 pub struct SwapchainResources {
     pub debug_material_per_frame_data: DynDescriptorSet,
@@ -14,6 +17,7 @@ pub struct SwapchainResources {
     pub bloom_extract_material_dyn_set: DynDescriptorSet,
     pub bloom_combine_material_dyn_set: DynDescriptorSet,
 
+    pub shadow_map_renderpass: VkShadowMapRenderPass,
     pub opaque_renderpass: VkOpaqueRenderPass,
     pub msaa_renderpass: VkMsaaRenderPass,
     pub bloom_extract_renderpass: VkBloomExtractRenderPass,
@@ -21,6 +25,8 @@ pub struct SwapchainResources {
     pub bloom_combine_renderpass: VkBloomCombineRenderPass,
     pub ui_renderpass: VkUiRenderPass,
 
+    pub gpu_profiler: VkGpuProfiler,
+
     pub swapchain_surface_info: SwapchainSurfaceInfo,
 }
 
@@ -34,6 +40,16 @@ impl SwapchainResources {
     ) -> VkResult<SwapchainResources> {
         log::debug!("creating swapchain resources");
 
+        log::trace!("Create VkShadowMapRenderPass");
+        let shadow_map_pipeline_info = resource_manager.get_pipeline_info(
+            &game_renderer.static_resources.mesh_material,
+            &swapchain_surface_info,
+            1,
+        );
+
+        let shadow_map_renderpass =
+            VkShadowMapRenderPass::new(device_context, swapchain, shadow_map_pipeline_info)?;
+
         log::trace!("Create VkOpaqueRenderPass");
         //TODO: We probably want to move to just using a pipeline here and not a specific material
         let opaque_pipeline_info = resource_manager.get_pipeline_info(
@@ -139,6 +155,67 @@ impl SwapchainResources {
                 &debug_per_frame_layout.descriptor_set_layout,
             )?;
 
+        // Frames are recorded per swapchain image, see RenderFrameJob
+        let gpu_profiler = VkGpuProfiler::new(
+            device_context,
+            swapchain.swapchain_info.image_count,
+            MAX_GPU_PROFILER_SCOPES,
+        )?;
+
+        set_renderpass_debug_names(
+            device_context,
+            "shadow_map",
+            &shadow_map_renderpass.command_buffers,
+            &shadow_map_renderpass.frame_buffers,
+        );
+        set_renderpass_debug_names(
+            device_context,
+            "opaque",
+            &opaque_renderpass.command_buffers,
+            &opaque_renderpass.frame_buffers,
+        );
+        set_renderpass_debug_names(
+            device_context,
+            "msaa",
+            &msaa_renderpass.command_buffers,
+            &[],
+        );
+        set_renderpass_debug_names(
+            device_context,
+            "bloom_extract",
+            &bloom_extract_renderpass.command_buffers,
+            &bloom_extract_renderpass.frame_buffers,
+        );
+        set_renderpass_debug_names(
+            device_context,
+            "bloom_blur",
+            &bloom_blur_renderpass.command_buffers,
+            &bloom_blur_renderpass.frame_buffers,
+        );
+        set_renderpass_debug_names(
+            device_context,
+            "bloom_combine",
+            &bloom_combine_renderpass.command_buffers,
+            &bloom_combine_renderpass.frame_buffers,
+        );
+        set_renderpass_debug_names(
+            device_context,
+            "ui",
+            &ui_renderpass.command_buffers,
+            &ui_renderpass.frame_buffers,
+        );
+
+        // Flushing a DynDescriptorSet replaces its descriptor set, so these are named after the
+        // final flush
+        device_context.set_debug_name(
+            bloom_extract_material_dyn_set.descriptor_set().get(),
+            "bloom_extract",
+        );
+        device_context.set_debug_name(
+            bloom_combine_material_dyn_set.descriptor_set().get(),
+            "bloom_combine",
+        );
+
         log::debug!("game renderer swapchain_created finished");
 
         VkResult::Ok(SwapchainResources {
@@ -146,13 +223,31 @@ impl SwapchainResources {
             bloom_resources,
             bloom_extract_material_dyn_set,
             bloom_combine_material_dyn_set,
+            shadow_map_renderpass,
             opaque_renderpass,
             msaa_renderpass,
             bloom_extract_renderpass,
             bloom_blur_renderpass,
             bloom_combine_renderpass,
             ui_renderpass,
+            gpu_profiler,
             swapchain_surface_info,
         })
     }
 }
+
+// Command buffers and framebuffers are indexed by present index (or by blur pass for bloom_blur)
+fn set_renderpass_debug_names(
+    device_context: &VkDeviceContext,
+    name: &str,
+    command_buffers: &[vk::CommandBuffer],
+    frame_buffers: &[vk::Framebuffer],
+) {
+    for (index, command_buffer) in command_buffers.iter().enumerate() {
+        device_context.set_debug_name(*command_buffer, &format!("{} {}", name, index));
+    }
+
+    for (index, frame_buffer) in frame_buffers.iter().enumerate() {
+        device_context.set_debug_name(*frame_buffer, &format!("{} {}", name, index));
+    }
+}
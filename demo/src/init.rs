@@ -5,7 +5,7 @@ use renderer::vulkan::{
 };
 use crate::features::sprite::{SpriteRenderNodeSet, SpriteRenderFeature};
 use crate::features::mesh::{MeshRenderNodeSet, MeshRenderFeature};
-use renderer::visibility::{StaticVisibilityNodeSet, DynamicVisibilityNodeSet};
+use renderer::visibility::{StaticVisibilityNodeSet, DynamicVisibilityNodeSet, OccluderSet};
 use renderer_shell_vulkan_sdl2::Sdl2Window;
 use crate::game_renderer::{SwapchainLifetimeListener, GameRenderer};
 use crate::features::debug3d::{DebugDraw3DResource, Debug3dRenderFeature};
@@ -13,7 +13,7 @@ use renderer::nodes::RenderRegistry;
 use crate::assets::gltf::{MeshAssetData, GltfMaterialAsset};
 use crate::resource_manager::GameResourceManager;
 use renderer::assets::ResourceManager;
-use crate::phases::{OpaqueRenderPhase, UiRenderPhase};
+use crate::phases::{OpaqueRenderPhase, UiRenderPhase, ShadowMapRenderPhase};
 use crate::phases::TransparentRenderPhase;
 use crate::features::imgui::ImGuiRenderFeature;
 use crate::game_asset_lookup::MeshAsset;
@@ -28,6 +28,12 @@ use renderer::assets::{
 };
 use crate::asset_loader::ResourceAssetLoader;
 
+// Compiled pipelines are cached here so they don't need to be rebuilt every launch
+const PIPELINE_CACHE_FILE: &str = "pipeline_cache.bin";
+
+// A warning is logged if images, buffers, etc. use more GPU memory than this
+const GPU_MEMORY_BUDGET: u64 = 1024 * 1024 * 1024;
+
 pub fn logging_init() {
     #[allow(unused_assignments)]
     let mut log_level = log::LevelFilter::Info;
@@ -125,6 +131,7 @@ pub fn rendering_init(
     resources.insert(MeshRenderNodeSet::default());
     resources.insert(StaticVisibilityNodeSet::default());
     resources.insert(DynamicVisibilityNodeSet::default());
+    resources.insert(OccluderSet::default());
     resources.insert(DebugDraw3DResource::new());
 
     let mut context = VkContextBuilder::new()
@@ -140,7 +147,17 @@ pub fn rendering_init(
 
     let vk_context = context.build(&window_wrapper).unwrap();
     let device_context = vk_context.device_context().clone();
-    let resource_manager = renderer::assets::ResourceManager::new(&device_context);
+    let mut resource_manager = renderer::assets::ResourceManager::new(
+        &device_context,
+        Some(std::path::Path::new(PIPELINE_CACHE_FILE)),
+    );
+    resource_manager.set_memory_budget(Some(GPU_MEMORY_BUDGET));
+
+    // Report where leaked resources were created
+    #[cfg(debug_assertions)]
+    {
+        resource_manager.set_resource_lifetime_tracking(true);
+    }
 
     {
         let loaders = resource_manager.create_loaders();
@@ -199,6 +216,7 @@ pub fn rendering_init(
         .register_feature::<MeshRenderFeature>()
         .register_feature::<Debug3dRenderFeature>()
         .register_feature::<ImGuiRenderFeature>()
+        .register_render_phase::<ShadowMapRenderPhase>()
         .register_render_phase::<OpaqueRenderPhase>()
         .register_render_phase::<TransparentRenderPhase>()
         .register_render_phase::<UiRenderPhase>()
@@ -224,6 +242,7 @@ pub fn rendering_destroy(resources: &mut Resources) {
         resources.remove::<MeshRenderNodeSet>();
         resources.remove::<StaticVisibilityNodeSet>();
         resources.remove::<DynamicVisibilityNodeSet>();
+        resources.remove::<OccluderSet>();
         resources.remove::<DebugDraw3DResource>();
         resources.remove::<GameResourceManager>();
         resources.remove::<RenderRegistry>();
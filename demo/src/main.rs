@@ -1,7 +1,7 @@
 // There's a decent amount of code that's just for example and isn't called
 #![allow(dead_code)]
 
-use renderer::vulkan::VkDeviceContext;
+use renderer::vulkan::{VkDeviceContext, VkGpuProfileFrame, VkGpuProfileScope};
 use renderer_shell_vulkan_sdl2::Sdl2Window;
 use sdl2::event::Event;
 use sdl2::keyboard::Keycode;
@@ -18,6 +18,7 @@ use renderer::assets::resources::ResourceManager;
 use crate::game_renderer::GameRenderer;
 use crate::features::debug3d::DebugDraw3DResource;
 use crate::resource_manager::GameResourceManager;
+use renderer::visibility::{DynamicVisibilityNodeSet, LooseOctreeStats};
 
 mod assets;
 mod features;
@@ -138,6 +139,15 @@ fn main() {
         {
             let imgui_manager = resources.get::<Sdl2ImguiManager>().unwrap();
             let time_state = resources.get::<TimeState>().unwrap();
+            let dynamic_visibility_stats = resources
+                .get::<DynamicVisibilityNodeSet>()
+                .unwrap()
+                .octree_stats();
+            let gpu_profile_results = resources
+                .get::<GameRenderer>()
+                .unwrap()
+                .gpu_profile_results();
+            let resource_manager = resources.get::<ResourceManager>().unwrap();
             imgui_manager.with_ui(|ui| {
                 ui.main_menu_bar(|| {
                     ui.text(imgui::im_str!(
@@ -147,6 +157,10 @@ fn main() {
                     ui.separator();
                     ui.text(imgui::im_str!("Frame: {}", time_state.update_count()));
                 });
+
+                draw_dynamic_visibility_stats(ui, &dynamic_visibility_stats);
+                draw_gpu_profile(ui, gpu_profile_results.as_ref());
+                draw_memory_metrics(ui, &resource_manager);
             });
         }
 
@@ -182,6 +196,159 @@ fn main() {
     init::rendering_destroy(&mut resources);
 }
 
+fn draw_dynamic_visibility_stats(
+    ui: &imgui::Ui,
+    stats: &LooseOctreeStats,
+) {
+    imgui::Window::new(imgui::im_str!("Dynamic Visibility"))
+        .collapsed(true, imgui::Condition::FirstUseEver)
+        .build(ui, || {
+            ui.text(imgui::im_str!(
+                "Objects: {} ({} outside root)",
+                stats.object_count,
+                stats.outside_root_object_count
+            ));
+            ui.text(imgui::im_str!(
+                "Cells: {} ({} occupied)",
+                stats.cell_count,
+                stats.occupied_cell_count
+            ));
+            ui.text(imgui::im_str!("Max depth: {}", stats.max_depth));
+            ui.text(imgui::im_str!(
+                "Objects per occupied cell: {:.2} avg, {} max",
+                stats.average_objects_per_occupied_cell(),
+                stats.max_objects_per_cell
+            ));
+
+            ui.separator();
+            for (depth, (cell_count, object_count)) in stats
+                .cells_per_depth
+                .iter()
+                .zip(&stats.objects_per_depth)
+                .enumerate()
+            {
+                ui.text(imgui::im_str!(
+                    "Depth {}: {} cells, {} objects",
+                    depth,
+                    cell_count,
+                    object_count
+                ));
+            }
+        });
+}
+
+fn draw_gpu_profile(
+    ui: &imgui::Ui,
+    results: Option<&VkGpuProfileFrame>,
+) {
+    imgui::Window::new(imgui::im_str!("GPU Profiler"))
+        .collapsed(true, imgui::Condition::FirstUseEver)
+        .build(ui, || {
+            if let Some(results) = results {
+                ui.text(imgui::im_str!(
+                    "Frame {}: {:.3} ms",
+                    results.frame_number,
+                    results.duration_ms
+                ));
+                ui.separator();
+                for scope in &results.scopes {
+                    draw_gpu_profile_scope(ui, scope);
+                }
+            } else {
+                ui.text(imgui::im_str!("No results (timestamps may be unsupported)"));
+            }
+        });
+}
+
+fn draw_gpu_profile_scope(
+    ui: &imgui::Ui,
+    scope: &VkGpuProfileScope,
+) {
+    ui.text(imgui::im_str!(
+        "{}: {:.3} ms",
+        scope.name,
+        scope.duration_ms()
+    ));
+
+    ui.indent();
+    for child in &scope.children {
+        draw_gpu_profile_scope(ui, child);
+    }
+    ui.unindent();
+}
+
+fn bytes_to_mb(bytes: u64) -> f64 {
+    bytes as f64 / (1024.0 * 1024.0)
+}
+
+// Querying the metrics walks the allocator's stats, so it's only done while the window is open
+fn draw_memory_metrics(
+    ui: &imgui::Ui,
+    resource_manager: &ResourceManager,
+) {
+    imgui::Window::new(imgui::im_str!("GPU Memory"))
+        .collapsed(true, imgui::Condition::FirstUseEver)
+        .build(ui, || {
+            let metrics = resource_manager.memory_metrics();
+            match metrics.budget {
+                Some(budget) => ui.text(imgui::im_str!(
+                    "Tracked: {:.1} / {:.1} MB",
+                    bytes_to_mb(metrics.tracked_bytes()),
+                    bytes_to_mb(budget)
+                )),
+                None => ui.text(imgui::im_str!(
+                    "Tracked: {:.1} MB",
+                    bytes_to_mb(metrics.tracked_bytes())
+                )),
+            }
+
+            ui.indent();
+            ui.text(imgui::im_str!(
+                "Images: {:.1} MB",
+                bytes_to_mb(metrics.image_bytes())
+            ));
+            ui.text(imgui::im_str!(
+                "Buffers: {:.1} MB",
+                bytes_to_mb(metrics.buffer_bytes())
+            ));
+            ui.text(imgui::im_str!(
+                "Staging: {:.1} MB",
+                bytes_to_mb(metrics.staging_bytes)
+            ));
+            ui.text(imgui::im_str!(
+                "Descriptor buffers: {:.1} MB",
+                bytes_to_mb(metrics.descriptor_buffer_bytes)
+            ));
+            ui.unindent();
+
+            ui.separator();
+            for heap in &metrics.device_memory.heaps {
+                ui.text(imgui::im_str!(
+                    "Heap {}{}: {:.1} MB allocated, {:.1} MB in blocks, {:.1} MB size",
+                    heap.heap_index,
+                    if heap.is_device_local() {
+                        " (device local)"
+                    } else {
+                        ""
+                    },
+                    bytes_to_mb(heap.allocated_bytes),
+                    bytes_to_mb(heap.block_bytes),
+                    bytes_to_mb(heap.size)
+                ));
+
+                if let Some(budget) = heap.budget {
+                    ui.indent();
+                    ui.text(imgui::im_str!(
+                        "Usage: {:.1} / {:.1} MB",
+                        bytes_to_mb(budget.usage),
+                        bytes_to_mb(budget.budget)
+                    ));
+                    ui.unindent();
+                }
+            }
+        });
+}
+
 fn add_light_debug_draw(
     resources: &Resources,
     world: &World,
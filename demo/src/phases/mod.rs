@@ -6,3 +6,6 @@ pub use transparent_render_phase::TransparentRenderPhase;
 
 mod ui_render_phase;
 pub use ui_render_phase::UiRenderPhase;
+
+mod shadow_map_render_phase;
+pub use shadow_map_render_phase::ShadowMapRenderPhase;
@@ -0,0 +1,30 @@
+use renderer::nodes::{RenderPhaseIndex, SubmitNode};
+use std::sync::atomic::Ordering;
+use renderer::nodes::RenderPhase;
+use renderer::nodes::sort_submit_nodes_by_state;
+use std::sync::atomic::AtomicI32;
+use std::convert::TryInto;
+
+static SHADOW_MAP_RENDER_PHASE_INDEX: AtomicI32 = AtomicI32::new(-1);
+
+pub struct ShadowMapRenderPhase;
+
+impl RenderPhase for ShadowMapRenderPhase {
+    fn set_render_phase_index(index: RenderPhaseIndex) {
+        SHADOW_MAP_RENDER_PHASE_INDEX.store(index.try_into().unwrap(), Ordering::Release);
+    }
+
+    fn render_phase_index() -> RenderPhaseIndex {
+        SHADOW_MAP_RENDER_PHASE_INDEX.load(Ordering::Acquire) as RenderPhaseIndex
+    }
+
+    fn sort_submit_nodes(submit_nodes: Vec<SubmitNode>) -> Vec<SubmitNode> {
+        // Depth-only, so only state changes matter
+        log::trace!("Sort phase {}", Self::render_phase_debug_name());
+        sort_submit_nodes_by_state(submit_nodes)
+    }
+
+    fn render_phase_debug_name() -> &'static str {
+        "ShadowMapRenderPhase"
+    }
+}
@@ -1,6 +1,7 @@
 use renderer::nodes::{RenderPhaseIndex, SubmitNode};
 use std::sync::atomic::Ordering;
 use renderer::nodes::RenderPhase;
+use renderer::nodes::sort_submit_nodes_back_to_front;
 use std::sync::atomic::AtomicI32;
 use std::convert::TryInto;
 
@@ -17,16 +18,10 @@ impl RenderPhase for TransparentRenderPhase {
         TRANSPARENT_RENDER_PHASE_INDEX.load(Ordering::Acquire) as RenderPhaseIndex
     }
 
-    fn sort_submit_nodes(mut submit_nodes: Vec<SubmitNode>) -> Vec<SubmitNode> {
-        // Sort by distance from camera back to front
+    fn sort_submit_nodes(submit_nodes: Vec<SubmitNode>) -> Vec<SubmitNode> {
+        // Blending requires drawing farthest first
         log::trace!("Sort phase {}", Self::render_phase_debug_name());
-        submit_nodes.sort_unstable_by(|a, b| {
-            b.distance_from_camera()
-                .partial_cmp(&a.distance_from_camera())
-                .unwrap()
-        });
-
-        submit_nodes
+        sort_submit_nodes_back_to_front(submit_nodes)
     }
 
     fn render_phase_debug_name() -> &'static str {
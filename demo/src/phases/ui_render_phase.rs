@@ -1,6 +1,7 @@
 use renderer::nodes::{RenderPhaseIndex, SubmitNode};
 use std::sync::atomic::Ordering;
 use renderer::nodes::RenderPhase;
+use renderer::nodes::sort_submit_nodes_by_state;
 use std::sync::atomic::AtomicI32;
 use std::convert::TryInto;
 
@@ -17,12 +18,10 @@ impl RenderPhase for UiRenderPhase {
         UI_RENDER_PHASE_INDEX.load(Ordering::Acquire) as RenderPhaseIndex
     }
 
-    fn sort_submit_nodes(mut submit_nodes: Vec<SubmitNode>) -> Vec<SubmitNode> {
-        // Sort by feature
+    fn sort_submit_nodes(submit_nodes: Vec<SubmitNode>) -> Vec<SubmitNode> {
+        // Keep the order features submitted nodes in, since UI draws rely on it
         log::trace!("Sort phase {}", Self::render_phase_debug_name());
-        submit_nodes.sort_unstable_by(|a, b| a.feature_index().cmp(&b.feature_index()));
-
-        submit_nodes
+        sort_submit_nodes_by_state(submit_nodes)
     }
 
     fn render_phase_debug_name() -> &'static str {
@@ -1,4 +1,6 @@
 use ash::vk;
+use ash::prelude::VkResult;
+use ash::version::DeviceV1_0;
 use legion::prelude::*;
 use renderer::assets::{ResourceManager, DynResourceAllocatorSet};
 use renderer::vulkan::VkDeviceContext;
@@ -37,7 +39,8 @@ impl RenderJobPrepareContext {
     }
 }
 
-// Used to produce RenderJobWriteContexts per each job
+// Used to produce RenderJobWriteContexts per each job. This is shared between threads when a
+// phase is recorded into secondary command buffers in parallel.
 pub struct RenderJobWriteContextFactory {
     pub device_context: VkDeviceContext,
     pub dyn_resource_lookups: DynResourceAllocatorSet,
@@ -64,6 +67,34 @@ impl RenderJobWriteContextFactory {
             command_buffer,
         )
     }
+
+    /// Begins recording a secondary command buffer that will be executed inside the given
+    /// renderpass/framebuffer and returns a context that writes to it. The caller must end the
+    /// command buffer after writing.
+    pub fn create_secondary_context(
+        &self,
+        command_buffer: vk::CommandBuffer,
+        renderpass: vk::RenderPass,
+        subpass: u32,
+        framebuffer: vk::Framebuffer,
+    ) -> VkResult<RenderJobWriteContext> {
+        let inheritance_info = vk::CommandBufferInheritanceInfo::builder()
+            .render_pass(renderpass)
+            .subpass(subpass)
+            .framebuffer(framebuffer);
+
+        let command_buffer_begin_info = vk::CommandBufferBeginInfo::builder()
+            .flags(vk::CommandBufferUsageFlags::RENDER_PASS_CONTINUE)
+            .inheritance_info(&inheritance_info);
+
+        unsafe {
+            self.device_context
+                .device()
+                .begin_command_buffer(command_buffer, &command_buffer_begin_info)?;
+        }
+
+        Ok(self.create_context(command_buffer))
+    }
 }
 
 pub struct RenderJobWriteContext {
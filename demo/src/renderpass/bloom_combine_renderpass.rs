@@ -4,6 +4,7 @@ use ash::prelude::VkResult;
 use ash::version::DeviceV1_0;
 
 use renderer::vulkan::VkDeviceContext;
+use renderer::vulkan::VkGpuProfiler;
 use renderer::vulkan::VkSwapchain;
 use renderer::vulkan::SwapchainInfo;
 use renderer::vulkan::VkQueueFamilyIndices;
@@ -125,6 +126,7 @@ impl VkBloomCombineRenderPass {
         pipeline: vk::Pipeline,
         pipeline_layout: vk::PipelineLayout,
         descriptor_set: vk::DescriptorSet,
+        gpu_profiler: &mut VkGpuProfiler,
     ) -> VkResult<()> {
         let command_buffer_begin_info = vk::CommandBufferBeginInfo::builder();
 
@@ -147,6 +149,7 @@ impl VkBloomCombineRenderPass {
         unsafe {
             let logical_device = device_context.device();
             logical_device.begin_command_buffer(command_buffer, &command_buffer_begin_info)?;
+            gpu_profiler.cmd_begin_scope(command_buffer, "bloom_combine");
 
             logical_device.cmd_begin_render_pass(
                 command_buffer,
@@ -172,6 +175,7 @@ impl VkBloomCombineRenderPass {
             logical_device.cmd_draw(command_buffer, 3, 1, 0, 0);
 
             logical_device.cmd_end_render_pass(command_buffer);
+            gpu_profiler.cmd_end_scope(command_buffer);
             logical_device.end_command_buffer(command_buffer)
         }
     }
@@ -180,6 +184,7 @@ impl VkBloomCombineRenderPass {
         &mut self,
         present_index: usize,
         descriptor_set: vk::DescriptorSet,
+        gpu_profiler: &mut VkGpuProfiler,
     ) -> VkResult<()> {
         Self::update_command_buffer(
             &self.device_context,
@@ -190,6 +195,7 @@ impl VkBloomCombineRenderPass {
             self.pipeline_info.pipeline.get_raw().pipelines[0],
             self.pipeline_info.pipeline_layout.get_raw().pipeline_layout,
             descriptor_set,
+            gpu_profiler,
         )
     }
 }
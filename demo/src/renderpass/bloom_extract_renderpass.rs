@@ -5,6 +5,7 @@ use std::mem::ManuallyDrop;
 use ash::version::DeviceV1_0;
 
 use renderer::vulkan::{VkDeviceContext, MsaaLevel, RenderpassAttachmentImage};
+use renderer::vulkan::VkGpuProfiler;
 use renderer::vulkan::VkSwapchain;
 use renderer::vulkan::SwapchainInfo;
 use renderer::vulkan::VkQueueFamilyIndices;
@@ -238,6 +239,7 @@ impl VkBloomExtractRenderPass {
         pipeline: vk::Pipeline,
         pipeline_layout: vk::PipelineLayout,
         descriptor_set: vk::DescriptorSet,
+        gpu_profiler: &mut VkGpuProfiler,
     ) -> VkResult<()> {
         let command_buffer_begin_info = vk::CommandBufferBeginInfo::builder();
 
@@ -267,6 +269,7 @@ impl VkBloomExtractRenderPass {
         unsafe {
             let logical_device = device_context.device();
             logical_device.begin_command_buffer(command_buffer, &command_buffer_begin_info)?;
+            gpu_profiler.cmd_begin_scope(command_buffer, "bloom_extract");
 
             logical_device.cmd_begin_render_pass(
                 command_buffer,
@@ -292,6 +295,7 @@ impl VkBloomExtractRenderPass {
             logical_device.cmd_draw(command_buffer, 3, 1, 0, 0);
 
             logical_device.cmd_end_render_pass(command_buffer);
+            gpu_profiler.cmd_end_scope(command_buffer);
             logical_device.end_command_buffer(command_buffer)
         }
     }
@@ -300,6 +304,7 @@ impl VkBloomExtractRenderPass {
         &mut self,
         present_index: usize,
         descriptor_set: vk::DescriptorSet,
+        gpu_profiler: &mut VkGpuProfiler,
     ) -> VkResult<()> {
         Self::update_command_buffer(
             &self.device_context,
@@ -310,6 +315,7 @@ impl VkBloomExtractRenderPass {
             self.pipeline_info.pipeline.get_raw().pipelines[0],
             self.pipeline_info.pipeline_layout.get_raw().pipeline_layout,
             descriptor_set,
+            gpu_profiler,
         )
     }
 }
@@ -11,8 +11,14 @@ pub use bloom_blur_renderpass::VkBloomBlurRenderPass;
 pub mod bloom_combine_renderpass;
 pub use bloom_combine_renderpass::VkBloomCombineRenderPass;
 
+pub mod secondary_command_buffers;
+pub use secondary_command_buffers::VkSecondaryCommandBuffers;
+
 pub mod opaque_renderpass;
 pub use opaque_renderpass::VkOpaqueRenderPass;
 
 pub mod ui_renderpass;
 pub use ui_renderpass::VkUiRenderPass;
+
+pub mod shadow_map_renderpass;
+pub use shadow_map_renderpass::VkShadowMapRenderPass;
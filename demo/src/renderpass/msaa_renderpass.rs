@@ -4,6 +4,7 @@ use ash::prelude::VkResult;
 use ash::version::DeviceV1_0;
 
 use renderer::vulkan::{VkDeviceContext, MsaaLevel};
+use renderer::vulkan::VkGpuProfiler;
 use renderer::vulkan::VkSwapchain;
 use renderer::vulkan::SwapchainInfo;
 use renderer::vulkan::VkQueueFamilyIndices;
@@ -90,6 +91,7 @@ impl VkMsaaRenderPass {
         command_buffer: &vk::CommandBuffer,
         color_target_image: vk::Image,
         color_resolved_image: vk::Image,
+        gpu_profiler: &mut VkGpuProfiler,
     ) -> VkResult<()> {
         let command_buffer_begin_info = vk::CommandBufferBeginInfo::builder();
 
@@ -97,6 +99,7 @@ impl VkMsaaRenderPass {
         unsafe {
             let logical_device = device_context.device();
             logical_device.begin_command_buffer(*command_buffer, &command_buffer_begin_info)?;
+            gpu_profiler.cmd_begin_scope(*command_buffer, "debug");
 
             if swapchain_info.msaa_level != MsaaLevel::Sample1 {
                 Self::resolve_image(
@@ -108,6 +111,7 @@ impl VkMsaaRenderPass {
                 );
             }
 
+            gpu_profiler.cmd_end_scope(*command_buffer);
             logical_device.end_command_buffer(*command_buffer)
         }
     }
@@ -213,6 +217,7 @@ impl VkMsaaRenderPass {
         &mut self,
         present_index: usize,
         _descriptor_set_per_view: vk::DescriptorSet,
+        gpu_profiler: &mut VkGpuProfiler,
     ) -> VkResult<()> {
         //TODO: Can probably record these once and maybe even just have one
         Self::update_command_buffer(
@@ -221,6 +226,7 @@ impl VkMsaaRenderPass {
             &self.command_buffers[present_index],
             self.color_target_image,
             self.color_resolved_image,
+            gpu_profiler,
         )
     }
 }
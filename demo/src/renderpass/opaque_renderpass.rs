@@ -4,15 +4,21 @@ use ash::prelude::VkResult;
 use ash::version::DeviceV1_0;
 
 use renderer::vulkan::{VkDeviceContext, MAX_FRAMES_IN_FLIGHT};
+use renderer::vulkan::VkGpuProfiler;
 use renderer::vulkan::VkSwapchain;
 use renderer::vulkan::SwapchainInfo;
 use renderer::vulkan::VkQueueFamilyIndices;
 
 use renderer::assets::resources::PipelineSwapchainInfo;
-use renderer::nodes::{PreparedRenderData, RenderView};
+use renderer::nodes::{PreparedRenderData, RenderView, RenderPhase};
 use crate::phases::OpaqueRenderPhase;
 use crate::render_contexts::{RenderJobWriteContext, RenderJobWriteContextFactory};
 use renderer::vulkan::cleanup::VkCombinedDropSink;
+use super::VkSecondaryCommandBuffers;
+
+// The opaque phase is split into ranges of this many submit nodes. Each range is recorded into its
+// own secondary command buffer on a worker thread.
+const OPAQUE_SUBMIT_NODES_PER_RANGE: usize = 256;
 
 /// Draws sprites
 pub struct VkOpaqueRenderPass {
@@ -26,6 +32,9 @@ pub struct VkOpaqueRenderPass {
     pub command_pool: vk::CommandPool,
     pub command_buffers: Vec<vk::CommandBuffer>,
 
+    // Secondary command buffers that the opaque phase is recorded into, per present index
+    pub secondary_command_buffers: VkSecondaryCommandBuffers,
+
     pub drop_sink: VkCombinedDropSink,
 
     renderpass: vk::RenderPass,
@@ -69,6 +78,10 @@ impl VkOpaqueRenderPass {
             frame_buffers,
             command_pool,
             command_buffers,
+            secondary_command_buffers: VkSecondaryCommandBuffers::new(
+                device_context,
+                swapchain.swapchain_info.image_count,
+            ),
             renderpass: pipeline_info.pipeline.get_raw().renderpass.get_raw(),
             drop_sink: VkCombinedDropSink::new(MAX_FRAMES_IN_FLIGHT as u32),
         })
@@ -135,9 +148,12 @@ impl VkOpaqueRenderPass {
         renderpass: &vk::RenderPass,
         framebuffer: vk::Framebuffer,
         command_buffer: &vk::CommandBuffer,
+        secondary_command_buffers: &mut VkSecondaryCommandBuffers,
+        present_index: usize,
         prepared_render_data: &PreparedRenderData<RenderJobWriteContext>,
         view: &RenderView,
         write_context_factory: &RenderJobWriteContextFactory,
+        gpu_profiler: &mut VkGpuProfiler,
     ) -> VkResult<()> {
         let command_buffer_begin_info = vk::CommandBufferBeginInfo::builder();
 
@@ -164,22 +180,67 @@ impl VkOpaqueRenderPass {
             })
             .clear_values(&clear_values);
 
-        // Implicitly resets the command buffer
-        unsafe {
-            let logical_device = device_context.device();
-            logical_device.begin_command_buffer(*command_buffer, &command_buffer_begin_info)?;
+        // Begin a secondary command buffer per range of submit nodes. The ranges are recorded in
+        // parallel and then executed in order from the primary command buffer.
+        let range_count = prepared_render_data
+            .view_phase_range_count::<OpaqueRenderPhase>(view, OPAQUE_SUBMIT_NODES_PER_RANGE);
+        let secondary_command_buffers =
+            secondary_command_buffers.reset_command_buffers(present_index, range_count)?;
 
-            logical_device.cmd_begin_render_pass(
-                *command_buffer,
-                &render_pass_begin_info,
-                vk::SubpassContents::INLINE,
-            );
+        let mut write_contexts = secondary_command_buffers
+            .iter()
+            .map(|secondary_command_buffer| {
+                write_context_factory.create_secondary_context(
+                    *secondary_command_buffer,
+                    *renderpass,
+                    0,
+                    framebuffer,
+                )
+            })
+            .collect::<VkResult<Vec<_>>>()?;
 
-            let mut write_context = write_context_factory.create_context(*command_buffer);
+        prepared_render_data.write_view_phase_parallel::<OpaqueRenderPhase>(
+            view,
+            OPAQUE_SUBMIT_NODES_PER_RANGE,
+            &mut write_contexts,
+        );
 
-            prepared_render_data.write_view_phase::<OpaqueRenderPhase>(&view, &mut write_context);
+        unsafe {
+            let logical_device = device_context.device();
+            for secondary_command_buffer in &secondary_command_buffers {
+                logical_device.end_command_buffer(*secondary_command_buffer)?;
+            }
 
-            logical_device.cmd_end_render_pass(*command_buffer);
+            // Implicitly resets the command buffer
+            logical_device.begin_command_buffer(*command_buffer, &command_buffer_begin_info)?;
+            gpu_profiler.cmd_begin_scope(*command_buffer, "opaque");
+
+            // Only vkCmdExecuteCommands is allowed inside a subpass recorded with secondary
+            // command buffers, so the label goes around the whole renderpass
+            {
+                let _label = device_context.debug_label_scope(
+                    *command_buffer,
+                    &format!(
+                        "{} ({})",
+                        OpaqueRenderPhase::render_phase_debug_name(),
+                        view.debug_name()
+                    ),
+                );
+
+                logical_device.cmd_begin_render_pass(
+                    *command_buffer,
+                    &render_pass_begin_info,
+                    vk::SubpassContents::SECONDARY_COMMAND_BUFFERS,
+                );
+
+                if !secondary_command_buffers.is_empty() {
+                    logical_device
+                        .cmd_execute_commands(*command_buffer, &secondary_command_buffers);
+                }
+
+                logical_device.cmd_end_render_pass(*command_buffer);
+            }
+            gpu_profiler.cmd_end_scope(*command_buffer);
             logical_device.end_command_buffer(*command_buffer)
         }
     }
@@ -191,6 +252,7 @@ impl VkOpaqueRenderPass {
         prepared_render_data: &PreparedRenderData<RenderJobWriteContext>,
         view: &RenderView,
         write_context_factory: &RenderJobWriteContextFactory,
+        gpu_profiler: &mut VkGpuProfiler,
     ) -> VkResult<()> {
         assert!(self.renderpass == pipeline_info.pipeline.get_raw().renderpass.get_raw());
         Self::update_command_buffer(
@@ -199,9 +261,12 @@ impl VkOpaqueRenderPass {
             &pipeline_info.pipeline.get_raw().renderpass.get_raw(),
             self.frame_buffers[present_index],
             &self.command_buffers[present_index],
+            &mut self.secondary_command_buffers,
+            present_index,
             prepared_render_data,
             view,
             write_context_factory,
+            gpu_profiler,
         )
     }
 }
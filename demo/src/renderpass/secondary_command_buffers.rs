@@ -0,0 +1,114 @@
+use ash::vk;
+use ash::prelude::VkResult;
+
+use ash::version::DeviceV1_0;
+
+use renderer::vulkan::VkDeviceContext;
+
+// Command buffers allocated from the same pool can't be recorded on different threads at the same
+// time, so every secondary command buffer gets its own pool
+struct SecondaryCommandBuffer {
+    command_pool: vk::CommandPool,
+    command_buffer: vk::CommandBuffer,
+}
+
+/// Secondary command buffers for recording ranges of a render phase in parallel. Buffers are kept
+/// per present index and reused whenever that present index is recorded again.
+pub struct VkSecondaryCommandBuffers {
+    device_context: VkDeviceContext,
+
+    // Indexed by present index
+    command_buffers: Vec<Vec<SecondaryCommandBuffer>>,
+}
+
+impl VkSecondaryCommandBuffers {
+    pub fn new(
+        device_context: &VkDeviceContext,
+        present_index_count: usize,
+    ) -> Self {
+        VkSecondaryCommandBuffers {
+            device_context: device_context.clone(),
+            command_buffers: (0..present_index_count).map(|_| Vec::new()).collect(),
+        }
+    }
+
+    /// Resets the buffers for the present index and returns `count` of them, allocating more if
+    /// needed. The GPU must be finished with the previous frame that used this present index.
+    pub fn reset_command_buffers(
+        &mut self,
+        present_index: usize,
+        count: usize,
+    ) -> VkResult<Vec<vk::CommandBuffer>> {
+        let device = self.device_context.device();
+        let command_buffers = &mut self.command_buffers[present_index];
+
+        for command_buffer in command_buffers.iter() {
+            unsafe {
+                device.reset_command_pool(
+                    command_buffer.command_pool,
+                    vk::CommandPoolResetFlags::empty(),
+                )?;
+            }
+        }
+
+        while command_buffers.len() < count {
+            command_buffers.push(Self::create_command_buffer(&self.device_context)?);
+        }
+
+        Ok(command_buffers[0..count]
+            .iter()
+            .map(|command_buffer| command_buffer.command_buffer)
+            .collect())
+    }
+
+    fn create_command_buffer(device_context: &VkDeviceContext) -> VkResult<SecondaryCommandBuffer> {
+        let logical_device = device_context.device();
+        let queue_family_indices = device_context.queue_family_indices();
+
+        log::trace!(
+            "Creating secondary command pool with queue family index {}",
+            queue_family_indices.graphics_queue_family_index
+        );
+        let pool_create_info = vk::CommandPoolCreateInfo::builder()
+            .flags(vk::CommandPoolCreateFlags::TRANSIENT)
+            .queue_family_index(queue_family_indices.graphics_queue_family_index);
+
+        let command_pool = unsafe { logical_device.create_command_pool(&pool_create_info, None)? };
+
+        let command_buffer_allocate_info = vk::CommandBufferAllocateInfo::builder()
+            .command_buffer_count(1)
+            .command_pool(command_pool)
+            .level(vk::CommandBufferLevel::SECONDARY);
+
+        let command_buffers =
+            unsafe { logical_device.allocate_command_buffers(&command_buffer_allocate_info) };
+
+        match command_buffers {
+            Ok(command_buffers) => Ok(SecondaryCommandBuffer {
+                command_pool,
+                command_buffer: command_buffers[0],
+            }),
+            Err(e) => {
+                unsafe { logical_device.destroy_command_pool(command_pool, None) };
+                Err(e)
+            }
+        }
+    }
+}
+
+impl Drop for VkSecondaryCommandBuffers {
+    fn drop(&mut self) {
+        log::trace!("destroying VkSecondaryCommandBuffers");
+
+        unsafe {
+            let device = self.device_context.device();
+            for command_buffers in &self.command_buffers {
+                for command_buffer in command_buffers {
+                    device.destroy_command_pool(command_buffer.command_pool, None);
+                }
+            }
+        }
+
+        log::trace!("destroyed VkSecondaryCommandBuffers");
+    }
+}
@@ -0,0 +1,312 @@
+use ash::vk;
+use ash::prelude::VkResult;
+use std::mem::ManuallyDrop;
+
+use ash::version::DeviceV1_0;
+
+use renderer::vulkan::VkDeviceContext;
+use renderer::vulkan::VkGpuProfiler;
+use renderer::vulkan::VkSwapchain;
+use renderer::vulkan::VkQueueFamilyIndices;
+use renderer::vulkan::VkImage;
+
+use renderer::assets::resources::PipelineSwapchainInfo;
+use renderer::nodes::{PreparedRenderData, RenderView, RenderPhase};
+use crate::phases::ShadowMapRenderPhase;
+use crate::render_contexts::{RenderJobWriteContext, RenderJobWriteContextFactory};
+use crate::game_renderer::{SHADOW_MAP_CASCADE_COUNT, SHADOW_MAP_RESOLUTION};
+
+const SHADOW_MAP_FORMAT: vk::Format = vk::Format::D32_SFLOAT;
+
+/// Draws shadow casters into one layer of a depth image array per cascade
+pub struct VkShadowMapRenderPass {
+    pub device_context: VkDeviceContext,
+
+    // Depth image with a layer per cascade. The opaque pass samples it through shadow_map_image_view
+    pub shadow_map_image: ManuallyDrop<VkImage>,
+    pub shadow_map_image_view: vk::ImageView,
+    pub cascade_image_views: Vec<vk::ImageView>,
+
+    // A frame buffer per cascade
+    pub frame_buffers: Vec<vk::Framebuffer>,
+
+    // Command pool and list of command buffers, one per present index
+    pub command_pool: vk::CommandPool,
+    pub command_buffers: Vec<vk::CommandBuffer>,
+
+    renderpass: vk::RenderPass,
+}
+
+impl VkShadowMapRenderPass {
+    pub fn new(
+        device_context: &VkDeviceContext,
+        swapchain: &VkSwapchain,
+        pipeline_info: PipelineSwapchainInfo,
+    ) -> VkResult<Self> {
+        //
+        // Command Buffers
+        //
+        let command_pool = Self::create_command_pool(
+            &device_context.device(),
+            &device_context.queue_family_indices(),
+        )?;
+
+        //
+        // Renderpass Resources
+        //
+        let shadow_map_image = VkImage::new(
+            device_context,
+            vk_mem::MemoryUsage::GpuOnly,
+            vk::ImageUsageFlags::DEPTH_STENCIL_ATTACHMENT | vk::ImageUsageFlags::SAMPLED,
+            vk::Extent3D {
+                width: SHADOW_MAP_RESOLUTION,
+                height: SHADOW_MAP_RESOLUTION,
+                depth: 1,
+            },
+            SHADOW_MAP_FORMAT,
+            vk::ImageTiling::OPTIMAL,
+            vk::SampleCountFlags::TYPE_1,
+            1,
+            SHADOW_MAP_CASCADE_COUNT as u32,
+            vk::ImageCreateFlags::empty(),
+            vk::MemoryPropertyFlags::DEVICE_LOCAL,
+        )?;
+
+        log::trace!("shadow_map_image: {:?}", shadow_map_image);
+
+        let shadow_map_image_view = Self::create_image_view(
+            &device_context.device(),
+            &shadow_map_image,
+            vk::ImageViewType::TYPE_2D_ARRAY,
+            0,
+            SHADOW_MAP_CASCADE_COUNT as u32,
+        )?;
+
+        let cascade_image_views = (0..SHADOW_MAP_CASCADE_COUNT as u32)
+            .map(|cascade_index| {
+                Self::create_image_view(
+                    &device_context.device(),
+                    &shadow_map_image,
+                    vk::ImageViewType::TYPE_2D,
+                    cascade_index,
+                    1,
+                )
+            })
+            .collect::<VkResult<Vec<_>>>()?;
+
+        let frame_buffers = Self::create_framebuffers(
+            &device_context.device(),
+            &cascade_image_views,
+            &pipeline_info.pipeline.get_raw().renderpass.get_raw(),
+        )?;
+
+        let command_buffers = Self::create_command_buffers(
+            &device_context.device(),
+            swapchain.swapchain_info.image_count as u32,
+            &command_pool,
+        )?;
+
+        Ok(VkShadowMapRenderPass {
+            device_context: device_context.clone(),
+            shadow_map_image: ManuallyDrop::new(shadow_map_image),
+            shadow_map_image_view,
+            cascade_image_views,
+            frame_buffers,
+            command_pool,
+            command_buffers,
+            renderpass: pipeline_info.pipeline.get_raw().renderpass.get_raw(),
+        })
+    }
+
+    fn create_command_pool(
+        logical_device: &ash::Device,
+        queue_family_indices: &VkQueueFamilyIndices,
+    ) -> VkResult<vk::CommandPool> {
+        log::trace!(
+            "Creating command pool with queue family index {}",
+            queue_family_indices.graphics_queue_family_index
+        );
+        let pool_create_info = vk::CommandPoolCreateInfo::builder()
+            .flags(
+                vk::CommandPoolCreateFlags::TRANSIENT
+                    | vk::CommandPoolCreateFlags::RESET_COMMAND_BUFFER,
+            )
+            .queue_family_index(queue_family_indices.graphics_queue_family_index);
+
+        unsafe { logical_device.create_command_pool(&pool_create_info, None) }
+    }
+
+    fn create_image_view(
+        logical_device: &ash::Device,
+        image: &VkImage,
+        view_type: vk::ImageViewType,
+        base_array_layer: u32,
+        layer_count: u32,
+    ) -> VkResult<vk::ImageView> {
+        let subresource_range = vk::ImageSubresourceRange::builder()
+            .aspect_mask(vk::ImageAspectFlags::DEPTH)
+            .base_mip_level(0)
+            .level_count(1)
+            .base_array_layer(base_array_layer)
+            .layer_count(layer_count);
+
+        let image_view_create_info = vk::ImageViewCreateInfo::builder()
+            .image(image.image())
+            .view_type(view_type)
+            .format(SHADOW_MAP_FORMAT)
+            .subresource_range(*subresource_range);
+
+        unsafe { logical_device.create_image_view(&*image_view_create_info, None) }
+    }
+
+    fn create_framebuffers(
+        logical_device: &ash::Device,
+        cascade_image_views: &[vk::ImageView],
+        renderpass: &vk::RenderPass,
+    ) -> VkResult<Vec<vk::Framebuffer>> {
+        cascade_image_views
+            .iter()
+            .map(|&cascade_image_view| {
+                let framebuffer_attachments = [cascade_image_view];
+                let frame_buffer_create_info = vk::FramebufferCreateInfo::builder()
+                    .render_pass(*renderpass)
+                    .attachments(&framebuffer_attachments)
+                    .width(SHADOW_MAP_RESOLUTION)
+                    .height(SHADOW_MAP_RESOLUTION)
+                    .layers(1);
+
+                unsafe { logical_device.create_framebuffer(&frame_buffer_create_info, None) }
+            })
+            .collect()
+    }
+
+    fn create_command_buffers(
+        logical_device: &ash::Device,
+        command_buffer_count: u32,
+        command_pool: &vk::CommandPool,
+    ) -> VkResult<Vec<vk::CommandBuffer>> {
+        let command_buffer_allocate_info = vk::CommandBufferAllocateInfo::builder()
+            .command_buffer_count(command_buffer_count)
+            .command_pool(*command_pool)
+            .level(vk::CommandBufferLevel::PRIMARY);
+
+        unsafe { logical_device.allocate_command_buffers(&command_buffer_allocate_info) }
+    }
+
+    fn update_command_buffer(
+        device_context: &VkDeviceContext,
+        renderpass: &vk::RenderPass,
+        frame_buffers: &[vk::Framebuffer],
+        command_buffer: &vk::CommandBuffer,
+        prepared_render_data: &PreparedRenderData<RenderJobWriteContext>,
+        cascade_views: &[&RenderView],
+        write_context_factory: &RenderJobWriteContextFactory,
+        gpu_profiler: &mut VkGpuProfiler,
+    ) -> VkResult<()> {
+        let command_buffer_begin_info = vk::CommandBufferBeginInfo::builder();
+
+        let clear_values = [vk::ClearValue {
+            depth_stencil: vk::ClearDepthStencilValue {
+                depth: 1.0,
+                stencil: 0,
+            },
+        }];
+
+        // Implicitly resets the command buffer
+        unsafe {
+            let logical_device = device_context.device();
+            logical_device.begin_command_buffer(*command_buffer, &command_buffer_begin_info)?;
+            gpu_profiler.cmd_begin_scope(*command_buffer, "shadow_map");
+
+            for (view, framebuffer) in cascade_views.iter().zip(frame_buffers) {
+                let render_pass_begin_info = vk::RenderPassBeginInfo::builder()
+                    .render_pass(*renderpass)
+                    .framebuffer(*framebuffer)
+                    .render_area(vk::Rect2D {
+                        offset: vk::Offset2D { x: 0, y: 0 },
+                        extent: vk::Extent2D {
+                            width: SHADOW_MAP_RESOLUTION,
+                            height: SHADOW_MAP_RESOLUTION,
+                        },
+                    })
+                    .clear_values(&clear_values);
+
+                logical_device.cmd_begin_render_pass(
+                    *command_buffer,
+                    &render_pass_begin_info,
+                    vk::SubpassContents::INLINE,
+                );
+
+                let mut write_context = write_context_factory.create_context(*command_buffer);
+
+                {
+                    let _label = device_context.debug_label_scope(
+                        *command_buffer,
+                        &format!(
+                            "{} ({})",
+                            ShadowMapRenderPhase::render_phase_debug_name(),
+                            view.debug_name()
+                        ),
+                    );
+                    prepared_render_data
+                        .write_view_phase::<ShadowMapRenderPhase>(view, &mut write_context);
+                }
+
+                logical_device.cmd_end_render_pass(*command_buffer);
+            }
+
+            gpu_profiler.cmd_end_scope(*command_buffer);
+            logical_device.end_command_buffer(*command_buffer)
+        }
+    }
+
+    /// Records all cascades into the command buffer for the present index. `cascade_views` are
+    /// in cascade order.
+    pub fn update(
+        &mut self,
+        pipeline_info: &PipelineSwapchainInfo,
+        present_index: usize,
+        prepared_render_data: &PreparedRenderData<RenderJobWriteContext>,
+        cascade_views: &[&RenderView],
+        write_context_factory: &RenderJobWriteContextFactory,
+        gpu_profiler: &mut VkGpuProfiler,
+    ) -> VkResult<()> {
+        assert!(self.renderpass == pipeline_info.pipeline.get_raw().renderpass.get_raw());
+        assert_eq!(cascade_views.len(), SHADOW_MAP_CASCADE_COUNT);
+        Self::update_command_buffer(
+            &self.device_context,
+            &pipeline_info.pipeline.get_raw().renderpass.get_raw(),
+            &self.frame_buffers,
+            &self.command_buffers[present_index],
+            prepared_render_data,
+            cascade_views,
+            write_context_factory,
+            gpu_profiler,
+        )
+    }
+}
+
+impl Drop for VkShadowMapRenderPass {
+    fn drop(&mut self) {
+        log::trace!("destroying VkShadowMapRenderPass");
+
+        unsafe {
+            let device = self.device_context.device();
+
+            device.destroy_command_pool(self.command_pool, None);
+
+            for frame_buffer in &self.frame_buffers {
+                device.destroy_framebuffer(*frame_buffer, None);
+            }
+
+            for image_view in &self.cascade_image_views {
+                device.destroy_image_view(*image_view, None);
+            }
+
+            device.destroy_image_view(self.shadow_map_image_view, None);
+            ManuallyDrop::drop(&mut self.shadow_map_image);
+        }
+
+        log::trace!("destroyed VkShadowMapRenderPass");
+    }
+}
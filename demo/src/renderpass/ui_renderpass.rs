@@ -4,12 +4,13 @@ use ash::prelude::VkResult;
 use ash::version::DeviceV1_0;
 
 use renderer::vulkan::{VkDeviceContext, MAX_FRAMES_IN_FLIGHT};
+use renderer::vulkan::VkGpuProfiler;
 use renderer::vulkan::VkSwapchain;
 use renderer::vulkan::SwapchainInfo;
 use renderer::vulkan::VkQueueFamilyIndices;
 
 use renderer::assets::resources::PipelineSwapchainInfo;
-use renderer::nodes::{PreparedRenderData, RenderView};
+use renderer::nodes::{PreparedRenderData, RenderView, RenderPhase};
 use crate::render_contexts::{RenderJobWriteContext, RenderJobWriteContextFactory};
 use renderer::vulkan::cleanup::VkCombinedDropSink;
 use crate::phases::UiRenderPhase;
@@ -134,6 +135,7 @@ impl VkUiRenderPass {
         prepared_render_data: &PreparedRenderData<RenderJobWriteContext>,
         view: &RenderView,
         write_context_factory: &RenderJobWriteContextFactory,
+        gpu_profiler: &mut VkGpuProfiler,
     ) -> VkResult<()> {
         let command_buffer_begin_info = vk::CommandBufferBeginInfo::builder();
 
@@ -149,6 +151,7 @@ impl VkUiRenderPass {
         unsafe {
             let logical_device = device_context.device();
             logical_device.begin_command_buffer(*command_buffer, &command_buffer_begin_info)?;
+            gpu_profiler.cmd_begin_scope(*command_buffer, "ui");
 
             logical_device.cmd_begin_render_pass(
                 *command_buffer,
@@ -158,9 +161,20 @@ impl VkUiRenderPass {
 
             let mut write_context = write_context_factory.create_context(*command_buffer);
 
-            prepared_render_data.write_view_phase::<UiRenderPhase>(&view, &mut write_context);
+            {
+                let _label = device_context.debug_label_scope(
+                    *command_buffer,
+                    &format!(
+                        "{} ({})",
+                        UiRenderPhase::render_phase_debug_name(),
+                        view.debug_name()
+                    ),
+                );
+                prepared_render_data.write_view_phase::<UiRenderPhase>(&view, &mut write_context);
+            }
 
             logical_device.cmd_end_render_pass(*command_buffer);
+            gpu_profiler.cmd_end_scope(*command_buffer);
             logical_device.end_command_buffer(*command_buffer)
         }
     }
@@ -172,6 +186,7 @@ impl VkUiRenderPass {
         prepared_render_data: &PreparedRenderData<RenderJobWriteContext>,
         view: &RenderView,
         write_context_factory: &RenderJobWriteContextFactory,
+        gpu_profiler: &mut VkGpuProfiler,
     ) -> VkResult<()> {
         assert!(self.renderpass == pipeline_info.pipeline.get_raw().renderpass.get_raw());
         Self::update_command_buffer(
@@ -183,6 +198,7 @@ impl VkUiRenderPass {
             prepared_render_data,
             view,
             write_context_factory,
+            gpu_profiler,
         )
     }
 }
@@ -2,7 +2,7 @@ use legion::prelude::{Resources, World, EntityStore};
 use crate::asset_resource::AssetResource;
 use glam::f32::Vec3;
 use crate::features::sprite::{SpriteRenderNodeSet, SpriteRenderNode};
-use renderer::visibility::{DynamicVisibilityNodeSet, DynamicAabbVisibilityNode};
+use renderer::visibility::{DynamicVisibilityNodeSet, DynamicAabbVisibilityNode, Aabb};
 use crate::components::{
     PositionComponent, SpriteComponent, PointLightComponent, SpotLightComponent,
     DirectionalLightComponent,
@@ -57,10 +57,9 @@ pub fn populate_test_sprite_entities(
         //   render node needs the entity.
         // - ALTERNATIVE: Could create an empty entity, create the components, and then add all of them
         sprite_render_nodes.register_sprite_with_handle(|sprite_handle| {
-            let aabb_info = DynamicAabbVisibilityNode {
-                handle: sprite_handle.into(),
-                // aabb bounds
-            };
+            // Sprites are currently always 50x50 quads centered on their position
+            let aabb = Aabb::from_center_half_extents(position, Vec3::new(25.0, 25.0, 0.0));
+            let aabb_info = DynamicAabbVisibilityNode::new(sprite_handle.into(), aabb);
 
             // User calls functions to register visibility objects
             // - This is a retained API because presumably we don't want to rebuild spatial structures every frame
@@ -117,10 +116,9 @@ pub fn populate_test_mesh_entities(
         //   render node needs the entity.
         // - ALTERNATIVE: Could create an empty entity, create the components, and then add all of them
         mesh_render_nodes.register_mesh_with_handle(|mesh_handle| {
-            let aabb_info = DynamicAabbVisibilityNode {
-                handle: mesh_handle.into(),
-                // aabb bounds
-            };
+            //TODO: Use the bounds of the mesh asset once it's loaded
+            let aabb = Aabb::from_center_half_extents(position, Vec3::new(1.0, 1.0, 1.0));
+            let aabb_info = DynamicAabbVisibilityNode::new(mesh_handle.into(), aabb);
 
             // User calls functions to register visibility objects
             // - This is a retained API because presumably we don't want to rebuild spatial structures every frame
@@ -4,7 +4,7 @@ use serde::export::Formatter;
 use crate::{ResourceArc, ImageViewResource, ImageKey};
 use renderer_shell_vulkan::VkImageRaw;
 
-#[derive(Copy, Clone, Debug, Serialize, Deserialize)]
+#[derive(Copy, Clone, Debug, Serialize, Deserialize, PartialEq)]
 pub enum ColorSpace {
     Srgb,
     Linear,
@@ -19,12 +19,149 @@ impl Into<crate::image_utils::ColorSpace> for ColorSpace {
     }
 }
 
+/// Layout of the texel data of an image. The BC formats are block compressed, each 4x4 block of
+/// texels is stored in 8 (BC1, BC4) or 16 (BC3, BC5, BC7) bytes.
+#[derive(Copy, Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub enum ImageAssetDataFormat {
+    /// Uncompressed, 4 bytes per texel
+    RGBA8,
+
+    /// RGB, no alpha. Good for opaque color textures.
+    BC1,
+
+    /// RGBA, with alpha compressed separately. Good for color textures with smooth alpha.
+    BC3,
+
+    /// Only R, stored as linear. Good for grayscale masks like roughness or occlusion.
+    BC4,
+
+    /// Only R and G, stored as linear. Good for tangent space normal maps, where B is
+    /// reconstructed in the shader.
+    BC5,
+
+    /// RGBA at higher quality than BC1/BC3, but slower to encode
+    BC7,
+
+    // New formats go at the end so the serialized values of existing formats don't change
+
+    /// Half precision float, 8 bytes per texel. Always linear. Good for HDR skyboxes and
+    /// environment maps.
+    RGBA16F,
+
+    /// Single precision float, 16 bytes per texel. Always linear.
+    RGBA32F,
+}
+
+impl ImageAssetDataFormat {
+    pub fn is_block_compressed(&self) -> bool {
+        match self {
+            ImageAssetDataFormat::RGBA8
+            | ImageAssetDataFormat::RGBA16F
+            | ImageAssetDataFormat::RGBA32F => false,
+            _ => true,
+        }
+    }
+
+    pub fn is_float(&self) -> bool {
+        match self {
+            ImageAssetDataFormat::RGBA16F | ImageAssetDataFormat::RGBA32F => true,
+            _ => false,
+        }
+    }
+
+    /// Float formats, BC4 and BC5 don't have sRGB variants
+    pub fn supports_srgb(&self) -> bool {
+        match self {
+            ImageAssetDataFormat::RGBA16F
+            | ImageAssetDataFormat::RGBA32F
+            | ImageAssetDataFormat::BC4
+            | ImageAssetDataFormat::BC5 => false,
+            _ => true,
+        }
+    }
+
+    /// Size of a single mip level of a single layer
+    pub fn mip_level_size_bytes(
+        &self,
+        width: u32,
+        height: u32,
+    ) -> usize {
+        let format: crate::image_utils::DecodedTextureFormat = (*self).into();
+        format.mip_level_size_bytes(width, height)
+    }
+
+    /// Size of a single mip level of a single layer, or None if it does not fit in a usize
+    pub fn checked_mip_level_size_bytes(
+        &self,
+        width: u32,
+        height: u32,
+    ) -> Option<usize> {
+        let format: crate::image_utils::DecodedTextureFormat = (*self).into();
+        format.checked_mip_level_size_bytes(width, height)
+    }
+}
+
+impl Into<crate::image_utils::DecodedTextureFormat> for ImageAssetDataFormat {
+    fn into(self) -> crate::image_utils::DecodedTextureFormat {
+        use crate::image_utils::DecodedTextureFormat;
+        match self {
+            ImageAssetDataFormat::RGBA8 => DecodedTextureFormat::RGBA8,
+            ImageAssetDataFormat::RGBA16F => DecodedTextureFormat::RGBA16F,
+            ImageAssetDataFormat::RGBA32F => DecodedTextureFormat::RGBA32F,
+            ImageAssetDataFormat::BC1 => DecodedTextureFormat::BC1,
+            ImageAssetDataFormat::BC3 => DecodedTextureFormat::BC3,
+            ImageAssetDataFormat::BC4 => DecodedTextureFormat::BC4,
+            ImageAssetDataFormat::BC5 => DecodedTextureFormat::BC5,
+            ImageAssetDataFormat::BC7 => DecodedTextureFormat::BC7,
+        }
+    }
+}
+
+/// How the mip chain of an image is provided when it's uploaded
+#[derive(Copy, Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub enum ImageAssetMips {
+    /// Only the base level is used
+    None,
+
+    /// The mip chain is generated on the GPU when the image is uploaded
+    Runtime,
+
+    /// `data` contains every mip level, largest first, each tightly packed in the image's format.
+    /// Each mip level contains all layers.
+    Precomputed { mip_level_count: u32 },
+}
+
+impl ImageAssetMips {
+    pub fn to_decoded_texture_mips(
+        &self,
+        width: u32,
+        height: u32,
+    ) -> crate::image_utils::DecodedTextureMips {
+        use crate::image_utils::{DecodedTextureMips, DecodedTextureMipInfo};
+        match *self {
+            ImageAssetMips::None => DecodedTextureMips::None,
+            ImageAssetMips::Runtime => {
+                crate::image_utils::default_mip_settings_for_image(width, height)
+            }
+            ImageAssetMips::Precomputed { mip_level_count } => {
+                DecodedTextureMips::Precomputed(DecodedTextureMipInfo { mip_level_count })
+            }
+        }
+    }
+}
+
 #[derive(TypeUuid, Serialize, Deserialize, Clone)]
 #[uuid = "e6166902-8716-401b-9d2e-8b01701c5626"]
 pub struct ImageAssetData {
     pub width: u32,
     pub height: u32,
     pub color_space: ColorSpace,
+    pub format: ImageAssetDataFormat,
+    pub mips: ImageAssetMips,
+
+    /// Number of array layers. Cube maps have 6 layers per cube, in +X, -X, +Y, -Y, +Z, -Z order.
+    pub layer_count: u32,
+    pub is_cube_map: bool,
 
     #[serde(with = "serde_bytes")]
     pub data: Vec<u8>,
@@ -38,11 +175,18 @@ impl std::fmt::Debug for ImageAssetData {
         f.debug_struct("Point")
             .field("width", &self.width)
             .field("width", &self.height)
+            .field("format", &self.format)
+            .field("mips", &self.mips)
+            .field("layer_count", &self.layer_count)
+            .field("is_cube_map", &self.is_cube_map)
             .field("byte_count", &self.data.len())
             .finish()
     }
 }
 
+/// A loaded image. The view covers every mip level and layer. Its type (2D, 2D array, cube or cube
+/// array) follows the image's layers, so material slots can reference cube maps and arrays like
+/// any other image.
 #[derive(TypeUuid, Clone)]
 #[uuid = "7a67b850-17f9-4877-8a6e-293a1589bbd8"]
 pub struct ImageAsset {
@@ -0,0 +1,108 @@
+use crate::assets::image::ImageAssetDataFormat;
+
+const BLOCK_EXTENT: u32 = 4;
+
+/// Encodes a single mip level of tightly packed RGBA8 texels into the given block compressed
+/// format. RGBA8 data is returned as is.
+pub(super) fn compress_mip_level(
+    width: u32,
+    height: u32,
+    rgba: Vec<u8>,
+    format: ImageAssetDataFormat,
+) -> Vec<u8> {
+    if !format.is_block_compressed() {
+        return rgba;
+    }
+
+    let (padded_width, padded_height, padded) = pad_to_whole_blocks(width, height, &rgba);
+    let surface = intel_tex::RgbaSurface {
+        data: &padded,
+        width: padded_width,
+        height: padded_height,
+        stride: padded_width * 4,
+    };
+
+    match format {
+        ImageAssetDataFormat::RGBA8
+        | ImageAssetDataFormat::RGBA16F
+        | ImageAssetDataFormat::RGBA32F => unreachable!(),
+        ImageAssetDataFormat::BC1 => intel_tex::bc1::compress_blocks(&surface),
+        ImageAssetDataFormat::BC3 => intel_tex::bc3::compress_blocks(&surface),
+        ImageAssetDataFormat::BC4 => {
+            let r: Vec<u8> = padded.chunks_exact(4).map(|texel| texel[0]).collect();
+            intel_tex::bc4::compress_blocks(&intel_tex::RSurface {
+                data: &r,
+                width: padded_width,
+                height: padded_height,
+                stride: padded_width,
+            })
+        }
+        ImageAssetDataFormat::BC5 => {
+            let rg: Vec<u8> = padded
+                .chunks_exact(4)
+                .flat_map(|texel| texel[0..2].iter().copied())
+                .collect();
+            intel_tex::bc5::compress_blocks(&intel_tex::RgSurface {
+                data: &rg,
+                width: padded_width,
+                height: padded_height,
+                stride: padded_width * 2,
+            })
+        }
+        ImageAssetDataFormat::BC7 => {
+            // The opaque settings spend all of the bits on color
+            let is_opaque = rgba.chunks_exact(4).all(|texel| texel[3] == 255);
+            let settings = if is_opaque {
+                intel_tex::bc7::opaque_basic_settings()
+            } else {
+                intel_tex::bc7::alpha_basic_settings()
+            };
+            intel_tex::bc7::compress_blocks(&settings, &surface)
+        }
+    }
+}
+
+// The encoder only handles whole 4x4 blocks. Partial blocks at the right and bottom edges are
+// filled by repeating the edge texels so they don't pull the block's colors towards black.
+fn pad_to_whole_blocks(
+    width: u32,
+    height: u32,
+    rgba: &[u8],
+) -> (u32, u32, Vec<u8>) {
+    let padded_width = (width + BLOCK_EXTENT - 1) / BLOCK_EXTENT * BLOCK_EXTENT;
+    let padded_height = (height + BLOCK_EXTENT - 1) / BLOCK_EXTENT * BLOCK_EXTENT;
+    if padded_width == width && padded_height == height {
+        return (width, height, rgba.to_vec());
+    }
+
+    let mut padded = Vec::with_capacity(padded_width as usize * padded_height as usize * 4);
+    for y in 0..padded_height {
+        let row_start = y.min(height - 1) as usize * width as usize * 4;
+        let row = &rgba[row_start..row_start + width as usize * 4];
+        padded.extend_from_slice(row);
+        for _ in width..padded_width {
+            padded.extend_from_slice(&row[row.len() - 4..]);
+        }
+    }
+
+    (padded_width, padded_height, padded)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pad_to_whole_blocks() {
+        // 5x1, each texel's red channel is its index
+        let rgba: Vec<u8> = (0..5).flat_map(|x| vec![x, 0, 0, 255]).collect();
+        let (width, height, padded) = pad_to_whole_blocks(5, 1, &rgba);
+        assert_eq!((width, height), (8, 4));
+        assert_eq!(padded.len(), 8 * 4 * 4);
+
+        let red: Vec<u8> = padded.chunks_exact(4).map(|texel| texel[0]).collect();
+        for row in red.chunks_exact(8) {
+            assert_eq!(row, &[0, 1, 2, 3, 4, 4, 4, 4]);
+        }
+    }
+}
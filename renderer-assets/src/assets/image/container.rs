@@ -0,0 +1,158 @@
+use ash::vk;
+use crate::assets::image::{ColorSpace, ImageAssetDataFormat};
+
+/// Produced when a KTX2 or DDS file can't be imported
+#[derive(Debug, PartialEq)]
+pub enum ImageContainerError {
+    /// The file is not a valid KTX2 or DDS file
+    InvalidHeader(String),
+
+    /// The texel format is not one that ImageAssetData can hold
+    UnsupportedFormat(String),
+
+    /// The file uses a feature that isn't supported, like 3D textures or supercompression
+    UnsupportedFeature(String),
+
+    /// The file is shorter than its header says
+    Truncated { expected: usize, actual: usize },
+}
+
+impl std::error::Error for ImageContainerError {}
+
+impl core::fmt::Display for ImageContainerError {
+    fn fmt(
+        &self,
+        fmt: &mut core::fmt::Formatter,
+    ) -> core::fmt::Result {
+        match *self {
+            ImageContainerError::InvalidHeader(ref message) => {
+                write!(fmt, "Invalid image header: {}", message)
+            }
+            ImageContainerError::UnsupportedFormat(ref format) => write!(
+                fmt,
+                "Unsupported image format {}, supported formats are RGBA8, RGBA16F, RGBA32F, BC1, BC3, BC4, BC5 and BC7",
+                format
+            ),
+            ImageContainerError::UnsupportedFeature(ref feature) => {
+                write!(fmt, "Unsupported image feature: {}", feature)
+            }
+            ImageContainerError::Truncated { expected, actual } => write!(
+                fmt,
+                "Image data is truncated, expected at least {} bytes but the file is {} bytes",
+                expected, actual
+            ),
+        }
+    }
+}
+
+pub(super) fn read_u32(
+    bytes: &[u8],
+    offset: usize,
+) -> Result<u32, ImageContainerError> {
+    let mut value = [0; 4];
+    value.copy_from_slice(read_bytes(bytes, offset, 4)?);
+    Ok(u32::from_le_bytes(value))
+}
+
+pub(super) fn read_u64(
+    bytes: &[u8],
+    offset: usize,
+) -> Result<u64, ImageContainerError> {
+    let mut value = [0; 8];
+    value.copy_from_slice(read_bytes(bytes, offset, 8)?);
+    Ok(u64::from_le_bytes(value))
+}
+
+pub(super) fn read_bytes(
+    bytes: &[u8],
+    offset: usize,
+    length: usize,
+) -> Result<&[u8], ImageContainerError> {
+    offset
+        .checked_add(length)
+        .and_then(|end| bytes.get(offset..end))
+        .ok_or_else(|| ImageContainerError::Truncated {
+            expected: offset.saturating_add(length),
+            actual: bytes.len(),
+        })
+}
+
+/// Size of a mip level with `layer_count` layers. The sizes in a header can be large enough to
+/// overflow, which makes the header invalid.
+pub(super) fn mip_level_size_bytes(
+    format: ImageAssetDataFormat,
+    width: u32,
+    height: u32,
+    layer_count: u32,
+) -> Result<usize, ImageContainerError> {
+    format
+        .checked_mip_level_size_bytes(width, height)
+        .and_then(|size| size.checked_mul(layer_count as usize))
+        .ok_or_else(|| too_large_error(width, height, layer_count))
+}
+
+pub(super) fn too_large_error(
+    width: u32,
+    height: u32,
+    layer_count: u32,
+) -> ImageContainerError {
+    ImageContainerError::InvalidHeader(format!(
+        "a {}x{} image with {} layers is too large",
+        width, height, layer_count
+    ))
+}
+
+/// The ImageAssetData format and color space that can hold images of the given vulkan format
+pub(super) fn format_from_vk(
+    format: vk::Format
+) -> Result<(ImageAssetDataFormat, ColorSpace), ImageContainerError> {
+    Ok(match format {
+        vk::Format::R8G8B8A8_UNORM => (ImageAssetDataFormat::RGBA8, ColorSpace::Linear),
+        vk::Format::R8G8B8A8_SRGB => (ImageAssetDataFormat::RGBA8, ColorSpace::Srgb),
+        vk::Format::R16G16B16A16_SFLOAT => (ImageAssetDataFormat::RGBA16F, ColorSpace::Linear),
+        vk::Format::R32G32B32A32_SFLOAT => (ImageAssetDataFormat::RGBA32F, ColorSpace::Linear),
+        // BC1 images are uploaded without alpha
+        vk::Format::BC1_RGB_UNORM_BLOCK | vk::Format::BC1_RGBA_UNORM_BLOCK => {
+            (ImageAssetDataFormat::BC1, ColorSpace::Linear)
+        }
+        vk::Format::BC1_RGB_SRGB_BLOCK | vk::Format::BC1_RGBA_SRGB_BLOCK => {
+            (ImageAssetDataFormat::BC1, ColorSpace::Srgb)
+        }
+        vk::Format::BC3_UNORM_BLOCK => (ImageAssetDataFormat::BC3, ColorSpace::Linear),
+        vk::Format::BC3_SRGB_BLOCK => (ImageAssetDataFormat::BC3, ColorSpace::Srgb),
+        vk::Format::BC4_UNORM_BLOCK => (ImageAssetDataFormat::BC4, ColorSpace::Linear),
+        vk::Format::BC5_UNORM_BLOCK => (ImageAssetDataFormat::BC5, ColorSpace::Linear),
+        vk::Format::BC7_UNORM_BLOCK => (ImageAssetDataFormat::BC7, ColorSpace::Linear),
+        vk::Format::BC7_SRGB_BLOCK => (ImageAssetDataFormat::BC7, ColorSpace::Srgb),
+        _ => {
+            return Err(ImageContainerError::UnsupportedFormat(format!(
+                "{:?}",
+                format
+            )))
+        }
+    })
+}
+
+/// Checks that the size and mip level count are usable
+pub(super) fn validate_extent(
+    width: u32,
+    height: u32,
+    mip_level_count: u32,
+) -> Result<(), ImageContainerError> {
+    if width == 0 || height == 0 {
+        return Err(ImageContainerError::InvalidHeader(format!(
+            "image size is {}x{}",
+            width, height
+        )));
+    }
+
+    let max_mip_level_count = 32 - width.max(height).leading_zeros();
+    if mip_level_count > max_mip_level_count {
+        return Err(ImageContainerError::InvalidHeader(format!(
+            "{} mip levels, but a {}x{} image has at most {}",
+            mip_level_count, width, height, max_mip_level_count
+        )));
+    }
+
+    Ok(())
+}
@@ -0,0 +1,503 @@
+use atelier_assets::core::AssetUuid;
+use atelier_assets::importer::{Error, ImportedAsset, Importer, ImporterValue, SourceFileImporter};
+use ash::vk;
+use serde::{Deserialize, Serialize};
+use type_uuid::*;
+use std::io::Read;
+use crate::assets::image::{ColorSpace, ImageAssetData, ImageAssetDataFormat, ImageAssetMips};
+use super::container::{
+    ImageContainerError, read_u32, read_bytes, format_from_vk, validate_extent,
+    mip_level_size_bytes, too_large_error,
+};
+
+const DDS_MAGIC: u32 = 0x2053_4444;
+const DDS_HEADER_SIZE: u32 = 124;
+// Magic + DDS_HEADER
+const DDS_DATA_OFFSET: usize = 128;
+// Magic + DDS_HEADER + DDS_HEADER_DXT10
+const DDS_DX10_DATA_OFFSET: usize = 148;
+
+const DDSD_MIPMAPCOUNT: u32 = 0x20000;
+const DDPF_ALPHAPIXELS: u32 = 0x1;
+const DDPF_FOURCC: u32 = 0x4;
+const DDPF_RGB: u32 = 0x40;
+const DDSCAPS2_CUBEMAP: u32 = 0x200;
+const DDSCAPS2_CUBEMAP_ALL_FACES: u32 = 0xFC00;
+const DDSCAPS2_VOLUME: u32 = 0x20_0000;
+
+const DDS_DIMENSION_TEXTURE2D: u32 = 3;
+const DDS_RESOURCE_MISC_TEXTURECUBE: u32 = 0x4;
+
+const D3DFMT_A16B16G16R16F: u32 = 113;
+const D3DFMT_A32B32G32R32F: u32 = 116;
+
+const DXGI_FORMAT_B8G8R8A8_UNORM: u32 = 87;
+const DXGI_FORMAT_B8G8R8A8_UNORM_SRGB: u32 = 91;
+
+fn four_cc(code: &[u8; 4]) -> u32 {
+    u32::from_le_bytes(*code)
+}
+
+// DXGI formats that ImageAssetData can hold, other than BGRA which needs its channels reordered
+fn dxgi_format_to_vk(dxgi_format: u32) -> Option<vk::Format> {
+    Some(match dxgi_format {
+        2 => vk::Format::R32G32B32A32_SFLOAT,
+        10 => vk::Format::R16G16B16A16_SFLOAT,
+        28 => vk::Format::R8G8B8A8_UNORM,
+        29 => vk::Format::R8G8B8A8_SRGB,
+        71 => vk::Format::BC1_RGBA_UNORM_BLOCK,
+        72 => vk::Format::BC1_RGBA_SRGB_BLOCK,
+        77 => vk::Format::BC3_UNORM_BLOCK,
+        78 => vk::Format::BC3_SRGB_BLOCK,
+        80 => vk::Format::BC4_UNORM_BLOCK,
+        83 => vk::Format::BC5_UNORM_BLOCK,
+        98 => vk::Format::BC7_UNORM_BLOCK,
+        99 => vk::Format::BC7_SRGB_BLOCK,
+        _ => return None,
+    })
+}
+
+// The texel layout of a DDS file, and how to turn it into ImageAssetData
+struct DdsFormat {
+    format: ImageAssetDataFormat,
+    color_space: ColorSpace,
+    // BGRA is converted to RGBA
+    swap_red_blue: bool,
+    // Set if the file has no alpha channel, in which case the alpha bytes are undefined
+    force_opaque: bool,
+}
+
+impl DdsFormat {
+    fn from_vk(format: vk::Format) -> Result<Self, ImageContainerError> {
+        let (format, color_space) = format_from_vk(format)?;
+        Ok(DdsFormat {
+            format,
+            color_space,
+            swap_red_blue: false,
+            force_opaque: false,
+        })
+    }
+
+    fn rgba8(
+        color_space: ColorSpace,
+        swap_red_blue: bool,
+        force_opaque: bool,
+    ) -> Self {
+        DdsFormat {
+            format: ImageAssetDataFormat::RGBA8,
+            color_space,
+            swap_red_blue,
+            force_opaque,
+        }
+    }
+}
+
+// Files without a DX10 header don't say whether they are sRGB, so color_space is used for formats
+// that have an sRGB variant
+fn legacy_dds_format(
+    bytes: &[u8],
+    color_space: ColorSpace,
+) -> Result<DdsFormat, ImageContainerError> {
+    let pixel_format_flags = read_u32(bytes, 80)?;
+    let pixel_format_four_cc = read_u32(bytes, 84)?;
+
+    if pixel_format_flags & DDPF_FOURCC != 0 {
+        let format = match &pixel_format_four_cc.to_le_bytes() {
+            // Float formats use their D3DFORMAT value instead of a character code
+            _ if pixel_format_four_cc == D3DFMT_A16B16G16R16F => ImageAssetDataFormat::RGBA16F,
+            _ if pixel_format_four_cc == D3DFMT_A32B32G32R32F => ImageAssetDataFormat::RGBA32F,
+            b"DXT1" => ImageAssetDataFormat::BC1,
+            b"DXT5" => ImageAssetDataFormat::BC3,
+            b"ATI1" | b"BC4U" => ImageAssetDataFormat::BC4,
+            b"ATI2" | b"BC5U" => ImageAssetDataFormat::BC5,
+            other => {
+                return Err(ImageContainerError::UnsupportedFormat(format!(
+                    "FourCC {}",
+                    String::from_utf8_lossy(other)
+                )))
+            }
+        };
+
+        let color_space = if format.supports_srgb() {
+            color_space
+        } else {
+            ColorSpace::Linear
+        };
+
+        return Ok(DdsFormat {
+            format,
+            color_space,
+            swap_red_blue: false,
+            force_opaque: false,
+        });
+    }
+
+    let rgb_bit_count = read_u32(bytes, 88)?;
+    let red_mask = read_u32(bytes, 92)?;
+    let green_mask = read_u32(bytes, 96)?;
+    let blue_mask = read_u32(bytes, 100)?;
+    if pixel_format_flags & DDPF_RGB != 0 && rgb_bit_count == 32 && green_mask == 0xFF00 {
+        let force_opaque = pixel_format_flags & DDPF_ALPHAPIXELS == 0;
+        if red_mask == 0xFF && blue_mask == 0xFF_0000 {
+            return Ok(DdsFormat::rgba8(color_space, false, force_opaque));
+        } else if red_mask == 0xFF_0000 && blue_mask == 0xFF {
+            return Ok(DdsFormat::rgba8(color_space, true, force_opaque));
+        }
+    }
+
+    Err(ImageContainerError::UnsupportedFormat(format!(
+        "{} bit with flags {:#x} and masks R {:#x} G {:#x} B {:#x}",
+        rgb_bit_count, pixel_format_flags, red_mask, green_mask, blue_mask
+    )))
+}
+
+/// Reads a DDS file, keeping its format, mip levels and layers. `color_space` is used for files
+/// without a DX10 header, which don't specify whether they are sRGB. Volume textures are not
+/// supported.
+pub fn parse_dds(
+    bytes: &[u8],
+    color_space: ColorSpace,
+) -> Result<ImageAssetData, ImageContainerError> {
+    if read_u32(bytes, 0)? != DDS_MAGIC {
+        return Err(ImageContainerError::InvalidHeader(
+            "missing DDS magic number".to_string(),
+        ));
+    }
+
+    if read_u32(bytes, 4)? != DDS_HEADER_SIZE {
+        return Err(ImageContainerError::InvalidHeader(
+            "unexpected DDS header size".to_string(),
+        ));
+    }
+
+    let flags = read_u32(bytes, 8)?;
+    let height = read_u32(bytes, 12)?;
+    let width = read_u32(bytes, 16)?;
+    let mip_map_count = read_u32(bytes, 28)?;
+    let pixel_format_flags = read_u32(bytes, 80)?;
+    let pixel_format_four_cc = read_u32(bytes, 84)?;
+    let caps2 = read_u32(bytes, 112)?;
+
+    let mip_level_count = if flags & DDSD_MIPMAPCOUNT != 0 {
+        mip_map_count.max(1)
+    } else {
+        1
+    };
+    validate_extent(width, height, mip_level_count)?;
+
+    let has_dx10_header =
+        pixel_format_flags & DDPF_FOURCC != 0 && pixel_format_four_cc == four_cc(b"DX10");
+    let (dds_format, layer_count, is_cube_map, data_offset) = if has_dx10_header {
+        let dxgi_format = read_u32(bytes, 128)?;
+        let resource_dimension = read_u32(bytes, 132)?;
+        let misc_flag = read_u32(bytes, 136)?;
+        let array_size = read_u32(bytes, 140)?;
+
+        if resource_dimension != DDS_DIMENSION_TEXTURE2D {
+            return Err(ImageContainerError::UnsupportedFeature(format!(
+                "resource dimension {}, only 2D textures are supported",
+                resource_dimension
+            )));
+        }
+
+        let dds_format = match dxgi_format {
+            DXGI_FORMAT_B8G8R8A8_UNORM => DdsFormat::rgba8(ColorSpace::Linear, true, false),
+            DXGI_FORMAT_B8G8R8A8_UNORM_SRGB => DdsFormat::rgba8(ColorSpace::Srgb, true, false),
+            _ => DdsFormat::from_vk(dxgi_format_to_vk(dxgi_format).ok_or_else(|| {
+                ImageContainerError::UnsupportedFormat(format!("DXGI format {}", dxgi_format))
+            })?)?,
+        };
+
+        // The array size counts cubes, not faces
+        let is_cube_map = misc_flag & DDS_RESOURCE_MISC_TEXTURECUBE != 0;
+        let faces_per_layer = if is_cube_map { 6 } else { 1 };
+        let layer_count = array_size
+            .max(1)
+            .checked_mul(faces_per_layer)
+            .ok_or_else(|| {
+                ImageContainerError::InvalidHeader(format!("array size is {}", array_size))
+            })?;
+        (dds_format, layer_count, is_cube_map, DDS_DX10_DATA_OFFSET)
+    } else {
+        if caps2 & DDSCAPS2_VOLUME != 0 {
+            return Err(ImageContainerError::UnsupportedFeature(
+                "volume textures".to_string(),
+            ));
+        }
+
+        let is_cube_map = caps2 & DDSCAPS2_CUBEMAP != 0;
+        if is_cube_map && caps2 & DDSCAPS2_CUBEMAP_ALL_FACES != DDSCAPS2_CUBEMAP_ALL_FACES {
+            return Err(ImageContainerError::UnsupportedFeature(
+                "cube maps with missing faces".to_string(),
+            ));
+        }
+
+        let layer_count = if is_cube_map { 6 } else { 1 };
+        let dds_format = legacy_dds_format(bytes, color_space)?;
+        (dds_format, layer_count, is_cube_map, DDS_DATA_OFFSET)
+    };
+
+    let format = dds_format.format;
+    let mip_level_sizes = (0..mip_level_count)
+        .map(|level| {
+            mip_level_size_bytes(format, (width >> level).max(1), (height >> level).max(1), 1)
+        })
+        .collect::<Result<Vec<usize>, _>>()?;
+    let data_size = mip_level_sizes
+        .iter()
+        .try_fold(0usize, |layer_size, mip_level_size| {
+            layer_size.checked_add(*mip_level_size)
+        })
+        .and_then(|layer_size| layer_size.checked_mul(layer_count as usize))
+        .ok_or_else(|| too_large_error(width, height, layer_count))?;
+    let layer_size = data_size / layer_count as usize;
+
+    // DDS stores the whole mip chain of a layer before the next layer, but ImageAssetData stores
+    // every layer of a mip level before the next mip level
+    read_bytes(bytes, data_offset, data_size)?;
+    let mut data = Vec::with_capacity(data_size);
+    let mut mip_level_offset = data_offset;
+    for mip_level_size in &mip_level_sizes {
+        for layer in 0..layer_count as usize {
+            let offset = mip_level_offset + layer * layer_size;
+            data.extend_from_slice(&bytes[offset..offset + mip_level_size]);
+        }
+        mip_level_offset += mip_level_size;
+    }
+
+    if dds_format.swap_red_blue || dds_format.force_opaque {
+        for texel in data.chunks_exact_mut(4) {
+            if dds_format.swap_red_blue {
+                texel.swap(0, 2);
+            }
+            if dds_format.force_opaque {
+                texel[3] = 255;
+            }
+        }
+    }
+
+    let mips = if mip_level_count > 1 {
+        ImageAssetMips::Precomputed { mip_level_count }
+    } else {
+        ImageAssetMips::None
+    };
+
+    Ok(ImageAssetData {
+        width,
+        height,
+        color_space: dds_format.color_space,
+        format,
+        mips,
+        layer_count,
+        is_cube_map,
+        data,
+    })
+}
+
+/// Settings stored in the .meta file of a DDS file
+#[derive(TypeUuid, Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[uuid = "0b3f64b4-3b51-47e5-9f6c-2f79b3c3a8d6"]
+#[serde(default)]
+pub struct DdsImporterOptions {
+    /// Files without a DX10 header don't specify whether they are sRGB
+    pub color_space: ColorSpace,
+}
+
+impl Default for DdsImporterOptions {
+    fn default() -> Self {
+        DdsImporterOptions {
+            color_space: ColorSpace::Srgb,
+        }
+    }
+}
+
+#[derive(TypeUuid, Serialize, Deserialize, Default)]
+#[uuid = "9d5e1c36-1a8b-4f0e-8c2f-6f1c3b7a4e92"]
+struct DdsImporterState(Option<AssetUuid>);
+
+#[derive(TypeUuid)]
+#[uuid = "e2b1f5a4-7c3d-4b8e-9a61-3d4f2c8b7e15"]
+struct DdsImporter;
+impl Importer for DdsImporter {
+    fn version_static() -> u32
+    where
+        Self: Sized,
+    {
+        2
+    }
+
+    fn version(&self) -> u32 {
+        Self::version_static()
+    }
+
+    type Options = DdsImporterOptions;
+
+    type State = DdsImporterState;
+
+    /// Reads the given bytes and produces assets.
+    fn import(
+        &self,
+        source: &mut dyn Read,
+        options: Self::Options,
+        state: &mut Self::State,
+    ) -> atelier_assets::importer::Result<ImporterValue> {
+        let id = state
+            .0
+            .unwrap_or_else(|| AssetUuid(*uuid::Uuid::new_v4().as_bytes()));
+        *state = DdsImporterState(Some(id));
+        let mut bytes = Vec::new();
+        source.read_to_end(&mut bytes)?;
+
+        let image_asset =
+            parse_dds(&bytes, options.color_space).map_err(|e| Error::Boxed(Box::new(e)))?;
+
+        Ok(ImporterValue {
+            assets: vec![ImportedAsset {
+                id,
+                search_tags: vec![],
+                build_deps: vec![],
+                load_deps: vec![],
+                build_pipeline: None,
+                asset_data: Box::new(image_asset),
+            }],
+        })
+    }
+}
+
+inventory::submit!(SourceFileImporter {
+    extension: "dds",
+    instantiator: || Box::new(DdsImporter {}),
+});
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn build_dds_header(
+        width: u32,
+        height: u32,
+        mip_level_count: u32,
+        pixel_format: [u32; 8],
+        caps2: u32,
+    ) -> Vec<u8> {
+        let mut header = [0u32; 32];
+        header[0] = DDS_MAGIC;
+        header[1] = DDS_HEADER_SIZE;
+        header[2] = DDSD_MIPMAPCOUNT;
+        header[3] = height;
+        header[4] = width;
+        header[7] = mip_level_count;
+        header[19..27].copy_from_slice(&pixel_format);
+        header[28] = caps2;
+        header
+            .iter()
+            .flat_map(|value| value.to_le_bytes().to_vec())
+            .collect()
+    }
+
+    fn four_cc_pixel_format(code: &[u8; 4]) -> [u32; 8] {
+        [32, DDPF_FOURCC, four_cc(code), 0, 0, 0, 0, 0]
+    }
+
+    #[test]
+    fn test_parse_bgra_without_alpha() {
+        let pixel_format = [32, DDPF_RGB, 0, 32, 0xFF_0000, 0xFF00, 0xFF, 0];
+        let mut bytes = build_dds_header(1, 1, 1, pixel_format, 0);
+        bytes.extend_from_slice(&[1, 2, 3, 4]);
+
+        let image = parse_dds(&bytes, ColorSpace::Srgb).unwrap();
+        assert_eq!(image.format, ImageAssetDataFormat::RGBA8);
+        assert_eq!(image.color_space, ColorSpace::Srgb);
+        assert_eq!(image.data, vec![3, 2, 1, 255]);
+    }
+
+    #[test]
+    fn test_parse_cube_map_reorders_layers() {
+        // 4x4 BC1 with 2 mips is 8 bytes per mip, each face of each mip is filled with
+        // face * 2 + mip
+        let caps2 = DDSCAPS2_CUBEMAP | DDSCAPS2_CUBEMAP_ALL_FACES;
+        let mut bytes = build_dds_header(4, 4, 2, four_cc_pixel_format(b"DXT1"), caps2);
+        for face in 0..6u8 {
+            for mip in 0..2u8 {
+                bytes.extend(std::iter::repeat(face * 2 + mip).take(8));
+            }
+        }
+
+        let image = parse_dds(&bytes, ColorSpace::Linear).unwrap();
+        assert_eq!(image.format, ImageAssetDataFormat::BC1);
+        assert!(image.is_cube_map);
+        assert_eq!(image.layer_count, 6);
+        assert_eq!(
+            image.mips,
+            ImageAssetMips::Precomputed { mip_level_count: 2 }
+        );
+
+        let first_bytes: Vec<u8> = image.data.chunks_exact(8).map(|block| block[0]).collect();
+        assert_eq!(first_bytes, vec![0, 2, 4, 6, 8, 10, 1, 3, 5, 7, 9, 11]);
+    }
+
+    #[test]
+    fn test_parse_dx10_array() {
+        let mut bytes = build_dds_header(4, 4, 1, four_cc_pixel_format(b"DX10"), 0);
+        for value in &[98, DDS_DIMENSION_TEXTURE2D, 0, 3, 0] {
+            bytes.extend_from_slice(&(*value as u32).to_le_bytes());
+        }
+        bytes.extend(vec![0; 16 * 3]);
+
+        let image = parse_dds(&bytes, ColorSpace::Srgb).unwrap();
+        assert_eq!(image.format, ImageAssetDataFormat::BC7);
+        assert_eq!(image.color_space, ColorSpace::Linear);
+        assert_eq!(image.layer_count, 3);
+        assert!(!image.is_cube_map);
+    }
+
+    #[test]
+    fn test_parse_legacy_float() {
+        let pixel_format = [32, DDPF_FOURCC, D3DFMT_A16B16G16R16F, 0, 0, 0, 0, 0];
+        let mut bytes = build_dds_header(2, 1, 1, pixel_format, 0);
+        bytes.extend(vec![0; 2 * 8]);
+
+        let image = parse_dds(&bytes, ColorSpace::Srgb).unwrap();
+        assert_eq!(image.format, ImageAssetDataFormat::RGBA16F);
+        assert_eq!(image.color_space, ColorSpace::Linear);
+        assert_eq!(image.data.len(), 16);
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        let bytes = build_dds_header(4, 4, 1, four_cc_pixel_format(b"DXT3"), 0);
+        assert!(matches!(
+            parse_dds(&bytes, ColorSpace::Srgb),
+            Err(ImageContainerError::UnsupportedFormat(_))
+        ));
+
+        let bytes = build_dds_header(4, 4, 1, four_cc_pixel_format(b"DXT1"), 0);
+        assert!(matches!(
+            parse_dds(&bytes, ColorSpace::Srgb),
+            Err(ImageContainerError::Truncated { .. })
+        ));
+
+        // Sizes that overflow are rejected instead of wrapping
+        let pixel_format = [32, DDPF_FOURCC, D3DFMT_A32B32G32R32F, 0, 0, 0, 0, 0];
+        let bytes = build_dds_header(std::u32::MAX, std::u32::MAX, 1, pixel_format, 0);
+        assert!(matches!(
+            parse_dds(&bytes, ColorSpace::Srgb),
+            Err(ImageContainerError::InvalidHeader(_))
+        ));
+
+        let mut bytes = build_dds_header(4, 4, 1, four_cc_pixel_format(b"DX10"), 0);
+        for value in &[
+            98,
+            DDS_DIMENSION_TEXTURE2D,
+            DDS_RESOURCE_MISC_TEXTURECUBE,
+            std::u32::MAX,
+            0,
+        ] {
+            bytes.extend_from_slice(&value.to_le_bytes());
+        }
+        assert!(matches!(
+            parse_dds(&bytes, ColorSpace::Srgb),
+            Err(ImageContainerError::InvalidHeader(_))
+        ));
+    }
+}
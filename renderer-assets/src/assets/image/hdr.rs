@@ -0,0 +1,205 @@
+use atelier_assets::core::AssetUuid;
+use atelier_assets::importer::{Error, ImportedAsset, Importer, ImporterValue, SourceFileImporter};
+use exr::prelude::{ReadChannels, ReadLayers, RgbaChannels};
+use serde::{Deserialize, Serialize};
+use type_uuid::*;
+use std::io::Read;
+use crate::assets::image::{ImageAssetDataFormat, ImageLayout, ImageMipGeneration};
+
+/// Float formats that HDR images can be stored in
+#[derive(Copy, Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub enum HdrImageFormat {
+    /// Half precision, enough for skyboxes and environment maps at half the memory of RGBA32F
+    RGBA16F,
+
+    /// Single precision. Devices aren't required to support linear filtering of this format, so
+    /// runtime mip generation may fall back to a single mip level.
+    RGBA32F,
+}
+
+impl Into<ImageAssetDataFormat> for HdrImageFormat {
+    fn into(self) -> ImageAssetDataFormat {
+        match self {
+            HdrImageFormat::RGBA16F => ImageAssetDataFormat::RGBA16F,
+            HdrImageFormat::RGBA32F => ImageAssetDataFormat::RGBA32F,
+        }
+    }
+}
+
+/// Settings stored in the .meta file of a Radiance (.hdr) or OpenEXR (.exr) image. HDR images are
+/// always linear.
+#[derive(TypeUuid, Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[uuid = "3c1e9a6f-5b2d-4e8a-b7f0-91d4c6a2e385"]
+#[serde(default)]
+pub struct HdrImageImporterOptions {
+    pub format: HdrImageFormat,
+
+    pub mips: ImageMipGeneration,
+
+    /// If set, images with a larger width or height are scaled down to fit, preserving the aspect
+    /// ratio
+    pub max_resolution: Option<u32>,
+
+    /// Splits the image into array layers or cube map faces, i.e. to turn a panorama into a
+    /// skybox. `max_resolution` applies to each layer.
+    pub layout: ImageLayout,
+}
+
+impl Default for HdrImageImporterOptions {
+    fn default() -> Self {
+        HdrImageImporterOptions {
+            format: HdrImageFormat::RGBA16F,
+            mips: ImageMipGeneration::Runtime,
+            max_resolution: None,
+            layout: ImageLayout::Single,
+        }
+    }
+}
+
+// Width, height and linear RGBA texels
+type DecodedHdrImage = (u32, u32, Vec<[f32; 4]>);
+
+fn decode_radiance(bytes: &[u8]) -> Result<DecodedHdrImage, image::ImageError> {
+    let decoder = image::hdr::HdrDecoder::new(bytes)?;
+    let metadata = decoder.metadata();
+    let texels = decoder
+        .read_image_hdr()?
+        .into_iter()
+        .map(|texel| [texel[0], texel[1], texel[2], 1.0])
+        .collect();
+
+    Ok((metadata.width, metadata.height, texels))
+}
+
+// Reads the R, G, B and (if present) A channels of the first layer at full resolution
+fn decode_openexr(bytes: &[u8]) -> Result<DecodedHdrImage, exr::error::Error> {
+    let image = exr::prelude::read()
+        .no_deep_data()
+        .largest_resolution_level()
+        .rgba_channels(
+            |resolution, _channels: &RgbaChannels| {
+                (
+                    resolution.width(),
+                    vec![[0.0, 0.0, 0.0, 1.0]; resolution.width() * resolution.height()],
+                )
+            },
+            |(width, texels): &mut (usize, Vec<[f32; 4]>),
+             position,
+             (r, g, b, a): (f32, f32, f32, f32)| {
+                texels[position.y() * *width + position.x()] = [r, g, b, a];
+            },
+        )
+        .first_valid_layer()
+        .all_attributes()
+        .from_buffered(std::io::Cursor::new(bytes))?;
+
+    let size = image.layer_data.size;
+    let (_, texels) = image.layer_data.channel_data.pixels;
+    Ok((size.width() as u32, size.height() as u32, texels))
+}
+
+#[derive(TypeUuid, Serialize, Deserialize, Default)]
+#[uuid = "b4e7d2a9-0c6f-4a31-8e5b-7f2c9d1a6e40"]
+struct HdrImageImporterState(Option<AssetUuid>);
+
+fn import_hdr_image<DecodeFn, E>(
+    source: &mut dyn Read,
+    options: &HdrImageImporterOptions,
+    state: &mut HdrImageImporterState,
+    decode: DecodeFn,
+) -> atelier_assets::importer::Result<ImporterValue>
+where
+    DecodeFn: Fn(&[u8]) -> Result<DecodedHdrImage, E>,
+    E: std::error::Error + Send + 'static,
+{
+    let id = state
+        .0
+        .unwrap_or_else(|| AssetUuid(*uuid::Uuid::new_v4().as_bytes()));
+    *state = HdrImageImporterState(Some(id));
+    let mut bytes = Vec::new();
+    source.read_to_end(&mut bytes)?;
+
+    let (width, height, texels) = decode(&bytes).map_err(|e| Error::Boxed(Box::new(e)))?;
+    let image_asset = super::process_hdr_image(width, height, texels, options)
+        .map_err(|e| Error::Boxed(Box::new(e)))?;
+
+    Ok(ImporterValue {
+        assets: vec![ImportedAsset {
+            id,
+            search_tags: vec![],
+            build_deps: vec![],
+            load_deps: vec![],
+            build_pipeline: None,
+            asset_data: Box::new(image_asset),
+        }],
+    })
+}
+
+#[derive(TypeUuid)]
+#[uuid = "6a9f3c2e-d81b-4f57-a0e4-2b5c8e7d1f93"]
+struct RadianceImageImporter;
+impl Importer for RadianceImageImporter {
+    fn version_static() -> u32
+    where
+        Self: Sized,
+    {
+        1
+    }
+
+    fn version(&self) -> u32 {
+        Self::version_static()
+    }
+
+    type Options = HdrImageImporterOptions;
+
+    type State = HdrImageImporterState;
+
+    /// Reads the given bytes and produces assets.
+    fn import(
+        &self,
+        source: &mut dyn Read,
+        options: Self::Options,
+        state: &mut Self::State,
+    ) -> atelier_assets::importer::Result<ImporterValue> {
+        import_hdr_image(source, &options, state, decode_radiance)
+    }
+}
+
+#[derive(TypeUuid)]
+#[uuid = "d07b5e41-93ac-4c2f-8b16-e5f2a7c39d08"]
+struct OpenExrImageImporter;
+impl Importer for OpenExrImageImporter {
+    fn version_static() -> u32
+    where
+        Self: Sized,
+    {
+        1
+    }
+
+    fn version(&self) -> u32 {
+        Self::version_static()
+    }
+
+    type Options = HdrImageImporterOptions;
+
+    type State = HdrImageImporterState;
+
+    /// Reads the given bytes and produces assets.
+    fn import(
+        &self,
+        source: &mut dyn Read,
+        options: Self::Options,
+        state: &mut Self::State,
+    ) -> atelier_assets::importer::Result<ImporterValue> {
+        import_hdr_image(source, &options, state, decode_openexr)
+    }
+}
+
+inventory::submit!(SourceFileImporter {
+    extension: "hdr",
+    instantiator: || Box::new(RadianceImageImporter {}),
+});
+inventory::submit!(SourceFileImporter {
+    extension: "exr",
+    instantiator: || Box::new(OpenExrImageImporter {}),
+});
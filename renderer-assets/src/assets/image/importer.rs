@@ -4,7 +4,136 @@ use image2::{Image};
 use serde::{Deserialize, Serialize};
 use type_uuid::*;
 use std::io::Read;
-use crate::assets::image::{ImageAssetData, ColorSpace};
+use crate::assets::image::{ColorSpace, ImageAssetDataFormat};
+
+/// How the mip chain of an imported image is produced
+#[derive(Copy, Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub enum ImageMipGeneration {
+    /// The image has a single mip level
+    None,
+
+    /// Mips are generated on the GPU when the image is uploaded
+    Runtime,
+
+    /// Mips are generated when the image is imported and stored with the asset
+    Precomputed(MipFilter),
+}
+
+/// Filter used to downsample precomputed mips or to scale down an image larger than the max
+/// resolution. Filtering is done in linear space.
+#[derive(Copy, Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub enum MipFilter {
+    /// Averages the source texels covered by each destination texel. Fast, but slightly blurry.
+    Box,
+
+    /// Kaiser-windowed sinc. Sharper than box, at the cost of slight ringing on hard edges.
+    Kaiser,
+}
+
+/// Where the value of an output channel comes from
+#[derive(Copy, Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub enum ImageChannelSource {
+    R,
+    G,
+    B,
+    A,
+    Zero,
+    One,
+}
+
+/// How the layers of an image are arranged in the source image. Cube map faces are in +X, -X, +Y,
+/// -Y, +Z, -Z order and are used as is, without rotation.
+#[derive(Copy, Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub enum ImageLayout {
+    /// A single 2D image
+    Single,
+
+    /// Array layers of equal size, stacked from top to bottom
+    Array { layer_count: u32 },
+
+    /// Cube map faces side by side, from left to right
+    CubeHorizontalStrip,
+
+    /// Cube map faces stacked from top to bottom
+    CubeVerticalStrip,
+
+    /// Cube map faces in a 4x3 grid. The middle row is -X, +Z, +X, -Z, with +Y above and -Y below
+    /// +Z.
+    CubeHorizontalCross,
+
+    /// An equirectangular (latitude/longitude) panorama, resampled to a cube map with faces of the
+    /// given size. The center of the panorama faces -Z and its top faces +Y.
+    CubeEquirectangular { face_size: u32 },
+}
+
+impl ImageLayout {
+    pub fn is_cube_map(&self) -> bool {
+        match self {
+            ImageLayout::Single | ImageLayout::Array { .. } => false,
+            _ => true,
+        }
+    }
+}
+
+/// Settings stored in the .meta file of an image
+#[derive(TypeUuid, Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[uuid = "c2bd5ef6-1ec4-4e65-8c87-30b1ad4c5d4d"]
+#[serde(default)]
+pub struct ImageImporterOptions {
+    pub color_space: ColorSpace,
+
+    /// Block compressed formats are encoded on the CPU when importing. They can't have mips
+    /// generated at runtime, so `ImageMipGeneration::Runtime` is treated as precomputed with a
+    /// kaiser filter. BC4 and BC5 are always stored as linear.
+    pub format: ImageAssetDataFormat,
+
+    pub mips: ImageMipGeneration,
+
+    /// If set, images with a larger width or height are scaled down to fit, preserving the aspect
+    /// ratio
+    pub max_resolution: Option<u32>,
+
+    /// Multiply color channels by alpha
+    pub premultiply_alpha: bool,
+
+    /// The source of the R, G, B and A output channels, i.e. to pack a grayscale roughness map
+    /// into a specific channel or to swap the channel order
+    pub swizzle: [ImageChannelSource; 4],
+
+    /// Splits the image into array layers or cube map faces. `max_resolution` applies to each
+    /// layer.
+    pub layout: ImageLayout,
+}
+
+impl ImageImporterOptions {
+    pub const IDENTITY_SWIZZLE: [ImageChannelSource; 4] = [
+        ImageChannelSource::R,
+        ImageChannelSource::G,
+        ImageChannelSource::B,
+        ImageChannelSource::A,
+    ];
+
+    pub fn with_color_space(color_space: ColorSpace) -> Self {
+        ImageImporterOptions {
+            color_space,
+            ..Default::default()
+        }
+    }
+}
+
+impl Default for ImageImporterOptions {
+    fn default() -> Self {
+        ImageImporterOptions {
+            color_space: ColorSpace::Srgb,
+            format: ImageAssetDataFormat::RGBA8,
+            mips: ImageMipGeneration::Runtime,
+            max_resolution: None,
+            premultiply_alpha: false,
+            swizzle: Self::IDENTITY_SWIZZLE,
+            layout: ImageLayout::Single,
+        }
+    }
+}
 
 #[derive(TypeUuid, Serialize, Deserialize, Default)]
 #[uuid = "23f90369-6916-4548-81d0-a76e0b162df2"]
@@ -18,14 +147,14 @@ impl Importer for ImageImporter {
     where
         Self: Sized,
     {
-        2
+        5
     }
 
     fn version(&self) -> u32 {
         Self::version_static()
     }
 
-    type Options = ();
+    type Options = ImageImporterOptions;
 
     type State = ImageImporterState;
 
@@ -33,7 +162,7 @@ impl Importer for ImageImporter {
     fn import(
         &self,
         source: &mut dyn Read,
-        _options: Self::Options,
+        options: Self::Options,
         state: &mut Self::State,
     ) -> atelier_assets::importer::Result<ImporterValue> {
         let id = state
@@ -46,12 +175,13 @@ impl Importer for ImageImporter {
         let decoded_image = image2::io::decode::<_, _, image2::Rgba>(&bytes)
             .map_err(|e| Error::Boxed(Box::new(e)))?;
 
-        let image_asset = ImageAssetData {
-            width: decoded_image.width() as u32,
-            height: decoded_image.height() as u32,
-            color_space: ColorSpace::Srgb,
-            data: decoded_image.data().to_vec(),
-        };
+        let image_asset = super::process_image(
+            decoded_image.width() as u32,
+            decoded_image.height() as u32,
+            decoded_image.data().to_vec(),
+            &options,
+        )
+        .map_err(|e| Error::Boxed(Box::new(e)))?;
 
         Ok(ImporterValue {
             assets: vec![ImportedAsset {
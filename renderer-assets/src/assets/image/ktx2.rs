@@ -0,0 +1,286 @@
+use atelier_assets::core::AssetUuid;
+use atelier_assets::importer::{Error, ImportedAsset, Importer, ImporterValue, SourceFileImporter};
+use ash::vk;
+use serde::{Deserialize, Serialize};
+use type_uuid::*;
+use std::io::Read;
+use crate::assets::image::{ImageAssetData, ImageAssetMips};
+use super::container::{
+    ImageContainerError, read_u32, read_u64, read_bytes, format_from_vk, validate_extent,
+    mip_level_size_bytes,
+};
+
+const KTX2_IDENTIFIER: [u8; 12] = [
+    0xAB, 0x4B, 0x54, 0x58, 0x20, 0x32, 0x30, 0xBB, 0x0D, 0x0A, 0x1A, 0x0A,
+];
+
+// The header is followed by the level index, one entry of three u64s per mip level
+const LEVEL_INDEX_OFFSET: usize = 80;
+const LEVEL_INDEX_ENTRY_SIZE: usize = 24;
+
+/// Reads a KTX2 file, keeping its format, mip levels and layers. Supercompressed files (i.e. Basis
+/// Universal) and 3D textures are not supported.
+pub fn parse_ktx2(bytes: &[u8]) -> Result<ImageAssetData, ImageContainerError> {
+    if read_bytes(bytes, 0, KTX2_IDENTIFIER.len())? != KTX2_IDENTIFIER {
+        return Err(ImageContainerError::InvalidHeader(
+            "missing KTX2 identifier".to_string(),
+        ));
+    }
+
+    let vk_format = read_u32(bytes, 12)?;
+    let width = read_u32(bytes, 20)?;
+    let height = read_u32(bytes, 24)?;
+    let depth = read_u32(bytes, 28)?;
+    let layer_count = read_u32(bytes, 32)?;
+    let face_count = read_u32(bytes, 36)?;
+    let level_count = read_u32(bytes, 40)?;
+    let supercompression_scheme = read_u32(bytes, 44)?;
+
+    if supercompression_scheme != 0 {
+        return Err(ImageContainerError::UnsupportedFeature(format!(
+            "supercompression scheme {}",
+            supercompression_scheme
+        )));
+    }
+
+    if depth > 1 {
+        return Err(ImageContainerError::UnsupportedFeature(
+            "3D textures".to_string(),
+        ));
+    }
+
+    if face_count != 1 && face_count != 6 {
+        return Err(ImageContainerError::InvalidHeader(format!(
+            "face count is {}, expected 1 or 6",
+            face_count
+        )));
+    }
+
+    let (format, color_space) = format_from_vk(vk::Format::from_raw(vk_format as i32))?;
+
+    // A level count of 0 means only the first level is stored and the rest should be generated
+    let stored_level_count = level_count.max(1);
+    validate_extent(width, height, stored_level_count)?;
+
+    // Layers are 0 if the image isn't an array
+    let layer_count = layer_count.max(1).checked_mul(face_count).ok_or_else(|| {
+        ImageContainerError::InvalidHeader(format!("layer count is {}", layer_count))
+    })?;
+
+    // Each level holds every layer and face, which matches ImageAssetData
+    let mut data = vec![];
+    for level in 0..stored_level_count {
+        let level_index_entry = LEVEL_INDEX_OFFSET + level as usize * LEVEL_INDEX_ENTRY_SIZE;
+        let byte_offset = read_u64(bytes, level_index_entry)? as usize;
+        let byte_length = read_u64(bytes, level_index_entry + 8)? as usize;
+
+        let expected_length = mip_level_size_bytes(
+            format,
+            (width >> level).max(1),
+            (height >> level).max(1),
+            layer_count,
+        )?;
+        if byte_length != expected_length {
+            return Err(ImageContainerError::InvalidHeader(format!(
+                "mip level {} is {} bytes, expected {}",
+                level, byte_length, expected_length
+            )));
+        }
+
+        data.extend_from_slice(read_bytes(bytes, byte_offset, byte_length)?);
+    }
+
+    let mips = match level_count {
+        0 => ImageAssetMips::Runtime,
+        1 => ImageAssetMips::None,
+        mip_level_count => ImageAssetMips::Precomputed { mip_level_count },
+    };
+
+    Ok(ImageAssetData {
+        width,
+        height,
+        color_space,
+        format,
+        mips,
+        layer_count,
+        is_cube_map: face_count == 6,
+        data,
+    })
+}
+
+#[derive(TypeUuid, Serialize, Deserialize, Default)]
+#[uuid = "8f2c8a43-61f5-4f5c-9c9e-0f2d4f6d0a51"]
+struct Ktx2ImporterState(Option<AssetUuid>);
+
+#[derive(TypeUuid)]
+#[uuid = "5a3d5e3b-0c0f-4f14-b3a4-53f6e0f1a2c7"]
+struct Ktx2Importer;
+impl Importer for Ktx2Importer {
+    fn version_static() -> u32
+    where
+        Self: Sized,
+    {
+        2
+    }
+
+    fn version(&self) -> u32 {
+        Self::version_static()
+    }
+
+    type Options = ();
+
+    type State = Ktx2ImporterState;
+
+    /// Reads the given bytes and produces assets.
+    fn import(
+        &self,
+        source: &mut dyn Read,
+        _options: Self::Options,
+        state: &mut Self::State,
+    ) -> atelier_assets::importer::Result<ImporterValue> {
+        let id = state
+            .0
+            .unwrap_or_else(|| AssetUuid(*uuid::Uuid::new_v4().as_bytes()));
+        *state = Ktx2ImporterState(Some(id));
+        let mut bytes = Vec::new();
+        source.read_to_end(&mut bytes)?;
+
+        let image_asset = parse_ktx2(&bytes).map_err(|e| Error::Boxed(Box::new(e)))?;
+
+        Ok(ImporterValue {
+            assets: vec![ImportedAsset {
+                id,
+                search_tags: vec![],
+                build_deps: vec![],
+                load_deps: vec![],
+                build_pipeline: None,
+                asset_data: Box::new(image_asset),
+            }],
+        })
+    }
+}
+
+inventory::submit!(SourceFileImporter {
+    extension: "ktx2",
+    instantiator: || Box::new(Ktx2Importer {}),
+});
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::assets::image::{ColorSpace, ImageAssetDataFormat};
+
+    // Builds a KTX2 file where each level is filled with its level index
+    fn build_ktx2(
+        vk_format: vk::Format,
+        width: u32,
+        height: u32,
+        layer_count: u32,
+        face_count: u32,
+        level_sizes: &[usize],
+    ) -> Vec<u8> {
+        let mut bytes = KTX2_IDENTIFIER.to_vec();
+        for value in &[
+            vk_format.as_raw() as u32,
+            1,
+            width,
+            height,
+            0,
+            layer_count,
+            face_count,
+            level_sizes.len() as u32,
+            0,
+        ] {
+            bytes.extend_from_slice(&value.to_le_bytes());
+        }
+
+        // No data format descriptor, key/value data or supercompression data
+        bytes.resize(LEVEL_INDEX_OFFSET, 0);
+
+        let mut byte_offset = LEVEL_INDEX_OFFSET + level_sizes.len() * LEVEL_INDEX_ENTRY_SIZE;
+        for &level_size in level_sizes {
+            bytes.extend_from_slice(&(byte_offset as u64).to_le_bytes());
+            bytes.extend_from_slice(&(level_size as u64).to_le_bytes());
+            bytes.extend_from_slice(&(level_size as u64).to_le_bytes());
+            byte_offset += level_size;
+        }
+
+        for (level, &level_size) in level_sizes.iter().enumerate() {
+            bytes.extend(std::iter::repeat(level as u8).take(level_size));
+        }
+
+        bytes
+    }
+
+    #[test]
+    fn test_parse_bc7_mips() {
+        // 8x8 has 2x2 blocks, then 1x1 for the 4x4, 2x2 and 1x1 levels
+        let bytes = build_ktx2(vk::Format::BC7_SRGB_BLOCK, 8, 8, 0, 1, &[64, 16, 16, 16]);
+        let image = parse_ktx2(&bytes).unwrap();
+        assert_eq!(image.format, ImageAssetDataFormat::BC7);
+        assert_eq!(image.color_space, ColorSpace::Srgb);
+        assert_eq!(
+            image.mips,
+            ImageAssetMips::Precomputed { mip_level_count: 4 }
+        );
+        assert_eq!(image.layer_count, 1);
+        assert_eq!(image.data.len(), 64 + 16 * 3);
+        assert_eq!(image.data[64], 1);
+    }
+
+    #[test]
+    fn test_parse_cube_map() {
+        let bytes = build_ktx2(vk::Format::R8G8B8A8_UNORM, 2, 2, 0, 6, &[2 * 2 * 4 * 6]);
+        let image = parse_ktx2(&bytes).unwrap();
+        assert!(image.is_cube_map);
+        assert_eq!(image.layer_count, 6);
+        assert_eq!(image.mips, ImageAssetMips::None);
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        let bytes = build_ktx2(vk::Format::R16G16_SFLOAT, 2, 2, 0, 1, &[16]);
+        assert!(matches!(
+            parse_ktx2(&bytes),
+            Err(ImageContainerError::UnsupportedFormat(_))
+        ));
+
+        // Level size doesn't match the format
+        let bytes = build_ktx2(vk::Format::BC1_RGB_UNORM_BLOCK, 4, 4, 0, 1, &[16]);
+        assert!(matches!(
+            parse_ktx2(&bytes),
+            Err(ImageContainerError::InvalidHeader(_))
+        ));
+
+        let bytes = build_ktx2(vk::Format::BC1_RGB_UNORM_BLOCK, 4, 4, 0, 1, &[8]);
+        assert!(matches!(
+            parse_ktx2(&bytes[..bytes.len() - 1]),
+            Err(ImageContainerError::Truncated { .. })
+        ));
+
+        assert!(matches!(
+            parse_ktx2(b"not a ktx2 file"),
+            Err(ImageContainerError::InvalidHeader(_))
+        ));
+
+        // Sizes that overflow are rejected instead of wrapping
+        let bytes = build_ktx2(
+            vk::Format::R32G32B32A32_SFLOAT,
+            std::u32::MAX,
+            std::u32::MAX,
+            0,
+            1,
+            &[16],
+        );
+        assert!(matches!(
+            parse_ktx2(&bytes),
+            Err(ImageContainerError::InvalidHeader(_))
+        ));
+
+        let bytes = build_ktx2(vk::Format::R8G8B8A8_UNORM, 1, 1, std::u32::MAX, 6, &[4]);
+        assert!(matches!(
+            parse_ktx2(&bytes),
+            Err(ImageContainerError::InvalidHeader(_))
+        ));
+    }
+}
@@ -3,3 +3,20 @@ pub use assets::*;
 
 mod importer;
 pub use importer::*;
+
+mod processing;
+pub use processing::*;
+
+mod compression;
+
+mod container;
+pub use container::ImageContainerError;
+
+mod ktx2;
+pub use ktx2::*;
+
+mod dds;
+pub use dds::*;
+
+mod hdr;
+pub use hdr::*;
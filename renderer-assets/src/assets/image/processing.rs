@@ -0,0 +1,921 @@
+use crate::assets::image::{
+    ColorSpace, HdrImageImporterOptions, ImageAssetData, ImageAssetDataFormat, ImageAssetMips,
+    ImageChannelSource, ImageImporterOptions, ImageLayout, ImageMipGeneration, MipFilter,
+};
+use super::compression::compress_mip_level;
+
+// Number of lobes of the windowed sinc on each side of the center
+const KAISER_LOBES: f32 = 3.0;
+// Larger values reduce ringing at the cost of sharpness
+const KAISER_BETA: f32 = 4.0;
+
+// Largest finite half precision float, larger values are clamped rather than becoming infinity
+const F16_MAX: f32 = 65504.0;
+
+// Upper bound of the samples per axis taken from a panorama for each cube map texel
+const MAX_PANORAMA_SAMPLES_PER_AXIS: u32 = 8;
+
+/// Produced when an image's size doesn't match the layout it should be split into, i.e. a
+/// horizontal strip that isn't 6 times as wide as it is high
+#[derive(Debug, PartialEq)]
+pub struct ImageLayoutError {
+    pub layout: ImageLayout,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl std::error::Error for ImageLayoutError {}
+
+impl core::fmt::Display for ImageLayoutError {
+    fn fmt(
+        &self,
+        fmt: &mut core::fmt::Formatter,
+    ) -> core::fmt::Result {
+        write!(
+            fmt,
+            "A {}x{} image can't be split into layers with layout {:?}",
+            self.width, self.height, self.layout
+        )
+    }
+}
+
+/// Applies importer options to tightly packed RGBA8 data, producing the image asset
+pub fn process_image(
+    width: u32,
+    height: u32,
+    mut data: Vec<u8>,
+    options: &ImageImporterOptions,
+) -> Result<ImageAssetData, ImageLayoutError> {
+    assert_eq!(data.len(), width as usize * height as usize * 4);
+
+    if options.swizzle != ImageImporterOptions::IDENTITY_SWIZZLE {
+        swizzle_channels(&mut data, &options.swizzle);
+    }
+
+    let is_scaled = options.max_resolution.map_or(false, |max_resolution| {
+        fit_within(width, height, max_resolution) != (width, height)
+    });
+
+    // Everything except swizzling is done on linear floating point data
+    if !options.premultiply_alpha
+        && !is_scaled
+        && precomputed_mip_filter(options.mips, options.format).is_none()
+        && options.format == ImageAssetDataFormat::RGBA8
+        && options.layout == ImageLayout::Single
+    {
+        return Ok(ImageAssetData {
+            width,
+            height,
+            color_space: options.color_space,
+            format: ImageAssetDataFormat::RGBA8,
+            mips: runtime_asset_mips(options.mips),
+            layer_count: 1,
+            is_cube_map: false,
+            data,
+        });
+    }
+
+    let mut image = LinearImage::from_rgba8(width, height, &data, options.color_space);
+    if options.premultiply_alpha {
+        image.premultiply_alpha();
+    }
+
+    let color_space = if options.format.supports_srgb() {
+        options.color_space
+    } else {
+        ColorSpace::Linear
+    };
+
+    Ok(build_image_asset(
+        split_layers(image, options.layout)?,
+        options.layout.is_cube_map(),
+        options.max_resolution,
+        options.mips,
+        options.format,
+        color_space,
+    ))
+}
+
+/// Applies importer options to linear RGBA float texels, i.e. decoded from a HDR image
+pub fn process_hdr_image(
+    width: u32,
+    height: u32,
+    texels: Vec<[f32; 4]>,
+    options: &HdrImageImporterOptions,
+) -> Result<ImageAssetData, ImageLayoutError> {
+    assert_eq!(texels.len(), width as usize * height as usize);
+
+    let image = LinearImage {
+        width,
+        height,
+        texels,
+    };
+
+    Ok(build_image_asset(
+        split_layers(image, options.layout)?,
+        options.layout.is_cube_map(),
+        options.max_resolution,
+        options.mips,
+        options.format.into(),
+        ColorSpace::Linear,
+    ))
+}
+
+// Scales the layers to fit max_resolution, then encodes them and their precomputed mips, if any.
+// The data of each mip level contains every layer.
+fn build_image_asset(
+    mut layers: Vec<LinearImage>,
+    is_cube_map: bool,
+    max_resolution: Option<u32>,
+    mips: ImageMipGeneration,
+    format: ImageAssetDataFormat,
+    color_space: ColorSpace,
+) -> ImageAssetData {
+    if let Some(max_resolution) = max_resolution {
+        let (scaled_width, scaled_height) =
+            fit_within(layers[0].width, layers[0].height, max_resolution);
+        if (scaled_width, scaled_height) != (layers[0].width, layers[0].height) {
+            // Scaling can be large, so use the higher quality filter regardless of the mip filter
+            layers = layers
+                .iter()
+                .map(|layer| layer.resample(scaled_width, scaled_height, MipFilter::Kaiser))
+                .collect();
+        }
+    }
+
+    let (width, height) = (layers[0].width, layers[0].height);
+    let encode = |layers: &[LinearImage]| -> Vec<u8> {
+        layers
+            .iter()
+            .flat_map(|layer| layer.encode(format, color_space))
+            .collect()
+    };
+    let mut data = encode(&layers);
+
+    let mips = match precomputed_mip_filter(mips, format) {
+        Some(filter) => {
+            let mut mip_level_count = 1;
+            while layers[0].width > 1 || layers[0].height > 1 {
+                let (mip_width, mip_height) =
+                    ((layers[0].width / 2).max(1), (layers[0].height / 2).max(1));
+                layers = layers
+                    .iter()
+                    .map(|layer| layer.resample(mip_width, mip_height, filter))
+                    .collect();
+                data.extend(encode(&layers));
+                mip_level_count += 1;
+            }
+
+            ImageAssetMips::Precomputed { mip_level_count }
+        }
+        None => runtime_asset_mips(mips),
+    };
+
+    ImageAssetData {
+        width,
+        height,
+        color_space,
+        format,
+        mips,
+        layer_count: layers.len() as u32,
+        is_cube_map,
+        data,
+    }
+}
+
+// Cuts the image into the layers described by the layout, in the order they are stored
+fn split_layers(
+    image: LinearImage,
+    layout: ImageLayout,
+) -> Result<Vec<LinearImage>, ImageLayoutError> {
+    let (width, height) = (image.width, image.height);
+    let layout_error = ImageLayoutError {
+        layout,
+        width,
+        height,
+    };
+
+    let layers = match layout {
+        ImageLayout::Single => vec![image],
+        ImageLayout::Array { layer_count } => {
+            if layer_count == 0 || height % layer_count != 0 {
+                return Err(layout_error);
+            }
+
+            let layer_height = height / layer_count;
+            (0..layer_count)
+                .map(|layer| image.crop(0, layer * layer_height, width, layer_height))
+                .collect()
+        }
+        ImageLayout::CubeHorizontalStrip => {
+            if width != height * 6 {
+                return Err(layout_error);
+            }
+
+            (0..6)
+                .map(|face| image.crop(face * height, 0, height, height))
+                .collect()
+        }
+        ImageLayout::CubeVerticalStrip => {
+            if height != width * 6 {
+                return Err(layout_error);
+            }
+
+            (0..6)
+                .map(|face| image.crop(0, face * width, width, width))
+                .collect()
+        }
+        ImageLayout::CubeHorizontalCross => {
+            if width % 4 != 0 || width / 4 * 3 != height {
+                return Err(layout_error);
+            }
+
+            // Column and row of +X, -X, +Y, -Y, +Z, -Z
+            const FACE_CELLS: [(u32, u32); 6] = [(2, 1), (0, 1), (1, 0), (1, 2), (1, 1), (3, 1)];
+            let face_size = width / 4;
+            FACE_CELLS
+                .iter()
+                .map(|&(column, row)| {
+                    image.crop(column * face_size, row * face_size, face_size, face_size)
+                })
+                .collect()
+        }
+        ImageLayout::CubeEquirectangular { face_size } => {
+            if face_size == 0 {
+                return Err(layout_error);
+            }
+
+            (0..6)
+                .map(|face| cube_face_from_equirectangular(&image, face, face_size))
+                .collect()
+        }
+    };
+
+    Ok(layers)
+}
+
+// Direction from the center of the cube through the given point on a face, where s and t go from
+// -1 to 1 across the face. This is the inverse of how vulkan selects a face and its coordinates.
+fn cube_face_direction(
+    face: u32,
+    s: f32,
+    t: f32,
+) -> [f32; 3] {
+    match face {
+        0 => [1.0, -t, -s],
+        1 => [-1.0, -t, s],
+        2 => [s, 1.0, t],
+        3 => [s, -1.0, -t],
+        4 => [s, -t, 1.0],
+        _ => [-s, -t, -1.0],
+    }
+}
+
+// Each face texel averages a grid of samples so that downsampling a large panorama doesn't alias
+fn cube_face_from_equirectangular(
+    panorama: &LinearImage,
+    face: u32,
+    face_size: u32,
+) -> LinearImage {
+    // A face covers a quarter of the panorama's width
+    let samples_per_axis = (panorama.width / (face_size * 4))
+        .max(1)
+        .min(MAX_PANORAMA_SAMPLES_PER_AXIS);
+    let sample_weight = 1.0 / (samples_per_axis * samples_per_axis) as f32;
+
+    let mut texels = Vec::with_capacity(face_size as usize * face_size as usize);
+    for y in 0..face_size {
+        for x in 0..face_size {
+            let mut texel = [0.0; 4];
+            for sample_y in 0..samples_per_axis {
+                for sample_x in 0..samples_per_axis {
+                    let to_face_coordinate = |texel_index: u32, sample_index: u32| {
+                        let offset = (sample_index as f32 + 0.5) / samples_per_axis as f32;
+                        2.0 * (texel_index as f32 + offset) / face_size as f32 - 1.0
+                    };
+                    let direction = cube_face_direction(
+                        face,
+                        to_face_coordinate(x, sample_x),
+                        to_face_coordinate(y, sample_y),
+                    );
+
+                    let sample = panorama.sample_equirectangular(direction);
+                    for channel in 0..4 {
+                        texel[channel] += sample[channel] * sample_weight;
+                    }
+                }
+            }
+            texels.push(texel);
+        }
+    }
+
+    LinearImage {
+        width: face_size,
+        height: face_size,
+        texels,
+    }
+}
+
+fn precomputed_mip_filter(
+    mips: ImageMipGeneration,
+    format: ImageAssetDataFormat,
+) -> Option<MipFilter> {
+    match mips {
+        ImageMipGeneration::Precomputed(filter) => Some(filter),
+        // Block compressed images can't generate mips at runtime
+        ImageMipGeneration::Runtime if format.is_block_compressed() => Some(MipFilter::Kaiser),
+        _ => None,
+    }
+}
+
+fn runtime_asset_mips(mips: ImageMipGeneration) -> ImageAssetMips {
+    match mips {
+        ImageMipGeneration::None => ImageAssetMips::None,
+        ImageMipGeneration::Runtime => ImageAssetMips::Runtime,
+        ImageMipGeneration::Precomputed(_) => unreachable!(),
+    }
+}
+
+// Largest size with the same aspect ratio where neither dimension exceeds max_resolution
+fn fit_within(
+    width: u32,
+    height: u32,
+    max_resolution: u32,
+) -> (u32, u32) {
+    let max_resolution = max_resolution.max(1);
+    let max_dimension = width.max(height);
+    if max_dimension <= max_resolution {
+        return (width, height);
+    }
+
+    let scale = |dimension: u32| {
+        ((dimension as u64 * max_resolution as u64) / max_dimension as u64).max(1) as u32
+    };
+    (scale(width), scale(height))
+}
+
+fn swizzle_channels(
+    data: &mut [u8],
+    swizzle: &[ImageChannelSource; 4],
+) {
+    for texel in data.chunks_exact_mut(4) {
+        let source = [texel[0], texel[1], texel[2], texel[3]];
+        for (channel, channel_source) in texel.iter_mut().zip(swizzle) {
+            *channel = match channel_source {
+                ImageChannelSource::R => source[0],
+                ImageChannelSource::G => source[1],
+                ImageChannelSource::B => source[2],
+                ImageChannelSource::A => source[3],
+                ImageChannelSource::Zero => 0,
+                ImageChannelSource::One => 255,
+            };
+        }
+    }
+}
+
+fn srgb_to_linear(value: f32) -> f32 {
+    if value <= 0.04045 {
+        value / 12.92
+    } else {
+        ((value + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+fn linear_to_srgb(value: f32) -> f32 {
+    if value <= 0.0031308 {
+        value * 12.92
+    } else {
+        1.055 * value.powf(1.0 / 2.4) - 0.055
+    }
+}
+
+// Zeroth order modified bessel function of the first kind, used by the kaiser window
+fn bessel_i0(x: f32) -> f32 {
+    let mut sum = 1.0;
+    let mut term = 1.0;
+    let half_x_squared = (x * 0.5) * (x * 0.5);
+    for k in 1..32 {
+        term *= half_x_squared / (k * k) as f32;
+        sum += term;
+        if term < sum * 1e-7 {
+            break;
+        }
+    }
+    sum
+}
+
+fn sinc(x: f32) -> f32 {
+    if x.abs() < 1e-5 {
+        1.0
+    } else {
+        let x = x * std::f32::consts::PI;
+        x.sin() / x
+    }
+}
+
+fn kaiser(x: f32) -> f32 {
+    if x.abs() >= KAISER_LOBES {
+        return 0.0;
+    }
+
+    let window_x = x / KAISER_LOBES;
+    sinc(x) * bessel_i0(KAISER_BETA * (1.0 - window_x * window_x).sqrt()) / bessel_i0(KAISER_BETA)
+}
+
+// For each destination texel, the source texels that contribute to it and their normalized weights
+fn filter_weights(
+    src_size: u32,
+    dst_size: u32,
+    filter: MipFilter,
+) -> Vec<Vec<(usize, f32)>> {
+    let ratio = src_size as f32 / dst_size as f32;
+
+    (0..dst_size)
+        .map(|dst_index| {
+            let mut weights: Vec<(usize, f32)> = match filter {
+                MipFilter::Box => {
+                    let start = dst_index as f32 * ratio;
+                    let end = (dst_index + 1) as f32 * ratio;
+                    (start.floor() as u32..(end.ceil() as u32).min(src_size))
+                        .map(|src_index| {
+                            let src_index_f = src_index as f32;
+                            let overlap = end.min(src_index_f + 1.0) - start.max(src_index_f);
+                            (src_index as usize, overlap.max(0.0))
+                        })
+                        .collect()
+                }
+                MipFilter::Kaiser => {
+                    // When scaling down, stretch the kernel to cover all of the source texels
+                    let scale = ratio.max(1.0);
+                    let center = (dst_index as f32 + 0.5) * ratio;
+                    let radius = KAISER_LOBES * scale;
+                    let first = (center - radius).floor() as i64;
+                    let last = (center + radius).ceil() as i64;
+                    (first..=last)
+                        .map(|src_index| {
+                            let weight = kaiser((src_index as f32 + 0.5 - center) / scale);
+                            let clamped_index = src_index.max(0).min(src_size as i64 - 1);
+                            (clamped_index as usize, weight)
+                        })
+                        .collect()
+                }
+            };
+
+            let total_weight: f32 = weights.iter().map(|(_, weight)| weight).sum();
+            if total_weight.abs() > std::f32::EPSILON {
+                for (_, weight) in &mut weights {
+                    *weight /= total_weight;
+                }
+            }
+
+            weights
+        })
+        .collect()
+}
+
+// RGBA texels with linear color, used for filtering
+struct LinearImage {
+    width: u32,
+    height: u32,
+    texels: Vec<[f32; 4]>,
+}
+
+impl LinearImage {
+    fn from_rgba8(
+        width: u32,
+        height: u32,
+        data: &[u8],
+        color_space: ColorSpace,
+    ) -> Self {
+        let texels = data
+            .chunks_exact(4)
+            .map(|texel| {
+                let mut linear = [0.0; 4];
+                for channel in 0..4 {
+                    let value = texel[channel] as f32 / 255.0;
+                    // Alpha is always linear
+                    linear[channel] = match color_space {
+                        ColorSpace::Srgb if channel < 3 => srgb_to_linear(value),
+                        _ => value,
+                    };
+                }
+                linear
+            })
+            .collect();
+
+        LinearImage {
+            width,
+            height,
+            texels,
+        }
+    }
+
+    fn to_rgba8(
+        &self,
+        color_space: ColorSpace,
+    ) -> Vec<u8> {
+        let mut data = Vec::with_capacity(self.texels.len() * 4);
+        for texel in &self.texels {
+            for channel in 0..4 {
+                let value = texel[channel].max(0.0).min(1.0);
+                let value = match color_space {
+                    ColorSpace::Srgb if channel < 3 => linear_to_srgb(value),
+                    _ => value,
+                };
+                data.push((value * 255.0).round() as u8);
+            }
+        }
+
+        data
+    }
+
+    // Float formats keep values outside of 0..1, except negative values from filter ringing
+    fn encode(
+        &self,
+        format: ImageAssetDataFormat,
+        color_space: ColorSpace,
+    ) -> Vec<u8> {
+        match format {
+            ImageAssetDataFormat::RGBA16F => {
+                let mut data = Vec::with_capacity(self.texels.len() * 8);
+                for value in self.texels.iter().flat_map(|texel| texel.iter()) {
+                    let value = half::f16::from_f32(value.max(0.0).min(F16_MAX));
+                    data.extend_from_slice(&value.to_bits().to_le_bytes());
+                }
+                data
+            }
+            ImageAssetDataFormat::RGBA32F => {
+                let mut data = Vec::with_capacity(self.texels.len() * 16);
+                for value in self.texels.iter().flat_map(|texel| texel.iter()) {
+                    data.extend_from_slice(&value.max(0.0).to_le_bytes());
+                }
+                data
+            }
+            _ => compress_mip_level(self.width, self.height, self.to_rgba8(color_space), format),
+        }
+    }
+
+    fn crop(
+        &self,
+        x: u32,
+        y: u32,
+        width: u32,
+        height: u32,
+    ) -> LinearImage {
+        let mut texels = Vec::with_capacity(width as usize * height as usize);
+        for row in y..y + height {
+            let row_start = (row * self.width + x) as usize;
+            texels.extend_from_slice(&self.texels[row_start..row_start + width as usize]);
+        }
+
+        LinearImage {
+            width,
+            height,
+            texels,
+        }
+    }
+
+    // Bilinear sample of the panorama in the given direction. Wraps horizontally and clamps at the
+    // poles.
+    fn sample_equirectangular(
+        &self,
+        direction: [f32; 3],
+    ) -> [f32; 4] {
+        let [x, y, z] = direction;
+        let length = (x * x + y * y + z * z).sqrt();
+        let u = 0.5 + x.atan2(-z) / (2.0 * std::f32::consts::PI);
+        let v = (y / length).max(-1.0).min(1.0).acos() / std::f32::consts::PI;
+
+        let texel_x = u * self.width as f32 - 0.5;
+        let texel_y = (v * self.height as f32 - 0.5)
+            .max(0.0)
+            .min((self.height - 1) as f32);
+        let (x0, y0) = (texel_x.floor(), texel_y.floor());
+        let (fraction_x, fraction_y) = (texel_x - x0, texel_y - y0);
+
+        let column = |x: f32| (x as i64).rem_euclid(self.width as i64) as usize;
+        let row = |y: f32| (y as usize).min(self.height as usize - 1);
+        let texel = |x: f32, y: f32| self.texels[row(y) * self.width as usize + column(x)];
+
+        let mut sample = [0.0; 4];
+        let corners = [
+            (texel(x0, y0), (1.0 - fraction_x) * (1.0 - fraction_y)),
+            (texel(x0 + 1.0, y0), fraction_x * (1.0 - fraction_y)),
+            (texel(x0, y0 + 1.0), (1.0 - fraction_x) * fraction_y),
+            (texel(x0 + 1.0, y0 + 1.0), fraction_x * fraction_y),
+        ];
+        for (corner, weight) in &corners {
+            for channel in 0..4 {
+                sample[channel] += corner[channel] * weight;
+            }
+        }
+
+        sample
+    }
+
+    fn premultiply_alpha(&mut self) {
+        for texel in &mut self.texels {
+            let alpha = texel[3];
+            texel[0] *= alpha;
+            texel[1] *= alpha;
+            texel[2] *= alpha;
+        }
+    }
+
+    // Separable, so resample horizontally and then vertically
+    fn resample(
+        &self,
+        dst_width: u32,
+        dst_height: u32,
+        filter: MipFilter,
+    ) -> LinearImage {
+        let horizontal_weights = filter_weights(self.width, dst_width, filter);
+        let mut horizontal = Vec::with_capacity(dst_width as usize * self.height as usize);
+        for y in 0..self.height as usize {
+            let row = &self.texels[y * self.width as usize..(y + 1) * self.width as usize];
+            for weights in &horizontal_weights {
+                horizontal.push(weighted_sum(weights, |x| row[x]));
+            }
+        }
+
+        let vertical_weights = filter_weights(self.height, dst_height, filter);
+        let mut texels = Vec::with_capacity(dst_width as usize * dst_height as usize);
+        for weights in &vertical_weights {
+            for x in 0..dst_width as usize {
+                texels.push(weighted_sum(weights, |y| {
+                    horizontal[y * dst_width as usize + x]
+                }));
+            }
+        }
+
+        LinearImage {
+            width: dst_width,
+            height: dst_height,
+            texels,
+        }
+    }
+}
+
+fn weighted_sum<F: Fn(usize) -> [f32; 4]>(
+    weights: &[(usize, f32)],
+    texel: F,
+) -> [f32; 4] {
+    let mut sum = [0.0; 4];
+    for &(index, weight) in weights {
+        let texel = texel(index);
+        for channel in 0..4 {
+            sum[channel] += texel[channel] * weight;
+        }
+    }
+    sum
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::assets::image::HdrImageFormat;
+
+    fn linear_options(mips: ImageMipGeneration) -> ImageImporterOptions {
+        ImageImporterOptions {
+            mips,
+            ..ImageImporterOptions::with_color_space(ColorSpace::Linear)
+        }
+    }
+
+    #[test]
+    fn test_default_options_keep_data() {
+        let data = vec![10, 20, 30, 40, 50, 60, 70, 80];
+        let image = process_image(2, 1, data.clone(), &ImageImporterOptions::default()).unwrap();
+        assert_eq!(image.data, data);
+        assert_eq!(image.mips, ImageAssetMips::Runtime);
+    }
+
+    #[test]
+    fn test_precomputed_box_mips() {
+        // 4x2 image, left half black and right half white
+        let mut data = vec![];
+        for _ in 0..2 {
+            data.extend(&[
+                0, 0, 0, 255, 0, 0, 0, 255, 255, 255, 255, 255, 255, 255, 255, 255,
+            ]);
+        }
+
+        let image = process_image(
+            4,
+            2,
+            data,
+            &linear_options(ImageMipGeneration::Precomputed(MipFilter::Box)),
+        )
+        .unwrap();
+        assert_eq!(
+            image.mips,
+            ImageAssetMips::Precomputed { mip_level_count: 3 }
+        );
+
+        // 4x2 + 2x1 + 1x1 texels
+        assert_eq!(image.data.len(), (8 + 2 + 1) * 4);
+        assert_eq!(&image.data[32..40], &[0, 0, 0, 255, 255, 255, 255, 255]);
+        assert_eq!(&image.data[40..44], &[128, 128, 128, 255]);
+    }
+
+    #[test]
+    fn test_kaiser_preserves_constant_color() {
+        let data = [60, 120, 180, 255].repeat(7 * 5);
+        let image = process_image(
+            7,
+            5,
+            data,
+            &linear_options(ImageMipGeneration::Precomputed(MipFilter::Kaiser)),
+        )
+        .unwrap();
+        for texel in image.data.chunks_exact(4) {
+            assert_eq!(texel, &[60, 120, 180, 255]);
+        }
+    }
+
+    #[test]
+    fn test_max_resolution() {
+        let options = ImageImporterOptions {
+            max_resolution: Some(4),
+            ..linear_options(ImageMipGeneration::None)
+        };
+        let image = process_image(16, 8, vec![255; 16 * 8 * 4], &options).unwrap();
+        assert_eq!((image.width, image.height), (4, 2));
+        assert_eq!(image.data.len(), 4 * 2 * 4);
+        assert_eq!(image.mips, ImageAssetMips::None);
+    }
+
+    #[test]
+    fn test_block_compressed_format() {
+        let options = ImageImporterOptions {
+            format: ImageAssetDataFormat::BC5,
+            mips: ImageMipGeneration::None,
+            ..ImageImporterOptions::default()
+        };
+
+        // 6x6 rounds up to 2x2 blocks of 16 bytes each
+        let image = process_image(6, 6, vec![128; 6 * 6 * 4], &options).unwrap();
+        assert_eq!(image.format, ImageAssetDataFormat::BC5);
+        assert_eq!(image.data.len(), 2 * 2 * 16);
+
+        // BC5 has no sRGB variant
+        assert_eq!(image.color_space, ColorSpace::Linear);
+    }
+
+    #[test]
+    fn test_hdr_image() {
+        let options = HdrImageImporterOptions {
+            format: HdrImageFormat::RGBA32F,
+            mips: ImageMipGeneration::Precomputed(MipFilter::Box),
+            max_resolution: None,
+            layout: ImageLayout::Single,
+        };
+
+        // Values above 1 are kept
+        let image = process_hdr_image(2, 1, vec![[1000.0, 0.5, 0.0, 1.0]; 2], &options).unwrap();
+        assert_eq!(image.format, ImageAssetDataFormat::RGBA32F);
+        assert_eq!(image.color_space, ColorSpace::Linear);
+        assert_eq!(
+            image.mips,
+            ImageAssetMips::Precomputed { mip_level_count: 2 }
+        );
+        assert_eq!(image.data.len(), (2 + 1) * 16);
+        assert_eq!(&image.data[32..36], &1000.0f32.to_le_bytes());
+    }
+
+    #[test]
+    fn test_hdr_image_half_float() {
+        let options = HdrImageImporterOptions {
+            format: HdrImageFormat::RGBA16F,
+            mips: ImageMipGeneration::None,
+            max_resolution: None,
+            layout: ImageLayout::Single,
+        };
+
+        // Too large for half precision, so it's clamped instead of becoming infinity
+        let image = process_hdr_image(1, 1, vec![[1.0e6, 2.0, -1.0, 1.0]], &options).unwrap();
+        let values: Vec<f32> = image
+            .data
+            .chunks_exact(2)
+            .map(|bytes| half::f16::from_bits(u16::from_le_bytes([bytes[0], bytes[1]])).to_f32())
+            .collect();
+        assert_eq!(values, vec![65504.0, 2.0, 0.0, 1.0]);
+    }
+
+    #[test]
+    fn test_cube_horizontal_cross() {
+        // 4x3 with 1x1 faces, each texel's red channel is its index
+        let data: Vec<u8> = (0..12).flat_map(|index| vec![index, 0, 0, 255]).collect();
+        let options = ImageImporterOptions {
+            layout: ImageLayout::CubeHorizontalCross,
+            ..linear_options(ImageMipGeneration::None)
+        };
+
+        let image = process_image(4, 3, data, &options).unwrap();
+        assert!(image.is_cube_map);
+        assert_eq!(image.layer_count, 6);
+        assert_eq!((image.width, image.height), (1, 1));
+
+        let faces: Vec<u8> = image.data.chunks_exact(4).map(|texel| texel[0]).collect();
+        assert_eq!(faces, vec![6, 4, 1, 9, 5, 7]);
+    }
+
+    #[test]
+    fn test_array_mips_contain_every_layer() {
+        // Two 2x2 layers stacked vertically, the first black and the second white
+        let mut data = [0, 0, 0, 255].repeat(4);
+        data.extend([255, 255, 255, 255].repeat(4));
+        let options = ImageImporterOptions {
+            layout: ImageLayout::Array { layer_count: 2 },
+            ..linear_options(ImageMipGeneration::Precomputed(MipFilter::Box))
+        };
+
+        let image = process_image(2, 4, data, &options).unwrap();
+        assert!(!image.is_cube_map);
+        assert_eq!(image.layer_count, 2);
+        assert_eq!(
+            image.mips,
+            ImageAssetMips::Precomputed { mip_level_count: 2 }
+        );
+
+        // Both layers of the first level, then both layers of the second
+        assert_eq!(image.data.len(), (4 + 4 + 1 + 1) * 4);
+        assert_eq!(&image.data[32..40], &[0, 0, 0, 255, 255, 255, 255, 255]);
+    }
+
+    #[test]
+    fn test_layout_size_mismatch() {
+        let options = ImageImporterOptions {
+            layout: ImageLayout::CubeHorizontalStrip,
+            ..ImageImporterOptions::default()
+        };
+
+        let result = process_image(5, 1, vec![0; 5 * 4], &options);
+        assert_eq!(
+            result.unwrap_err(),
+            ImageLayoutError {
+                layout: ImageLayout::CubeHorizontalStrip,
+                width: 5,
+                height: 1,
+            }
+        );
+    }
+
+    #[test]
+    fn test_cube_from_equirectangular() {
+        // The top half is red and the bottom half is blue
+        let mut texels = vec![[1.0, 0.0, 0.0, 1.0]; 16 * 4];
+        texels.extend(vec![[0.0, 0.0, 1.0, 1.0]; 16 * 4]);
+        let options = HdrImageImporterOptions {
+            format: HdrImageFormat::RGBA32F,
+            mips: ImageMipGeneration::None,
+            max_resolution: None,
+            layout: ImageLayout::CubeEquirectangular { face_size: 2 },
+        };
+
+        let image = process_hdr_image(16, 8, texels, &options).unwrap();
+        assert!(image.is_cube_map);
+        assert_eq!(image.layer_count, 6);
+
+        let red_channel: Vec<f32> = image
+            .data
+            .chunks_exact(16)
+            .map(|texel| f32::from_le_bytes([texel[0], texel[1], texel[2], texel[3]]))
+            .collect();
+
+        let is_near = |values: &[f32], expected: &[f32]| {
+            values
+                .iter()
+                .zip(expected)
+                .all(|(value, expected)| (value - expected).abs() < 1e-5)
+        };
+
+        // +Y faces up and -Y faces down
+        assert!(is_near(&red_channel[8..12], &[1.0; 4]));
+        assert!(is_near(&red_channel[12..16], &[0.0; 4]));
+
+        // Side faces have the horizon in the middle
+        for face in &[0, 1, 4, 5] {
+            let face_texels = &red_channel[face * 4..face * 4 + 4];
+            assert!(is_near(face_texels, &[1.0, 1.0, 0.0, 0.0]));
+        }
+    }
+
+    #[test]
+    fn test_swizzle_and_premultiply() {
+        let options = ImageImporterOptions {
+            swizzle: [
+                ImageChannelSource::B,
+                ImageChannelSource::Zero,
+                ImageChannelSource::R,
+                ImageChannelSource::G,
+            ],
+            premultiply_alpha: true,
+            ..linear_options(ImageMipGeneration::Runtime)
+        };
+
+        let image = process_image(1, 1, vec![255, 51, 102, 0], &options).unwrap();
+        // Alpha comes from green (0.2), red from blue (0.4) and blue from red (1.0)
+        assert_eq!(image.data, vec![20, 0, 51, 51]);
+    }
+}
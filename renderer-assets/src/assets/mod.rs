@@ -2,6 +2,22 @@ mod image;
 pub use self::image::ImageAssetData;
 pub use self::image::ImageAsset;
 pub use self::image::ColorSpace;
+pub use self::image::ImageAssetMips;
+pub use self::image::ImageAssetDataFormat;
+pub use self::image::ImageImporterOptions;
+pub use self::image::ImageMipGeneration;
+pub use self::image::MipFilter;
+pub use self::image::ImageChannelSource;
+pub use self::image::ImageLayout;
+pub use self::image::ImageLayoutError;
+pub use self::image::process_image;
+pub use self::image::process_hdr_image;
+pub use self::image::HdrImageImporterOptions;
+pub use self::image::HdrImageFormat;
+pub use self::image::ImageContainerError;
+pub use self::image::parse_ktx2;
+pub use self::image::parse_dds;
+pub use self::image::DdsImporterOptions;
 
 mod shader;
 pub use shader::ShaderAssetData;
@@ -0,0 +1,281 @@
+//! Compares rendered images against known-good ("golden") PNGs for image-based regression tests.
+//!
+//! Render into a `VkOffscreenTarget`, copy the result back with `image_utils::readback_image`, and
+//! pass it to `compare_to_golden_png`. On failure, `<name>.actual.png` and `<name>.diff.png` are
+//! written next to the golden image. Set `RENDERER_UPDATE_GOLDEN_IMAGES=1` to overwrite the golden
+//! images with the rendered results instead of comparing.
+
+use crate::image_utils::ReadbackImage;
+use std::path::{Path, PathBuf};
+
+const UPDATE_GOLDEN_IMAGES_ENV_VAR: &str = "RENDERER_UPDATE_GOLDEN_IMAGES";
+
+/// How different a rendered image may be from the golden image and still pass
+#[derive(Copy, Clone, Debug)]
+pub struct GoldenImageTolerance {
+    // A pixel mismatches if any channel differs by more than this
+    pub max_channel_difference: u8,
+
+    // The comparison fails if more than this many pixels mismatch
+    pub max_mismatched_pixels: usize,
+}
+
+impl Default for GoldenImageTolerance {
+    fn default() -> Self {
+        // Allow for small differences in rasterization and rounding between drivers
+        GoldenImageTolerance {
+            max_channel_difference: 2,
+            max_mismatched_pixels: 0,
+        }
+    }
+}
+
+/// The result of comparing two RGBA8 images of the same size
+pub struct ImageDiff {
+    pub mismatched_pixel_count: usize,
+    pub max_channel_difference: u8,
+
+    // RGBA8. Mismatched pixels are red, other pixels are a darkened greyscale of the actual image
+    pub diff_rgba8: Vec<u8>,
+}
+
+/// Compares two RGBA8 images pixel by pixel
+pub fn diff_rgba8(
+    actual: &[u8],
+    expected: &[u8],
+    max_channel_difference: u8,
+) -> ImageDiff {
+    assert_eq!(actual.len(), expected.len());
+    assert_eq!(actual.len() % 4, 0);
+
+    let mut mismatched_pixel_count = 0;
+    let mut largest_channel_difference = 0;
+    let mut diff_rgba8 = Vec::with_capacity(actual.len());
+
+    for (actual_pixel, expected_pixel) in actual.chunks_exact(4).zip(expected.chunks_exact(4)) {
+        let pixel_difference = actual_pixel
+            .iter()
+            .zip(expected_pixel)
+            .map(|(a, e)| (*a as i32 - *e as i32).abs() as u8)
+            .max()
+            .unwrap();
+
+        largest_channel_difference = largest_channel_difference.max(pixel_difference);
+
+        if pixel_difference > max_channel_difference {
+            mismatched_pixel_count += 1;
+            diff_rgba8.extend_from_slice(&[255, 0, 0, 255]);
+        } else {
+            let luminance =
+                (actual_pixel[0] as u32 + actual_pixel[1] as u32 + actual_pixel[2] as u32) / 3;
+            let grey = (luminance / 4) as u8;
+            diff_rgba8.extend_from_slice(&[grey, grey, grey, 255]);
+        }
+    }
+
+    ImageDiff {
+        mismatched_pixel_count,
+        max_channel_difference: largest_channel_difference,
+        diff_rgba8,
+    }
+}
+
+/// Represents an error from comparing against a golden image
+#[derive(Debug)]
+pub enum GoldenImageError {
+    ImageError(image::ImageError),
+
+    // The golden image doesn't exist. The rendered image was written to `actual_path`.
+    MissingGolden {
+        golden_path: PathBuf,
+        actual_path: PathBuf,
+    },
+
+    SizeMismatch {
+        actual_size: (u32, u32),
+        golden_size: (u32, u32),
+        actual_path: PathBuf,
+    },
+
+    Mismatch {
+        mismatched_pixel_count: usize,
+        max_channel_difference: u8,
+        actual_path: PathBuf,
+        diff_path: PathBuf,
+    },
+}
+
+impl std::error::Error for GoldenImageError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match *self {
+            GoldenImageError::ImageError(ref e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl core::fmt::Display for GoldenImageError {
+    fn fmt(
+        &self,
+        fmt: &mut core::fmt::Formatter,
+    ) -> core::fmt::Result {
+        match *self {
+            GoldenImageError::ImageError(ref e) => e.fmt(fmt),
+            GoldenImageError::MissingGolden {
+                ref golden_path,
+                ref actual_path,
+            } => write!(
+                fmt,
+                "Golden image {:?} does not exist, rendered image written to {:?}. Set {}=1 to accept it.",
+                golden_path, actual_path, UPDATE_GOLDEN_IMAGES_ENV_VAR
+            ),
+            GoldenImageError::SizeMismatch {
+                actual_size,
+                golden_size,
+                ref actual_path,
+            } => write!(
+                fmt,
+                "Rendered image is {:?} but golden image is {:?}, rendered image written to {:?}",
+                actual_size, golden_size, actual_path
+            ),
+            GoldenImageError::Mismatch {
+                mismatched_pixel_count,
+                max_channel_difference,
+                ref actual_path,
+                ref diff_path,
+            } => write!(
+                fmt,
+                "{} pixels differ from the golden image (max channel difference {}), see {:?} and {:?}",
+                mismatched_pixel_count, max_channel_difference, actual_path, diff_path
+            ),
+        }
+    }
+}
+
+impl From<image::ImageError> for GoldenImageError {
+    fn from(result: image::ImageError) -> Self {
+        GoldenImageError::ImageError(result)
+    }
+}
+
+// golden.png -> golden.<suffix>.png
+fn sibling_path(
+    golden_path: &Path,
+    suffix: &str,
+) -> PathBuf {
+    let stem = golden_path
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    golden_path.with_file_name(format!("{}.{}.png", stem, suffix))
+}
+
+fn save_rgba8(
+    path: &Path,
+    data: &[u8],
+    width: u32,
+    height: u32,
+) -> Result<(), GoldenImageError> {
+    image::save_buffer(path, data, width, height, image::ColorType::Rgba8)?;
+    Ok(())
+}
+
+/// Compares a rendered image against a golden PNG. The rendered image is converted to RGBA8 with
+/// `ReadbackImage::to_rgba8` first.
+pub fn compare_to_golden_png<P: AsRef<Path>>(
+    actual: &ReadbackImage,
+    golden_path: P,
+    tolerance: &GoldenImageTolerance,
+) -> Result<(), GoldenImageError> {
+    let golden_path = golden_path.as_ref();
+    let actual_rgba8 = actual.to_rgba8();
+    let actual_path = sibling_path(golden_path, "actual");
+
+    let update_golden_images = std::env::var(UPDATE_GOLDEN_IMAGES_ENV_VAR)
+        .map(|value| value == "1")
+        .unwrap_or(false);
+    if update_golden_images {
+        log::info!("Updating golden image {:?}", golden_path);
+        return save_rgba8(golden_path, &actual_rgba8, actual.width, actual.height);
+    }
+
+    if !golden_path.exists() {
+        save_rgba8(&actual_path, &actual_rgba8, actual.width, actual.height)?;
+        return Err(GoldenImageError::MissingGolden {
+            golden_path: golden_path.to_path_buf(),
+            actual_path,
+        });
+    }
+
+    let golden = image::open(golden_path)?.to_rgba();
+    if golden.dimensions() != (actual.width, actual.height) {
+        save_rgba8(&actual_path, &actual_rgba8, actual.width, actual.height)?;
+        return Err(GoldenImageError::SizeMismatch {
+            actual_size: (actual.width, actual.height),
+            golden_size: golden.dimensions(),
+            actual_path,
+        });
+    }
+
+    let diff = diff_rgba8(&actual_rgba8, &golden, tolerance.max_channel_difference);
+    if diff.mismatched_pixel_count > tolerance.max_mismatched_pixels {
+        let diff_path = sibling_path(golden_path, "diff");
+        save_rgba8(&actual_path, &actual_rgba8, actual.width, actual.height)?;
+        save_rgba8(&diff_path, &diff.diff_rgba8, actual.width, actual.height)?;
+        return Err(GoldenImageError::Mismatch {
+            mismatched_pixel_count: diff.mismatched_pixel_count,
+            max_channel_difference: diff.max_channel_difference,
+            actual_path,
+            diff_path,
+        });
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::image_utils::ReadbackFormat;
+
+    #[test]
+    fn test_diff_rgba8() {
+        let expected = [10, 20, 30, 255, 100, 100, 100, 255];
+
+        let diff = diff_rgba8(&expected, &expected, 0);
+        assert_eq!(diff.mismatched_pixel_count, 0);
+        assert_eq!(diff.max_channel_difference, 0);
+
+        // Within tolerance
+        let actual = [12, 20, 30, 255, 100, 99, 100, 255];
+        let diff = diff_rgba8(&actual, &expected, 2);
+        assert_eq!(diff.mismatched_pixel_count, 0);
+        assert_eq!(diff.max_channel_difference, 2);
+
+        // Second pixel is off
+        let actual = [10, 20, 30, 255, 100, 150, 100, 255];
+        let diff = diff_rgba8(&actual, &expected, 2);
+        assert_eq!(diff.mismatched_pixel_count, 1);
+        assert_eq!(diff.max_channel_difference, 50);
+        assert_eq!(&diff.diff_rgba8[4..8], &[255, 0, 0, 255]);
+        assert_ne!(&diff.diff_rgba8[0..4], &[255, 0, 0, 255]);
+    }
+
+    #[test]
+    fn test_rgba16f_to_rgba8() {
+        // 0.0, 0.5, 1.0, 2.0 as half floats
+        let halfs: [u16; 4] = [0x0000, 0x3800, 0x3C00, 0x4000];
+        let image = ReadbackImage {
+            width: 1,
+            height: 1,
+            format: ReadbackFormat::Rgba16F,
+            data: halfs
+                .iter()
+                .flat_map(|x| x.to_le_bytes().to_vec())
+                .collect(),
+        };
+
+        assert_eq!(image.to_rgba_f32(), vec![0.0, 0.5, 1.0, 2.0]);
+        assert_eq!(image.to_rgba8(), vec![0, 128, 255, 255]);
+    }
+}
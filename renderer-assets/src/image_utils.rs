@@ -17,6 +17,100 @@ pub enum ColorSpace {
     Linear,
 }
 
+// Layout of the texel data of a DecodedTexture. All formats are stored in the image as is.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum DecodedTextureFormat {
+    RGBA8,
+    // Half and single precision floats, always linear
+    RGBA16F,
+    RGBA32F,
+    BC1,
+    BC3,
+    BC4,
+    BC5,
+    BC7,
+}
+
+impl DecodedTextureFormat {
+    pub fn is_block_compressed(&self) -> bool {
+        match self {
+            DecodedTextureFormat::RGBA8
+            | DecodedTextureFormat::RGBA16F
+            | DecodedTextureFormat::RGBA32F => false,
+            _ => true,
+        }
+    }
+
+    // Float formats, BC4 and BC5 only have linear variants
+    pub fn vk_format(
+        &self,
+        color_space: ColorSpace,
+    ) -> vk::Format {
+        match (self, color_space) {
+            (DecodedTextureFormat::RGBA8, ColorSpace::Linear) => vk::Format::R8G8B8A8_UNORM,
+            (DecodedTextureFormat::RGBA8, ColorSpace::Srgb) => vk::Format::R8G8B8A8_SRGB,
+            (DecodedTextureFormat::RGBA16F, _) => vk::Format::R16G16B16A16_SFLOAT,
+            (DecodedTextureFormat::RGBA32F, _) => vk::Format::R32G32B32A32_SFLOAT,
+            (DecodedTextureFormat::BC1, ColorSpace::Linear) => vk::Format::BC1_RGB_UNORM_BLOCK,
+            (DecodedTextureFormat::BC1, ColorSpace::Srgb) => vk::Format::BC1_RGB_SRGB_BLOCK,
+            (DecodedTextureFormat::BC3, ColorSpace::Linear) => vk::Format::BC3_UNORM_BLOCK,
+            (DecodedTextureFormat::BC3, ColorSpace::Srgb) => vk::Format::BC3_SRGB_BLOCK,
+            (DecodedTextureFormat::BC4, _) => vk::Format::BC4_UNORM_BLOCK,
+            (DecodedTextureFormat::BC5, _) => vk::Format::BC5_UNORM_BLOCK,
+            (DecodedTextureFormat::BC7, ColorSpace::Linear) => vk::Format::BC7_UNORM_BLOCK,
+            (DecodedTextureFormat::BC7, ColorSpace::Srgb) => vk::Format::BC7_SRGB_BLOCK,
+        }
+    }
+
+    // Width and height in texels of a block. Uncompressed formats have 1x1 blocks
+    pub fn block_extent(&self) -> u32 {
+        if self.is_block_compressed() {
+            4
+        } else {
+            1
+        }
+    }
+
+    pub fn bytes_per_block(&self) -> u32 {
+        match self {
+            DecodedTextureFormat::RGBA8 => 4,
+            DecodedTextureFormat::RGBA16F => 8,
+            DecodedTextureFormat::RGBA32F => 16,
+            DecodedTextureFormat::BC1 | DecodedTextureFormat::BC4 => 8,
+            DecodedTextureFormat::BC3 | DecodedTextureFormat::BC5 | DecodedTextureFormat::BC7 => 16,
+        }
+    }
+
+    // Size of the data of a single mip level. Partial blocks at the edges take a whole block.
+    pub fn mip_level_size_bytes(
+        &self,
+        width: u32,
+        height: u32,
+    ) -> usize {
+        self.checked_mip_level_size_bytes(width, height)
+            .expect("Mip level size does not fit in a usize")
+    }
+
+    // Same as mip_level_size_bytes, but returns None if the size does not fit in a usize. Use this
+    // for sizes that come from untrusted data, like file headers.
+    pub fn checked_mip_level_size_bytes(
+        &self,
+        width: u32,
+        height: u32,
+    ) -> Option<usize> {
+        use std::convert::TryInto;
+
+        let block_extent = self.block_extent() as u64;
+        let blocks_wide = (width as u64 + block_extent - 1) / block_extent;
+        let blocks_high = (height as u64 + block_extent - 1) / block_extent;
+        blocks_wide
+            .checked_mul(blocks_high)?
+            .checked_mul(self.bytes_per_block() as u64)?
+            .try_into()
+            .ok()
+    }
+}
+
 #[derive(Copy, Clone, Debug)]
 pub struct DecodedTextureMipInfo {
     pub mip_level_count: u32,
@@ -49,10 +143,26 @@ pub struct DecodedTexture {
     pub width: u32,
     pub height: u32,
     pub color_space: ColorSpace,
+    pub format: DecodedTextureFormat,
     pub mips: DecodedTextureMips,
+    // Cube maps have 6 layers per cube, in +X, -X, +Y, -Y, +Z, -Z order
+    pub layer_count: u32,
+    pub is_cube_map: bool,
     pub data: Vec<u8>,
 }
 
+// The size of a mip level, halving each dimension per level (but never going below 1)
+pub fn mip_level_extent(
+    extent: &vk::Extent3D,
+    mip_level: u32,
+) -> vk::Extent3D {
+    vk::Extent3D {
+        width: (extent.width >> mip_level).max(1),
+        height: (extent.height >> mip_level).max(1),
+        depth: (extent.depth >> mip_level).max(1),
+    }
+}
+
 // Provides default settings for an image that's loaded without metadata specifying mip settings
 pub fn default_mip_settings_for_image(
     width: u32,
@@ -82,6 +192,9 @@ pub fn decode_texture(
         mips: decoded_texture_mip_info,
         data: example_image,
         color_space: ColorSpace::Srgb,
+        format: DecodedTextureFormat::RGBA8,
+        layer_count: 1,
+        is_cube_map: false,
     }
 }
 
@@ -91,15 +204,47 @@ pub enum ImageMemoryBarrierType {
     PostUploadUnifiedQueues,
     PostUploadTransferQueue,
     PostUploadDstQueue,
+    // Rendered color attachment -> transfer source
+    PreReadback,
+    // Transfer source -> color attachment, so the image can be rendered to again
+    PostReadback,
+    // Presentable image (i.e. the final pass of a frame) -> transfer source
+    PreReadbackFromPresent,
+    // Transfer source -> presentable image
+    PostReadbackToPresent,
 }
 
 pub fn cmd_image_memory_barrier(
+    logical_device: &ash::Device,
+    command_buffer: vk::CommandBuffer,
+    images: &[vk::Image],
+    barrier_type: ImageMemoryBarrierType,
+    src_queue_family: u32,
+    dst_queue_family: u32,
+) {
+    cmd_image_memory_barrier_subresources(
+        logical_device,
+        command_buffer,
+        images,
+        barrier_type,
+        src_queue_family,
+        dst_queue_family,
+        1,
+        1,
+    );
+}
+
+// Same as cmd_image_memory_barrier, but covers the first mip_level_count mip levels of the first
+// layer_count array layers
+pub fn cmd_image_memory_barrier_subresources(
     logical_device: &ash::Device,
     command_buffer: vk::CommandBuffer,
     images: &[vk::Image],
     barrier_type: ImageMemoryBarrierType,
     mut src_queue_family: u32,
     mut dst_queue_family: u32,
+    mip_level_count: u32,
+    layer_count: u32,
 ) {
     if src_queue_family == dst_queue_family {
         src_queue_family = vk::QUEUE_FAMILY_IGNORED;
@@ -148,14 +293,46 @@ pub fn cmd_image_memory_barrier(
             src_layout: vk::ImageLayout::TRANSFER_DST_OPTIMAL,
             dst_layout: vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
         },
+        ImageMemoryBarrierType::PreReadback => SyncInfo {
+            src_access_mask: vk::AccessFlags::COLOR_ATTACHMENT_WRITE,
+            dst_access_mask: vk::AccessFlags::TRANSFER_READ,
+            src_stage: vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT,
+            dst_stage: vk::PipelineStageFlags::TRANSFER,
+            src_layout: vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL,
+            dst_layout: vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+        },
+        ImageMemoryBarrierType::PostReadback => SyncInfo {
+            src_access_mask: vk::AccessFlags::TRANSFER_READ,
+            dst_access_mask: vk::AccessFlags::COLOR_ATTACHMENT_WRITE,
+            src_stage: vk::PipelineStageFlags::TRANSFER,
+            dst_stage: vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT,
+            src_layout: vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+            dst_layout: vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL,
+        },
+        ImageMemoryBarrierType::PreReadbackFromPresent => SyncInfo {
+            src_access_mask: vk::AccessFlags::COLOR_ATTACHMENT_WRITE,
+            dst_access_mask: vk::AccessFlags::TRANSFER_READ,
+            src_stage: vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT,
+            dst_stage: vk::PipelineStageFlags::TRANSFER,
+            src_layout: vk::ImageLayout::PRESENT_SRC_KHR,
+            dst_layout: vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+        },
+        ImageMemoryBarrierType::PostReadbackToPresent => SyncInfo {
+            src_access_mask: vk::AccessFlags::TRANSFER_READ,
+            dst_access_mask: vk::AccessFlags::empty(),
+            src_stage: vk::PipelineStageFlags::TRANSFER,
+            dst_stage: vk::PipelineStageFlags::BOTTOM_OF_PIPE,
+            src_layout: vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+            dst_layout: vk::ImageLayout::PRESENT_SRC_KHR,
+        },
     };
 
     let subresource_range = vk::ImageSubresourceRange::builder()
         .aspect_mask(vk::ImageAspectFlags::COLOR)
         .base_mip_level(0)
-        .level_count(1)
+        .level_count(mip_level_count)
         .base_array_layer(0)
-        .layer_count(1);
+        .layer_count(layer_count);
 
     let barrier_infos: Vec<_> = images
         .iter()
@@ -193,12 +370,36 @@ pub fn cmd_copy_buffer_to_image(
     offset: vk::DeviceSize,
     image: vk::Image,
     extent: &vk::Extent3D,
+) {
+    cmd_copy_buffer_to_image_mip_level(
+        logical_device,
+        command_buffer,
+        buffer,
+        offset,
+        image,
+        extent,
+        0,
+        1,
+    );
+}
+
+// Copies into a single mip level of the first layer_count array layers. extent is the size of that
+// mip level, and the layers must be tightly packed in the buffer
+pub fn cmd_copy_buffer_to_image_mip_level(
+    logical_device: &ash::Device,
+    command_buffer: vk::CommandBuffer,
+    buffer: vk::Buffer,
+    offset: vk::DeviceSize,
+    image: vk::Image,
+    extent: &vk::Extent3D,
+    mip_level: u32,
+    layer_count: u32,
 ) {
     let image_subresource = vk::ImageSubresourceLayers::builder()
         .aspect_mask(vk::ImageAspectFlags::COLOR)
-        .mip_level(0)
+        .mip_level(mip_level)
         .base_array_layer(0)
-        .layer_count(1);
+        .layer_count(layer_count);
 
     let image_copy = vk::BufferImageCopy::builder()
         .buffer_offset(offset)
@@ -219,6 +420,78 @@ pub fn cmd_copy_buffer_to_image(
     }
 }
 
+pub fn cmd_copy_image_to_buffer(
+    logical_device: &ash::Device,
+    command_buffer: vk::CommandBuffer,
+    image: vk::Image,
+    buffer: vk::Buffer,
+    offset: vk::DeviceSize,
+    extent: &vk::Extent3D,
+) {
+    let image_subresource = vk::ImageSubresourceLayers::builder()
+        .aspect_mask(vk::ImageAspectFlags::COLOR)
+        .mip_level(0)
+        .base_array_layer(0)
+        .layer_count(1);
+
+    let image_copy = vk::BufferImageCopy::builder()
+        .buffer_offset(offset)
+        .buffer_row_length(0)
+        .buffer_image_height(0)
+        .image_subresource(*image_subresource)
+        .image_offset(vk::Offset3D { x: 0, y: 0, z: 0 })
+        .image_extent(*extent);
+
+    unsafe {
+        logical_device.cmd_copy_image_to_buffer(
+            command_buffer,
+            image,
+            vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+            buffer,
+            &[*image_copy],
+        );
+    }
+}
+
+// Block compressed formats also need the texture_compression_bc feature to be enabled
+fn is_texture_format_supported(
+    device_context: &VkDeviceContext,
+    decoded_texture_format: DecodedTextureFormat,
+    format: vk::Format,
+) -> bool {
+    if decoded_texture_format.is_block_compressed()
+        && device_context
+            .enabled_features()
+            .features
+            .texture_compression_bc
+            == vk::FALSE
+    {
+        return false;
+    }
+
+    device_context.supports_sampled_image_format(format)
+}
+
+// Mips are generated by blitting with linear filtering. This is never supported for block
+// compressed formats, and isn't guaranteed for 32-bit float formats.
+fn supports_mip_generation(
+    device_context: &VkDeviceContext,
+    decoded_texture_format: DecodedTextureFormat,
+    format: vk::Format,
+) -> bool {
+    if decoded_texture_format.is_block_compressed() {
+        return false;
+    }
+
+    let required_features = vk::FormatFeatureFlags::BLIT_SRC
+        | vk::FormatFeatureFlags::BLIT_DST
+        | vk::FormatFeatureFlags::SAMPLED_IMAGE_FILTER_LINEAR;
+    device_context
+        .format_properties(format)
+        .optimal_tiling_features
+        .contains(required_features)
+}
+
 pub fn enqueue_load_images(
     device_context: &VkDeviceContext,
     upload: &mut VkTransferUpload,
@@ -235,13 +508,44 @@ pub fn enqueue_load_images(
             depth: 1,
         };
 
+        let format = decoded_texture
+            .format
+            .vk_format(decoded_texture.color_space);
+        if !is_texture_format_supported(device_context, decoded_texture.format, format) {
+            log::error!(
+                "Cannot load a {:?} image, the device does not support sampling {:?}",
+                decoded_texture.format,
+                format
+            );
+            return Err(vk::Result::ERROR_FORMAT_NOT_SUPPORTED);
+        }
+
         let (mip_level_count, generate_mips) = match decoded_texture.mips {
             DecodedTextureMips::None => (1, false),
-            DecodedTextureMips::Precomputed(_info) => unimplemented!(), //(info.mip_level_count, false),
+            DecodedTextureMips::Precomputed(info) => (info.mip_level_count, false),
+            // Mips are generated by blitting, which block compressed formats don't support
+            DecodedTextureMips::Runtime(_)
+                if !supports_mip_generation(device_context, decoded_texture.format, format) =>
+            {
+                log::warn!(
+                    "Mips can't be generated at runtime for a {:?} image, only the first mip will be used",
+                    decoded_texture.format
+                );
+                (1, false)
+            }
             DecodedTextureMips::Runtime(info) => (info.mip_level_count, true),
         };
 
-        // Arbitrary, not sure if there is any requirement
+        let create_flags = if decoded_texture.is_cube_map {
+            vk::ImageCreateFlags::CUBE_COMPATIBLE
+        } else {
+            vk::ImageCreateFlags::empty()
+        };
+
+        // Precomputed mips are tightly packed after the first mip, each containing every layer
+        let uploaded_mip_level_count = if generate_mips { 1 } else { mip_level_count };
+
+        // Copies must start at a multiple of the texel block size, which is at most 16 bytes
         const REQUIRED_ALIGNMENT: usize = 16;
 
         // Push data into the staging buffer
@@ -252,11 +556,6 @@ pub fn enqueue_load_images(
             image_usage |= vk::ImageUsageFlags::TRANSFER_SRC;
         };
 
-        let format = match decoded_texture.color_space {
-            ColorSpace::Linear => vk::Format::R8G8B8A8_UNORM,
-            ColorSpace::Srgb => vk::Format::R8G8B8A8_SRGB,
-        };
-
         // Allocate an image
         let image = ManuallyDrop::new(VkImage::new(
             device_context,
@@ -267,6 +566,8 @@ pub fn enqueue_load_images(
             vk::ImageTiling::OPTIMAL,
             vk::SampleCountFlags::TYPE_1,
             mip_level_count,
+            decoded_texture.layer_count,
+            create_flags,
             vk::MemoryPropertyFlags::DEVICE_LOCAL,
         )?);
 
@@ -277,24 +578,37 @@ pub fn enqueue_load_images(
         // - transition the destination to the graphics queue
         //
 
-        // This just copies first mip of the chain
-        cmd_image_memory_barrier(
+        // This copies the first mip of the chain, or every mip if they were precomputed
+        cmd_image_memory_barrier_subresources(
             device_context.device(),
             upload.transfer_command_buffer(),
             &[image.image()],
             ImageMemoryBarrierType::PreUpload,
             transfer_queue_family_index,
             transfer_queue_family_index,
+            uploaded_mip_level_count,
+            decoded_texture.layer_count,
         );
 
-        cmd_copy_buffer_to_image(
-            device_context.device(),
-            upload.transfer_command_buffer(),
-            upload.staging_buffer().buffer(),
-            offset,
-            image.image(),
-            &image.extent,
-        );
+        let mut mip_offset = offset;
+        for mip_level in 0..uploaded_mip_level_count {
+            let mip_extent = mip_level_extent(&image.extent, mip_level);
+            cmd_copy_buffer_to_image_mip_level(
+                device_context.device(),
+                upload.transfer_command_buffer(),
+                upload.staging_buffer().buffer(),
+                mip_offset,
+                image.image(),
+                &mip_extent,
+                mip_level,
+                decoded_texture.layer_count,
+            );
+
+            mip_offset += (decoded_texture
+                .format
+                .mip_level_size_bytes(mip_extent.width, mip_extent.height)
+                * decoded_texture.layer_count as usize) as vk::DeviceSize;
+        }
 
         if generate_mips {
             // Generating mipmaps includes image barriers, so this function will handle writing the
@@ -306,24 +620,29 @@ pub fn enqueue_load_images(
                 dst_queue_family_index,
                 &image,
                 mip_level_count,
+                decoded_texture.layer_count,
             );
         } else {
-            cmd_image_memory_barrier(
+            cmd_image_memory_barrier_subresources(
                 device_context.device(),
                 upload.transfer_command_buffer(),
                 &[image.image()],
                 ImageMemoryBarrierType::PostUploadTransferQueue,
                 transfer_queue_family_index,
                 dst_queue_family_index,
+                uploaded_mip_level_count,
+                decoded_texture.layer_count,
             );
 
-            cmd_image_memory_barrier(
+            cmd_image_memory_barrier_subresources(
                 device_context.device(),
                 upload.dst_command_buffer(),
                 &[image.image()],
                 ImageMemoryBarrierType::PostUploadDstQueue,
                 transfer_queue_family_index,
                 dst_queue_family_index,
+                uploaded_mip_level_count,
+                decoded_texture.layer_count,
             );
         }
 
@@ -340,10 +659,11 @@ fn generate_mips_for_image(
     dst_queue_family_index: u32,
     image: &ManuallyDrop<VkImage>,
     mip_level_count: u32,
+    layer_count: u32,
 ) {
     let first_mip_range = vk::ImageSubresourceRange::builder()
         .aspect_mask(vk::ImageAspectFlags::COLOR)
-        .layer_count(1)
+        .layer_count(layer_count)
         .level_count(1)
         .build();
 
@@ -383,11 +703,12 @@ fn generate_mips_for_image(
         dst_queue_family_index,
         &image,
         mip_level_count,
+        layer_count,
     );
 
     let all_mips_range = vk::ImageSubresourceRange::builder()
         .aspect_mask(vk::ImageAspectFlags::COLOR)
-        .layer_count(1)
+        .layer_count(layer_count)
         .level_count(mip_level_count)
         .build();
 
@@ -414,10 +735,11 @@ fn do_generate_mips_for_image(
     queue_family_index: u32, // queue family that will do mip generation
     image: &ManuallyDrop<VkImage>,
     mip_level_count: u32,
+    layer_count: u32,
 ) {
     log::debug!("Generating mipmaps");
 
-    // Walk through each mip level n:
+    // Walk through each mip level n, blitting every layer at once:
     // - put level n+1 into write mode
     // - blit from n to n+1
     // - put level n+1 into read mode
@@ -427,7 +749,7 @@ fn do_generate_mips_for_image(
 
         let src_subresource = vk::ImageSubresourceLayers::builder()
             .aspect_mask(vk::ImageAspectFlags::COLOR)
-            .layer_count(1)
+            .layer_count(layer_count)
             .mip_level(src_level);
 
         let src_offsets = [
@@ -441,7 +763,7 @@ fn do_generate_mips_for_image(
 
         let dst_subresource = vk::ImageSubresourceLayers::builder()
             .aspect_mask(vk::ImageAspectFlags::COLOR)
-            .layer_count(1)
+            .layer_count(layer_count)
             .mip_level(dst_level);
 
         let dst_offsets = [
@@ -460,7 +782,7 @@ fn do_generate_mips_for_image(
             .aspect_mask(vk::ImageAspectFlags::COLOR)
             .base_mip_level(dst_level)
             .level_count(1)
-            .layer_count(1);
+            .layer_count(layer_count);
 
         log::trace!("  transition to write");
         transition_for_mipmap(
@@ -750,3 +1072,221 @@ pub fn enqueue_load_buffers(
 
     Ok(dst_buffers)
 }
+
+/// Pixel formats that can be read back from the GPU
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ReadbackFormat {
+    // R8G8B8A8_UNORM or R8G8B8A8_SRGB. For SRGB images the bytes are still SRGB-encoded
+    Rgba8,
+    // R16G16B16A16_SFLOAT
+    Rgba16F,
+}
+
+impl ReadbackFormat {
+    pub fn from_vk(format: vk::Format) -> Option<ReadbackFormat> {
+        match format {
+            vk::Format::R8G8B8A8_UNORM | vk::Format::R8G8B8A8_SRGB => Some(ReadbackFormat::Rgba8),
+            vk::Format::R16G16B16A16_SFLOAT => Some(ReadbackFormat::Rgba16F),
+            _ => None,
+        }
+    }
+
+    pub fn bytes_per_pixel(self) -> usize {
+        match self {
+            ReadbackFormat::Rgba8 => 4,
+            ReadbackFormat::Rgba16F => 8,
+        }
+    }
+}
+
+/// Pixels copied back from an image. Rows are tightly packed, starting from the top of the image.
+#[derive(Clone)]
+pub struct ReadbackImage {
+    pub width: u32,
+    pub height: u32,
+    pub format: ReadbackFormat,
+    pub data: Vec<u8>,
+}
+
+impl ReadbackImage {
+    /// Returns 4 floats per pixel
+    pub fn to_rgba_f32(&self) -> Vec<f32> {
+        match self.format {
+            ReadbackFormat::Rgba8 => self.data.iter().map(|x| *x as f32 / 255.0).collect(),
+            ReadbackFormat::Rgba16F => self
+                .data
+                .chunks_exact(2)
+                .map(|x| half::f16::from_bits(u16::from_le_bytes([x[0], x[1]])).to_f32())
+                .collect(),
+        }
+    }
+
+    /// Returns 4 bytes per pixel. Float values are clamped to [0, 1] without any color space
+    /// conversion.
+    pub fn to_rgba8(&self) -> Vec<u8> {
+        match self.format {
+            ReadbackFormat::Rgba8 => self.data.clone(),
+            ReadbackFormat::Rgba16F => self
+                .to_rgba_f32()
+                .into_iter()
+                .map(|x| (x.max(0.0).min(1.0) * 255.0).round() as u8)
+                .collect(),
+        }
+    }
+}
+
+/// Copies the first mip of a single-sampled color image back to host memory and blocks until it
+/// completes. `image_layout` is the layout the image was left in by the last renderpass that wrote
+/// it, either `COLOR_ATTACHMENT_OPTIMAL` or `PRESENT_SRC_KHR`, and the image is returned to that
+/// layout. It must have been created with `TRANSFER_SRC` usage.
+pub fn readback_image(
+    device_context: &VkDeviceContext,
+    queue_family_index: u32,
+    queue: &Arc<Mutex<vk::Queue>>,
+    image: &VkImage,
+    image_layout: vk::ImageLayout,
+) -> VkResult<ReadbackImage> {
+    let (pre_barrier, post_barrier) = match image_layout {
+        vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL => (
+            ImageMemoryBarrierType::PreReadback,
+            ImageMemoryBarrierType::PostReadback,
+        ),
+        vk::ImageLayout::PRESENT_SRC_KHR => (
+            ImageMemoryBarrierType::PreReadbackFromPresent,
+            ImageMemoryBarrierType::PostReadbackToPresent,
+        ),
+        _ => return Err(vk::Result::ERROR_FORMAT_NOT_SUPPORTED),
+    };
+
+    let format =
+        ReadbackFormat::from_vk(image.format).ok_or(vk::Result::ERROR_FORMAT_NOT_SUPPORTED)?;
+    let size =
+        image.extent.width as u64 * image.extent.height as u64 * format.bytes_per_pixel() as u64;
+
+    let buffer = VkBuffer::new(
+        device_context,
+        vk_mem::MemoryUsage::GpuToCpu,
+        vk::BufferUsageFlags::TRANSFER_DST,
+        vk::MemoryPropertyFlags::HOST_VISIBLE | vk::MemoryPropertyFlags::HOST_COHERENT,
+        size,
+    )?;
+
+    let device = device_context.device();
+    let pool_create_info = vk::CommandPoolCreateInfo::builder()
+        .flags(vk::CommandPoolCreateFlags::TRANSIENT)
+        .queue_family_index(queue_family_index);
+    let command_pool = unsafe { device.create_command_pool(&pool_create_info, None)? };
+
+    let result = record_and_submit_readback(
+        device_context,
+        command_pool,
+        queue,
+        image,
+        &buffer,
+        pre_barrier,
+        post_barrier,
+    );
+
+    unsafe {
+        device.destroy_command_pool(command_pool, None);
+    }
+    result?;
+
+    Ok(ReadbackImage {
+        width: image.extent.width,
+        height: image.extent.height,
+        format,
+        data: buffer.read_from_host_visible_buffer(0, size)?,
+    })
+}
+
+fn record_and_submit_readback(
+    device_context: &VkDeviceContext,
+    command_pool: vk::CommandPool,
+    queue: &Arc<Mutex<vk::Queue>>,
+    image: &VkImage,
+    buffer: &VkBuffer,
+    pre_barrier: ImageMemoryBarrierType,
+    post_barrier: ImageMemoryBarrierType,
+) -> VkResult<()> {
+    let device = device_context.device();
+    let command_buffer_allocate_info = vk::CommandBufferAllocateInfo::builder()
+        .command_buffer_count(1)
+        .command_pool(command_pool)
+        .level(vk::CommandBufferLevel::PRIMARY);
+    let command_buffer =
+        unsafe { device.allocate_command_buffers(&command_buffer_allocate_info)? }[0];
+
+    let begin_info =
+        vk::CommandBufferBeginInfo::builder().flags(vk::CommandBufferUsageFlags::ONE_TIME_SUBMIT);
+    unsafe {
+        device.begin_command_buffer(command_buffer, &begin_info)?;
+    }
+
+    cmd_image_memory_barrier(
+        device,
+        command_buffer,
+        &[image.image()],
+        pre_barrier,
+        vk::QUEUE_FAMILY_IGNORED,
+        vk::QUEUE_FAMILY_IGNORED,
+    );
+
+    cmd_copy_image_to_buffer(
+        device,
+        command_buffer,
+        image.image(),
+        buffer.buffer(),
+        0,
+        &image.extent,
+    );
+
+    // Make the copy visible to the host
+    let buffer_barrier = vk::BufferMemoryBarrier::builder()
+        .src_access_mask(vk::AccessFlags::TRANSFER_WRITE)
+        .dst_access_mask(vk::AccessFlags::HOST_READ)
+        .src_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+        .dst_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+        .buffer(buffer.buffer())
+        .size(vk::WHOLE_SIZE)
+        .offset(0)
+        .build();
+
+    unsafe {
+        device.cmd_pipeline_barrier(
+            command_buffer,
+            vk::PipelineStageFlags::TRANSFER,
+            vk::PipelineStageFlags::HOST,
+            vk::DependencyFlags::empty(),
+            &[],
+            &[buffer_barrier],
+            &[],
+        );
+    }
+
+    cmd_image_memory_barrier(
+        device,
+        command_buffer,
+        &[image.image()],
+        post_barrier,
+        vk::QUEUE_FAMILY_IGNORED,
+        vk::QUEUE_FAMILY_IGNORED,
+    );
+
+    unsafe {
+        device.end_command_buffer(command_buffer)?;
+
+        let fence = device.create_fence(&vk::FenceCreateInfo::default(), None)?;
+
+        let command_buffers = [command_buffer];
+        let submit_info = vk::SubmitInfo::builder().command_buffers(&command_buffers);
+        let result = {
+            let queue = queue.lock().unwrap();
+            device.queue_submit(*queue, &[*submit_info], fence)
+        }
+        .and_then(|_| device.wait_for_fences(&[fence], true, std::u64::MAX));
+
+        device.destroy_fence(fence, None);
+        result
+    }
+}
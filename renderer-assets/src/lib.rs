@@ -1,4 +1,5 @@
 pub mod assets;
+pub mod golden_image;
 pub mod image_utils;
 pub mod push_buffer;
 pub mod vk_description;
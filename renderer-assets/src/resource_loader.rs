@@ -1,4 +1,5 @@
 use atelier_assets::loader::{AssetLoadOp, LoadHandle};
+use atelier_assets::core::AssetUuid;
 
 use crossbeam_channel::Receiver;
 
@@ -27,6 +28,7 @@ where
     fn update_asset(
         &mut self,
         load_handle: LoadHandle,
+        asset_uuid: AssetUuid,
         load_op: AssetLoadOp,
         asset: AssetDataT,
     ) -> ResourceLoadResult<AssetT>;
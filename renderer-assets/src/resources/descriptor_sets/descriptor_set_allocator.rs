@@ -5,6 +5,7 @@ use super::ManagedDescriptorSetPool;
 use super::{FrameInFlightIndex, DescriptorSetArc};
 use super::DescriptorSetWriteSet;
 use ash::prelude::VkResult;
+use ash::vk;
 use crate::resources::{DynDescriptorSet, DynPassMaterialInstance, DynMaterialInstance, ResourceArc};
 use crate::assets::{MaterialPass, MaterialInstanceAsset, MaterialAsset};
 
@@ -51,6 +52,14 @@ impl DescriptorSetAllocator {
         }
     }
 
+    /// Size of the buffers backing descriptor sets allocated by this allocator
+    pub fn backing_buffer_bytes(&self) -> vk::DeviceSize {
+        self.pools
+            .values()
+            .map(|pool| pool.backing_buffer_bytes())
+            .sum()
+    }
+
     pub fn flush_changes(&mut self) -> VkResult<()> {
         // Now process drops and flush writes to GPU
         for pool in self.pools.values_mut() {
@@ -6,6 +6,7 @@ use std::sync::atomic::{AtomicU64, Ordering};
 use std::collections::VecDeque;
 use std::ops::{Deref, DerefMut};
 use ash::prelude::VkResult;
+use ash::vk;
 
 // This holds the allocator and the frame on which it was "borrowed" from the allocator manager
 struct DescriptorSetAllocatorRefInner {
@@ -142,6 +143,19 @@ impl DescriptorSetAllocatorManagerInner {
         }
     }
 
+    // Allocators that are currently borrowed are not included
+    fn backing_buffer_bytes(&self) -> vk::DeviceSize {
+        let frame_index = self.frame_index.load(Ordering::Relaxed);
+        let mut allocators = self.allocators.lock().unwrap();
+
+        Self::drain_drop_rx(&self.drop_rx, &mut *allocators, frame_index);
+
+        allocators
+            .iter()
+            .map(|allocator| allocator.backing_buffer_bytes())
+            .sum()
+    }
+
     fn destroy(&self) -> VkResult<()> {
         let frame_index = self.frame_index.load(Ordering::Relaxed);
         let mut allocators = self.allocators.lock().unwrap();
@@ -193,6 +207,12 @@ impl DescriptorSetAllocatorManager {
         self.inner.on_frame_complete();
     }
 
+    /// Size of the buffers backing descriptor sets allocated by the pooled allocators. Allocators
+    /// that are currently borrowed are not included.
+    pub fn backing_buffer_bytes(&self) -> vk::DeviceSize {
+        self.inner.backing_buffer_bytes()
+    }
+
     pub fn destroy(&mut self) -> VkResult<()> {
         self.inner.destroy()
     }
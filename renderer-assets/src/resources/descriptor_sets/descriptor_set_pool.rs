@@ -222,6 +222,13 @@ impl ManagedDescriptorSetPool {
             .update(device_context.device())
     }
 
+    pub fn backing_buffer_bytes(&self) -> vk::DeviceSize {
+        self.chunks
+            .iter()
+            .map(|chunk| chunk.backing_buffer_bytes())
+            .sum()
+    }
+
     pub fn destroy(
         &mut self,
         device_context: &VkDeviceContext,
@@ -123,6 +123,15 @@ impl ManagedDescriptorSetPoolChunk {
         })
     }
 
+    // Size of the buffers backing the descriptor sets in this chunk
+    pub(super) fn backing_buffer_bytes(&self) -> vk::DeviceSize {
+        self.buffers
+            .buffer_sets
+            .values()
+            .map(|buffer_set| buffer_set.buffer.size())
+            .sum()
+    }
+
     pub(super) fn destroy(
         &mut self,
         pool_allocator: &mut VkDescriptorPoolAllocator,
@@ -1,15 +1,18 @@
 use crossbeam_channel::{Sender, Receiver};
 use std::hash::Hash;
-use std::sync::Arc;
+use std::sync::{Arc, Mutex};
 use renderer_shell_vulkan::{
     VkResource, VkResourceDropSink, VkDeviceContext, VkImageRaw, VkImage, VkBufferRaw, VkBuffer,
 };
 use ash::vk;
 use super::ResourceId;
 use crate::resources::ResourceArc;
-use std::sync::atomic::{AtomicU32, AtomicU64, Ordering};
-use crate::resources::resource_arc::ResourceWithHash;
+use std::sync::atomic::{AtomicBool, AtomicU32, AtomicU64, Ordering};
+use crate::resources::resource_arc::{ResourceWithHash, WeakResourceArc};
+use fnv::FnvHashMap;
 use crate::ImageViewResource;
+use crate::resources::resource_lookup::DebugNamedResource;
+use crate::resources::memory_metrics::{AllocatedResource, ResourceMemoryUsage};
 use ash::prelude::VkResult;
 
 #[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
@@ -39,6 +42,76 @@ where
     drop_tx: Sender<ResourceWithHash<ResourceT>>,
     next_index: AtomicU64,
     active_count: Arc<AtomicU32>,
+    allocated_bytes: Arc<AtomicU64>,
+    lifetime_tracking: Arc<DynResourceLifetimeTracking<ResourceT>>,
+}
+
+// Shared by a DynResourceAllocatorManager and its allocators. Resources are only registered if
+// tracking was enabled when they were created.
+pub struct DynResourceLifetimeTracking<ResourceT>
+where
+    ResourceT: VkResource + Clone,
+{
+    enabled: AtomicBool,
+    live_resources: Mutex<FnvHashMap<ResourceId, WeakResourceArc<ResourceT>>>,
+}
+
+impl<ResourceT> Default for DynResourceLifetimeTracking<ResourceT>
+where
+    ResourceT: VkResource + Clone,
+{
+    fn default() -> Self {
+        DynResourceLifetimeTracking {
+            enabled: AtomicBool::new(false),
+            live_resources: Default::default(),
+        }
+    }
+}
+
+impl<ResourceT> DynResourceLifetimeTracking<ResourceT>
+where
+    ResourceT: VkResource + Clone + std::fmt::Debug,
+{
+    // Wraps a new resource in an arc, registering it if tracking is enabled
+    fn create_arc(
+        &self,
+        resource: ResourceT,
+        resource_id: ResourceId,
+        drop_tx: Sender<ResourceWithHash<ResourceT>>,
+    ) -> ResourceArc<ResourceT> {
+        let track_lifetime = self.enabled.load(Ordering::Relaxed);
+        let arc = ResourceArc::new(resource, resource_id, drop_tx, track_lifetime);
+
+        if track_lifetime {
+            self.live_resources
+                .lock()
+                .unwrap()
+                .insert(resource_id, arc.downgrade());
+        }
+
+        arc
+    }
+
+    fn on_dropped(
+        &self,
+        resource_id: ResourceId,
+    ) {
+        self.live_resources.lock().unwrap().remove(&resource_id);
+    }
+
+    // Logs every resource that is still referenced and returns how many were logged
+    fn report_live_resources(&self) -> usize {
+        let live_resources = self.live_resources.lock().unwrap();
+        let mut count = 0;
+        for resource in live_resources.values() {
+            if let Some(resource) = resource.upgrade() {
+                resource.log_still_alive();
+                count += 1;
+            }
+        }
+
+        count
+    }
 }
 
 #[derive(Clone)]
@@ -51,12 +124,14 @@ where
 
 impl<ResourceT> DynResourceAllocator<ResourceT>
 where
-    ResourceT: VkResource + Clone + std::fmt::Debug,
+    ResourceT: VkResource + AllocatedResource + Clone + std::fmt::Debug,
 {
     fn new(
         drop_tx: Sender<ResourceWithHash<ResourceT>>,
         allocator_index: u32,
         active_count: Arc<AtomicU32>,
+        allocated_bytes: Arc<AtomicU64>,
+        lifetime_tracking: Arc<DynResourceLifetimeTracking<ResourceT>>,
     ) -> Self {
         let next_index = (allocator_index as u64) << 32 + 1;
 
@@ -64,6 +139,8 @@ where
             drop_tx,
             next_index: AtomicU64::new(next_index),
             active_count,
+            allocated_bytes,
+            lifetime_tracking,
         };
 
         DynResourceAllocator {
@@ -73,6 +150,7 @@ where
 
     fn insert(
         &self,
+        device_context: &VkDeviceContext,
         resource: ResourceT,
     ) -> ResourceArc<ResourceT> {
         // This index is not strictly necessary. However, we do want to be compatible with ResourceArc,
@@ -81,6 +159,9 @@ where
         let resource_index =
             DynResourceIndex(self.inner.next_index.fetch_add(1, Ordering::Relaxed));
         self.inner.active_count.fetch_add(1, Ordering::Relaxed);
+        self.inner
+            .allocated_bytes
+            .fetch_add(resource.allocated_bytes(device_context), Ordering::Relaxed);
 
         log::trace!(
             "insert resource {} {:?}",
@@ -88,12 +169,17 @@ where
             resource
         );
 
-        ResourceArc::new(resource, resource_index.into(), self.inner.drop_tx.clone())
+        self.inner.lifetime_tracking.create_arc(
+            resource,
+            resource_index.into(),
+            self.inner.drop_tx.clone(),
+        )
     }
 }
 
 #[derive(Clone)]
 pub struct DynResourceAllocatorSet {
+    device_context: VkDeviceContext,
     pub images: DynResourceAllocator<VkImageRaw>,
     pub image_views: DynResourceAllocator<ImageViewResource>,
     pub buffers: DynResourceAllocator<VkBufferRaw>,
@@ -105,7 +191,7 @@ impl DynResourceAllocatorSet {
         image: VkImage,
     ) -> ResourceArc<VkImageRaw> {
         let raw_image = image.take_raw().unwrap();
-        self.images.insert(raw_image)
+        self.images.insert(&self.device_context, raw_image)
     }
 
     pub fn insert_image_view(
@@ -115,7 +201,8 @@ impl DynResourceAllocatorSet {
     ) -> ResourceArc<ImageViewResource> {
         let image_view_resource = ImageViewResource { image, image_view };
 
-        self.image_views.insert(image_view_resource)
+        self.image_views
+            .insert(&self.device_context, image_view_resource)
     }
 
     pub fn insert_buffer(
@@ -123,7 +210,20 @@ impl DynResourceAllocatorSet {
         buffer: VkBuffer,
     ) -> ResourceArc<VkBufferRaw> {
         let raw_buffer = buffer.take_raw().unwrap();
-        self.buffers.insert(raw_buffer)
+        self.buffers.insert(&self.device_context, raw_buffer)
+    }
+
+    /// Name the vulkan objects of a resource, see `VkDeviceContext::set_debug_name`
+    pub fn set_debug_name<ResourceT>(
+        &self,
+        resource: &ResourceArc<ResourceT>,
+        name: &str,
+    ) where
+        ResourceT: VkResource + DebugNamedResource + Clone,
+    {
+        resource
+            .get_raw()
+            .set_debug_name(&self.device_context, name);
     }
 }
 
@@ -137,11 +237,13 @@ where
     //allocator: DynResourceAllocator<ResourceT>
     next_allocator_index: AtomicU32,
     active_count: Arc<AtomicU32>,
+    allocated_bytes: Arc<AtomicU64>,
+    lifetime_tracking: Arc<DynResourceLifetimeTracking<ResourceT>>,
 }
 
 impl<ResourceT> DynResourceAllocatorManager<ResourceT>
 where
-    ResourceT: VkResource + Clone + std::fmt::Debug,
+    ResourceT: VkResource + AllocatedResource + Clone + std::fmt::Debug,
 {
     fn new(max_frames_in_flight: u32) -> Self {
         let (drop_tx, drop_rx) = crossbeam_channel::unbounded();
@@ -153,6 +255,8 @@ where
             drop_rx,
             next_allocator_index: AtomicU32::new(0),
             active_count: Arc::new(AtomicU32::new(0)),
+            allocated_bytes: Arc::new(AtomicU64::new(0)),
+            lifetime_tracking: Default::default(),
         }
     }
 
@@ -162,19 +266,29 @@ where
             self.drop_tx.clone(),
             allocator_index,
             self.active_count.clone(),
+            self.allocated_bytes.clone(),
+            self.lifetime_tracking.clone(),
         );
         allocator
     }
 
-    fn handle_dropped_resources(&mut self) {
+    fn handle_dropped_resources(
+        &mut self,
+        device_context: &VkDeviceContext,
+    ) {
         for dropped in self.drop_rx.try_iter() {
             log::trace!(
                 "dropping {} {:?}",
                 core::any::type_name::<ResourceT>(),
                 dropped.resource
             );
+            self.allocated_bytes.fetch_sub(
+                dropped.resource.allocated_bytes(device_context),
+                Ordering::Relaxed,
+            );
             self.drop_sink.retire(dropped.resource);
             self.active_count.fetch_sub(1, Ordering::Relaxed);
+            self.lifetime_tracking.on_dropped(dropped.resource_hash);
         }
     }
 
@@ -182,7 +296,7 @@ where
         &mut self,
         device_context: &VkDeviceContext,
     ) -> VkResult<()> {
-        self.handle_dropped_resources();
+        self.handle_dropped_resources(device_context);
         self.drop_sink.on_frame_complete(device_context)
     }
 
@@ -190,7 +304,7 @@ where
         &mut self,
         device_context: &VkDeviceContext,
     ) -> VkResult<()> {
-        self.handle_dropped_resources();
+        self.handle_dropped_resources(device_context);
 
         if self.len() > 0 {
             log::warn!(
@@ -198,6 +312,7 @@ where
                 core::any::type_name::<ResourceT>(),
                 self.len()
             );
+            self.report_live_resources();
         }
 
         self.drop_sink.destroy(device_context)
@@ -206,6 +321,25 @@ where
     fn len(&self) -> usize {
         self.active_count.load(Ordering::Relaxed) as usize
     }
+
+    fn allocated_bytes(&self) -> vk::DeviceSize {
+        self.allocated_bytes.load(Ordering::Relaxed)
+    }
+
+    fn set_track_lifetimes(
+        &self,
+        track_lifetimes: bool,
+    ) {
+        self.lifetime_tracking
+            .enabled
+            .store(track_lifetimes, Ordering::Relaxed);
+    }
+
+    // Logs every resource that is still referenced. Only resources created while lifetime
+    // tracking was enabled can be found.
+    fn report_live_resources(&self) {
+        self.lifetime_tracking.report_live_resources();
+    }
 }
 
 #[derive(Debug)]
@@ -243,6 +377,7 @@ impl DynResourceAllocatorManagerSet {
 
     pub fn create_allocator_set(&self) -> DynResourceAllocatorSet {
         DynResourceAllocatorSet {
+            device_context: self.device_context.clone(),
             images: self.images.create_allocator(),
             image_views: self.image_views.create_allocator(),
             buffers: self.buffers.create_allocator(),
@@ -265,6 +400,32 @@ impl DynResourceAllocatorManagerSet {
         Ok(())
     }
 
+    /// If enabled, a backtrace is captured for every resource created afterwards so that resources
+    /// still referenced on destroy can be reported with where they were created. This is slow, so
+    /// it's meant for debugging leaks.
+    pub fn set_track_lifetimes(
+        &self,
+        track_lifetimes: bool,
+    ) {
+        self.images.set_track_lifetimes(track_lifetimes);
+        self.image_views.set_track_lifetimes(track_lifetimes);
+        self.buffers.set_track_lifetimes(track_lifetimes);
+    }
+
+    /// Logs every resource created with lifetime tracking enabled that is still referenced
+    pub fn report_live_resources(&self) {
+        self.images.report_live_resources();
+        self.image_views.report_live_resources();
+        self.buffers.report_live_resources();
+    }
+
+    pub fn memory_usage(&self) -> ResourceMemoryUsage {
+        ResourceMemoryUsage {
+            image_bytes: self.images.allocated_bytes(),
+            buffer_bytes: self.buffers.allocated_bytes(),
+        }
+    }
+
     pub fn metrics(&self) -> ResourceMetrics {
         ResourceMetrics {
             image_count: self.images.len(),
@@ -273,3 +434,53 @@ impl DynResourceAllocatorManagerSet {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ash::vk::Handle;
+
+    #[test]
+    fn test_only_tracked_while_enabled() {
+        let (drop_tx, _drop_rx) = crossbeam_channel::unbounded();
+        let tracking = DynResourceLifetimeTracking::default();
+
+        let _untracked =
+            tracking.create_arc(vk::Sampler::from_raw(1), ResourceId(1), drop_tx.clone());
+        assert!(tracking.live_resources.lock().unwrap().is_empty());
+        assert_eq!(tracking.report_live_resources(), 0);
+
+        tracking.enabled.store(true, Ordering::Relaxed);
+        let _tracked = tracking.create_arc(vk::Sampler::from_raw(2), ResourceId(2), drop_tx);
+        assert!(tracking
+            .live_resources
+            .lock()
+            .unwrap()
+            .contains_key(&ResourceId(2)));
+        assert_eq!(tracking.report_live_resources(), 1);
+    }
+
+    #[test]
+    fn test_dropped_resources_are_untracked() {
+        let (drop_tx, drop_rx) = crossbeam_channel::unbounded();
+        let tracking = DynResourceLifetimeTracking::default();
+        tracking.enabled.store(true, Ordering::Relaxed);
+
+        let _kept = tracking.create_arc(vk::Sampler::from_raw(1), ResourceId(1), drop_tx.clone());
+        let released = tracking.create_arc(vk::Sampler::from_raw(2), ResourceId(2), drop_tx);
+        assert_eq!(tracking.report_live_resources(), 2);
+
+        // Released resources aren't reported even before the drop is handled
+        drop(released);
+        assert_eq!(tracking.report_live_resources(), 1);
+
+        // Same as DynResourceAllocatorManager::handle_dropped_resources
+        for dropped in drop_rx.try_iter() {
+            tracking.on_dropped(dropped.resource_hash);
+        }
+
+        let live_resources = tracking.live_resources.lock().unwrap();
+        assert_eq!(live_resources.len(), 1);
+        assert!(live_resources.contains_key(&ResourceId(1)));
+    }
+}
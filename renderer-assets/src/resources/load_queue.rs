@@ -9,6 +9,7 @@ use crate::assets::{
 };
 use crate::assets::ImageAssetData;
 use atelier_assets::loader::LoadHandle;
+use atelier_assets::core::AssetUuid;
 use crate::assets::BufferAssetData;
 use crate::resource_loader::ResourceLoadResult;
 use crate::assets::{
@@ -21,6 +22,7 @@ use crate::assets::{
 //
 pub struct LoadRequest<AssetDataT, AssetT> {
     pub load_handle: LoadHandle,
+    pub asset_uuid: AssetUuid,
     pub load_op: AssetLoadOp,
     pub result_tx: Sender<AssetT>,
     pub asset: AssetDataT,
@@ -130,6 +132,7 @@ where
     fn update_asset(
         &mut self,
         load_handle: LoadHandle,
+        asset_uuid: AssetUuid,
         load_op: AssetLoadOp,
         asset: AssetDataT,
     ) -> ResourceLoadResult<AssetT> {
@@ -143,6 +146,7 @@ where
 
         let request = LoadRequest {
             load_handle,
+            asset_uuid,
             load_op,
             result_tx,
             asset,
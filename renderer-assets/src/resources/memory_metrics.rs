@@ -0,0 +1,259 @@
+use ash::vk;
+use renderer_shell_vulkan::{
+    VkDeviceContext, VkImageRaw, VkBufferRaw, VkMemoryHeapBudget, VkMemoryUsage,
+};
+use crate::resources::resource_lookup::{
+    DescriptorSetLayoutResource, PipelineLayoutResource, PipelineResource, ImageViewResource,
+};
+
+/// Implemented by everything stored in a ResourceLookup or DynResourceAllocator so that the device
+/// memory they own can be counted. Only images and buffers own memory directly.
+pub trait AllocatedResource {
+    fn allocated_bytes(
+        &self,
+        _device_context: &VkDeviceContext,
+    ) -> vk::DeviceSize {
+        0
+    }
+}
+
+fn allocation_size(
+    device_context: &VkDeviceContext,
+    allocation: &vk_mem::Allocation,
+) -> vk::DeviceSize {
+    device_context
+        .allocator()
+        .get_allocation_info(allocation)
+        .map(|allocation_info| allocation_info.get_size() as vk::DeviceSize)
+        .unwrap_or(0)
+}
+
+impl AllocatedResource for VkImageRaw {
+    fn allocated_bytes(
+        &self,
+        device_context: &VkDeviceContext,
+    ) -> vk::DeviceSize {
+        allocation_size(device_context, &self.allocation)
+    }
+}
+
+impl AllocatedResource for VkBufferRaw {
+    fn allocated_bytes(
+        &self,
+        device_context: &VkDeviceContext,
+    ) -> vk::DeviceSize {
+        allocation_size(device_context, &self.allocation)
+    }
+}
+
+// Image views reference an image but the memory is counted with the image
+impl AllocatedResource for ImageViewResource {}
+impl AllocatedResource for PipelineResource {}
+impl AllocatedResource for PipelineLayoutResource {}
+impl AllocatedResource for DescriptorSetLayoutResource {}
+impl AllocatedResource for vk::RenderPass {}
+impl AllocatedResource for vk::ShaderModule {}
+impl AllocatedResource for vk::Sampler {}
+
+/// Bytes of device memory held by the images and buffers in a resource lookup or dyn resource
+/// allocator. Resources that were dropped but are waiting for in-flight frames to finish before
+/// being destroyed are not included.
+#[derive(Copy, Clone, Debug, Default)]
+pub struct ResourceMemoryUsage {
+    pub image_bytes: vk::DeviceSize,
+    pub buffer_bytes: vk::DeviceSize,
+}
+
+impl ResourceMemoryUsage {
+    pub fn total_bytes(&self) -> vk::DeviceSize {
+        self.image_bytes + self.buffer_bytes
+    }
+}
+
+/// A snapshot of memory usage, see `ResourceManager::memory_metrics()`
+#[derive(Clone, Debug)]
+pub struct MemoryMetrics {
+    /// Images and buffers loaded from assets
+    pub loaded_resources: ResourceMemoryUsage,
+
+    /// Images and buffers created at runtime through a DynResourceAllocatorSet
+    pub dyn_resources: ResourceMemoryUsage,
+
+    /// Staging buffers of uploads that are in progress
+    pub staging_bytes: vk::DeviceSize,
+
+    /// Buffers that back descriptor sets with internally managed uniform data
+    pub descriptor_buffer_bytes: vk::DeviceSize,
+
+    /// The budget set with `ResourceManager::set_memory_budget()`
+    pub budget: Option<vk::DeviceSize>,
+
+    /// Usage of each memory heap as reported by vk-mem and VK_EXT_memory_budget. This includes
+    /// memory the categories above don't track, like swapchain resources.
+    pub device_memory: VkMemoryUsage,
+}
+
+impl MemoryMetrics {
+    pub fn image_bytes(&self) -> vk::DeviceSize {
+        self.loaded_resources.image_bytes + self.dyn_resources.image_bytes
+    }
+
+    pub fn buffer_bytes(&self) -> vk::DeviceSize {
+        self.loaded_resources.buffer_bytes + self.dyn_resources.buffer_bytes
+    }
+
+    /// Sum of all categories. This is what is compared against the budget.
+    pub fn tracked_bytes(&self) -> vk::DeviceSize {
+        self.loaded_resources.total_bytes()
+            + self.dyn_resources.total_bytes()
+            + self.staging_bytes
+            + self.descriptor_buffer_bytes
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub enum MemoryBudgetWarning {
+    /// The memory tracked by the resource manager exceeds the budget that was set
+    OverBudget {
+        tracked_bytes: vk::DeviceSize,
+        budget: vk::DeviceSize,
+    },
+
+    /// VK_EXT_memory_budget reports a heap using more than the driver's budget
+    HeapOverBudget {
+        heap_index: usize,
+        usage: vk::DeviceSize,
+        budget: vk::DeviceSize,
+    },
+}
+
+impl core::fmt::Display for MemoryBudgetWarning {
+    fn fmt(
+        &self,
+        fmt: &mut core::fmt::Formatter,
+    ) -> core::fmt::Result {
+        match *self {
+            MemoryBudgetWarning::OverBudget {
+                tracked_bytes,
+                budget,
+            } => write!(
+                fmt,
+                "GPU memory used by resources ({} bytes) exceeds the budget of {} bytes",
+                tracked_bytes, budget
+            ),
+            MemoryBudgetWarning::HeapOverBudget {
+                heap_index,
+                usage,
+                budget,
+            } => write!(
+                fmt,
+                "Memory heap {} is using {} bytes, which exceeds the driver's budget of {} bytes",
+                heap_index, usage, budget
+            ),
+        }
+    }
+}
+
+/// Checks memory usage against the budgets every frame. A warning is produced when usage goes over
+/// a budget, and not again until it has gone back under it.
+#[derive(Default)]
+pub(super) struct MemoryBudgetMonitor {
+    budget: Option<vk::DeviceSize>,
+    over_budget: bool,
+    heaps_over_budget: Vec<bool>,
+}
+
+impl MemoryBudgetMonitor {
+    pub(super) fn budget(&self) -> Option<vk::DeviceSize> {
+        self.budget
+    }
+
+    pub(super) fn set_budget(
+        &mut self,
+        budget: Option<vk::DeviceSize>,
+    ) {
+        self.budget = budget;
+        self.over_budget = false;
+    }
+
+    pub(super) fn update(
+        &mut self,
+        tracked_bytes: vk::DeviceSize,
+        heap_budgets: Option<&[VkMemoryHeapBudget]>,
+    ) -> Vec<MemoryBudgetWarning> {
+        let mut warnings = vec![];
+
+        let over_budget = self.budget.map(|budget| tracked_bytes > budget);
+        if over_budget == Some(true) && !self.over_budget {
+            warnings.push(MemoryBudgetWarning::OverBudget {
+                tracked_bytes,
+                budget: self.budget.unwrap(),
+            });
+        }
+        self.over_budget = over_budget.unwrap_or(false);
+
+        let heap_budgets = heap_budgets.unwrap_or(&[]);
+        self.heaps_over_budget.resize(heap_budgets.len(), false);
+        for (heap_index, heap_budget) in heap_budgets.iter().enumerate() {
+            let over_budget = heap_budget.usage > heap_budget.budget;
+            if over_budget && !self.heaps_over_budget[heap_index] {
+                warnings.push(MemoryBudgetWarning::HeapOverBudget {
+                    heap_index,
+                    usage: heap_budget.usage,
+                    budget: heap_budget.budget,
+                });
+            }
+            self.heaps_over_budget[heap_index] = over_budget;
+        }
+
+        warnings
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_budget_warns_once_per_crossing() {
+        let mut monitor = MemoryBudgetMonitor::default();
+        assert!(monitor.update(1000, None).is_empty());
+
+        monitor.set_budget(Some(500));
+        assert_eq!(
+            monitor.update(1000, None),
+            vec![MemoryBudgetWarning::OverBudget {
+                tracked_bytes: 1000,
+                budget: 500
+            }]
+        );
+        assert!(monitor.update(1200, None).is_empty());
+
+        assert!(monitor.update(400, None).is_empty());
+        assert_eq!(monitor.update(600, None).len(), 1);
+    }
+
+    #[test]
+    fn test_heap_budget() {
+        let under = VkMemoryHeapBudget {
+            budget: 100,
+            usage: 50,
+        };
+        let over = VkMemoryHeapBudget {
+            budget: 100,
+            usage: 150,
+        };
+
+        let mut monitor = MemoryBudgetMonitor::default();
+        assert_eq!(
+            monitor.update(0, Some(&[under, over])),
+            vec![MemoryBudgetWarning::HeapOverBudget {
+                heap_index: 1,
+                usage: 150,
+                budget: 100
+            }]
+        );
+        assert!(monitor.update(0, Some(&[under, over])).is_empty());
+        assert_eq!(monitor.update(0, Some(&[over, over])).len(), 1);
+    }
+}
@@ -7,6 +7,13 @@ pub use resource_lookup::ResourceHash;
 pub use resource_lookup::DescriptorSetLayoutResource;
 pub use resource_lookup::ImageKey;
 pub use resource_lookup::BufferKey;
+pub use resource_lookup::DebugNamedResource;
+
+mod memory_metrics;
+pub use memory_metrics::AllocatedResource;
+pub use memory_metrics::ResourceMemoryUsage;
+pub use memory_metrics::MemoryMetrics;
+pub use memory_metrics::MemoryBudgetWarning;
 
 mod dyn_resource_allocator;
 pub use dyn_resource_allocator::DynResourceAllocatorSet;
@@ -37,6 +44,9 @@ pub use crate::resources::resource_lookup::PipelineResource;
 
 pub use resource_lookup::ImageViewResource;
 
+mod pipeline_cache;
+pub use pipeline_cache::PipelineCache;
+
 mod pipeline_create_data;
 pub use pipeline_create_data::PipelineCreateData;
 
@@ -0,0 +1,417 @@
+use ash::vk;
+use ash::prelude::VkResult;
+use ash::version::DeviceV1_0;
+use renderer_shell_vulkan::VkDeviceContext;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+use std::hash::Hasher;
+
+// How often the cache is written to disk if new pipelines were created
+const SAVE_INTERVAL: Duration = Duration::from_secs(60);
+
+const FILE_MAGIC: &[u8; 8] = b"RPPCACHE";
+const FILE_VERSION: u32 = 1;
+
+// magic, version, vendor id, device id, driver version, cache uuid, data length, data hash
+const FILE_HEADER_SIZE: usize = 8 + 4 + 4 + 4 + 4 + vk::UUID_SIZE + 8 + 8;
+
+// The header vulkan puts at the start of pipeline cache data (VK_PIPELINE_CACHE_HEADER_VERSION_ONE)
+const VK_CACHE_HEADER_SIZE: usize = 16 + vk::UUID_SIZE;
+
+/// Identifies the device and driver a pipeline cache was created with. Cache data is only valid
+/// for the same device and driver.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct PipelineCacheDeviceInfo {
+    pub vendor_id: u32,
+    pub device_id: u32,
+    pub driver_version: u32,
+    pub pipeline_cache_uuid: [u8; vk::UUID_SIZE],
+}
+
+impl PipelineCacheDeviceInfo {
+    pub fn new(properties: &vk::PhysicalDeviceProperties) -> Self {
+        PipelineCacheDeviceInfo {
+            vendor_id: properties.vendor_id,
+            device_id: properties.device_id,
+            driver_version: properties.driver_version,
+            pipeline_cache_uuid: properties.pipeline_cache_uuid,
+        }
+    }
+}
+
+/// Reasons a pipeline cache file is discarded
+#[derive(Debug, PartialEq)]
+pub enum PipelineCacheFileError {
+    NotACacheFile,
+    UnsupportedVersion(u32),
+    DeviceMismatch,
+    DriverVersionMismatch,
+    Corrupt,
+}
+
+impl std::error::Error for PipelineCacheFileError {}
+
+impl core::fmt::Display for PipelineCacheFileError {
+    fn fmt(
+        &self,
+        fmt: &mut core::fmt::Formatter,
+    ) -> core::fmt::Result {
+        match *self {
+            PipelineCacheFileError::NotACacheFile => write!(fmt, "Not a pipeline cache file"),
+            PipelineCacheFileError::UnsupportedVersion(version) => {
+                write!(fmt, "Unsupported pipeline cache file version {}", version)
+            }
+            PipelineCacheFileError::DeviceMismatch => {
+                write!(fmt, "Pipeline cache was created for a different device")
+            }
+            PipelineCacheFileError::DriverVersionMismatch => {
+                write!(
+                    fmt,
+                    "Pipeline cache was created with a different driver version"
+                )
+            }
+            PipelineCacheFileError::Corrupt => write!(fmt, "Pipeline cache file is corrupt"),
+        }
+    }
+}
+
+/// Wraps vulkan pipeline cache data with the device info and a hash of the data
+pub fn write_pipeline_cache_file(
+    device_info: &PipelineCacheDeviceInfo,
+    data: &[u8],
+) -> Vec<u8> {
+    let mut file = Vec::with_capacity(FILE_HEADER_SIZE + data.len());
+    file.extend_from_slice(FILE_MAGIC);
+    file.extend_from_slice(&FILE_VERSION.to_le_bytes());
+    file.extend_from_slice(&device_info.vendor_id.to_le_bytes());
+    file.extend_from_slice(&device_info.device_id.to_le_bytes());
+    file.extend_from_slice(&device_info.driver_version.to_le_bytes());
+    file.extend_from_slice(&device_info.pipeline_cache_uuid);
+    file.extend_from_slice(&(data.len() as u64).to_le_bytes());
+    file.extend_from_slice(&hash_data(data).to_le_bytes());
+    file.extend_from_slice(data);
+    file
+}
+
+/// Returns the vulkan pipeline cache data in a file written by `write_pipeline_cache_file`, if
+/// the file is intact and was written for the given device and driver
+pub fn read_pipeline_cache_file<'a>(
+    device_info: &PipelineCacheDeviceInfo,
+    file: &'a [u8],
+) -> Result<&'a [u8], PipelineCacheFileError> {
+    if file.len() < FILE_HEADER_SIZE || &file[0..8] != FILE_MAGIC {
+        return Err(PipelineCacheFileError::NotACacheFile);
+    }
+
+    let version = read_u32(file, 8);
+    if version != FILE_VERSION {
+        return Err(PipelineCacheFileError::UnsupportedVersion(version));
+    }
+
+    let vendor_id = read_u32(file, 12);
+    let device_id = read_u32(file, 16);
+    let driver_version = read_u32(file, 20);
+    let pipeline_cache_uuid = &file[24..24 + vk::UUID_SIZE];
+    if vendor_id != device_info.vendor_id
+        || device_id != device_info.device_id
+        || pipeline_cache_uuid != device_info.pipeline_cache_uuid
+    {
+        return Err(PipelineCacheFileError::DeviceMismatch);
+    }
+
+    if driver_version != device_info.driver_version {
+        return Err(PipelineCacheFileError::DriverVersionMismatch);
+    }
+
+    let data_len = read_u64(file, 24 + vk::UUID_SIZE);
+    let data_hash = read_u64(file, 32 + vk::UUID_SIZE);
+    let data = &file[FILE_HEADER_SIZE..];
+    if data.len() as u64 != data_len || hash_data(data) != data_hash {
+        return Err(PipelineCacheFileError::Corrupt);
+    }
+
+    // Drivers are supposed to reject data that doesn't match, but don't rely on it
+    if data.len() < VK_CACHE_HEADER_SIZE
+        || (read_u32(data, 0) as usize) < VK_CACHE_HEADER_SIZE
+        || read_u32(data, 4) != vk::PipelineCacheHeaderVersion::ONE.as_raw() as u32
+        || read_u32(data, 8) != device_info.vendor_id
+        || read_u32(data, 12) != device_info.device_id
+        || data[16..VK_CACHE_HEADER_SIZE] != device_info.pipeline_cache_uuid
+    {
+        return Err(PipelineCacheFileError::Corrupt);
+    }
+
+    Ok(data)
+}
+
+fn read_u32(
+    bytes: &[u8],
+    offset: usize,
+) -> u32 {
+    let mut value = [0; 4];
+    value.copy_from_slice(&bytes[offset..offset + 4]);
+    u32::from_le_bytes(value)
+}
+
+fn read_u64(
+    bytes: &[u8],
+    offset: usize,
+) -> u64 {
+    let mut value = [0; 8];
+    value.copy_from_slice(&bytes[offset..offset + 8]);
+    u64::from_le_bytes(value)
+}
+
+fn hash_data(data: &[u8]) -> u64 {
+    let mut hasher = fnv::FnvHasher::default();
+    hasher.write(data);
+    hasher.finish()
+}
+
+/// Owns the vk::PipelineCache used for all pipeline creation. If a file path is given, the cache
+/// is loaded from it on startup and written back periodically and on destroy, so pipelines don't
+/// need to be recompiled from scratch on every launch. Files written for a different device or
+/// driver, or that are damaged, are ignored (and replaced on the next save).
+pub struct PipelineCache {
+    device_context: VkDeviceContext,
+    pipeline_cache: vk::PipelineCache,
+    file_path: Option<PathBuf>,
+    device_info: PipelineCacheDeviceInfo,
+
+    // True if pipelines were created since the cache was last saved
+    dirty: bool,
+    last_save: Instant,
+}
+
+impl PipelineCache {
+    pub fn new(
+        device_context: &VkDeviceContext,
+        file_path: Option<&Path>,
+    ) -> Self {
+        let device_info =
+            PipelineCacheDeviceInfo::new(&device_context.physical_device_info().properties);
+
+        let file = file_path.and_then(|file_path| match std::fs::read(file_path) {
+            Ok(file) => Some(file),
+            Err(e) => {
+                log::info!("No pipeline cache loaded from {:?}: {}", file_path, e);
+                None
+            }
+        });
+
+        let initial_data = match &file {
+            Some(file) => match read_pipeline_cache_file(&device_info, file) {
+                Ok(data) => {
+                    log::info!(
+                        "Loaded pipeline cache from {:?} ({} bytes)",
+                        file_path.unwrap(),
+                        data.len()
+                    );
+                    data
+                }
+                Err(e) => {
+                    log::warn!("Discarding pipeline cache {:?}: {}", file_path.unwrap(), e);
+                    &[]
+                }
+            },
+            None => &[],
+        };
+
+        // A null cache is valid to create pipelines with, it just doesn't cache anything
+        let pipeline_cache = Self::create_pipeline_cache(device_context, initial_data)
+            .or_else(|e| {
+                // Shouldn't happen since the data was validated, but an empty cache always works
+                log::warn!("Failed to create pipeline cache from file: {:?}", e);
+                Self::create_pipeline_cache(device_context, &[])
+            })
+            .unwrap_or_else(|e| {
+                log::warn!(
+                    "Failed to create pipeline cache, pipelines won't be cached: {:?}",
+                    e
+                );
+                vk::PipelineCache::null()
+            });
+
+        PipelineCache {
+            device_context: device_context.clone(),
+            pipeline_cache,
+            file_path: file_path.map(|x| x.to_path_buf()),
+            device_info,
+            dirty: false,
+            last_save: Instant::now(),
+        }
+    }
+
+    fn create_pipeline_cache(
+        device_context: &VkDeviceContext,
+        initial_data: &[u8],
+    ) -> VkResult<vk::PipelineCache> {
+        let create_info = vk::PipelineCacheCreateInfo::builder().initial_data(initial_data);
+        unsafe {
+            device_context
+                .device()
+                .create_pipeline_cache(&create_info, None)
+        }
+    }
+
+    pub fn pipeline_cache(&self) -> vk::PipelineCache {
+        self.pipeline_cache
+    }
+
+    /// Call after creating pipelines with the cache
+    pub fn mark_dirty(&mut self) {
+        self.dirty = true;
+    }
+
+    /// Saves the cache if pipelines were created and it hasn't been saved for a while
+    pub fn save_if_due(&mut self) -> VkResult<()> {
+        if self.dirty && self.last_save.elapsed() >= SAVE_INTERVAL {
+            self.save()?;
+        }
+
+        Ok(())
+    }
+
+    /// Writes the cache to the file it was loaded from. Failing to write the file is logged but
+    /// not returned as an error.
+    pub fn save(&mut self) -> VkResult<()> {
+        self.dirty = false;
+        self.last_save = Instant::now();
+
+        let file_path = match &self.file_path {
+            Some(file_path) if self.pipeline_cache != vk::PipelineCache::null() => file_path,
+            _ => return Ok(()),
+        };
+
+        let data = unsafe {
+            self.device_context
+                .device()
+                .get_pipeline_cache_data(self.pipeline_cache)?
+        };
+
+        // Write to a temporary file and rename it so that a crash while writing can't leave a
+        // partial file behind
+        let file = write_pipeline_cache_file(&self.device_info, &data);
+        let temp_path = file_path.with_extension("tmp");
+        let result =
+            std::fs::write(&temp_path, &file).and_then(|_| std::fs::rename(&temp_path, file_path));
+
+        match result {
+            Ok(()) => log::info!(
+                "Saved pipeline cache to {:?} ({} bytes)",
+                file_path,
+                data.len()
+            ),
+            Err(e) => log::warn!("Failed to save pipeline cache to {:?}: {}", file_path, e),
+        }
+
+        Ok(())
+    }
+
+    /// Saves the cache if needed and destroys it. Pipelines created with it remain valid.
+    pub fn destroy(&mut self) -> VkResult<()> {
+        if self.pipeline_cache == vk::PipelineCache::null() {
+            return Ok(());
+        }
+
+        if self.dirty {
+            self.save()?;
+        }
+
+        unsafe {
+            self.device_context
+                .device()
+                .destroy_pipeline_cache(self.pipeline_cache, None);
+        }
+        self.pipeline_cache = vk::PipelineCache::null();
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn device_info() -> PipelineCacheDeviceInfo {
+        PipelineCacheDeviceInfo {
+            vendor_id: 0x10DE,
+            device_id: 0x1234,
+            driver_version: 42,
+            pipeline_cache_uuid: [7; vk::UUID_SIZE],
+        }
+    }
+
+    // Cache data as a driver would return it: a vulkan cache header and some payload
+    fn cache_data(device_info: &PipelineCacheDeviceInfo) -> Vec<u8> {
+        let mut data = vec![];
+        data.extend_from_slice(&(VK_CACHE_HEADER_SIZE as u32).to_le_bytes());
+        data.extend_from_slice(&1u32.to_le_bytes());
+        data.extend_from_slice(&device_info.vendor_id.to_le_bytes());
+        data.extend_from_slice(&device_info.device_id.to_le_bytes());
+        data.extend_from_slice(&device_info.pipeline_cache_uuid);
+        data.extend_from_slice(&[1, 2, 3, 4, 5]);
+        data
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let device_info = device_info();
+        let data = cache_data(&device_info);
+        let file = write_pipeline_cache_file(&device_info, &data);
+        assert_eq!(read_pipeline_cache_file(&device_info, &file), Ok(&data[..]));
+    }
+
+    #[test]
+    fn test_mismatched_device_or_driver() {
+        let device_info = device_info();
+        let file = write_pipeline_cache_file(&device_info, &cache_data(&device_info));
+
+        let mut other_device = device_info;
+        other_device.pipeline_cache_uuid[0] = 8;
+        assert_eq!(
+            read_pipeline_cache_file(&other_device, &file),
+            Err(PipelineCacheFileError::DeviceMismatch)
+        );
+
+        let mut other_driver = device_info;
+        other_driver.driver_version = 43;
+        assert_eq!(
+            read_pipeline_cache_file(&other_driver, &file),
+            Err(PipelineCacheFileError::DriverVersionMismatch)
+        );
+    }
+
+    #[test]
+    fn test_corrupt_file() {
+        let device_info = device_info();
+        let file = write_pipeline_cache_file(&device_info, &cache_data(&device_info));
+
+        assert_eq!(
+            read_pipeline_cache_file(&device_info, b"not a cache"),
+            Err(PipelineCacheFileError::NotACacheFile)
+        );
+
+        let mut flipped = file.clone();
+        *flipped.last_mut().unwrap() ^= 0xFF;
+        assert_eq!(
+            read_pipeline_cache_file(&device_info, &flipped),
+            Err(PipelineCacheFileError::Corrupt)
+        );
+
+        let truncated = &file[..file.len() - 1];
+        assert_eq!(
+            read_pipeline_cache_file(&device_info, truncated),
+            Err(PipelineCacheFileError::Corrupt)
+        );
+
+        // Intact file, but the data inside isn't for this device
+        let mut other_device = device_info;
+        other_device.device_id = 0x5678;
+        let file = write_pipeline_cache_file(&device_info, &cache_data(&other_device));
+        assert_eq!(
+            read_pipeline_cache_file(&device_info, &file),
+            Err(PipelineCacheFileError::Corrupt)
+        );
+    }
+}
@@ -42,6 +42,10 @@ where
 {
     resource: ResourceWithHash<ResourceT>,
     drop_tx: Sender<ResourceWithHash<ResourceT>>,
+
+    // Only captured if lifetime tracking was enabled when the resource was created. It is resolved
+    // only if it needs to be printed
+    create_backtrace: Option<backtrace::Backtrace>,
 }
 
 impl<ResourceT> Drop for ResourceArcInner<ResourceT>
@@ -118,7 +122,14 @@ where
         resource: ResourceT,
         resource_hash: ResourceId,
         drop_tx: Sender<ResourceWithHash<ResourceT>>,
+        track_lifetime: bool,
     ) -> Self {
+        let create_backtrace = if track_lifetime {
+            Some(backtrace::Backtrace::new_unresolved())
+        } else {
+            None
+        };
+
         ResourceArc {
             inner: Arc::new(ResourceArcInner {
                 resource: ResourceWithHash {
@@ -126,6 +137,7 @@ where
                     resource_hash,
                 },
                 drop_tx,
+                create_backtrace,
             }),
         }
     }
@@ -144,6 +156,40 @@ where
     }
 }
 
+impl<ResourceT> ResourceArc<ResourceT>
+where
+    ResourceT: std::fmt::Debug + Clone,
+{
+    // Used to report resources that are still referenced when they are expected to be released
+    pub(super) fn log_still_alive(&self) {
+        // Don't count the reference held by the caller
+        let reference_count = Arc::strong_count(&self.inner) - 1;
+        match &self.inner.create_backtrace {
+            Some(create_backtrace) => {
+                let mut create_backtrace = create_backtrace.clone();
+                create_backtrace.resolve();
+                log::warn!(
+                    "{} {:?} is still alive ({} references): {:?}\nCreated at:\n{:?}",
+                    core::any::type_name::<ResourceT>(),
+                    self.get_hash(),
+                    reference_count,
+                    self.inner.resource.resource,
+                    create_backtrace
+                );
+            }
+            None => {
+                log::warn!(
+                    "{} {:?} is still alive ({} references): {:?}\nEnable resource lifetime tracking before it is created to see where it was created",
+                    core::any::type_name::<ResourceT>(),
+                    self.get_hash(),
+                    reference_count,
+                    self.inner.resource.resource
+                );
+            }
+        }
+    }
+}
+
 impl<ResourceT> std::fmt::Debug for ResourceArc<ResourceT>
 where
     ResourceT: std::fmt::Debug + Clone,
@@ -157,3 +203,38 @@ where
             .finish()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_backtrace_captured_only_when_tracking() {
+        let (drop_tx, _drop_rx) = crossbeam_channel::unbounded();
+
+        let untracked = ResourceArc::new(1u32, ResourceId(1), drop_tx.clone(), false);
+        assert!(untracked.inner.create_backtrace.is_none());
+
+        let tracked = ResourceArc::new(2u32, ResourceId(2), drop_tx, true);
+        assert!(tracked.inner.create_backtrace.is_some());
+    }
+
+    #[test]
+    fn test_drop_signalled_after_last_reference() {
+        let (drop_tx, drop_rx) = crossbeam_channel::unbounded();
+
+        let arc = ResourceArc::new(1u32, ResourceId(1), drop_tx, false);
+        let weak = arc.downgrade();
+        let clone = arc.clone();
+
+        drop(arc);
+        assert!(drop_rx.try_recv().is_err());
+        assert!(weak.upgrade().is_some());
+
+        drop(clone);
+        let dropped = drop_rx.try_recv().unwrap();
+        assert_eq!(dropped.resource, 1);
+        assert_eq!(dropped.resource_hash, ResourceId(1));
+        assert!(weak.upgrade().is_none());
+    }
+}
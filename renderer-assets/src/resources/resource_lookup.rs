@@ -9,6 +9,9 @@ use ash::vk;
 use ash::prelude::VkResult;
 use crate::vk_description::SwapchainSurfaceInfo;
 use super::PipelineCreateData;
+use super::PipelineCache;
+use super::memory_metrics::{AllocatedResource, ResourceMemoryUsage};
+use std::path::Path;
 use std::mem::ManuallyDrop;
 use crate::vk_description as dsc;
 use crate::resources::ResourceArc;
@@ -63,14 +66,21 @@ where
     drop_tx: Sender<ResourceWithHash<ResourceT>>,
     drop_rx: Receiver<ResourceWithHash<ResourceT>>,
     phantom_data: PhantomData<KeyT>,
-    #[cfg(debug_assertions)]
+
+    // Only populated in debug builds or if lifetime tracking is enabled
     keys: FnvHashMap<ResourceHash, KeyT>,
+
+    // Device memory owned by resources in the lookup
+    allocated_bytes: vk::DeviceSize,
+
+    // If true, a backtrace is captured when a resource is created so that leaks can be reported
+    track_lifetimes: bool,
 }
 
 impl<KeyT, ResourceT> ResourceLookup<KeyT, ResourceT>
 where
     KeyT: Eq + Hash + Clone,
-    ResourceT: VkResource + Clone + std::fmt::Debug,
+    ResourceT: VkResource + AllocatedResource + Clone + std::fmt::Debug,
 {
     fn new(max_frames_in_flight: u32) -> Self {
         let (drop_tx, drop_rx) = crossbeam_channel::unbounded();
@@ -81,8 +91,9 @@ where
             drop_tx,
             drop_rx,
             phantom_data: Default::default(),
-            #[cfg(debug_assertions)]
             keys: Default::default(),
+            allocated_bytes: 0,
+            track_lifetimes: false,
         }
     }
 
@@ -109,13 +120,16 @@ where
 
     fn insert(
         &mut self,
+        device_context: &VkDeviceContext,
         hash: ResourceHash,
         key: &KeyT,
         resource: ResourceT,
     ) -> ResourceArc<ResourceT> {
         // Process any pending drops. If we don't do this, it's possible that the pending drop could
         // wipe out the state we're about to set
-        self.handle_dropped_resources();
+        self.handle_dropped_resources(device_context);
+
+        self.allocated_bytes += resource.allocated_bytes(device_context);
 
         log::trace!(
             "insert resource {} {:?}",
@@ -123,34 +137,48 @@ where
             resource
         );
 
-        let arc = ResourceArc::new(resource, hash.into(), self.drop_tx.clone());
+        self.insert_arc(hash, key, resource)
+    }
+
+    // Wraps the resource in an arc and registers it. Doesn't handle pending drops or count the
+    // resource's memory, as both need a device.
+    fn insert_arc(
+        &mut self,
+        hash: ResourceHash,
+        key: &KeyT,
+        resource: ResourceT,
+    ) -> ResourceArc<ResourceT> {
+        let arc = ResourceArc::new(
+            resource,
+            hash.into(),
+            self.drop_tx.clone(),
+            self.track_lifetimes,
+        );
         let downgraded = arc.downgrade();
         let old = self.resources.insert(hash, downgraded);
         assert!(old.is_none());
 
-        #[cfg(debug_assertions)]
-        {
+        if cfg!(debug_assertions) || self.track_lifetimes {
             self.keys.insert(hash, key.clone());
-            assert!(old.is_none());
         }
 
         arc
     }
 
-    fn handle_dropped_resources(&mut self) {
+    fn handle_dropped_resources(
+        &mut self,
+        device_context: &VkDeviceContext,
+    ) {
         for dropped in self.drop_rx.try_iter() {
             log::trace!(
                 "dropping {} {:?}",
                 core::any::type_name::<ResourceT>(),
                 dropped.resource
             );
+            self.allocated_bytes -= dropped.resource.allocated_bytes(device_context);
             self.drop_sink.retire(dropped.resource);
             self.resources.remove(&dropped.resource_hash.into());
-
-            #[cfg(debug_assertions)]
-            {
-                self.keys.remove(&dropped.resource_hash.into());
-            }
+            self.keys.remove(&dropped.resource_hash.into());
         }
     }
 
@@ -158,11 +186,56 @@ where
         self.resources.len()
     }
 
+    fn set_track_lifetimes(
+        &mut self,
+        track_lifetimes: bool,
+    ) {
+        self.track_lifetimes = track_lifetimes;
+    }
+
+    // Logs every resource that is still referenced and returns how many were logged
+    fn report_live_resources(&self) -> usize {
+        let mut count = 0;
+        for resource in self.resources.values() {
+            if let Some(resource) = resource.upgrade() {
+                resource.log_still_alive();
+                count += 1;
+            }
+        }
+
+        count
+    }
+
+    // Logs every resource that is still referenced and has a key matching the filter. Only
+    // resources created in debug builds or with lifetime tracking enabled have a key to check.
+    fn report_live_resources_with_key<F: Fn(&KeyT) -> bool>(
+        &self,
+        filter: F,
+    ) -> usize {
+        let mut count = 0;
+        for (hash, key) in &self.keys {
+            if !filter(key) {
+                continue;
+            }
+
+            if let Some(resource) = self.resources.get(hash).and_then(|x| x.upgrade()) {
+                resource.log_still_alive();
+                count += 1;
+            }
+        }
+
+        count
+    }
+
+    fn allocated_bytes(&self) -> vk::DeviceSize {
+        self.allocated_bytes
+    }
+
     fn on_frame_complete(
         &mut self,
         device_context: &VkDeviceContext,
     ) -> VkResult<()> {
-        self.handle_dropped_resources();
+        self.handle_dropped_resources(device_context);
         self.drop_sink.on_frame_complete(device_context)
     }
 
@@ -170,7 +243,7 @@ where
         &mut self,
         device_context: &VkDeviceContext,
     ) -> VkResult<()> {
-        self.handle_dropped_resources();
+        self.handle_dropped_resources(device_context);
 
         if self.resources.len() > 0 {
             log::warn!(
@@ -178,6 +251,7 @@ where
                 core::any::type_name::<ResourceT>(),
                 self.resources.len()
             );
+            self.report_live_resources();
         }
 
         self.drop_sink.destroy(device_context)
@@ -301,6 +375,104 @@ impl VkResource for ImageViewResource {
     }
 }
 
+/// Resources whose vulkan objects can be given a debug name, see
+/// `VkDeviceContext::set_debug_name`
+pub trait DebugNamedResource {
+    fn set_debug_name(
+        &self,
+        device_context: &VkDeviceContext,
+        name: &str,
+    );
+}
+
+impl DebugNamedResource for VkImageRaw {
+    fn set_debug_name(
+        &self,
+        device_context: &VkDeviceContext,
+        name: &str,
+    ) {
+        device_context.set_debug_name(self.image, name);
+    }
+}
+
+impl DebugNamedResource for VkBufferRaw {
+    fn set_debug_name(
+        &self,
+        device_context: &VkDeviceContext,
+        name: &str,
+    ) {
+        device_context.set_debug_name(self.buffer, name);
+    }
+}
+
+impl DebugNamedResource for ImageViewResource {
+    fn set_debug_name(
+        &self,
+        device_context: &VkDeviceContext,
+        name: &str,
+    ) {
+        device_context.set_debug_name(self.image_view, name);
+    }
+}
+
+impl DebugNamedResource for PipelineResource {
+    fn set_debug_name(
+        &self,
+        device_context: &VkDeviceContext,
+        name: &str,
+    ) {
+        // One pipeline per subpass
+        if self.pipelines.len() == 1 {
+            device_context.set_debug_name(self.pipelines[0], name);
+        } else {
+            for (subpass_index, pipeline) in self.pipelines.iter().enumerate() {
+                device_context
+                    .set_debug_name(*pipeline, &format!("{} (subpass {})", name, subpass_index));
+            }
+        }
+    }
+}
+
+impl DebugNamedResource for PipelineLayoutResource {
+    fn set_debug_name(
+        &self,
+        device_context: &VkDeviceContext,
+        name: &str,
+    ) {
+        device_context.set_debug_name(self.pipeline_layout, name);
+    }
+}
+
+impl DebugNamedResource for DescriptorSetLayoutResource {
+    fn set_debug_name(
+        &self,
+        device_context: &VkDeviceContext,
+        name: &str,
+    ) {
+        device_context.set_debug_name(self.descriptor_set_layout, name);
+    }
+}
+
+impl DebugNamedResource for vk::RenderPass {
+    fn set_debug_name(
+        &self,
+        device_context: &VkDeviceContext,
+        name: &str,
+    ) {
+        device_context.set_debug_name(*self, name);
+    }
+}
+
+impl DebugNamedResource for vk::ShaderModule {
+    fn set_debug_name(
+        &self,
+        device_context: &VkDeviceContext,
+        name: &str,
+    ) {
+        device_context.set_debug_name(*self, name);
+    }
+}
+
 //
 // Handles raw lookup and destruction of GPU resources. Everything is reference counted. No safety
 // is provided for dependencies/order of destruction. The general expectation is that anything
@@ -323,6 +495,9 @@ pub struct ResourceLookupSet {
     pub samplers: ResourceLookup<dsc::Sampler, vk::Sampler>,
     pub buffers: ResourceLookup<BufferKey, VkBufferRaw>,
 
+    // Used for all pipeline creation, optionally persisted to disk
+    pub pipeline_cache: PipelineCache,
+
     // Used to generate keys for images/buffers
     pub next_image_id: u64,
     pub next_buffer_id: u64,
@@ -332,6 +507,7 @@ impl ResourceLookupSet {
     pub fn new(
         device_context: &VkDeviceContext,
         max_frames_in_flight: u32,
+        pipeline_cache_file: Option<&Path>,
     ) -> Self {
         ResourceLookupSet {
             device_context: device_context.clone(),
@@ -344,6 +520,7 @@ impl ResourceLookupSet {
             image_views: ResourceLookup::new(max_frames_in_flight),
             samplers: ResourceLookup::new(max_frames_in_flight),
             buffers: ResourceLookup::new(max_frames_in_flight),
+            pipeline_cache: PipelineCache::new(device_context, pipeline_cache_file),
             next_image_id: 0,
             next_buffer_id: 0,
         }
@@ -363,6 +540,7 @@ impl ResourceLookupSet {
             .on_frame_complete(&self.device_context)?;
         self.images.on_frame_complete(&self.device_context)?;
         self.image_views.on_frame_complete(&self.device_context)?;
+        self.pipeline_cache.save_if_due()?;
         Ok(())
     }
 
@@ -378,9 +556,82 @@ impl ResourceLookupSet {
         self.samplers.destroy(&self.device_context)?;
         self.shader_modules.destroy(&self.device_context)?;
         self.buffers.destroy(&self.device_context)?;
+        self.pipeline_cache.destroy()?;
         Ok(())
     }
 
+    /// If enabled, a backtrace is captured for every resource created afterwards. Resources that
+    /// are still referenced on destroy (or when reported with `report_live_resources`) are logged
+    /// with where they were created. This is slow, so it's meant for debugging leaks.
+    pub fn set_track_lifetimes(
+        &mut self,
+        track_lifetimes: bool,
+    ) {
+        self.shader_modules.set_track_lifetimes(track_lifetimes);
+        self.descriptor_set_layouts
+            .set_track_lifetimes(track_lifetimes);
+        self.pipeline_layouts.set_track_lifetimes(track_lifetimes);
+        self.render_passes.set_track_lifetimes(track_lifetimes);
+        self.graphics_pipelines.set_track_lifetimes(track_lifetimes);
+        self.images.set_track_lifetimes(track_lifetimes);
+        self.image_views.set_track_lifetimes(track_lifetimes);
+        self.samplers.set_track_lifetimes(track_lifetimes);
+        self.buffers.set_track_lifetimes(track_lifetimes);
+    }
+
+    /// Logs every resource that is still referenced
+    pub fn report_live_resources(&self) {
+        self.shader_modules.report_live_resources();
+        self.descriptor_set_layouts.report_live_resources();
+        self.pipeline_layouts.report_live_resources();
+        self.render_passes.report_live_resources();
+        self.graphics_pipelines.report_live_resources();
+        self.images.report_live_resources();
+        self.image_views.report_live_resources();
+        self.samplers.report_live_resources();
+        self.buffers.report_live_resources();
+    }
+
+    /// Logs renderpasses and pipelines created for the given swapchain surface that are still
+    /// referenced. Once a swapchain is destroyed and nothing uses its surface info anymore, these
+    /// should all have been released. Returns the number of resources that were logged.
+    pub fn report_live_swapchain_resources(
+        &self,
+        swapchain_surface_info: &SwapchainSurfaceInfo,
+    ) -> usize {
+        let render_pass_count = self.render_passes.report_live_resources_with_key(|key| {
+            key.swapchain_surface_info == *swapchain_surface_info
+        });
+        let pipeline_count = self
+            .graphics_pipelines
+            .report_live_resources_with_key(|key| {
+                key.swapchain_surface_info == *swapchain_surface_info
+            });
+        render_pass_count + pipeline_count
+    }
+
+    /// Name the vulkan objects of a resource, i.e. after the asset it was loaded from. Resources
+    /// are shared by everything that requests the same key, so the name is overwritten by the
+    /// last caller.
+    pub fn set_debug_name<ResourceT>(
+        &self,
+        resource: &ResourceArc<ResourceT>,
+        name: &str,
+    ) where
+        ResourceT: VkResource + DebugNamedResource + Clone,
+    {
+        resource
+            .get_raw()
+            .set_debug_name(&self.device_context, name);
+    }
+
+    pub fn memory_usage(&self) -> ResourceMemoryUsage {
+        ResourceMemoryUsage {
+            image_bytes: self.images.allocated_bytes(),
+            buffer_bytes: self.buffers.allocated_bytes(),
+        }
+    }
+
     pub fn metrics(&self) -> ResourceMetrics {
         ResourceMetrics {
             shader_module_count: self.shader_modules.len(),
@@ -409,7 +660,9 @@ impl ResourceLookupSet {
             );
             let resource = dsc::create_shader_module(self.device_context.device(), shader_module)?;
             log::trace!("Created shader module {:?}", resource);
-            let shader_module = self.shader_modules.insert(hash, shader_module, resource);
+            let shader_module =
+                self.shader_modules
+                    .insert(&self.device_context, hash, shader_module, resource);
             Ok(shader_module)
         }
     }
@@ -427,7 +680,9 @@ impl ResourceLookupSet {
             let resource = dsc::create_sampler(self.device_context.device(), sampler)?;
 
             log::trace!("Created sampler {:?}", resource);
-            let sampler = self.samplers.insert(hash, sampler, resource);
+            let sampler = self
+                .samplers
+                .insert(&self.device_context, hash, sampler, resource);
             Ok(sampler)
         }
     }
@@ -494,9 +749,12 @@ impl ResourceLookupSet {
             };
 
             log::trace!("Created descriptor set layout {:?}", resource);
-            let descriptor_set_layout =
-                self.descriptor_set_layouts
-                    .insert(hash, descriptor_set_layout_def, resource);
+            let descriptor_set_layout = self.descriptor_set_layouts.insert(
+                &self.device_context,
+                hash,
+                descriptor_set_layout_def,
+                resource,
+            );
             Ok(descriptor_set_layout)
         }
     }
@@ -537,9 +795,12 @@ impl ResourceLookupSet {
             };
 
             log::trace!("Created pipeline layout {:?}", resource);
-            let pipeline_layout = self
-                .pipeline_layouts
-                .insert(hash, pipeline_layout_def, resource);
+            let pipeline_layout = self.pipeline_layouts.insert(
+                &self.device_context,
+                hash,
+                pipeline_layout_def,
+                resource,
+            );
 
             Ok(pipeline_layout)
         }
@@ -567,7 +828,9 @@ impl ResourceLookupSet {
             )?;
             log::trace!("Created renderpass {:?}", resource);
 
-            let renderpass = self.render_passes.insert(hash, &renderpass_key, resource);
+            let renderpass =
+                self.render_passes
+                    .insert(&self.device_context, hash, &renderpass_key, resource);
             Ok(renderpass)
         }
     }
@@ -598,6 +861,7 @@ impl ResourceLookupSet {
             log::trace!("Creating pipeline\n{:#?}", pipeline_key);
             let resources = dsc::create_graphics_pipelines(
                 &self.device_context.device(),
+                self.pipeline_cache.pipeline_cache(),
                 pipeline_create_data.fixed_function_state(),
                 pipeline_create_data
                     .pipeline_layout()
@@ -609,6 +873,7 @@ impl ResourceLookupSet {
                 swapchain_surface_info,
                 pipeline_create_data.renderpass_def().subpasses.len() as u32,
             )?;
+            self.pipeline_cache.mark_dirty();
             log::trace!("Created pipelines {:?}", resources);
 
             let resource = PipelineResource {
@@ -617,9 +882,9 @@ impl ResourceLookupSet {
                 renderpass: renderpass.clone(),
             };
 
-            let pipeline = self
-                .graphics_pipelines
-                .insert(hash, &pipeline_key, resource);
+            let pipeline =
+                self.graphics_pipelines
+                    .insert(&self.device_context, hash, &pipeline_key, resource);
             Ok(pipeline)
         }
     }
@@ -638,7 +903,9 @@ impl ResourceLookupSet {
 
         let hash = ResourceHash::from_key(&image_key);
         let raw_image = ManuallyDrop::into_inner(image).take_raw().unwrap();
-        let image = self.images.insert(hash, &image_key, raw_image);
+        let image = self
+            .images
+            .insert(&self.device_context, hash, &image_key, raw_image);
         (image_key, image)
     }
 
@@ -656,7 +923,9 @@ impl ResourceLookupSet {
 
         let hash = ResourceHash::from_key(&buffer_key);
         let raw_buffer = ManuallyDrop::into_inner(buffer).take_raw().unwrap();
-        let buffer = self.buffers.insert(hash, &buffer_key, raw_buffer);
+        let buffer = self
+            .buffers
+            .insert(&self.device_context, hash, &buffer_key, raw_buffer);
         (buffer_key, buffer)
     }
 
@@ -693,8 +962,86 @@ impl ResourceLookupSet {
                 image: image.clone(),
             };
 
-            let image_view = self.image_views.insert(hash, &image_view_key, resource);
+            let image_view =
+                self.image_views
+                    .insert(&self.device_context, hash, &image_view_key, resource);
             Ok(image_view)
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ash::vk::Handle;
+    use renderer_shell_vulkan::MsaaLevel;
+
+    fn swapchain_surface_info(width: u32) -> SwapchainSurfaceInfo {
+        SwapchainSurfaceInfo {
+            extents: vk::Extent2D { width, height: 600 },
+            msaa_level: MsaaLevel::Sample1,
+            surface_format: vk::SurfaceFormatKHR {
+                format: vk::Format::B8G8R8A8_SRGB,
+                color_space: vk::ColorSpaceKHR::SRGB_NONLINEAR,
+            },
+            color_format: vk::Format::R8G8B8A8_SRGB,
+            depth_format: vk::Format::D32_SFLOAT,
+        }
+    }
+
+    fn insert_render_pass(
+        lookup: &mut ResourceLookup<RenderPassKey, vk::RenderPass>,
+        swapchain_surface_info: &SwapchainSurfaceInfo,
+        raw_handle: u64,
+    ) -> ResourceArc<vk::RenderPass> {
+        let key = RenderPassKey {
+            dsc: Default::default(),
+            swapchain_surface_info: swapchain_surface_info.clone(),
+        };
+        let hash = ResourceHash::from_key(&key);
+        lookup.insert_arc(hash, &key, vk::RenderPass::from_raw(raw_handle))
+    }
+
+    #[test]
+    fn test_report_live_resources_skips_released() {
+        let mut lookup = ResourceLookup::new(1);
+        let kept = insert_render_pass(&mut lookup, &swapchain_surface_info(800), 1);
+        let released = insert_render_pass(&mut lookup, &swapchain_surface_info(1024), 2);
+        assert_eq!(lookup.report_live_resources(), 2);
+
+        // The lookup still holds a weak reference until the drop is handled, but it can't be
+        // upgraded anymore
+        drop(released);
+        assert_eq!(lookup.len(), 2);
+        assert_eq!(lookup.report_live_resources(), 1);
+
+        drop(kept);
+        assert_eq!(lookup.report_live_resources(), 0);
+    }
+
+    #[test]
+    fn test_report_live_resources_with_key_filters_by_swapchain() {
+        let mut lookup = ResourceLookup::new(1);
+        lookup.set_track_lifetimes(true);
+
+        let small = swapchain_surface_info(800);
+        let large = swapchain_surface_info(1024);
+        let small_render_pass = insert_render_pass(&mut lookup, &small, 1);
+        let _large_render_pass = insert_render_pass(&mut lookup, &large, 2);
+
+        let report_for = |lookup: &ResourceLookup<RenderPassKey, vk::RenderPass>,
+                          swapchain_surface_info: &SwapchainSurfaceInfo| {
+            lookup.report_live_resources_with_key(|key| {
+                key.swapchain_surface_info == *swapchain_surface_info
+            })
+        };
+
+        assert_eq!(report_for(&lookup, &small), 1);
+        assert_eq!(report_for(&lookup, &large), 1);
+        assert_eq!(report_for(&lookup, &swapchain_surface_info(640)), 0);
+
+        drop(small_render_pass);
+        assert_eq!(report_for(&lookup, &small), 0);
+        assert_eq!(report_for(&lookup, &large), 1);
+    }
+}
@@ -1,5 +1,6 @@
-use renderer_shell_vulkan::{VkDeviceContext, VkImage, VkImageRaw, VkBuffer};
+use renderer_shell_vulkan::{VkDeviceContext, VkImage, VkImageRaw, VkBuffer, VkMemoryUsage};
 use ash::prelude::*;
+use ash::vk;
 use crate::assets::ImageAssetData;
 use crate::assets::ShaderAssetData;
 use crate::assets::{
@@ -8,6 +9,7 @@ use crate::assets::{
 use crate::vk_description::SwapchainSurfaceInfo;
 use atelier_assets::loader::handle::Handle;
 use std::mem::ManuallyDrop;
+use std::path::Path;
 use crate::{
     vk_description as dsc, ResourceArc, DescriptorSetLayoutResource, PipelineLayoutResource,
     PipelineResource, ImageViewResource, DescriptorSetArc, DescriptorSetAllocatorMetrics,
@@ -24,6 +26,7 @@ use super::dyn_resource_allocator;
 use super::resource_lookup;
 
 use atelier_assets::loader::AssetLoadOp;
+use atelier_assets::core::AssetUuid;
 use atelier_assets::loader::handle::AssetHandle;
 use std::sync::{Arc, Mutex};
 use crate::resources::asset_lookup::LoadedAssetMetrics;
@@ -34,6 +37,7 @@ use crate::resources::load_queue::LoadQueueSet;
 use crate::resources::swapchain_management::ActiveSwapchainSurfaceInfoSet;
 use crate::resources::descriptor_sets::{DescriptorSetAllocator, DescriptorSetAllocatorManager};
 use crate::resources::upload::{UploadManager, ImageUploadOpResult, BufferUploadOpResult};
+use crate::resources::memory_metrics::{MemoryBudgetMonitor, MemoryMetrics};
 use crossbeam_channel::Sender;
 
 //TODO: Support descriptors that can be different per-view
@@ -42,6 +46,7 @@ use crossbeam_channel::Sender;
 
 // Information about a pipeline for a particular swapchain, resources may or may not be shared
 // across swapchains depending on if they are the same size/format
+#[derive(Clone)]
 pub struct PipelineSwapchainInfo {
     pub descriptor_set_layouts: Vec<ResourceArc<DescriptorSetLayoutResource>>,
     pub pipeline_layout: ResourceArc<PipelineLayoutResource>,
@@ -74,6 +79,15 @@ pub struct ResourceManagerMetrics {
     pub resource_descriptor_sets_metrics: DescriptorSetAllocatorMetrics,
 }
 
+// Debug names use the asset's UUID as it's the only metadata the loader has for an asset, and it
+// can be looked up in the .meta files to find the source file
+fn asset_debug_name(
+    kind: &str,
+    asset_uuid: &AssetUuid,
+) -> String {
+    format!("{} {}", kind, uuid::Uuid::from_bytes(asset_uuid.0))
+}
+
 pub struct ResourceManagerLoaders {
     pub shader_loader: GenericLoader<ShaderAssetData, ShaderAsset>,
     pub pipeline_loader: GenericLoader<PipelineAssetData, PipelineAsset>,
@@ -93,6 +107,10 @@ pub struct ResourceManager {
     resource_descriptor_sets: DescriptorSetAllocator,
     descriptor_set_allocator: DescriptorSetAllocatorManager,
     upload_manager: UploadManager,
+    memory_budget_monitor: MemoryBudgetMonitor,
+    track_resource_lifetimes: bool,
+    // Swapchains that were removed but haven't been checked for resources that outlived them yet
+    removed_swapchains: Vec<dsc::SwapchainSurfaceInfo>,
 }
 
 impl ResourceManager {
@@ -116,7 +134,11 @@ impl ResourceManager {
         &mut self.loaded_assets
     }
 
-    pub fn new(device_context: &VkDeviceContext) -> Self {
+    /// If `pipeline_cache_file` is set, compiled pipelines are cached in that file across runs
+    pub fn new(
+        device_context: &VkDeviceContext,
+        pipeline_cache_file: Option<&Path>,
+    ) -> Self {
         ResourceManager {
             dyn_resources: DynResourceAllocatorManagerSet::new(
                 device_context,
@@ -125,6 +147,7 @@ impl ResourceManager {
             resources: ResourceLookupSet::new(
                 device_context,
                 renderer_shell_vulkan::MAX_FRAMES_IN_FLIGHT as u32,
+                pipeline_cache_file,
             ),
             loaded_assets: Default::default(),
             load_queues: Default::default(),
@@ -132,6 +155,9 @@ impl ResourceManager {
             resource_descriptor_sets: DescriptorSetAllocator::new(device_context),
             descriptor_set_allocator: DescriptorSetAllocatorManager::new(device_context),
             upload_manager: UploadManager::new(device_context),
+            memory_budget_monitor: Default::default(),
+            track_resource_lifetimes: false,
+            removed_swapchains: Default::default(),
         }
     }
 
@@ -301,6 +327,10 @@ impl ResourceManager {
         log::info!("remove_swapchain {:?}", swapchain_surface_info);
         self.swapchain_surfaces
             .remove(swapchain_surface_info, &mut self.loaded_assets);
+
+        if self.track_resource_lifetimes {
+            self.removed_swapchains.push(swapchain_surface_info.clone());
+        }
     }
 
     // Call whenever you want to handle assets loading/unloading
@@ -330,9 +360,108 @@ impl ResourceManager {
         self.dyn_resources.on_frame_complete()?;
         self.resource_descriptor_sets.on_frame_complete();
         self.descriptor_set_allocator.on_frame_complete();
+        self.check_memory_budget();
+        self.report_resources_outliving_swapchains();
         Ok(())
     }
 
+    /// Record where every resource is created so that resources that are still referenced when
+    /// the resource manager is dropped, or that are still referenced after the swapchain they were
+    /// created for is removed, can be reported along with where they were created. Capturing a
+    /// backtrace for every resource is slow, so this is meant for debugging. Only resources created
+    /// after this is enabled are tracked.
+    pub fn set_resource_lifetime_tracking(
+        &mut self,
+        enabled: bool,
+    ) {
+        self.track_resource_lifetimes = enabled;
+        self.resources.set_track_lifetimes(enabled);
+        self.dyn_resources.set_track_lifetimes(enabled);
+        if !enabled {
+            self.removed_swapchains.clear();
+        }
+    }
+
+    /// Log every resource that is still referenced, with where it was created if lifetime tracking
+    /// is enabled
+    pub fn report_live_resources(&self) {
+        self.resources.report_live_resources();
+        self.dyn_resources.report_live_resources();
+    }
+
+    // Resources dropped when a swapchain is removed are released the next time the lookups handle
+    // dropped resources, so anything created for a removed swapchain that is still in a lookup
+    // after that is being held by someone.
+    fn report_resources_outliving_swapchains(&mut self) {
+        for swapchain_surface_info in self.removed_swapchains.drain(..) {
+            // The same swapchain configuration may have been added again since it was removed
+            if self
+                .swapchain_surfaces
+                .unique_swapchain_infos()
+                .contains(&swapchain_surface_info)
+            {
+                continue;
+            }
+
+            let live_count = self
+                .resources
+                .report_live_swapchain_resources(&swapchain_surface_info);
+            if live_count > 0 {
+                log::warn!(
+                    "{} resources created for swapchain {:?} are still referenced after it was removed",
+                    live_count,
+                    swapchain_surface_info
+                );
+            }
+        }
+    }
+
+    /// Log a warning when the memory used by images, buffers, staging buffers and descriptor set
+    /// buffers exceeds this many bytes. Warnings are also logged when a memory heap exceeds the
+    /// budget reported by VK_EXT_memory_budget, regardless of this setting.
+    pub fn set_memory_budget(
+        &mut self,
+        budget: Option<vk::DeviceSize>,
+    ) {
+        self.memory_budget_monitor.set_budget(budget);
+    }
+
+    fn tracked_memory_metrics(
+        &self,
+        device_memory: VkMemoryUsage,
+    ) -> MemoryMetrics {
+        MemoryMetrics {
+            loaded_resources: self.resources.memory_usage(),
+            dyn_resources: self.dyn_resources.memory_usage(),
+            staging_bytes: self.upload_manager.staging_bytes(),
+            descriptor_buffer_bytes: self.resource_descriptor_sets.backing_buffer_bytes()
+                + self.descriptor_set_allocator.backing_buffer_bytes(),
+            budget: self.memory_budget_monitor.budget(),
+            device_memory,
+        }
+    }
+
+    fn check_memory_budget(&mut self) {
+        // Querying vk-mem statistics is relatively expensive, so only use the cheap counters here
+        let tracked_bytes = self
+            .tracked_memory_metrics(VkMemoryUsage::default())
+            .tracked_bytes();
+        let heap_budgets = self.resources.device_context.memory_heap_budgets();
+
+        for warning in self
+            .memory_budget_monitor
+            .update(tracked_bytes, heap_budgets.as_deref())
+        {
+            log::warn!("{}", warning);
+        }
+    }
+
+    /// A snapshot of GPU memory usage by category, along with the usage of each memory heap
+    pub fn memory_metrics(&self) -> MemoryMetrics {
+        let device_memory = VkMemoryUsage::query(&self.resources.device_context);
+        self.tracked_memory_metrics(device_memory)
+    }
+
     pub fn metrics(&self) -> ResourceManagerMetrics {
         let dyn_resource_metrics = self.dyn_resources.metrics();
         let resource_metrics = self.resources.metrics();
@@ -350,7 +479,8 @@ impl ResourceManager {
     fn process_shader_load_requests(&mut self) {
         for request in self.load_queues.shader_modules.take_load_requests() {
             log::trace!("Create shader module {:?}", request.load_handle);
-            let loaded_asset = self.load_shader_module(&request.asset);
+            let debug_name = asset_debug_name("shader", &request.asset_uuid);
+            let loaded_asset = self.load_shader_module(&request.asset, &debug_name);
             Self::handle_load_result(
                 request.load_op,
                 loaded_asset,
@@ -416,7 +546,8 @@ impl ResourceManager {
     fn process_material_load_requests(&mut self) {
         for request in self.load_queues.materials.take_load_requests() {
             log::trace!("Create material {:?}", request.load_handle);
-            let loaded_asset = self.load_material(&request.asset);
+            let debug_name = asset_debug_name("material", &request.asset_uuid);
+            let loaded_asset = self.load_material(&request.asset, &debug_name);
             Self::handle_load_result(
                 request.load_op,
                 loaded_asset,
@@ -438,7 +569,8 @@ impl ResourceManager {
     fn process_material_instance_load_requests(&mut self) {
         for request in self.load_queues.material_instances.take_load_requests() {
             log::trace!("Create material instance {:?}", request.load_handle);
-            let loaded_asset = self.load_material_instance(&request.asset);
+            let debug_name = asset_debug_name("material instance", &request.asset_uuid);
+            let loaded_asset = self.load_material_instance(&request.asset, &debug_name);
             Self::handle_load_result(
                 request.load_op,
                 loaded_asset,
@@ -471,9 +603,10 @@ impl ResourceManager {
             .collect();
         for result in results {
             match result {
-                ImageUploadOpResult::UploadComplete(load_op, result_tx, image) => {
+                ImageUploadOpResult::UploadComplete(load_op, asset_uuid, result_tx, image) => {
                     log::trace!("Uploading image {:?} complete", load_op.load_handle());
-                    let loaded_asset = self.finish_load_image(image);
+                    let debug_name = asset_debug_name("image", &asset_uuid);
+                    let loaded_asset = self.finish_load_image(image, &debug_name);
                     Self::handle_load_result(
                         load_op,
                         loaded_asset,
@@ -511,9 +644,10 @@ impl ResourceManager {
             .collect();
         for result in results {
             match result {
-                BufferUploadOpResult::UploadComplete(load_op, result_tx, buffer) => {
+                BufferUploadOpResult::UploadComplete(load_op, asset_uuid, result_tx, buffer) => {
                     log::trace!("Uploading buffer {:?} complete", load_op.load_handle());
-                    let loaded_asset = self.finish_load_buffer(buffer);
+                    let debug_name = asset_debug_name("buffer", &asset_uuid);
+                    let loaded_asset = self.finish_load_buffer(buffer, &debug_name);
                     Self::handle_load_result(
                         load_op,
                         loaded_asset,
@@ -588,21 +722,52 @@ impl ResourceManager {
     fn finish_load_image(
         &mut self,
         image: VkImage,
+        debug_name: &str,
     ) -> VkResult<ImageAsset> {
         let format = image.format.into();
         let mip_level_count = image.mip_level_count;
+        let layer_count = image.array_layer_count;
+        let supports_cube_arrays = self
+            .resources
+            .device_context
+            .enabled_features()
+            .features
+            .image_cube_array
+            == vk::TRUE;
+        let view_type = if image
+            .create_flags
+            .contains(vk::ImageCreateFlags::CUBE_COMPATIBLE)
+        {
+            if layer_count == 6 {
+                dsc::ImageViewType::Cube
+            } else if supports_cube_arrays {
+                dsc::ImageViewType::CubeArray
+            } else {
+                log::warn!(
+                    "Image {} has {} cube map layers, but the image_cube_array feature is not enabled. It will be viewed as a 2D array.",
+                    debug_name,
+                    layer_count
+                );
+                dsc::ImageViewType::Type2DArray
+            }
+        } else if layer_count > 1 {
+            dsc::ImageViewType::Type2DArray
+        } else {
+            dsc::ImageViewType::Type2D
+        };
 
         let (image_key, image_arc) = self.resources.insert_image(ManuallyDrop::new(image));
+        self.resources.set_debug_name(&image_arc, debug_name);
 
         let image_view_meta = dsc::ImageViewMeta {
-            view_type: dsc::ImageViewType::Type2D,
+            view_type,
             format,
             subresource_range: dsc::ImageSubresourceRange {
                 aspect_mask: dsc::ImageAspectFlags::Color,
                 base_mip_level: 0,
                 level_count: mip_level_count,
                 base_array_layer: 0,
-                layer_count: 1,
+                layer_count,
             },
             components: dsc::ComponentMapping {
                 r: dsc::ComponentSwizzle::Identity,
@@ -615,6 +780,7 @@ impl ResourceManager {
         let image_view = self
             .resources
             .get_or_create_image_view(image_key, &image_view_meta)?;
+        self.resources.set_debug_name(&image_view, debug_name);
 
         Ok(ImageAsset {
             image_key,
@@ -626,8 +792,10 @@ impl ResourceManager {
     fn finish_load_buffer(
         &mut self,
         buffer: VkBuffer,
+        debug_name: &str,
     ) -> VkResult<BufferAsset> {
         let (buffer_key, buffer) = self.resources.insert_buffer(ManuallyDrop::new(buffer));
+        self.resources.set_debug_name(&buffer, debug_name);
 
         Ok(BufferAsset { buffer_key, buffer })
     }
@@ -635,10 +803,12 @@ impl ResourceManager {
     fn load_shader_module(
         &mut self,
         shader_module: &ShaderAssetData,
+        debug_name: &str,
     ) -> VkResult<ShaderAsset> {
         let shader_module = self
             .resources
             .get_or_create_shader_module(&shader_module.shader)?;
+        self.resources.set_debug_name(&shader_module, debug_name);
         Ok(ShaderAsset { shader_module })
     }
 
@@ -663,10 +833,13 @@ impl ResourceManager {
     fn load_material(
         &mut self,
         material_asset: &MaterialAssetData,
+        debug_name: &str,
     ) -> VkResult<MaterialAsset> {
         let mut passes = Vec::with_capacity(material_asset.passes.len());
 
-        for pass in &material_asset.passes {
+        for (pass_index, pass) in material_asset.passes.iter().enumerate() {
+            let pass_debug_name = format!("{} pass {}", debug_name, pass_index);
+
             let loaded_pipeline_asset = self
                 .loaded_assets
                 .graphics_pipelines
@@ -702,6 +875,8 @@ impl ResourceManager {
                 pass,
                 shader_hashes,
             )?;
+            self.resources
+                .set_debug_name(pipeline_create_data.pipeline_layout(), &pass_debug_name);
 
             // Will contain the vulkan resources being created per swapchain
             let mut per_swapchain_data = Vec::with_capacity(swapchain_surface_infos.len());
@@ -712,6 +887,7 @@ impl ResourceManager {
                     &pipeline_create_data,
                     &swapchain_surface_info,
                 )?;
+                self.resources.set_debug_name(&pipeline, &pass_debug_name);
 
                 per_swapchain_data.push(MaterialPassSwapchainResources { pipeline });
             }
@@ -757,6 +933,7 @@ impl ResourceManager {
     fn load_material_instance(
         &mut self,
         material_instance_asset: &MaterialInstanceAssetData,
+        debug_name: &str,
     ) -> VkResult<MaterialInstanceAsset> {
         // Find the material we will bind over, we need the metadata from it
         let material_asset = self
@@ -775,7 +952,7 @@ impl ResourceManager {
 
         // This will be references to descriptor sets. Indexed by pass, and then by set within the pass.
         let mut material_descriptor_sets = Vec::with_capacity(material_asset.passes.len());
-        for pass in &*material_asset.passes {
+        for (pass_index, pass) in material_asset.passes.iter().enumerate() {
             let pass_descriptor_set_writes =
                 descriptor_sets::create_write_sets_for_material_instance_pass(
                     pass,
@@ -805,6 +982,10 @@ impl ResourceManager {
                     &pass.pipeline_create_data.descriptor_set_layout_arcs()[layout_index],
                     layout_writes,
                 )?;
+                self.resources.device_context.set_debug_name(
+                    descriptor_set.get(),
+                    &format!("{} pass {} set {}", debug_name, pass_index, layout_index),
+                );
 
                 pass_descriptor_sets.push(descriptor_set);
             }
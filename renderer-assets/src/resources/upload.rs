@@ -6,6 +6,7 @@ use ash::prelude::VkResult;
 use crate::image_utils::{enqueue_load_images, DecodedTexture, enqueue_load_buffers};
 use std::mem::ManuallyDrop;
 use atelier_assets::loader::{LoadHandle, AssetLoadOp};
+use atelier_assets::core::AssetUuid;
 use ash::vk;
 use crate::resources::load_queue::LoadRequest;
 use crate::assets::ImageAssetData;
@@ -17,12 +18,13 @@ use crate::assets::{ImageAsset, BufferAsset};
 //
 pub enum UploadOpResult<ResourceT, AssetT> {
     UploadError(LoadHandle),
-    UploadComplete(AssetLoadOp, Sender<AssetT>, ResourceT),
+    UploadComplete(AssetLoadOp, AssetUuid, Sender<AssetT>, ResourceT),
     UploadDrop(LoadHandle),
 }
 
 pub struct UploadOp<ResourceT, AssetT> {
     load_handle: LoadHandle,
+    asset_uuid: AssetUuid,
     asset_sender: Option<Sender<AssetT>>, // This sends back to the asset storage, we just pass it along
     sender: Option<Sender<UploadOpResult<ResourceT, AssetT>>>, // This sends back to the resource manager to finalize the load
 }
@@ -30,11 +32,13 @@ pub struct UploadOp<ResourceT, AssetT> {
 impl<ResourceT, AssetT> UploadOp<ResourceT, AssetT> {
     pub fn new(
         load_handle: LoadHandle,
+        asset_uuid: AssetUuid,
         asset_sender: Sender<AssetT>,
         sender: Sender<UploadOpResult<ResourceT, AssetT>>,
     ) -> Self {
         Self {
             load_handle,
+            asset_uuid,
             asset_sender: Some(asset_sender),
             sender: Some(sender),
         }
@@ -51,6 +55,7 @@ impl<ResourceT, AssetT> UploadOp<ResourceT, AssetT> {
             .unwrap()
             .send(UploadOpResult::UploadComplete(
                 load_op,
+                self.asset_uuid,
                 self.asset_sender.take().unwrap(),
                 image,
             ));
@@ -153,6 +158,13 @@ impl InProgressUpload {
         InProgressUpload { inner: Some(inner) }
     }
 
+    fn staging_bytes(&self) -> vk::DeviceSize {
+        self.inner
+            .as_ref()
+            .map(|inner| inner.upload.staging_buffer().size())
+            .unwrap_or(0)
+    }
+
     // The main state machine for an upload:
     // - Submits on the transfer queue and waits
     // - Submits on the graphics queue and waits
@@ -450,6 +462,14 @@ impl UploadQueue {
         self.update_existing_uploads();
         Ok(())
     }
+
+    // Size of the staging buffers of all uploads in progress
+    pub fn staging_bytes(&self) -> vk::DeviceSize {
+        self.uploads_in_progress
+            .iter()
+            .map(|upload| upload.staging_bytes())
+            .sum()
+    }
 }
 
 pub struct UploadManager {
@@ -480,14 +500,18 @@ impl UploadManager {
         self.upload_queue.update()
     }
 
+    pub fn staging_bytes(&self) -> vk::DeviceSize {
+        self.upload_queue.staging_bytes()
+    }
+
     pub fn upload_image(
         &self,
         request: LoadRequest<ImageAssetData, ImageAsset>,
     ) -> VkResult<()> {
-        let mips = crate::image_utils::default_mip_settings_for_image(
-            request.asset.width,
-            request.asset.height,
-        );
+        let mips = request
+            .asset
+            .mips
+            .to_decoded_texture_mips(request.asset.width, request.asset.height);
 
         let color_space = request.asset.color_space.into();
 
@@ -496,6 +520,9 @@ impl UploadManager {
             height: request.asset.height,
             mips,
             color_space,
+            format: request.asset.format.into(),
+            layer_count: request.asset.layer_count,
+            is_cube_map: request.asset.is_cube_map,
             data: request.asset.data,
         };
 
@@ -505,6 +532,7 @@ impl UploadManager {
                 load_op: request.load_op,
                 upload_op: UploadOp::new(
                     request.load_handle,
+                    request.asset_uuid,
                     request.result_tx,
                     self.image_upload_result_tx.clone(),
                 ),
@@ -526,6 +554,7 @@ impl UploadManager {
                 load_op: request.load_op,
                 upload_op: UploadOp::new(
                     request.load_handle,
+                    request.asset_uuid,
                     request.result_tx,
                     self.buffer_upload_result_tx.clone(),
                 ),
@@ -167,6 +167,7 @@ pub fn create_renderpass(
 
 pub fn create_graphics_pipelines(
     device: &ash::Device,
+    pipeline_cache: vk::PipelineCache,
     //graphics_pipeline: &dsc::GraphicsPipeline,
     fixed_function_state: &dsc::FixedFunctionState,
     pipeline_layout: vk::PipelineLayout,
@@ -284,7 +285,7 @@ pub fn create_graphics_pipelines(
         .collect();
 
     unsafe {
-        match device.create_graphics_pipelines(vk::PipelineCache::null(), &pipeline_infos, None) {
+        match device.create_graphics_pipelines(pipeline_cache, &pipeline_infos, None) {
             Ok(result) => Ok(result),
             Err(e) => Err(e.1),
         }
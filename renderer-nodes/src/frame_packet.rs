@@ -1,6 +1,6 @@
 use std::sync::Mutex;
 use crate::{RenderView, GenericRenderNodeHandle, RenderRegistry, RenderFeatureIndex};
-use crate::VisibilityResult;
+use crate::{VisibilityResult, VisibleNode, LodIndex};
 use crate::render_nodes::{AllRenderNodes, RenderNodeIndex};
 use crate::registry::RenderFeatureCount;
 
@@ -25,6 +25,8 @@ impl PerFrameNode {
 pub struct PerViewNode {
     render_node_index: u32,
     frame_node_index: u32,
+    distance_from_camera: f32,
+    lod_index: LodIndex,
 }
 
 impl PerViewNode {
@@ -35,6 +37,14 @@ impl PerViewNode {
     pub fn frame_node_index(self) -> FrameNodeIndex {
         self.frame_node_index
     }
+
+    pub fn distance_from_camera(self) -> f32 {
+        self.distance_from_camera
+    }
+
+    pub fn lod_index(self) -> LodIndex {
+        self.lod_index
+    }
 }
 
 #[derive(Debug)]
@@ -119,13 +129,16 @@ impl ViewPacketBuilder {
 
     pub fn append_view_node(
         &self,
-        handle: GenericRenderNodeHandle,
+        visible_node: &VisibleNode,
         frame_node_index: u32,
     ) {
+        let handle = visible_node.handle;
         let mut guard = self.inner.lock().unwrap();
         guard.view_nodes[handle.render_feature_index() as usize].push(PerViewNode {
             frame_node_index,
             render_node_index: handle.render_node_index(),
+            distance_from_camera: visible_node.distance_from_camera,
+            lod_index: visible_node.lod_index,
         });
         log::trace!("push view node");
     }
@@ -198,9 +211,9 @@ impl FramePacketBuilder {
         let view_packet_builder = ViewPacketBuilder::new(feature_count);
 
         for visibility_result in visibility_results {
-            for handle in &visibility_result.handles {
-                let frame_node_index = self.append_frame_node(*handle);
-                view_packet_builder.append_view_node(*handle, frame_node_index);
+            for visible_node in &visibility_result.visible_nodes {
+                let frame_node_index = self.append_frame_node(visible_node.handle);
+                view_packet_builder.append_view_node(visible_node, frame_node_index);
             }
         }
 
@@ -0,0 +1,147 @@
+/// Number of nodes processed by a single task when extract/prepare loops are split into chunks
+pub const RENDER_JOB_CHUNK_SIZE: usize = 64;
+
+/// A unit of work handed to a `RenderJobExecutor`. Tasks may borrow from the caller's stack because
+/// `RenderJobExecutor::execute` does not return until all tasks have completed.
+pub type RenderJobTask<'a> = Box<dyn FnOnce() + Send + 'a>;
+
+/// Runs the tasks produced by extract and prepare jobs. Implementations may run tasks in any order
+/// and on any thread, but must not return until every task has completed.
+pub trait RenderJobExecutor: Send + Sync {
+    fn execute<'a>(
+        &self,
+        tasks: Vec<RenderJobTask<'a>>,
+    );
+}
+
+/// Runs tasks one after another on the calling thread. Useful for debugging and profiling.
+#[derive(Default)]
+pub struct SerialRenderJobExecutor;
+
+impl RenderJobExecutor for SerialRenderJobExecutor {
+    fn execute<'a>(
+        &self,
+        tasks: Vec<RenderJobTask<'a>>,
+    ) {
+        for task in tasks {
+            (task)();
+        }
+    }
+}
+
+/// Runs tasks on rayon's global thread pool
+#[cfg(feature = "rayon")]
+#[derive(Default)]
+pub struct RayonRenderJobExecutor;
+
+#[cfg(feature = "rayon")]
+impl RenderJobExecutor for RayonRenderJobExecutor {
+    fn execute<'a>(
+        &self,
+        mut tasks: Vec<RenderJobTask<'a>>,
+    ) {
+        // No reason to pay for a scope if there's nothing to run in parallel
+        if tasks.len() <= 1 {
+            if let Some(task) = tasks.pop() {
+                (task)();
+            }
+            return;
+        }
+
+        rayon::scope(|scope| {
+            for task in tasks {
+                scope.spawn(move |_| (task)());
+            }
+        });
+    }
+}
+
+/// The executor used by job sets that aren't given one explicitly. This is rayon if the feature
+/// is enabled, otherwise serial.
+pub fn default_render_job_executor() -> std::sync::Arc<dyn RenderJobExecutor> {
+    #[cfg(feature = "rayon")]
+    {
+        std::sync::Arc::new(RayonRenderJobExecutor)
+    }
+
+    #[cfg(not(feature = "rayon"))]
+    {
+        std::sync::Arc::new(SerialRenderJobExecutor)
+    }
+}
+
+/// Splits `items` into chunks of `chunk_size` and calls `f` once per chunk, possibly in parallel.
+/// `f` receives the index of the first item in the chunk and the chunk itself. The results are
+/// returned in chunk order.
+pub fn execute_chunked<T, R, F>(
+    executor: &dyn RenderJobExecutor,
+    items: &[T],
+    chunk_size: usize,
+    f: F,
+) -> Vec<R>
+where
+    T: Sync,
+    R: Send,
+    F: Fn(usize, &[T]) -> R + Sync,
+{
+    assert!(chunk_size > 0);
+    let chunk_count = (items.len() + chunk_size - 1) / chunk_size;
+    let mut results: Vec<Option<R>> = (0..chunk_count).map(|_| None).collect();
+
+    {
+        let f = &f;
+        let tasks = results
+            .iter_mut()
+            .zip(items.chunks(chunk_size))
+            .enumerate()
+            .map(|(chunk_index, (result, chunk))| -> RenderJobTask {
+                Box::new(move || {
+                    *result = Some((f)(chunk_index * chunk_size, chunk));
+                })
+            })
+            .collect();
+
+        executor.execute(tasks);
+    }
+
+    results
+        .into_iter()
+        .map(|result| result.expect("RenderJobExecutor returned before running all tasks"))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn check_execute_chunked(executor: &dyn RenderJobExecutor) {
+        let items: Vec<u32> = (0..1000).collect();
+        let chunks = execute_chunked(executor, &items, 64, |first_index, chunk| {
+            assert_eq!(first_index as u32, chunk[0]);
+            chunk.iter().map(|x| x * 2).collect::<Vec<_>>()
+        });
+
+        assert_eq!(chunks.len(), 16);
+        let flattened: Vec<u32> = chunks.into_iter().flatten().collect();
+        let expected: Vec<u32> = items.iter().map(|x| x * 2).collect();
+        assert_eq!(flattened, expected);
+    }
+
+    #[test]
+    fn test_serial_execute_chunked() {
+        check_execute_chunked(&SerialRenderJobExecutor);
+    }
+
+    #[cfg(feature = "rayon")]
+    #[test]
+    fn test_rayon_execute_chunked() {
+        check_execute_chunked(&RayonRenderJobExecutor);
+    }
+
+    #[test]
+    fn test_execute_chunked_empty() {
+        let items: Vec<u32> = vec![];
+        let chunks = execute_chunked(&SerialRenderJobExecutor, &items, 64, |_, chunk| chunk.len());
+        assert!(chunks.is_empty());
+    }
+}
@@ -1,11 +1,15 @@
 use crate::{FramePacket, RenderView, PrepareJob, PrepareJobSet};
+use crate::{RenderJobExecutor, RenderJobTask, RENDER_JOB_CHUNK_SIZE};
+use crate::{default_render_job_executor, execute_chunked};
+use std::sync::Arc;
 
-pub trait ExtractJob<ExtractContextT, PrepareContextT, WriteContextT> {
+pub trait ExtractJob<ExtractContextT, PrepareContextT, WriteContextT>: Send {
     fn extract(
         self: Box<Self>,
         extract_context: &ExtractContextT,
         frame_packet: &FramePacket,
         views: &[&RenderView],
+        job_executor: &dyn RenderJobExecutor,
     ) -> Box<dyn PrepareJob<PrepareContextT, WriteContextT>>;
 
     fn feature_debug_name(&self) -> &'static str;
@@ -14,6 +18,7 @@ pub trait ExtractJob<ExtractContextT, PrepareContextT, WriteContextT> {
 
 pub struct ExtractJobSet<ExtractContextT, PrepareContextT, WriteContextT> {
     extract_jobs: Vec<Box<dyn ExtractJob<ExtractContextT, PrepareContextT, WriteContextT>>>,
+    job_executor: Arc<dyn RenderJobExecutor>,
 }
 
 impl<ExtractContextT, PrepareContextT, WriteContextT> Default
@@ -22,6 +27,7 @@ impl<ExtractContextT, PrepareContextT, WriteContextT> Default
     fn default() -> Self {
         ExtractJobSet {
             extract_jobs: Default::default(),
+            job_executor: default_render_job_executor(),
         }
     }
 }
@@ -33,6 +39,15 @@ impl<ExtractContextT, PrepareContextT, WriteContextT>
         Default::default()
     }
 
+    /// Create a job set that runs its jobs (and the prepare jobs they produce) on the given
+    /// executor
+    pub fn with_job_executor(job_executor: Arc<dyn RenderJobExecutor>) -> Self {
+        ExtractJobSet {
+            extract_jobs: Default::default(),
+            job_executor,
+        }
+    }
+
     pub fn add_job(
         &mut self,
         extract_job: Box<dyn ExtractJob<ExtractContextT, PrepareContextT, WriteContextT>>,
@@ -45,18 +60,49 @@ impl<ExtractContextT, PrepareContextT, WriteContextT>
         extract_context: &ExtractContextT,
         frame_packet: &FramePacket,
         views: &[&RenderView],
-    ) -> PrepareJobSet<PrepareContextT, WriteContextT> {
+    ) -> PrepareJobSet<PrepareContextT, WriteContextT>
+    where
+        ExtractContextT: Sync,
+    {
         log::trace!("Start extract job set");
 
-        let mut prepare_jobs = vec![];
-        for extract_job in self.extract_jobs {
-            log::trace!("Start job {}", extract_job.feature_debug_name());
+        let ExtractJobSet {
+            extract_jobs,
+            job_executor,
+        } = self;
 
-            let prepare_job = extract_job.extract(extract_context, frame_packet, views);
-            prepare_jobs.push(prepare_job);
+        // Each feature is extracted as its own task. Results are written into a slot per job so
+        // that prepare jobs stay in the same order as the extract jobs.
+        let mut prepare_jobs: Vec<Option<Box<dyn PrepareJob<PrepareContextT, WriteContextT>>>> =
+            (0..extract_jobs.len()).map(|_| None).collect();
+
+        {
+            let executor = &*job_executor;
+            let tasks = extract_jobs
+                .into_iter()
+                .zip(prepare_jobs.iter_mut())
+                .map(|(extract_job, prepare_job)| -> RenderJobTask {
+                    Box::new(move || {
+                        log::trace!("Start job {}", extract_job.feature_debug_name());
+                        *prepare_job = Some(extract_job.extract(
+                            extract_context,
+                            frame_packet,
+                            views,
+                            executor,
+                        ));
+                    })
+                })
+                .collect();
+
+            executor.execute(tasks);
         }
 
-        PrepareJobSet::new(prepare_jobs)
+        let prepare_jobs = prepare_jobs
+            .into_iter()
+            .map(|prepare_job| prepare_job.expect("RenderJobExecutor did not run an extract job"))
+            .collect();
+
+        PrepareJobSet::with_job_executor(prepare_jobs, job_executor)
     }
 }
 
@@ -65,7 +111,15 @@ use crate::RenderFeatureIndex;
 use std::marker::PhantomData;
 use crate::{PerFrameNode, PerViewNode};
 
-pub trait DefaultExtractJobImpl<ExtractContextT, PrepareContextT, WriteContextT> {
+/// Implements the per-node steps of a `DefaultExtractJob`. Frame and view nodes are extracted in
+/// parallel chunks, so the node callbacks take `&self` and return their results rather than
+/// storing them. The results are handed back in node order to the finalize callbacks.
+pub trait DefaultExtractJobImpl<ExtractContextT, PrepareContextT, WriteContextT>:
+    Send + Sync
+{
+    type ExtractedFrameNodeData: Send + Sync;
+    type ExtractedViewNodeData: Send;
+
     fn extract_begin(
         &mut self,
         extract_context: &ExtractContextT,
@@ -73,26 +127,31 @@ pub trait DefaultExtractJobImpl<ExtractContextT, PrepareContextT, WriteContextT>
         views: &[&RenderView],
     );
     fn extract_frame_node(
-        &mut self,
+        &self,
         extract_context: &ExtractContextT,
         frame_node: PerFrameNode,
         frame_node_index: u32,
-    );
+    ) -> Self::ExtractedFrameNodeData;
     fn extract_view_node(
-        &mut self,
+        &self,
         extract_context: &ExtractContextT,
         view: &RenderView,
         view_node: PerViewNode,
         view_node_index: u32,
-    );
+        extracted_frame_node_data: &[Self::ExtractedFrameNodeData],
+    ) -> Self::ExtractedViewNodeData;
     fn extract_view_finalize(
         &mut self,
         extract_context: &ExtractContextT,
         view: &RenderView,
     );
+    // extracted_view_node_data has an entry per view, in the same order as the views passed to
+    // extract_begin
     fn extract_frame_finalize(
         self,
         extract_context: &ExtractContextT,
+        extracted_frame_node_data: Vec<Self::ExtractedFrameNodeData>,
+        extracted_view_node_data: Vec<Vec<Self::ExtractedViewNodeData>>,
     ) -> Box<dyn PrepareJob<PrepareContextT, WriteContextT>>;
 
     fn feature_debug_name(&self) -> &'static str;
@@ -125,9 +184,9 @@ impl<
 }
 
 impl<
-        ExtractContextT,
-        PrepareContextT,
-        WriteContextT,
+        ExtractContextT: Send + Sync,
+        PrepareContextT: Send,
+        WriteContextT: Send,
         ExtractImplT: DefaultExtractJobImpl<ExtractContextT, PrepareContextT, WriteContextT>,
     > ExtractJob<ExtractContextT, PrepareContextT, WriteContextT>
     for DefaultExtractJob<ExtractContextT, PrepareContextT, WriteContextT, ExtractImplT>
@@ -137,67 +196,95 @@ impl<
         extract_context: &ExtractContextT,
         frame_packet: &FramePacket,
         views: &[&RenderView],
+        job_executor: &dyn RenderJobExecutor,
     ) -> Box<dyn PrepareJob<PrepareContextT, WriteContextT>> {
         let feature_index = self.extract_impl.feature_index();
+        let feature_debug_name = self.extract_impl.feature_debug_name();
 
-        // In the future, make features run in parallel
-        log::trace!(
-            "extract_begin feature: {}",
-            self.extract_impl.feature_debug_name()
-        );
+        log::trace!("extract_begin feature: {}", feature_debug_name);
         self.extract_impl
             .extract_begin(extract_context, frame_packet, views);
 
-        // foreach frame node, call extract
-        for (frame_node_index, frame_node) in
-            frame_packet.frame_nodes(feature_index).iter().enumerate()
-        {
-            log::trace!(
-                "extract_frame_node feature: {} frame node: {}",
-                self.extract_impl.feature_debug_name(),
-                frame_node_index
-            );
+        let extract_impl = &self.extract_impl;
 
-            self.extract_impl.extract_frame_node(
-                extract_context,
-                *frame_node,
-                frame_node_index as u32,
-            );
-        }
-
-        // foreach view node, call extract
-        //TODO: Views can run in parallel
-        for view in views {
-            log::trace!(
-                "extract_view_nodes feature: {} view: {}, eye_position: {:?}",
-                self.extract_impl.feature_debug_name(),
-                view.debug_name(),
-                view.eye_position()
-            );
-
-            let view_nodes = frame_packet.view_nodes(view, feature_index);
-            if let Some(view_nodes) = view_nodes {
-                for (view_node_index, view_node) in view_nodes.iter().enumerate() {
+        // foreach frame node, call extract
+        let extracted_frame_node_data: Vec<_> = execute_chunked(
+            job_executor,
+            frame_packet.frame_nodes(feature_index),
+            RENDER_JOB_CHUNK_SIZE,
+            |first_frame_node_index, frame_nodes| {
+                let mut chunk_data = Vec::with_capacity(frame_nodes.len());
+                for (i, frame_node) in frame_nodes.iter().enumerate() {
+                    let frame_node_index = first_frame_node_index + i;
                     log::trace!(
-                        "extract_view_node feature: {} view node: {} node index: {}",
-                        self.extract_impl.feature_debug_name(),
-                        view.debug_name(),
-                        view_node_index
+                        "extract_frame_node feature: {} frame node: {}",
+                        feature_debug_name,
+                        frame_node_index
                     );
 
-                    self.extract_impl.extract_view_node(
+                    chunk_data.push(extract_impl.extract_frame_node(
                         extract_context,
-                        view,
-                        *view_node,
-                        view_node_index as u32,
-                    );
+                        *frame_node,
+                        frame_node_index as u32,
+                    ));
                 }
-            }
+                chunk_data
+            },
+        )
+        .into_iter()
+        .flatten()
+        .collect();
 
-            // call once after all view nodes extracted
+        // foreach view node, call extract. Views run in parallel, and each view's nodes are split
+        // into chunks
+        let extracted_frame_node_data_ref = &extracted_frame_node_data;
+        let extracted_view_node_data: Vec<Vec<_>> =
+            execute_chunked(job_executor, views, 1, |_, views| {
+                let view = views[0];
+                log::trace!(
+                    "extract_view_nodes feature: {} view: {}, eye_position: {:?}",
+                    feature_debug_name,
+                    view.debug_name(),
+                    view.eye_position()
+                );
+
+                let view_nodes = frame_packet.view_nodes(view, feature_index).unwrap_or(&[]);
+                execute_chunked(
+                    job_executor,
+                    view_nodes,
+                    RENDER_JOB_CHUNK_SIZE,
+                    |first_view_node_index, view_nodes| {
+                        let mut chunk_data = Vec::with_capacity(view_nodes.len());
+                        for (i, view_node) in view_nodes.iter().enumerate() {
+                            let view_node_index = first_view_node_index + i;
+                            log::trace!(
+                                "extract_view_node feature: {} view node: {} node index: {}",
+                                feature_debug_name,
+                                view.debug_name(),
+                                view_node_index
+                            );
+
+                            chunk_data.push(extract_impl.extract_view_node(
+                                extract_context,
+                                view,
+                                *view_node,
+                                view_node_index as u32,
+                                extracted_frame_node_data_ref,
+                            ));
+                        }
+                        chunk_data
+                    },
+                )
+                .into_iter()
+                .flatten()
+                .collect()
+            });
+
+        // call once per view after all view nodes extracted
+        for view in views {
             log::trace!(
                 "extract_view_finalize feature: {} view: {}",
-                self.extract_impl.feature_debug_name(),
+                feature_debug_name,
                 view.debug_name()
             );
             self.extract_impl
@@ -205,11 +292,12 @@ impl<
         }
 
         // call once after all nodes extracted
-        log::trace!(
-            "extract_frame_finalize {}",
-            self.extract_impl.feature_debug_name()
-        );
-        self.extract_impl.extract_frame_finalize(extract_context)
+        log::trace!("extract_frame_finalize {}", feature_debug_name);
+        self.extract_impl.extract_frame_finalize(
+            extract_context,
+            extracted_frame_node_data,
+            extracted_view_node_data,
+        )
     }
 
     fn feature_debug_name(&self) -> &'static str {
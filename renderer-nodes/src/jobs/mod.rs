@@ -1,3 +1,6 @@
+mod executor;
+pub use executor::*;
+
 mod extract;
 pub use extract::*;
 
@@ -1,9 +1,12 @@
 use crate::{
     FramePacket, RenderView, PerFrameNode, PerViewNode, RenderFeatureIndex, FeatureCommandWriter,
     PreparedRenderData, FeatureSubmitNodes, MergedFrameSubmitNodes, ViewSubmitNodes,
-    RenderRegistry,
+    RenderRegistry, SubmitNodeId,
 };
+use crate::{RenderJobExecutor, RenderJobTask, RENDER_JOB_CHUNK_SIZE};
+use crate::{default_render_job_executor, execute_chunked};
 use std::marker::PhantomData;
+use std::sync::Arc;
 
 pub trait PrepareJob<PrepareContextT, WriteContextT>: Send {
     fn prepare(
@@ -11,6 +14,7 @@ pub trait PrepareJob<PrepareContextT, WriteContextT>: Send {
         prepare_context: &PrepareContextT,
         frame_packet: &FramePacket,
         views: &[&RenderView],
+        job_executor: &dyn RenderJobExecutor,
     ) -> (
         Box<dyn FeatureCommandWriter<WriteContextT>>,
         FeatureSubmitNodes,
@@ -20,13 +24,29 @@ pub trait PrepareJob<PrepareContextT, WriteContextT>: Send {
     fn feature_index(&self) -> RenderFeatureIndex;
 }
 
+type PrepareJobResult<WriteContextT> = (
+    Box<dyn FeatureCommandWriter<WriteContextT>>,
+    FeatureSubmitNodes,
+);
+
 pub struct PrepareJobSet<PrepareContextT, WriteContextT> {
     prepare_jobs: Vec<Box<dyn PrepareJob<PrepareContextT, WriteContextT>>>,
+    job_executor: Arc<dyn RenderJobExecutor>,
 }
 
 impl<PrepareContextT, WriteContextT> PrepareJobSet<PrepareContextT, WriteContextT> {
     pub fn new(prepare_jobs: Vec<Box<dyn PrepareJob<PrepareContextT, WriteContextT>>>) -> Self {
-        PrepareJobSet { prepare_jobs }
+        Self::with_job_executor(prepare_jobs, default_render_job_executor())
+    }
+
+    pub fn with_job_executor(
+        prepare_jobs: Vec<Box<dyn PrepareJob<PrepareContextT, WriteContextT>>>,
+        job_executor: Arc<dyn RenderJobExecutor>,
+    ) -> Self {
+        PrepareJobSet {
+            prepare_jobs,
+            job_executor,
+        }
     }
 
     pub fn prepare(
@@ -35,14 +55,45 @@ impl<PrepareContextT, WriteContextT> PrepareJobSet<PrepareContextT, WriteContext
         frame_packet: &FramePacket,
         views: &[&RenderView],
         registry: &RenderRegistry,
-    ) -> Box<PreparedRenderData<WriteContextT>> {
-        let mut feature_command_writers = Vec::with_capacity(self.prepare_jobs.len());
-        let mut all_submit_nodes = Vec::with_capacity(self.prepare_jobs.len());
+    ) -> Box<PreparedRenderData<WriteContextT>>
+    where
+        PrepareContextT: Sync,
+    {
+        let PrepareJobSet {
+            prepare_jobs,
+            job_executor,
+        } = self;
+
+        // Each feature is prepared as its own task. Results are written into a slot per job so
+        // that writers and submit nodes stay in feature order.
+        let mut results: Vec<Option<PrepareJobResult<WriteContextT>>> =
+            (0..prepare_jobs.len()).map(|_| None).collect();
 
-        //TODO: Kick these to happen in parallel
-        for prepare_job in self.prepare_jobs {
-            let (writer, submit_nodes) = prepare_job.prepare(prepare_context, frame_packet, views);
+        {
+            let executor = &*job_executor;
+            let tasks = prepare_jobs
+                .into_iter()
+                .zip(results.iter_mut())
+                .map(|(prepare_job, result)| -> RenderJobTask {
+                    Box::new(move || {
+                        *result = Some(prepare_job.prepare(
+                            prepare_context,
+                            frame_packet,
+                            views,
+                            executor,
+                        ));
+                    })
+                })
+                .collect();
+
+            executor.execute(tasks);
+        }
 
+        let mut feature_command_writers = Vec::with_capacity(results.len());
+        let mut all_submit_nodes = Vec::with_capacity(results.len());
+        for result in results {
+            let (writer, submit_nodes) =
+                result.expect("RenderJobExecutor did not run a prepare job");
             feature_command_writers.push(writer);
             all_submit_nodes.push(submit_nodes);
         }
@@ -53,11 +104,21 @@ impl<PrepareContextT, WriteContextT> PrepareJobSet<PrepareContextT, WriteContext
         Box::new(PreparedRenderData::new(
             feature_command_writers,
             merged_submit_nodes,
+            job_executor,
         ))
     }
 }
 
-pub trait DefaultPrepareJobImpl<PrepareContextT, WriteContextT>: Send {
+/// Implements the per-node steps of a `DefaultPrepareJob`. Like `DefaultExtractJobImpl`, node
+/// callbacks run in parallel chunks and return their results.
+///
+/// View node data from all views is concatenated (in view order) before being passed to
+/// prepare_frame_finalize. The submit_node_id passed to prepare_view_node is the index the
+/// returned data will have in that list, so it can be used directly as the submit node's id.
+pub trait DefaultPrepareJobImpl<PrepareContextT, WriteContextT>: Send + Sync {
+    type PreparedFrameNodeData: Send + Sync;
+    type PreparedViewNodeData: Send;
+
     fn prepare_begin(
         &mut self,
         prepare_context: &PrepareContextT,
@@ -66,20 +127,22 @@ pub trait DefaultPrepareJobImpl<PrepareContextT, WriteContextT>: Send {
         submit_nodes: &mut FeatureSubmitNodes,
     );
     fn prepare_frame_node(
-        &mut self,
+        &self,
         prepare_context: &PrepareContextT,
         frame_node: PerFrameNode,
         frame_node_index: u32,
-        submit_nodes: &mut FeatureSubmitNodes,
-    );
+    ) -> Self::PreparedFrameNodeData;
+    #[allow(clippy::too_many_arguments)]
     fn prepare_view_node(
-        &mut self,
+        &self,
         prepare_context: &PrepareContextT,
         view: &RenderView,
         view_node: PerViewNode,
         view_node_index: u32,
+        prepared_frame_node_data: &[Self::PreparedFrameNodeData],
+        submit_node_id: SubmitNodeId,
         submit_nodes: &mut ViewSubmitNodes,
-    );
+    ) -> Self::PreparedViewNodeData;
     fn prepare_view_finalize(
         &mut self,
         prepare_context: &PrepareContextT,
@@ -89,6 +152,8 @@ pub trait DefaultPrepareJobImpl<PrepareContextT, WriteContextT>: Send {
     fn prepare_frame_finalize(
         self,
         prepare_context: &PrepareContextT,
+        prepared_frame_node_data: Vec<Self::PreparedFrameNodeData>,
+        prepared_view_node_data: Vec<Self::PreparedViewNodeData>,
         submit_nodes: &mut FeatureSubmitNodes,
     ) -> Box<dyn FeatureCommandWriter<WriteContextT>>;
 
@@ -120,7 +185,7 @@ impl<
 }
 
 impl<
-        PrepareContextT: Send,
+        PrepareContextT: Send + Sync,
         WriteContextT: Send,
         PrepareImplT: DefaultPrepareJobImpl<PrepareContextT, WriteContextT>,
     > PrepareJob<PrepareContextT, WriteContextT>
@@ -131,77 +196,124 @@ impl<
         prepare_context: &PrepareContextT,
         frame_packet: &FramePacket,
         views: &[&RenderView],
+        job_executor: &dyn RenderJobExecutor,
     ) -> (
         Box<dyn FeatureCommandWriter<WriteContextT>>,
         FeatureSubmitNodes,
     ) {
         let feature_index = self.prepare_impl.feature_index();
+        let feature_debug_name = self.prepare_impl.feature_debug_name();
 
         let mut submit_nodes = FeatureSubmitNodes::default();
 
-        // In the future, make features run in parallel
-        log::trace!(
-            "prepare_begin feature: {}",
-            self.prepare_impl.feature_debug_name()
-        );
+        log::trace!("prepare_begin feature: {}", feature_debug_name);
         self.prepare_impl
             .prepare_begin(prepare_context, frame_packet, views, &mut submit_nodes);
 
-        // foreach frame node, call extract
-        for (frame_node_index, frame_node) in
-            frame_packet.frame_nodes(feature_index).iter().enumerate()
-        {
-            log::trace!(
-                "prepare_frame_node feature: {} frame node: {}",
-                self.prepare_impl.feature_debug_name(),
-                frame_node_index
-            );
+        let prepare_impl = &self.prepare_impl;
 
-            self.prepare_impl.prepare_frame_node(
-                prepare_context,
-                *frame_node,
-                frame_node_index as u32,
-                &mut submit_nodes,
-            );
-        }
+        // foreach frame node, call prepare
+        let prepared_frame_node_data: Vec<_> = execute_chunked(
+            job_executor,
+            frame_packet.frame_nodes(feature_index),
+            RENDER_JOB_CHUNK_SIZE,
+            |first_frame_node_index, frame_nodes| {
+                let mut chunk_data = Vec::with_capacity(frame_nodes.len());
+                for (i, frame_node) in frame_nodes.iter().enumerate() {
+                    let frame_node_index = first_frame_node_index + i;
+                    log::trace!(
+                        "prepare_frame_node feature: {} frame node: {}",
+                        feature_debug_name,
+                        frame_node_index
+                    );
 
-        // foreach view node, call extract
-        //TODO: Views can run in parallel
-        for view in views {
-            let mut view_submit_nodes =
-                ViewSubmitNodes::new(self.prepare_impl.feature_index(), view.render_phase_mask());
+                    chunk_data.push(prepare_impl.prepare_frame_node(
+                        prepare_context,
+                        *frame_node,
+                        frame_node_index as u32,
+                    ));
+                }
+                chunk_data
+            },
+        )
+        .into_iter()
+        .flatten()
+        .collect();
+
+        // Where each view's node data starts in the concatenated list of view node data
+        let view_node_data_offsets: Vec<usize> = views
+            .iter()
+            .scan(0, |offset, view| {
+                let view_offset = *offset;
+                *offset += frame_packet
+                    .view_nodes(view, feature_index)
+                    .map_or(0, |view_nodes| view_nodes.len());
+                Some(view_offset)
+            })
+            .collect();
 
-            // foreach view node, call extract
+        // foreach view node, call prepare. Views run in parallel, and each view's nodes are split
+        // into chunks that each produce their own submit node list
+        let prepared_frame_node_data_ref = &prepared_frame_node_data;
+        let view_node_data_offsets_ref = &view_node_data_offsets;
+        let prepared_views = execute_chunked(job_executor, views, 1, |view_index, views| {
+            let view = views[0];
+            let view_node_data_offset = view_node_data_offsets_ref[view_index];
             log::trace!(
                 "prepare_view_nodes feature: {} view: {}",
-                self.prepare_impl.feature_debug_name(),
+                feature_debug_name,
                 view.debug_name()
             );
 
-            let view_nodes = frame_packet.view_nodes(view, feature_index);
-            if let Some(view_nodes) = view_nodes {
-                for (view_node_index, view_node) in view_nodes.iter().enumerate() {
-                    log::trace!(
-                        "prepare_view_node feature: {} view: {} node index: {}",
-                        self.prepare_impl.feature_debug_name(),
-                        view.debug_name(),
-                        view_node_index
-                    );
+            let view_nodes = frame_packet.view_nodes(view, feature_index).unwrap_or(&[]);
+            let chunks = execute_chunked(
+                job_executor,
+                view_nodes,
+                RENDER_JOB_CHUNK_SIZE,
+                |first_view_node_index, view_nodes| {
+                    let mut chunk_submit_nodes =
+                        ViewSubmitNodes::new(feature_index, view.render_phase_mask());
+                    let mut chunk_data = Vec::with_capacity(view_nodes.len());
+                    for (i, view_node) in view_nodes.iter().enumerate() {
+                        let view_node_index = first_view_node_index + i;
+                        log::trace!(
+                            "prepare_view_node feature: {} view: {} node index: {}",
+                            feature_debug_name,
+                            view.debug_name(),
+                            view_node_index
+                        );
 
-                    self.prepare_impl.prepare_view_node(
-                        prepare_context,
-                        view,
-                        *view_node,
-                        view_node_index as u32,
-                        &mut view_submit_nodes,
-                    );
-                }
+                        chunk_data.push(prepare_impl.prepare_view_node(
+                            prepare_context,
+                            view,
+                            *view_node,
+                            view_node_index as u32,
+                            prepared_frame_node_data_ref,
+                            (view_node_data_offset + view_node_index) as SubmitNodeId,
+                            &mut chunk_submit_nodes,
+                        ));
+                    }
+                    (chunk_data, chunk_submit_nodes)
+                },
+            );
+
+            let mut view_data = Vec::with_capacity(view_nodes.len());
+            let mut view_submit_nodes =
+                ViewSubmitNodes::new(feature_index, view.render_phase_mask());
+            for (mut chunk_data, chunk_submit_nodes) in chunks {
+                view_data.append(&mut chunk_data);
+                view_submit_nodes.append(chunk_submit_nodes);
             }
 
-            // call once after all view nodes extracted
+            (view_data, view_submit_nodes)
+        });
+
+        // call once per view after all view nodes prepared
+        let mut prepared_view_node_data = vec![];
+        for (view, (mut view_data, mut view_submit_nodes)) in views.iter().zip(prepared_views) {
             log::trace!(
                 "prepare_view_finalize feature: {} view: {}",
-                self.prepare_impl.feature_debug_name(),
+                feature_debug_name,
                 view.debug_name()
             );
 
@@ -209,17 +321,18 @@ impl<
                 .prepare_view_finalize(prepare_context, view, &mut view_submit_nodes);
 
             submit_nodes.add_submit_nodes_for_view(view, view_submit_nodes);
+            prepared_view_node_data.append(&mut view_data);
         }
 
-        // call once after all nodes extracted
-        log::trace!(
-            "prepare_frame_finalize {}",
-            self.prepare_impl.feature_debug_name()
-        );
+        // call once after all nodes prepared
+        log::trace!("prepare_frame_finalize {}", feature_debug_name);
 
-        let writer = self
-            .prepare_impl
-            .prepare_frame_finalize(prepare_context, &mut submit_nodes);
+        let writer = self.prepare_impl.prepare_frame_finalize(
+            prepare_context,
+            prepared_frame_node_data,
+            prepared_view_node_data,
+            &mut submit_nodes,
+        );
         (writer, submit_nodes)
     }
 
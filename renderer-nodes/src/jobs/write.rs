@@ -1,9 +1,112 @@
 use crate::{
     RenderFeatureIndex, RenderPhase, RenderView, MergedFrameSubmitNodes, RenderRegistry,
-    SubmitNodeId, RenderPhaseIndex
+    SubmitNodeId, RenderPhaseIndex, SubmitNode, RenderJobExecutor, RenderJobTask,
 };
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU32, Ordering};
 
-pub trait FeatureCommandWriter<WriteContextT> {
+/// The most descriptor sets a `RenderElementState` can track
+pub const MAX_RENDER_ELEMENT_DESCRIPTOR_SETS: usize = 4;
+
+/// The pipeline and descriptor sets a render element needs bound, as raw API handles. A handle of
+/// 0 means that slot isn't tracked and the writer binds it some other way.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub struct RenderElementState {
+    pub pipeline: u64,
+    pub descriptor_sets: [u64; MAX_RENDER_ELEMENT_DESCRIPTOR_SETS],
+}
+
+impl RenderElementState {
+    /// True if the pipeline must be bound to go from `previous_state` to this state
+    pub fn pipeline_changed(
+        &self,
+        previous_state: Option<&RenderElementState>,
+    ) -> bool {
+        match previous_state {
+            Some(previous_state) => self.pipeline != previous_state.pipeline,
+            None => true,
+        }
+    }
+
+    /// True if the descriptor set at `set_index` must be bound to go from `previous_state` to
+    /// this state. Changing the pipeline invalidates all sets.
+    pub fn descriptor_set_changed(
+        &self,
+        set_index: usize,
+        previous_state: Option<&RenderElementState>,
+    ) -> bool {
+        match previous_state {
+            Some(previous_state) => {
+                self.pipeline_changed(Some(previous_state))
+                    || self.descriptor_sets[set_index] != previous_state.descriptor_sets[set_index]
+            }
+            None => true,
+        }
+    }
+
+    fn count_binds(
+        &self,
+        previous_state: Option<&RenderElementState>,
+        stats: &mut RenderPhaseStats,
+    ) {
+        if self.pipeline != 0 && self.pipeline_changed(previous_state) {
+            stats.pipeline_bind_count += 1;
+        }
+
+        for (set_index, descriptor_set) in self.descriptor_sets.iter().enumerate() {
+            if *descriptor_set != 0 && self.descriptor_set_changed(set_index, previous_state) {
+                stats.descriptor_set_bind_count += 1;
+            }
+        }
+    }
+}
+
+/// Counters for the work done writing a render phase, summed over all views and ranges written
+/// for the frame. Binds are only counted for state that writers declare with
+/// `FeatureCommandWriter::render_element_state`.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct RenderPhaseStats {
+    pub setup_count: u32,
+    pub pipeline_bind_count: u32,
+    pub descriptor_set_bind_count: u32,
+    pub draw_count: u32,
+}
+
+#[derive(Default)]
+struct RenderPhaseStatsCounters {
+    setup_count: AtomicU32,
+    pipeline_bind_count: AtomicU32,
+    descriptor_set_bind_count: AtomicU32,
+    draw_count: AtomicU32,
+}
+
+impl RenderPhaseStatsCounters {
+    fn add(
+        &self,
+        stats: &RenderPhaseStats,
+    ) {
+        self.setup_count
+            .fetch_add(stats.setup_count, Ordering::Relaxed);
+        self.pipeline_bind_count
+            .fetch_add(stats.pipeline_bind_count, Ordering::Relaxed);
+        self.descriptor_set_bind_count
+            .fetch_add(stats.descriptor_set_bind_count, Ordering::Relaxed);
+        self.draw_count
+            .fetch_add(stats.draw_count, Ordering::Relaxed);
+    }
+
+    fn get(&self) -> RenderPhaseStats {
+        RenderPhaseStats {
+            setup_count: self.setup_count.load(Ordering::Relaxed),
+            pipeline_bind_count: self.pipeline_bind_count.load(Ordering::Relaxed),
+            descriptor_set_bind_count: self.descriptor_set_bind_count.load(Ordering::Relaxed),
+            draw_count: self.draw_count.load(Ordering::Relaxed),
+        }
+    }
+}
+
+// Sync because ranges of a phase may be written from several threads at once
+pub trait FeatureCommandWriter<WriteContextT>: Send + Sync {
     fn apply_setup(
         &self,
         write_context: &mut WriteContextT,
@@ -24,6 +127,31 @@ pub trait FeatureCommandWriter<WriteContextT> {
         render_phase_index: RenderPhaseIndex
     );
 
+    /// Declares the state the element needs bound. When consecutive elements of this feature
+    /// declare state, bind_render_element_state is called before each render_element with the
+    /// previous element's state so that only what changed is bound. Returning None (the default)
+    /// means render_element binds everything itself.
+    fn render_element_state(
+        &self,
+        _view: &RenderView,
+        _render_phase_index: RenderPhaseIndex,
+        _index: SubmitNodeId,
+    ) -> Option<RenderElementState> {
+        None
+    }
+
+    /// Binds the parts of `state` that differ from `previous_state`. `previous_state` is None for
+    /// the first element after apply_setup, in which case everything must be bound.
+    fn bind_render_element_state(
+        &self,
+        _write_context: &mut WriteContextT,
+        _view: &RenderView,
+        _render_phase_index: RenderPhaseIndex,
+        _state: &RenderElementState,
+        _previous_state: Option<&RenderElementState>,
+    ) {
+    }
+
     fn feature_debug_name(&self) -> &'static str;
     fn feature_index(&self) -> RenderFeatureIndex;
 }
@@ -35,12 +163,17 @@ pub trait FeatureCommandWriter<WriteContextT> {
 pub struct PreparedRenderData<WriteContextT> {
     feature_writers: Vec<Option<Box<dyn FeatureCommandWriter<WriteContextT>>>>,
     submit_nodes: MergedFrameSubmitNodes,
+    job_executor: Arc<dyn RenderJobExecutor>,
+
+    // Indexed by render phase index
+    render_phase_stats: Vec<RenderPhaseStatsCounters>,
 }
 
 impl<WriteContextT> PreparedRenderData<WriteContextT> {
     pub fn new(
         feature_writers: Vec<Box<dyn FeatureCommandWriter<WriteContextT>>>,
         submit_nodes: MergedFrameSubmitNodes,
+        job_executor: Arc<dyn RenderJobExecutor>,
     ) -> Self {
         let mut writers: Vec<_> = (0..RenderRegistry::registered_feature_count())
             .map(|_| None)
@@ -51,12 +184,30 @@ impl<WriteContextT> PreparedRenderData<WriteContextT> {
             writers[feature_index as usize] = Some(writer);
         }
 
+        let render_phase_stats = (0..RenderRegistry::registered_render_phase_count())
+            .map(|_| Default::default())
+            .collect();
+
         PreparedRenderData {
             feature_writers: writers,
             submit_nodes,
+            job_executor,
+            render_phase_stats,
         }
     }
 
+    /// Counters for everything written for the phase so far
+    pub fn render_phase_stats<PhaseT: RenderPhase>(&self) -> RenderPhaseStats {
+        self.render_phase_stats_by_index(PhaseT::render_phase_index())
+    }
+
+    pub fn render_phase_stats_by_index(
+        &self,
+        render_phase_index: RenderPhaseIndex,
+    ) -> RenderPhaseStats {
+        self.render_phase_stats[render_phase_index as usize].get()
+    }
+
     pub fn write_view_phase<PhaseT: RenderPhase>(
         &self,
         view: &RenderView,
@@ -64,42 +215,460 @@ impl<WriteContextT> PreparedRenderData<WriteContextT> {
     ) {
         let submit_nodes = self.submit_nodes.submit_nodes::<PhaseT>(view);
         let render_phase_index = PhaseT::render_phase_index();
+        self.write_submit_nodes(view, render_phase_index, submit_nodes, write_context);
+    }
+
+    /// The number of write contexts `write_view_phase_parallel` needs for the given view/phase
+    pub fn view_phase_range_count<PhaseT: RenderPhase>(
+        &self,
+        view: &RenderView,
+        submit_nodes_per_range: usize,
+    ) -> usize {
+        assert!(submit_nodes_per_range > 0);
+        let submit_node_count = self.submit_nodes.submit_nodes::<PhaseT>(view).len();
+        (submit_node_count + submit_nodes_per_range - 1) / submit_nodes_per_range
+    }
+
+    /// Splits the sorted submit nodes for the view/phase into ranges of `submit_nodes_per_range`
+    /// and writes each range into its own write context, in parallel. `write_contexts` must have
+    /// `view_phase_range_count` elements. Range `i` is written into `write_contexts[i]`, so
+    /// executing the contexts in order preserves the sort order of the phase.
+    ///
+    /// Features are set up and reverted at the start and end of every range since each range
+    /// starts with no state.
+    pub fn write_view_phase_parallel<PhaseT: RenderPhase>(
+        &self,
+        view: &RenderView,
+        submit_nodes_per_range: usize,
+        write_contexts: &mut [WriteContextT],
+    ) where
+        WriteContextT: Send,
+    {
+        assert_eq!(
+            write_contexts.len(),
+            self.view_phase_range_count::<PhaseT>(view, submit_nodes_per_range)
+        );
+
+        let submit_nodes = self.submit_nodes.submit_nodes::<PhaseT>(view);
+        let render_phase_index = PhaseT::render_phase_index();
+
+        let tasks = submit_nodes
+            .chunks(submit_nodes_per_range)
+            .zip(write_contexts.iter_mut())
+            .map(|(submit_nodes, write_context)| -> RenderJobTask {
+                Box::new(move || {
+                    self.write_submit_nodes(view, render_phase_index, submit_nodes, write_context)
+                })
+            })
+            .collect();
+
+        self.job_executor.execute(tasks);
+    }
 
-        let mut previous_node_feature_index: i32 = -1;
+    // Writes a contiguous run of sorted submit nodes. Setup is applied whenever the feature
+    // changes and reverted before the next feature (and at the end), so each call leaves the
+    // write context the way it found it.
+    fn write_submit_nodes(
+        &self,
+        view: &RenderView,
+        render_phase_index: RenderPhaseIndex,
+        submit_nodes: &[SubmitNode],
+        write_context: &mut WriteContextT,
+    ) {
+        let mut stats = RenderPhaseStats::default();
+        let mut previous_writer: Option<&dyn FeatureCommandWriter<WriteContextT>> = None;
+        let mut previous_state: Option<RenderElementState> = None;
         for submit_node in submit_nodes {
-            if submit_node.feature_index() as i32 != previous_node_feature_index {
-                if previous_node_feature_index != -1 {
-                    // call revert setup
-                    log::trace!("revert setup for feature {}", previous_node_feature_index);
-                    self.feature_writers[previous_node_feature_index as usize]
-                        .as_ref()
-                        .unwrap()
-                        .revert_setup(write_context, view, render_phase_index);
+            let writer = self.feature_writers[submit_node.feature_index() as usize]
+                .as_deref()
+                .unwrap();
+
+            if previous_writer.map(|w| w.feature_index()) != Some(submit_node.feature_index()) {
+                if let Some(previous_writer) = previous_writer {
+                    log::trace!(
+                        "revert setup for feature {}",
+                        previous_writer.feature_index()
+                    );
+                    previous_writer.revert_setup(write_context, view, render_phase_index);
                 }
 
-                // call apply setup
                 log::trace!("apply setup for feature {}", submit_node.feature_index());
-                self.feature_writers[submit_node.feature_index() as usize]
-                    .as_ref()
-                    .unwrap()
-                    .apply_setup(write_context, view, render_phase_index);
+                writer.apply_setup(write_context, view, render_phase_index);
+                stats.setup_count += 1;
+                previous_writer = Some(writer);
+
+                // Whatever the previous feature bound is unknown to this one
+                previous_state = None;
+            }
+
+            let state =
+                writer.render_element_state(view, render_phase_index, submit_node.submit_node_id());
+            if let Some(state) = &state {
+                if previous_state.as_ref() != Some(state) {
+                    state.count_binds(previous_state.as_ref(), &mut stats);
+                    writer.bind_render_element_state(
+                        write_context,
+                        view,
+                        render_phase_index,
+                        state,
+                        previous_state.as_ref(),
+                    );
+                }
             }
+            previous_state = state;
 
             log::trace!(
                 "draw render node feature: {} node id: {}",
                 submit_node.feature_index(),
                 submit_node.submit_node_id(),
             );
-            self.feature_writers[submit_node.feature_index() as usize]
-                .as_ref()
-                .unwrap()
-                .render_element(write_context, view, render_phase_index, submit_node.submit_node_id());
-            previous_node_feature_index = submit_node.feature_index() as i32;
+            writer.render_element(
+                write_context,
+                view,
+                render_phase_index,
+                submit_node.submit_node_id(),
+            );
+            stats.draw_count += 1;
+        }
+
+        if let Some(previous_writer) = previous_writer {
+            log::trace!(
+                "revert setup for feature: {}",
+                previous_writer.feature_index()
+            );
+            previous_writer.revert_setup(write_context, view, render_phase_index);
+        }
+
+        self.render_phase_stats[render_phase_index as usize].add(&stats);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        default_render_job_executor, FeatureSubmitNodes, RenderPhaseMaskBuilder,
+        RenderRegistryBuilder, SerialRenderJobExecutor, SubmitNodeSortKey, ViewSubmitNodes,
+    };
+    use glam::{Mat4, Vec3};
+    use std::sync::atomic::AtomicPtr;
+    use std::sync::Once;
+
+    static TEST_PHASE_INDEX: AtomicU32 = AtomicU32::new(0);
+
+    struct TestPhase;
+
+    impl RenderPhase for TestPhase {
+        fn set_render_phase_index(index: RenderPhaseIndex) {
+            TEST_PHASE_INDEX.store(index, Ordering::Release);
+        }
+
+        fn render_phase_index() -> RenderPhaseIndex {
+            TEST_PHASE_INDEX.load(Ordering::Acquire)
+        }
+
+        fn sort_submit_nodes(mut submit_nodes: Vec<SubmitNode>) -> Vec<SubmitNode> {
+            submit_nodes.sort_by_key(|submit_node| submit_node.sort_key());
+            submit_nodes
+        }
+
+        fn render_phase_debug_name() -> &'static str {
+            "TestPhase"
+        }
+    }
+
+    // Phases are registered globally, so the test phase is registered once and the registry is
+    // shared by all tests
+    fn test_registry() -> RenderRegistry {
+        static REGISTER: Once = Once::new();
+        static REGISTRY: AtomicPtr<RenderRegistry> = AtomicPtr::new(std::ptr::null_mut());
+        REGISTER.call_once(|| {
+            let registry = RenderRegistryBuilder::default()
+                .register_render_phase::<TestPhase>()
+                .build();
+            REGISTRY.store(Box::into_raw(Box::new(registry)), Ordering::Release);
+        });
+
+        unsafe { (*REGISTRY.load(Ordering::Acquire)).clone() }
+    }
+
+    // Records every call into the write context. Pairs of consecutive submit node IDs share a
+    // pipeline so that some elements don't need anything bound.
+    struct RecordingWriter {
+        feature_index: RenderFeatureIndex,
+    }
+
+    impl FeatureCommandWriter<Vec<String>> for RecordingWriter {
+        fn apply_setup(
+            &self,
+            write_context: &mut Vec<String>,
+            _view: &RenderView,
+            _render_phase_index: RenderPhaseIndex,
+        ) {
+            write_context.push(format!("apply {}", self.feature_index));
+        }
+
+        fn render_element(
+            &self,
+            write_context: &mut Vec<String>,
+            _view: &RenderView,
+            _render_phase_index: RenderPhaseIndex,
+            index: SubmitNodeId,
+        ) {
+            write_context.push(format!("draw {} {}", self.feature_index, index));
+        }
+
+        fn revert_setup(
+            &self,
+            write_context: &mut Vec<String>,
+            _view: &RenderView,
+            _render_phase_index: RenderPhaseIndex,
+        ) {
+            write_context.push(format!("revert {}", self.feature_index));
+        }
+
+        fn render_element_state(
+            &self,
+            _view: &RenderView,
+            _render_phase_index: RenderPhaseIndex,
+            index: SubmitNodeId,
+        ) -> Option<RenderElementState> {
+            Some(RenderElementState {
+                pipeline: (index / 2 + 1) as u64,
+                descriptor_sets: Default::default(),
+            })
+        }
+
+        fn bind_render_element_state(
+            &self,
+            write_context: &mut Vec<String>,
+            _view: &RenderView,
+            _render_phase_index: RenderPhaseIndex,
+            state: &RenderElementState,
+            previous_state: Option<&RenderElementState>,
+        ) {
+            write_context.push(format!(
+                "bind {} {} {:?}",
+                self.feature_index,
+                state.pipeline,
+                previous_state.map(|previous_state| previous_state.pipeline)
+            ));
         }
 
-        if previous_node_feature_index != -1 {
-            // call revert setup
-            log::trace!("revert setup for feature: {}", previous_node_feature_index);
+        fn feature_debug_name(&self) -> &'static str {
+            "RecordingWriter"
         }
+
+        fn feature_index(&self) -> RenderFeatureIndex {
+            self.feature_index
+        }
+    }
+
+    fn test_view() -> RenderView {
+        RenderView::new(
+            0,
+            Vec3::zero(),
+            Mat4::identity(),
+            Mat4::identity(),
+            RenderPhaseMaskBuilder::default()
+                .add_render_phase::<TestPhase>()
+                .build(),
+            "test".to_string(),
+        )
+    }
+
+    // The submit node with ID i belongs to feature_indices[i], and the phase is sorted by ID
+    fn test_prepared_render_data(
+        view: &RenderView,
+        feature_indices: &[RenderFeatureIndex],
+    ) -> PreparedRenderData<Vec<String>> {
+        let registry = test_registry();
+
+        let mut feature_writers: Vec<Option<Box<dyn FeatureCommandWriter<Vec<String>>>>> = vec![];
+        let mut feature_submit_nodes = vec![];
+        for feature_index in 0..2 {
+            feature_writers.push(Some(Box::new(RecordingWriter { feature_index })));
+
+            let mut view_submit_nodes =
+                ViewSubmitNodes::new(feature_index, view.render_phase_mask());
+            for (submit_node_id, _) in feature_indices
+                .iter()
+                .enumerate()
+                .filter(|(_, x)| **x == feature_index)
+            {
+                view_submit_nodes.add_submit_node::<TestPhase>(
+                    submit_node_id as SubmitNodeId,
+                    submit_node_id as SubmitNodeSortKey,
+                    0.0,
+                );
+            }
+
+            let mut submit_nodes = FeatureSubmitNodes::default();
+            submit_nodes.add_submit_nodes_for_view(view, view_submit_nodes);
+            feature_submit_nodes.push(submit_nodes);
+        }
+
+        PreparedRenderData {
+            feature_writers,
+            submit_nodes: MergedFrameSubmitNodes::new(feature_submit_nodes, &registry),
+            job_executor: Arc::new(SerialRenderJobExecutor),
+            render_phase_stats: (0..RenderRegistry::registered_render_phase_count())
+                .map(|_| Default::default())
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn test_write_view_phase() {
+        let view = test_view();
+        let prepared_render_data = test_prepared_render_data(&view, &[0, 0, 0, 1, 1, 0]);
+
+        let mut write_context = vec![];
+        prepared_render_data.write_view_phase::<TestPhase>(&view, &mut write_context);
+
+        // Setup is reverted before the next feature is applied and at the end. Bound state isn't
+        // carried over to the next feature even if it matches.
+        let expected = [
+            "apply 0",
+            "bind 0 1 None",
+            "draw 0 0",
+            "draw 0 1",
+            "bind 0 2 Some(1)",
+            "draw 0 2",
+            "revert 0",
+            "apply 1",
+            "bind 1 2 None",
+            "draw 1 3",
+            "bind 1 3 Some(2)",
+            "draw 1 4",
+            "revert 1",
+            "apply 0",
+            "bind 0 3 None",
+            "draw 0 5",
+            "revert 0",
+        ];
+        assert_eq!(write_context, expected);
+
+        let stats = prepared_render_data.render_phase_stats::<TestPhase>();
+        assert_eq!(
+            stats,
+            RenderPhaseStats {
+                setup_count: 3,
+                pipeline_bind_count: 5,
+                descriptor_set_bind_count: 0,
+                draw_count: 6,
+            }
+        );
+    }
+
+    #[test]
+    fn test_write_view_phase_parallel() {
+        let view = test_view();
+        let mut prepared_render_data = test_prepared_render_data(&view, &[0, 0, 0, 1, 1, 0]);
+        prepared_render_data.job_executor = default_render_job_executor();
+
+        assert_eq!(
+            prepared_render_data.view_phase_range_count::<TestPhase>(&view, 4),
+            2
+        );
+        assert_eq!(
+            prepared_render_data.view_phase_range_count::<TestPhase>(&view, 6),
+            1
+        );
+
+        let range_count = prepared_render_data.view_phase_range_count::<TestPhase>(&view, 2);
+        assert_eq!(range_count, 3);
+
+        let mut write_contexts = vec![vec![]; range_count];
+        prepared_render_data.write_view_phase_parallel::<TestPhase>(&view, 2, &mut write_contexts);
+
+        // Each range starts with no state, so it applies and reverts its own setup and binds
+        // everything for its first element
+        let expected: [&[&str]; 3] = [
+            &[
+                "apply 0",
+                "bind 0 1 None",
+                "draw 0 0",
+                "draw 0 1",
+                "revert 0",
+            ],
+            &[
+                "apply 0",
+                "bind 0 2 None",
+                "draw 0 2",
+                "revert 0",
+                "apply 1",
+                "bind 1 2 None",
+                "draw 1 3",
+                "revert 1",
+            ],
+            &[
+                "apply 1",
+                "bind 1 3 None",
+                "draw 1 4",
+                "revert 1",
+                "apply 0",
+                "bind 0 3 None",
+                "draw 0 5",
+                "revert 0",
+            ],
+        ];
+        assert_eq!(write_contexts, expected);
+
+        let stats = prepared_render_data.render_phase_stats::<TestPhase>();
+        assert_eq!(stats.setup_count, 5);
+        assert_eq!(stats.draw_count, 6);
+    }
+
+    #[test]
+    fn test_write_submit_nodes_empty() {
+        let view = test_view();
+        let prepared_render_data = test_prepared_render_data(&view, &[]);
+
+        // Nothing is applied or reverted if there is nothing to draw
+        let mut write_context = vec![];
+        prepared_render_data.write_submit_nodes(
+            &view,
+            TestPhase::render_phase_index(),
+            &[],
+            &mut write_context,
+        );
+        assert!(write_context.is_empty());
+    }
+
+    #[test]
+    fn test_render_element_state_binds() {
+        let state_a = RenderElementState {
+            pipeline: 1,
+            descriptor_sets: [10, 11, 0, 0],
+        };
+
+        // Everything is bound when nothing is known about the previous state
+        let mut stats = RenderPhaseStats::default();
+        state_a.count_binds(None, &mut stats);
+        assert_eq!(stats.pipeline_bind_count, 1);
+        assert_eq!(stats.descriptor_set_bind_count, 2);
+
+        // Only the changed set is bound
+        let state_b = RenderElementState {
+            pipeline: 1,
+            descriptor_sets: [10, 12, 0, 0],
+        };
+        let mut stats = RenderPhaseStats::default();
+        state_b.count_binds(Some(&state_a), &mut stats);
+        assert_eq!(stats.pipeline_bind_count, 0);
+        assert_eq!(stats.descriptor_set_bind_count, 1);
+        assert!(!state_b.descriptor_set_changed(0, Some(&state_a)));
+        assert!(state_b.descriptor_set_changed(1, Some(&state_a)));
+
+        // A new pipeline rebinds all sets
+        let state_c = RenderElementState {
+            pipeline: 2,
+            descriptor_sets: [10, 12, 0, 0],
+        };
+        let mut stats = RenderPhaseStats::default();
+        state_c.count_binds(Some(&state_b), &mut stats);
+        assert_eq!(stats.pipeline_bind_count, 1);
+        assert_eq!(stats.descriptor_set_bind_count, 2);
     }
 }
@@ -13,6 +13,16 @@ pub use submit_nodes::MergedFrameSubmitNodes;
 pub use submit_nodes::SubmitNodeId;
 pub use submit_nodes::SubmitNodeSortKey;
 
+mod submit_node_sort;
+pub use submit_node_sort::SubmitNodeSortKeyBuilder;
+pub use submit_node_sort::sort_submit_nodes_front_to_back;
+pub use submit_node_sort::sort_submit_nodes_back_to_front;
+pub use submit_node_sort::sort_submit_nodes_by_state;
+pub use submit_node_sort::SORT_KEY_FEATURE_BITS;
+pub use submit_node_sort::SORT_KEY_PIPELINE_BITS;
+pub use submit_node_sort::SORT_KEY_MATERIAL_BITS;
+pub use submit_node_sort::SORT_KEY_DEPTH_BUCKET_BITS;
+
 mod render_views;
 pub use render_views::RenderViewSet;
 pub use render_views::RenderView;
@@ -42,7 +52,18 @@ pub use registry::RenderPhase;
 pub use registry::RenderFeatureIndex;
 pub use registry::RenderPhaseIndex;
 
+pub type LodIndex = u32;
+
+/// A render node that passed visibility for a view, along with per-view data calculated during
+/// visibility that extract/prepare jobs would otherwise need to recalculate
+#[derive(Copy, Clone, Debug)]
+pub struct VisibleNode {
+    pub handle: GenericRenderNodeHandle,
+    pub distance_from_camera: f32,
+    pub lod_index: LodIndex,
+}
+
 #[derive(Default)]
 pub struct VisibilityResult {
-    pub handles: Vec<GenericRenderNodeHandle>,
+    pub visible_nodes: Vec<VisibleNode>,
 }
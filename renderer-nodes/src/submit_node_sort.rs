@@ -0,0 +1,384 @@
+use crate::{SubmitNode, SubmitNodeSortKey, RenderFeatureIndex};
+
+// Bits used by each of the standard sort key fields. They add up to 64 so that a key holding all
+// of them fills the whole SubmitNodeSortKey.
+pub const SORT_KEY_FEATURE_BITS: u32 = 8;
+pub const SORT_KEY_PIPELINE_BITS: u32 = 16;
+pub const SORT_KEY_MATERIAL_BITS: u32 = 24;
+pub const SORT_KEY_DEPTH_BUCKET_BITS: u32 = 16;
+
+// Below this many nodes a comparison sort is faster than radix sorting
+const RADIX_SORT_THRESHOLD: usize = 256;
+
+/// Packs fields into a `SubmitNodeSortKey`. Fields are laid out from the most significant bit
+/// down in the order they are added, so earlier fields take priority when sorting by the key.
+///
+/// Values that don't fit in their field are folded into it (xor of each chunk of the value), so
+/// large values like API handles can be used directly. Folding can make different values collide,
+/// which only affects how well nodes batch, not correctness.
+#[derive(Copy, Clone, Debug)]
+pub struct SubmitNodeSortKeyBuilder {
+    key: SubmitNodeSortKey,
+    bits_remaining: u32,
+}
+
+impl Default for SubmitNodeSortKeyBuilder {
+    fn default() -> Self {
+        SubmitNodeSortKeyBuilder {
+            key: 0,
+            bits_remaining: 64,
+        }
+    }
+}
+
+impl SubmitNodeSortKeyBuilder {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Appends a field of `bit_count` bits below all previously added fields
+    pub fn field(
+        mut self,
+        value: u64,
+        bit_count: u32,
+    ) -> Self {
+        assert!(bit_count > 0 && bit_count <= self.bits_remaining);
+        self.bits_remaining -= bit_count;
+        self.key |= fold_to_bits(value, bit_count) << self.bits_remaining;
+        self
+    }
+
+    pub fn feature(
+        self,
+        feature_index: RenderFeatureIndex,
+    ) -> Self {
+        self.field(feature_index as u64, SORT_KEY_FEATURE_BITS)
+    }
+
+    pub fn pipeline(
+        self,
+        pipeline: u64,
+    ) -> Self {
+        self.field(pipeline, SORT_KEY_PIPELINE_BITS)
+    }
+
+    pub fn material(
+        self,
+        material: u64,
+    ) -> Self {
+        self.field(material, SORT_KEY_MATERIAL_BITS)
+    }
+
+    /// Quantizes the distance into buckets between 0 and `max_distance`, nearest first
+    pub fn depth_bucket(
+        self,
+        distance_from_camera: f32,
+        max_distance: f32,
+    ) -> Self {
+        let bucket = depth_bucket(
+            distance_from_camera,
+            max_distance,
+            SORT_KEY_DEPTH_BUCKET_BITS,
+        );
+        self.field(bucket, SORT_KEY_DEPTH_BUCKET_BITS)
+    }
+
+    /// Quantizes the distance into buckets between 0 and `max_distance`, farthest first
+    pub fn reverse_depth_bucket(
+        self,
+        distance_from_camera: f32,
+        max_distance: f32,
+    ) -> Self {
+        let max_bucket = (1 << SORT_KEY_DEPTH_BUCKET_BITS) - 1;
+        let bucket = depth_bucket(
+            distance_from_camera,
+            max_distance,
+            SORT_KEY_DEPTH_BUCKET_BITS,
+        );
+        self.field(max_bucket - bucket, SORT_KEY_DEPTH_BUCKET_BITS)
+    }
+
+    pub fn build(self) -> SubmitNodeSortKey {
+        self.key
+    }
+}
+
+fn fold_to_bits(
+    mut value: u64,
+    bit_count: u32,
+) -> u64 {
+    if bit_count >= 64 {
+        return value;
+    }
+
+    let mask = (1 << bit_count) - 1;
+    let mut folded = 0;
+    while value != 0 {
+        folded ^= value & mask;
+        value >>= bit_count;
+    }
+    folded
+}
+
+fn depth_bucket(
+    distance_from_camera: f32,
+    max_distance: f32,
+    bit_count: u32,
+) -> u64 {
+    let max_bucket = ((1u64 << bit_count) - 1) as f32;
+    let normalized = if max_distance > 0.0 {
+        (distance_from_camera / max_distance).max(0.0).min(1.0)
+    } else {
+        0.0
+    };
+
+    // NaN casts to 0
+    (normalized * max_bucket) as u64
+}
+
+/// Sorts opaque nodes grouped by feature (so each feature is set up once), then roughly nearest
+/// first to make the most of early depth testing, then by sort key. Distances are compared in
+/// coarse buckets so that nodes at similar depths are still batched by sort key.
+pub fn sort_submit_nodes_front_to_back(submit_nodes: Vec<SubmitNode>) -> Vec<SubmitNode> {
+    sort_submit_nodes_by_keys(
+        submit_nodes,
+        &[feature_key, distance_bucket_ascending_key, sort_key],
+    )
+}
+
+/// Sorts blended nodes farthest first. Ties are broken by feature and sort key.
+pub fn sort_submit_nodes_back_to_front(submit_nodes: Vec<SubmitNode>) -> Vec<SubmitNode> {
+    sort_submit_nodes_by_keys(
+        submit_nodes,
+        &[distance_descending_key, feature_key, sort_key],
+    )
+}
+
+/// Sorts by feature and sort key only, to minimize state changes. Nodes with equal keys keep the
+/// order they were submitted in, so this also suits UI where features submit nodes in draw order.
+pub fn sort_submit_nodes_by_state(submit_nodes: Vec<SubmitNode>) -> Vec<SubmitNode> {
+    sort_submit_nodes_by_keys(submit_nodes, &[feature_key, sort_key])
+}
+
+type SubmitNodeKeyFn = fn(&SubmitNode) -> u64;
+
+fn feature_key(submit_node: &SubmitNode) -> u64 {
+    submit_node.feature_index() as u64
+}
+
+fn sort_key(submit_node: &SubmitNode) -> u64 {
+    submit_node.sort_key()
+}
+
+// Maps the float's bits to an integer with the same ordering
+fn distance_ascending_key(submit_node: &SubmitNode) -> u64 {
+    let bits = submit_node.distance_from_camera().to_bits();
+    let sortable_bits = if bits & 0x8000_0000 != 0 {
+        !bits
+    } else {
+        bits | 0x8000_0000
+    };
+    sortable_bits as u64
+}
+
+// Keeps the sign, exponent and top two mantissa bits, so each power of two of distance is split
+// into four buckets (i.e. 8-10, 10-12, 12-14 and 14-16 units). Buckets grow with distance, where
+// exact depth order matters less.
+fn distance_bucket_ascending_key(submit_node: &SubmitNode) -> u64 {
+    distance_ascending_key(submit_node) >> 21
+}
+
+fn distance_descending_key(submit_node: &SubmitNode) -> u64 {
+    !distance_ascending_key(submit_node)
+}
+
+// Stable sort by several keys, the first key having the highest priority
+fn sort_submit_nodes_by_keys(
+    mut submit_nodes: Vec<SubmitNode>,
+    keys: &[SubmitNodeKeyFn],
+) -> Vec<SubmitNode> {
+    if submit_nodes.len() < RADIX_SORT_THRESHOLD {
+        submit_nodes.sort_by(|a, b| {
+            keys.iter()
+                .map(|key| key(a).cmp(&key(b)))
+                .find(|ordering| *ordering != std::cmp::Ordering::Equal)
+                .unwrap_or(std::cmp::Ordering::Equal)
+        });
+        submit_nodes
+    } else {
+        radix_sort_submit_nodes(submit_nodes, keys)
+    }
+}
+
+// LSD radix sort over an index permutation. Keys are applied least significant first, and every
+// pass is stable, so the result is ordered by all keys with the first key taking priority.
+fn radix_sort_submit_nodes(
+    submit_nodes: Vec<SubmitNode>,
+    keys: &[SubmitNodeKeyFn],
+) -> Vec<SubmitNode> {
+    let mut order: Vec<u32> = (0..submit_nodes.len() as u32).collect();
+    let mut scratch = vec![0; submit_nodes.len()];
+
+    for key in keys.iter().rev() {
+        let key_values: Vec<u64> = submit_nodes.iter().map(key).collect();
+
+        for byte_index in 0..8 {
+            let shift = byte_index * 8;
+            let mut offsets = [0usize; 256];
+            for &index in &order {
+                offsets[((key_values[index as usize] >> shift) & 0xFF) as usize] += 1;
+            }
+
+            // Skip the pass if every key has the same value for this byte
+            if offsets.contains(&order.len()) {
+                continue;
+            }
+
+            let mut total = 0;
+            for offset in offsets.iter_mut() {
+                let count = *offset;
+                *offset = total;
+                total += count;
+            }
+
+            for &index in &order {
+                let byte = ((key_values[index as usize] >> shift) & 0xFF) as usize;
+                scratch[offsets[byte]] = index;
+                offsets[byte] += 1;
+            }
+
+            std::mem::swap(&mut order, &mut scratch);
+        }
+    }
+
+    order
+        .into_iter()
+        .map(|index| submit_nodes[index as usize])
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_submit_nodes(count: u32) -> Vec<SubmitNode> {
+        // Simple LCG so the test is deterministic without pulling in rand
+        let mut seed: u32 = 12345;
+        let mut next = move || {
+            seed = seed.wrapping_mul(1_103_515_245).wrapping_add(12345);
+            seed >> 8
+        };
+
+        (0..count)
+            .map(|submit_node_id| {
+                SubmitNode::new(
+                    next() % 4,
+                    submit_node_id,
+                    (next() % 16) as u64,
+                    (next() % 1000) as f32 / 10.0,
+                )
+            })
+            .collect()
+    }
+
+    fn ids(submit_nodes: &[SubmitNode]) -> Vec<u32> {
+        submit_nodes
+            .iter()
+            .map(|submit_node| submit_node.submit_node_id())
+            .collect()
+    }
+
+    #[test]
+    fn test_sort_key_builder() {
+        let key = SubmitNodeSortKeyBuilder::new()
+            .feature(3)
+            .pipeline(0x1234)
+            .material(0x56789A)
+            .field(0xBC, 8)
+            .build();
+        assert_eq!(key, 0x0312_3456_789A_BC00);
+
+        // Folded into 8 bits
+        let key = SubmitNodeSortKeyBuilder::new().field(0x0102, 8).build();
+        assert_eq!(key, 0x0300_0000_0000_0000);
+
+        let near = SubmitNodeSortKeyBuilder::new()
+            .depth_bucket(1.0, 100.0)
+            .build();
+        let far = SubmitNodeSortKeyBuilder::new()
+            .depth_bucket(50.0, 100.0)
+            .build();
+        assert!(near < far);
+
+        let near = SubmitNodeSortKeyBuilder::new()
+            .reverse_depth_bucket(1.0, 100.0)
+            .build();
+        let far = SubmitNodeSortKeyBuilder::new()
+            .reverse_depth_bucket(50.0, 100.0)
+            .build();
+        assert!(near > far);
+    }
+
+    #[test]
+    fn test_radix_sort_matches_comparison_sort() {
+        // Large enough to use the radix sort
+        let submit_nodes = test_submit_nodes(2000);
+
+        let key_sets: [&[SubmitNodeKeyFn]; 4] = [
+            &[feature_key, distance_bucket_ascending_key, sort_key],
+            &[distance_descending_key, feature_key, sort_key],
+            &[feature_key, sort_key],
+            &[sort_key],
+        ];
+
+        for keys in key_sets.iter() {
+            let radix_sorted = radix_sort_submit_nodes(submit_nodes.clone(), keys);
+
+            let mut expected = submit_nodes.clone();
+            expected.sort_by(|a, b| {
+                keys.iter()
+                    .map(|key| key(a).cmp(&key(b)))
+                    .find(|ordering| *ordering != std::cmp::Ordering::Equal)
+                    .unwrap_or(std::cmp::Ordering::Equal)
+            });
+
+            assert_eq!(ids(&radix_sorted), ids(&expected));
+        }
+    }
+
+    #[test]
+    fn test_standard_sorts() {
+        for &count in &[100, 1000] {
+            let submit_nodes = test_submit_nodes(count);
+
+            let sorted = sort_submit_nodes_front_to_back(submit_nodes.clone());
+            for pair in sorted.windows(2) {
+                assert!(pair[0].feature_index() <= pair[1].feature_index());
+                if pair[0].feature_index() == pair[1].feature_index() {
+                    let a = distance_bucket_ascending_key(&pair[0]);
+                    let b = distance_bucket_ascending_key(&pair[1]);
+                    assert!(a <= b);
+                    if a == b {
+                        assert!(pair[0].sort_key() <= pair[1].sort_key());
+                    }
+                }
+            }
+
+            let sorted = sort_submit_nodes_back_to_front(submit_nodes.clone());
+            for pair in sorted.windows(2) {
+                assert!(pair[0].distance_from_camera() >= pair[1].distance_from_camera());
+            }
+
+            // Submission order (submit_node_id here) is kept for equal keys
+            let sorted = sort_submit_nodes_by_state(submit_nodes.clone());
+            for pair in sorted.windows(2) {
+                let a = (pair[0].feature_index(), pair[0].sort_key());
+                let b = (pair[1].feature_index(), pair[1].sort_key());
+                assert!(a <= b);
+                if a == b {
+                    assert!(pair[0].submit_node_id() < pair[1].submit_node_id());
+                }
+            }
+        }
+    }
+}
@@ -5,7 +5,7 @@ use crate::{
 use fnv::FnvHashMap;
 
 pub type SubmitNodeId = u32;
-pub type SubmitNodeSortKey = u32;
+pub type SubmitNodeSortKey = u64;
 
 #[derive(Copy, Clone, Debug)]
 pub struct SubmitNode {
@@ -16,6 +16,20 @@ pub struct SubmitNode {
 }
 
 impl SubmitNode {
+    pub(crate) fn new(
+        feature_index: RenderFeatureIndex,
+        submit_node_id: SubmitNodeId,
+        sort_key: SubmitNodeSortKey,
+        distance_from_camera: f32,
+    ) -> Self {
+        SubmitNode {
+            feature_index,
+            submit_node_id,
+            sort_key,
+            distance_from_camera,
+        }
+    }
+
     pub fn feature_index(&self) -> RenderFeatureIndex {
         self.feature_index
     }
@@ -65,12 +79,12 @@ impl ViewSubmitNodes {
     ) {
         if self.render_phase_mask.is_included::<RenderPhaseT>() {
             log::trace!("add submit node render phase: {} feature: {} submit node id: {} sort key: {} distance: {}", RenderPhaseT::render_phase_index(), self.feature_index, submit_node_id, sort_key, distance_from_camera);
-            self.submit_nodes[RenderPhaseT::render_phase_index() as usize].push(SubmitNode {
-                feature_index: self.feature_index,
+            self.submit_nodes[RenderPhaseT::render_phase_index() as usize].push(SubmitNode::new(
+                self.feature_index,
                 submit_node_id,
                 sort_key,
                 distance_from_camera,
-            });
+            ));
         }
     }
 
@@ -80,6 +94,18 @@ impl ViewSubmitNodes {
     ) -> &[SubmitNode] {
         &self.submit_nodes[render_phase_index as usize]
     }
+
+    /// Moves all submit nodes from `other` into this list. Used to merge the lists produced by
+    /// view nodes that were prepared in parallel chunks.
+    pub fn append(
+        &mut self,
+        other: ViewSubmitNodes,
+    ) {
+        assert_eq!(self.feature_index, other.feature_index);
+        for (nodes, mut other_nodes) in self.submit_nodes.iter_mut().zip(other.submit_nodes) {
+            nodes.append(&mut other_nodes);
+        }
+    }
 }
 
 #[derive(Default, Debug)]
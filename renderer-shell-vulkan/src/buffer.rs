@@ -160,6 +160,39 @@ impl VkBuffer {
         Ok(())
     }
 
+    /// Copies `size` bytes starting at `offset` out of the buffer. The buffer must be host visible
+    /// and coherent, and the GPU must be finished writing to it.
+    pub fn read_from_host_visible_buffer(
+        &self,
+        offset: u64,
+        size: u64,
+    ) -> VkResult<Vec<u8>> {
+        assert!(offset + size <= self.size());
+        let allocation = self.allocation();
+
+        let src = if self.always_mapped {
+            self.allocation_info.get_mapped_data()
+        } else {
+            self.device_context
+                .allocator()
+                .map_memory(&allocation)
+                .map_err(|_| vk::Result::ERROR_MEMORY_MAP_FAILED)?
+        };
+
+        let data =
+            unsafe { std::slice::from_raw_parts(src.add(offset as usize), size as usize).to_vec() };
+
+        if !self.always_mapped {
+            //TODO: Better way of handling allocator errors
+            self.device_context
+                .allocator()
+                .unmap_memory(&allocation)
+                .map_err(|_| vk::Result::ERROR_MEMORY_MAP_FAILED)?;
+        }
+
+        Ok(data)
+    }
+
     pub fn buffer(&self) -> vk::Buffer {
         // Raw is only none if take_raw has not been called, and take_raw consumes the VkBuffer
         self.raw.unwrap().buffer
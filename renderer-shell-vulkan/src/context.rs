@@ -14,6 +14,8 @@ use super::PhysicalDeviceType;
 use super::VkEntry;
 use super::Window;
 use crate::{VkDeviceContext, MsaaLevel, VulkanLinkMethod};
+use crate::{VkDeviceFeatures, VkDeviceRequirements};
+use crate::{PhysicalDeviceSelection, VkPhysicalDeviceReport};
 //use crate::submit::PendingCommandBuffer;
 
 /// A builder to create the renderer. It's easier to use AppBuilder and implement an AppHandler, but
@@ -26,6 +28,8 @@ pub struct VkContextBuilder {
     physical_device_type_priority: Vec<PhysicalDeviceType>,
     msaa_level_priority: Vec<MsaaLevel>,
     link_method: VulkanLinkMethod,
+    device_requirements: VkDeviceRequirements,
+    physical_device_selection: PhysicalDeviceSelection,
 }
 
 impl VkContextBuilder {
@@ -40,7 +44,15 @@ impl VkContextBuilder {
                 PhysicalDeviceType::IntegratedGpu,
             ],
             msaa_level_priority: vec![MsaaLevel::Sample1],
-            link_method: VulkanLinkMethod::default()
+            link_method: VulkanLinkMethod::default(),
+            device_requirements: VkDeviceRequirements {
+                // Used for memory reporting if available
+                optional_extensions: vec![vk::ExtMemoryBudgetFn::name().to_owned()],
+                required_features: VkDeviceFeatures::default_required(),
+                optional_features: VkDeviceFeatures::default_optional(),
+                ..Default::default()
+            },
+            physical_device_selection: PhysicalDeviceSelection::Any,
         }
     }
 
@@ -127,6 +139,61 @@ impl VkContextBuilder {
         self
     }
 
+    /// Device extensions that must be supported. Devices that don't support all of them are not
+    /// considered. The swapchain extension is added automatically when building for a window.
+    pub fn required_device_extensions(
+        mut self,
+        required_device_extensions: Vec<CString>,
+    ) -> Self {
+        self.device_requirements.required_extensions = required_device_extensions;
+        self
+    }
+
+    /// Device extensions that are enabled if the device supports them. Check
+    /// `VkDeviceContext::enabled_extensions()` to see if they were enabled. By default,
+    /// VK_EXT_memory_budget is enabled if available. This replaces the defaults.
+    pub fn optional_device_extensions(
+        mut self,
+        optional_device_extensions: Vec<CString>,
+    ) -> Self {
+        self.device_requirements.optional_extensions = optional_device_extensions;
+        self
+    }
+
+    /// Device features that must be supported. Devices that don't support all of them are not
+    /// considered. Vulkan 1.1/1.2 features require a Vulkan 1.2 device. By default, sampler
+    /// anisotropy, sample rate shading and non-solid fill modes are required. These are needed by
+    /// the renderer and are supported very widely.
+    pub fn required_device_features(
+        mut self,
+        required_device_features: VkDeviceFeatures,
+    ) -> Self {
+        self.device_requirements.required_features = required_device_features;
+        self
+    }
+
+    /// Device features that are enabled if the device supports them. By default, block-compressed
+    /// texture formats are enabled if supported. Check `VkDeviceContext::enabled_features()` to see
+    /// if they were enabled.
+    pub fn optional_device_features(
+        mut self,
+        optional_device_features: VkDeviceFeatures,
+    ) -> Self {
+        self.device_requirements.optional_features = optional_device_features;
+        self
+    }
+
+    /// Pick a specific physical device, for example by name. Use `enumerate_physical_devices()` to
+    /// see what's available. Building fails if no device that meets the requirements matches. By
+    /// default, the best device according to `physical_device_type_priority` is used.
+    pub fn physical_device_selection(
+        mut self,
+        physical_device_selection: PhysicalDeviceSelection,
+    ) -> Self {
+        self.physical_device_selection = physical_device_selection;
+        self
+    }
+
     /// Easy shortcut to set device type priority to `Integrated`, then `Discrete`, then any.
     pub fn prefer_integrated_gpu(self) -> Self {
         self.physical_device_type_priority(vec![
@@ -181,14 +248,59 @@ impl VkContextBuilder {
     ) -> Result<VkContext, VkCreateContextError> {
         VkContext::new(
             &self.app_name,
-            window,
+            Some(window),
+            self.validation_layer_debug_report_flags,
+            self.physical_device_type_priority.clone(),
+            self.present_mode_priority.clone(),
+            self.msaa_level_priority.clone(),
+            self.link_method,
+            self.device_requirements.clone(),
+            self.physical_device_selection.clone(),
+        )
+    }
+
+    /// Builds the renderer without a window. No surface is created and no swapchain extension is
+    /// enabled, so this works on machines without a display and with software implementations like
+    /// lavapipe or SwiftShader. Render into a `VkOffscreenTarget` instead of a `VkSurface`.
+    pub fn build_headless(self) -> Result<VkContext, VkCreateContextError> {
+        VkContext::new(
+            &self.app_name,
+            None,
             self.validation_layer_debug_report_flags,
             self.physical_device_type_priority.clone(),
             self.present_mode_priority.clone(),
             self.msaa_level_priority.clone(),
-            self.link_method
+            self.link_method,
+            self.device_requirements.clone(),
+            self.physical_device_selection.clone(),
         )
     }
+
+    /// Lists every physical device and whether it meets this builder's device requirements, for
+    /// example to let the user pick one. A temporary instance is created to query them. Support for
+    /// presenting to a window is not checked.
+    pub fn enumerate_physical_devices(
+        &self
+    ) -> Result<Vec<VkPhysicalDeviceReport>, VkCreateContextError> {
+        let entry = match self.link_method {
+            VulkanLinkMethod::Dynamic => VkEntry::new_dynamic(),
+            #[cfg(feature = "static-vulkan")]
+            VulkanLinkMethod::Static => VkEntry::new_static()
+        }?;
+
+        let instance = VkInstance::new(
+            entry,
+            None,
+            &self.app_name,
+            vk::DebugReportFlagsEXT::empty(),
+        )?;
+
+        Ok(VkDevice::enumerate_physical_devices(
+            &instance,
+            &self.physical_device_type_priority,
+            &self.device_requirements,
+        )?)
+    }
 }
 
 /// Represents an error from creating the renderer
@@ -263,15 +375,17 @@ pub struct VkContext {
 }
 
 impl VkContext {
-    /// Create the renderer
+    /// Create the renderer. If no window is provided, the context is headless.
     pub fn new(
         app_name: &CString,
-        window: &dyn Window,
+        window: Option<&dyn Window>,
         validation_layer_debug_report_flags: vk::DebugReportFlagsEXT,
         physical_device_type_priority: Vec<PhysicalDeviceType>,
         present_mode_priority: Vec<PresentMode>,
         msaa_level_priority: Vec<MsaaLevel>,
-        link_method: VulkanLinkMethod
+        link_method: VulkanLinkMethod,
+        device_requirements: VkDeviceRequirements,
+        physical_device_selection: PhysicalDeviceSelection,
     ) -> Result<VkContext, VkCreateContextError> {
         // This loads the dll/so if needed
         info!("Link method for vulkan: {:?}", link_method);
@@ -292,6 +406,8 @@ impl VkContext {
             &instance,
             window,
             &physical_device_type_priority,
+            &device_requirements,
+            &physical_device_selection,
         )?);
 
         Ok(VkContext {
@@ -314,6 +430,10 @@ impl VkContext {
         &self.device.device_context
     }
 
+    pub fn is_headless(&self) -> bool {
+        self.device.surface.is_none()
+    }
+
     pub fn present_mode_priority(&self) -> &Vec<PresentMode> {
         &self.present_mode_priority
     }
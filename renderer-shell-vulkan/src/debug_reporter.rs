@@ -1,12 +1,12 @@
 use std::ffi::CStr;
 use std::os::raw::{c_char, c_void};
 
-use ash::extensions::ext::DebugReport;
+use ash::extensions::ext::{DebugReport, DebugUtils};
 
 pub use ash::version::{DeviceV1_0, EntryV1_0, InstanceV1_0};
 use ash::vk;
 
-/// Callback for vulkan validation layer logging
+/// Callback for vulkan validation layer logging (VK_EXT_debug_report)
 pub extern "system" fn vulkan_debug_callback(
     flags: vk::DebugReportFlagsEXT,
     _: vk::DebugReportObjectTypeEXT,
@@ -33,18 +33,77 @@ pub extern "system" fn vulkan_debug_callback(
     vk::FALSE
 }
 
-/// Handles dropping vulkan debug reporting
-pub struct VkDebugReporter {
-    pub debug_report_loader: DebugReport,
-    pub debug_callback: vk::DebugReportCallbackEXT,
+/// Callback for vulkan validation layer logging (VK_EXT_debug_utils). Messages include the names
+/// set with `VkDeviceContext::set_debug_name`.
+pub extern "system" fn vulkan_debug_utils_callback(
+    message_severity: vk::DebugUtilsMessageSeverityFlagsEXT,
+    _: vk::DebugUtilsMessageTypeFlagsEXT,
+    p_callback_data: *const vk::DebugUtilsMessengerCallbackDataEXT,
+    _: *mut c_void,
+) -> vk::Bool32 {
+    let msg = unsafe { CStr::from_ptr((*p_callback_data).p_message) };
+    if message_severity.intersects(vk::DebugUtilsMessageSeverityFlagsEXT::ERROR) {
+        error!("{:?}", msg);
+    } else if message_severity.intersects(vk::DebugUtilsMessageSeverityFlagsEXT::WARNING) {
+        warn!("{:?}", msg);
+    } else if message_severity.intersects(vk::DebugUtilsMessageSeverityFlagsEXT::INFO) {
+        info!("{:?}", msg);
+    } else {
+        debug!("{:?}", msg);
+    }
+
+    vk::FALSE
+}
+
+/// The debug utils severities that correspond to the given debug report flags
+pub fn debug_report_flags_to_severity(
+    flags: vk::DebugReportFlagsEXT
+) -> vk::DebugUtilsMessageSeverityFlagsEXT {
+    let mut severity = vk::DebugUtilsMessageSeverityFlagsEXT::empty();
+    if flags.intersects(vk::DebugReportFlagsEXT::ERROR) {
+        severity |= vk::DebugUtilsMessageSeverityFlagsEXT::ERROR;
+    }
+    if flags
+        .intersects(vk::DebugReportFlagsEXT::WARNING | vk::DebugReportFlagsEXT::PERFORMANCE_WARNING)
+    {
+        severity |= vk::DebugUtilsMessageSeverityFlagsEXT::WARNING;
+    }
+    if flags.intersects(vk::DebugReportFlagsEXT::INFORMATION) {
+        severity |= vk::DebugUtilsMessageSeverityFlagsEXT::INFO;
+    }
+    if flags.intersects(vk::DebugReportFlagsEXT::DEBUG) {
+        severity |= vk::DebugUtilsMessageSeverityFlagsEXT::VERBOSE;
+    }
+    severity
+}
+
+/// Handles dropping vulkan debug reporting. VK_EXT_debug_utils is used if the instance supports
+/// it, otherwise this falls back to the older VK_EXT_debug_report.
+pub enum VkDebugReporter {
+    DebugUtils {
+        debug_utils_loader: DebugUtils,
+        debug_messenger: vk::DebugUtilsMessengerEXT,
+    },
+    DebugReport {
+        debug_report_loader: DebugReport,
+        debug_callback: vk::DebugReportCallbackEXT,
+    },
 }
 
 impl Drop for VkDebugReporter {
     fn drop(&mut self) {
         unsafe {
             trace!("destroying VkDebugReporter");
-            self.debug_report_loader
-                .destroy_debug_report_callback(self.debug_callback, None);
+            match self {
+                VkDebugReporter::DebugUtils {
+                    debug_utils_loader,
+                    debug_messenger,
+                } => debug_utils_loader.destroy_debug_utils_messenger(*debug_messenger, None),
+                VkDebugReporter::DebugReport {
+                    debug_report_loader,
+                    debug_callback,
+                } => debug_report_loader.destroy_debug_report_callback(*debug_callback, None),
+            }
             trace!("destroyed VkDebugReporter");
         }
     }
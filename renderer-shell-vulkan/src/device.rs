@@ -5,12 +5,16 @@ use super::VkInstance;
 
 use ash::version::DeviceV1_0;
 use ash::version::InstanceV1_0;
+use ash::version::InstanceV1_1;
 use super::Window;
 
-use std::ffi::CStr;
+use std::ffi::{CStr, CString};
 
 use ash::extensions::khr;
+use ash::extensions::ext::DebugUtils;
 use crate::{PhysicalDeviceType /*, VkSubmitQueue*/};
+use crate::{VkDeviceFeatures, VkDeviceRequirements};
+use crate::VkMemoryHeapBudget;
 use std::mem::ManuallyDrop;
 
 use std::sync::{Arc, Mutex};
@@ -19,12 +23,23 @@ use std::sync::atomic::AtomicU64;
 use std::sync::atomic::Ordering;
 
 /// Has the indexes for all the queue families we will need. It's possible a single family
-/// is used for both graphics and presentation, in which case the index will be the same
-#[derive(Default, Clone)]
+/// is used for both graphics and presentation, in which case the index will be the same. When
+/// headless, nothing is presented and the present queue family is the graphics queue family. If
+/// the device has no queue family dedicated to compute, the compute queue family is the graphics
+/// queue family.
+#[derive(Default, Clone, Debug)]
 pub struct VkQueueFamilyIndices {
     pub transfer_queue_family_index: u32,
     pub graphics_queue_family_index: u32,
     pub present_queue_family_index: u32,
+    pub compute_queue_family_index: u32,
+}
+
+impl VkQueueFamilyIndices {
+    /// True if compute work can run asynchronously to graphics work, on a different queue family
+    pub fn has_async_compute(&self) -> bool {
+        self.compute_queue_family_index != self.graphics_queue_family_index
+    }
 }
 
 /// An instantiated queue per queue family. We only need one queue per family.
@@ -33,26 +48,63 @@ pub struct VkQueues {
     pub transfer_queue: Arc<Mutex<ash::vk::Queue>>,
     pub graphics_queue: Arc<Mutex<ash::vk::Queue>>,
     pub present_queue: Arc<Mutex<ash::vk::Queue>>,
+    pub compute_queue: Arc<Mutex<ash::vk::Queue>>,
 }
 
-#[derive(Clone)]
+// Formats that are included in PhysicalDeviceInfo::format_properties. These are the ones the
+// renderer uses or considers using
+const REPORTED_FORMATS: [vk::Format; 12] = [
+    vk::Format::R8G8B8A8_UNORM,
+    vk::Format::R8G8B8A8_SRGB,
+    vk::Format::B8G8R8A8_UNORM,
+    vk::Format::B8G8R8A8_SRGB,
+    vk::Format::R16G16B16A16_SFLOAT,
+    vk::Format::R32G32B32A32_SFLOAT,
+    vk::Format::B10G11R11_UFLOAT_PACK32,
+    vk::Format::A2B10G10R10_UNORM_PACK32,
+    vk::Format::D32_SFLOAT,
+    vk::Format::D32_SFLOAT_S8_UINT,
+    vk::Format::D24_UNORM_S8_UINT,
+    vk::Format::D16_UNORM,
+];
+
+/// Describes a physical device. This is available for every device, including ones that can't be
+/// used. For unusable devices, `score`, `queue_family_indices` and `enabled_*` are not meaningful.
+#[derive(Clone, Debug)]
 pub struct PhysicalDeviceInfo {
     pub score: i32,
     pub queue_family_indices: VkQueueFamilyIndices,
+    pub device_name: String,
     pub properties: vk::PhysicalDeviceProperties,
     pub features: vk::PhysicalDeviceFeatures,
     pub extension_properties: Vec<ash::vk::ExtensionProperties>,
+    pub memory_properties: vk::PhysicalDeviceMemoryProperties,
+    pub queue_family_properties: Vec<vk::QueueFamilyProperties>,
+
+    // Properties of a few commonly used formats (see REPORTED_FORMATS)
+    pub format_properties: Vec<(vk::Format, vk::FormatProperties)>,
+
+    // The device's API version, limited to the instance's API version
+    pub api_version: u32,
+
+    // Everything the device supports, including Vulkan 1.1/1.2 features if api_version >= 1.2
+    pub supported_features: VkDeviceFeatures,
+
+    // What will be enabled when creating the logical device
+    pub enabled_extensions: Vec<CString>,
+    pub enabled_features: VkDeviceFeatures,
 }
 
 pub struct VkDeviceContextInner {
     instance: ash::Instance,
     device: ash::Device,
     allocator: vk_mem::Allocator,
-    surface: vk::SurfaceKHR,
+    surface: Option<vk::SurfaceKHR>,
     surface_loader: ash::extensions::khr::Surface,
     physical_device: vk::PhysicalDevice,
     physical_device_info: PhysicalDeviceInfo,
     queues: VkQueues,
+    debug_utils: Option<DebugUtils>,
 
     #[cfg(debug_assertions)]
     next_create_index: AtomicU64,
@@ -123,13 +175,18 @@ impl VkDeviceContext {
             .allocator
     }
 
-    pub fn surface(&self) -> vk::SurfaceKHR {
+    /// The surface that swapchains are created for. None if the device is headless
+    pub fn surface(&self) -> Option<vk::SurfaceKHR> {
         self.inner
             .as_ref()
             .expect("inner is only None if VkDevice is dropped")
             .surface
     }
 
+    pub fn is_headless(&self) -> bool {
+        self.surface().is_none()
+    }
+
     pub fn surface_loader(&self) -> &ash::extensions::khr::Surface {
         &self
             .inner
@@ -157,6 +214,38 @@ impl VkDeviceContext {
         &self.physical_device_info().properties.limits
     }
 
+    /// Device extensions that were enabled, including optional ones that the device supports
+    pub fn enabled_extensions(&self) -> &[CString] {
+        &self.physical_device_info().enabled_extensions
+    }
+
+    /// Device features that were enabled, including optional ones that the device supports
+    pub fn enabled_features(&self) -> &VkDeviceFeatures {
+        &self.physical_device_info().enabled_features
+    }
+
+    /// What the device supports for a format with linear and optimal tiling and in buffers
+    pub fn format_properties(
+        &self,
+        format: vk::Format,
+    ) -> vk::FormatProperties {
+        unsafe {
+            self.instance()
+                .get_physical_device_format_properties(self.physical_device(), format)
+        }
+    }
+
+    /// True if images of the format can be created with optimal tiling and sampled. Block
+    /// compressed formats also require the texture_compression_bc feature to be enabled.
+    pub fn supports_sampled_image_format(
+        &self,
+        format: vk::Format,
+    ) -> bool {
+        self.format_properties(format)
+            .optimal_tiling_features
+            .contains(vk::FormatFeatureFlags::SAMPLED_IMAGE)
+    }
+
     pub fn queue_family_indices(&self) -> &VkQueueFamilyIndices {
         &self
             .inner
@@ -174,15 +263,137 @@ impl VkDeviceContext {
             .queues
     }
 
+    fn debug_utils(&self) -> Option<&DebugUtils> {
+        self.inner
+            .as_ref()
+            .expect("inner is only None if VkDevice is dropped")
+            .debug_utils
+            .as_ref()
+    }
+
+    /// True if VK_EXT_debug_utils is enabled. If it isn't, setting debug names and labels does
+    /// nothing.
+    pub fn debug_utils_enabled(&self) -> bool {
+        self.debug_utils().is_some()
+    }
+
+    /// Name a vulkan object. Names show up in validation messages and in tools like RenderDoc.
+    pub fn set_debug_name<T: vk::Handle>(
+        &self,
+        handle: T,
+        name: &str,
+    ) {
+        if let Some(debug_utils) = self.debug_utils() {
+            let name = debug_name_cstring(name);
+            let name_info = vk::DebugUtilsObjectNameInfoEXT::builder()
+                .object_type(T::TYPE)
+                .object_handle(handle.as_raw())
+                .object_name(&name);
+
+            let result = unsafe {
+                debug_utils.debug_utils_set_object_name(self.device().handle(), &name_info)
+            };
+
+            if let Err(e) = result {
+                warn!("Failed to set debug name {:?}: {:?}", name, e);
+            }
+        }
+    }
+
+    /// Begin a labeled region of a command buffer. Regions can be nested and must be ended with
+    /// `cmd_end_debug_label` in the same command buffer.
+    pub fn cmd_begin_debug_label(
+        &self,
+        command_buffer: vk::CommandBuffer,
+        name: &str,
+    ) {
+        if let Some(debug_utils) = self.debug_utils() {
+            let name = debug_name_cstring(name);
+            let label = vk::DebugUtilsLabelEXT::builder().label_name(&name);
+            unsafe {
+                debug_utils.cmd_begin_debug_utils_label(command_buffer, &label);
+            }
+        }
+    }
+
+    /// End the most recently begun labeled region of a command buffer
+    pub fn cmd_end_debug_label(
+        &self,
+        command_buffer: vk::CommandBuffer,
+    ) {
+        if let Some(debug_utils) = self.debug_utils() {
+            unsafe {
+                debug_utils.cmd_end_debug_utils_label(command_buffer);
+            }
+        }
+    }
+
+    /// Begin a labeled region of a command buffer that ends when the returned guard is dropped.
+    /// The guard must be dropped before the command buffer is ended.
+    pub fn debug_label_scope(
+        &self,
+        command_buffer: vk::CommandBuffer,
+        name: &str,
+    ) -> VkDebugLabelScope {
+        self.cmd_begin_debug_label(command_buffer, name);
+        VkDebugLabelScope {
+            device_context: self,
+            command_buffer,
+        }
+    }
+
+    /// True if VK_EXT_memory_budget is enabled, see `memory_heap_budgets()`
+    pub fn memory_budget_enabled(&self) -> bool {
+        let physical_device_info = self.physical_device_info();
+        physical_device_info.api_version >= vk::make_version(1, 1, 0)
+            && self
+                .enabled_extensions()
+                .iter()
+                .any(|name| name.as_c_str() == vk::ExtMemoryBudgetFn::name())
+    }
+
+    /// How much memory the process can use in each memory heap and how much it is currently
+    /// using, including memory not allocated through vk-mem. Returns None if VK_EXT_memory_budget
+    /// is not enabled. This is cheap enough to call every frame.
+    pub fn memory_heap_budgets(&self) -> Option<Vec<VkMemoryHeapBudget>> {
+        if !self.memory_budget_enabled() {
+            return None;
+        }
+
+        let mut budget_properties = vk::PhysicalDeviceMemoryBudgetPropertiesEXT::default();
+        let mut memory_properties = vk::PhysicalDeviceMemoryProperties2::builder()
+            .push_next(&mut budget_properties)
+            .build();
+
+        unsafe {
+            self.instance().get_physical_device_memory_properties2(
+                self.physical_device(),
+                &mut memory_properties,
+            );
+        }
+
+        let heap_count = memory_properties.memory_properties.memory_heap_count as usize;
+        Some(
+            (0..heap_count)
+                .map(|heap_index| VkMemoryHeapBudget {
+                    budget: budget_properties.heap_budget[heap_index],
+                    usage: budget_properties.heap_usage[heap_index],
+                })
+                .collect(),
+        )
+    }
+
+    #[allow(clippy::too_many_arguments)]
     fn new(
         instance: ash::Instance,
         device: ash::Device,
         allocator: vk_mem::Allocator,
-        surface: ash::vk::SurfaceKHR,
+        surface: Option<ash::vk::SurfaceKHR>,
         surface_loader: ash::extensions::khr::Surface,
         physical_device: ash::vk::PhysicalDevice,
         physical_device_info: PhysicalDeviceInfo,
         queues: VkQueues,
+        debug_utils: Option<DebugUtils>,
     ) -> Self {
         #[cfg(debug_assertions)]
         let all_contexts = {
@@ -202,6 +413,7 @@ impl VkDeviceContext {
                 physical_device,
                 physical_device_info,
                 queues,
+                debug_utils,
 
                 #[cfg(debug_assertions)]
                 all_contexts: Mutex::new(all_contexts),
@@ -258,11 +470,164 @@ impl Drop for VkDeviceContext {
     }
 }
 
+/// Ends a labeled region of a command buffer when dropped, see
+/// `VkDeviceContext::debug_label_scope`
+pub struct VkDebugLabelScope<'a> {
+    device_context: &'a VkDeviceContext,
+    command_buffer: vk::CommandBuffer,
+}
+
+impl<'a> Drop for VkDebugLabelScope<'a> {
+    fn drop(&mut self) {
+        self.device_context.cmd_end_debug_label(self.command_buffer);
+    }
+}
+
+// Names can't contain nul characters, so strip them rather than failing
+fn debug_name_cstring(name: &str) -> CString {
+    CString::new(name.replace('\0', "")).unwrap()
+}
+
+impl PhysicalDeviceInfo {
+    pub fn limits(&self) -> &vk::PhysicalDeviceLimits {
+        &self.properties.limits
+    }
+
+    pub fn vendor_id(&self) -> u32 {
+        self.properties.vendor_id
+    }
+
+    /// Total size of the device-local memory heaps
+    pub fn device_local_memory_size(&self) -> vk::DeviceSize {
+        let heaps = &self.memory_properties.memory_heaps
+            [0..self.memory_properties.memory_heap_count as usize];
+        heaps
+            .iter()
+            .filter(|heap| heap.flags.contains(vk::MemoryHeapFlags::DEVICE_LOCAL))
+            .map(|heap| heap.size)
+            .sum()
+    }
+
+    pub fn format_properties(
+        &self,
+        format: vk::Format,
+    ) -> Option<&vk::FormatProperties> {
+        self.format_properties
+            .iter()
+            .find(|(f, _)| *f == format)
+            .map(|(_, properties)| properties)
+    }
+}
+
+/// Chooses which physical device to use. Devices that don't meet the requirements are never
+/// chosen. If several devices match, `physical_device_type_priority` breaks the tie.
+#[derive(Clone, Debug)]
+pub enum PhysicalDeviceSelection {
+    /// Pick the best device according to `physical_device_type_priority` (default)
+    Any,
+
+    /// The device at this index in the list returned by vkEnumeratePhysicalDevices (this is also
+    /// `VkPhysicalDeviceReport::index`)
+    Index(usize),
+
+    /// A device whose name contains this string, ignoring case
+    NameContains(String),
+
+    /// A device with this PCI vendor ID, for example 0x10DE for NVIDIA
+    VendorId(u32),
+}
+
+impl Default for PhysicalDeviceSelection {
+    fn default() -> Self {
+        PhysicalDeviceSelection::Any
+    }
+}
+
+impl PhysicalDeviceSelection {
+    fn matches(
+        &self,
+        report: &VkPhysicalDeviceReport,
+    ) -> bool {
+        match self {
+            PhysicalDeviceSelection::Any => true,
+            PhysicalDeviceSelection::Index(index) => report.index == *index,
+            PhysicalDeviceSelection::NameContains(name) => report
+                .info
+                .device_name
+                .to_lowercase()
+                .contains(&name.to_lowercase()),
+            PhysicalDeviceSelection::VendorId(vendor_id) => report.info.vendor_id() == *vendor_id,
+        }
+    }
+}
+
+/// Why a physical device can't be used
+#[derive(Clone, Debug)]
+pub enum VkUnsuitableDeviceReason {
+    /// No queue families that support graphics and, if there is a surface, presenting to it
+    MissingQueueFamilies,
+
+    /// Required extensions or features are not supported
+    MissingRequirements {
+        extensions: Vec<String>,
+        features: Vec<String>,
+    },
+
+    /// The device is usable but does not match the `PhysicalDeviceSelection`
+    NotSelected,
+}
+
+impl core::fmt::Display for VkUnsuitableDeviceReason {
+    fn fmt(
+        &self,
+        fmt: &mut core::fmt::Formatter,
+    ) -> core::fmt::Result {
+        match *self {
+            VkUnsuitableDeviceReason::MissingQueueFamilies => {
+                write!(fmt, "could not find queue families")
+            }
+            VkUnsuitableDeviceReason::MissingRequirements {
+                ref extensions,
+                ref features,
+            } => write!(
+                fmt,
+                "missing extensions {:?} and features {:?}",
+                extensions, features
+            ),
+            VkUnsuitableDeviceReason::NotSelected => {
+                write!(fmt, "does not match the physical device selection")
+            }
+        }
+    }
+}
+
+/// A physical device found when enumerating devices, and whether it can be used
+#[derive(Clone, Debug)]
+pub struct VkPhysicalDeviceReport {
+    // Index of the device in the list returned by vkEnumeratePhysicalDevices
+    pub index: usize,
+
+    pub info: PhysicalDeviceInfo,
+
+    // None if the device can be used
+    pub unsuitable_reason: Option<VkUnsuitableDeviceReason>,
+}
+
+impl VkPhysicalDeviceReport {
+    pub fn is_suitable(&self) -> bool {
+        self.unsuitable_reason.is_none()
+    }
+}
+
 /// Represents an error from creating the renderer
 #[derive(Debug)]
 pub enum VkCreateDeviceError {
     VkError(vk::Result),
     VkMemError(vk_mem::Error),
+
+    // No physical device met the requirements and selection. Contains every device that was found
+    // and why it was rejected
+    NoSuitableDevice(Vec<VkPhysicalDeviceReport>),
 }
 
 impl std::error::Error for VkCreateDeviceError {
@@ -270,6 +635,7 @@ impl std::error::Error for VkCreateDeviceError {
         match *self {
             VkCreateDeviceError::VkError(ref e) => Some(e),
             VkCreateDeviceError::VkMemError(ref e) => Some(e),
+            VkCreateDeviceError::NoSuitableDevice(_) => None,
         }
     }
 }
@@ -282,6 +648,23 @@ impl core::fmt::Display for VkCreateDeviceError {
         match *self {
             VkCreateDeviceError::VkError(ref e) => e.fmt(fmt),
             VkCreateDeviceError::VkMemError(ref e) => e.fmt(fmt),
+            VkCreateDeviceError::NoSuitableDevice(ref reports) => {
+                if reports.is_empty() {
+                    return write!(fmt, "No physical devices found");
+                }
+
+                write!(fmt, "No suitable physical device:")?;
+                for report in reports {
+                    if let Some(reason) = &report.unsuitable_reason {
+                        write!(
+                            fmt,
+                            " '{}' (index {}) {};",
+                            report.info.device_name, report.index, reason
+                        )?;
+                    }
+                }
+                Ok(())
+            }
         }
     }
 }
@@ -299,17 +682,22 @@ impl From<vk_mem::Error> for VkCreateDeviceError {
 }
 
 /// Represents the instance and device. Most of the code here has to do with picking a good device
-/// that's compatible with the window we're given. The VkDevice is the "heavy-weight" structure
+/// that's compatible with the window we're given. If no window is given, the device is headless:
+/// it has no surface, the swapchain extension is not enabled and nothing can be presented. The
+/// VkDevice is the "heavy-weight" structure
 /// that will destroy all vulkan resources when it's dropped. VkDeviceContext is a lighter-weight
 /// structure that should generally be used instead. It is expected that all VkDeviceContext
 /// structures based on this VkDevice are destroyed before dropping the VkDevice.
 pub struct VkDevice {
     pub device_context: VkDeviceContext,
-    pub surface: ash::vk::SurfaceKHR,
+    pub surface: Option<ash::vk::SurfaceKHR>,
     pub surface_loader: ash::extensions::khr::Surface,
     pub physical_device: ash::vk::PhysicalDevice,
     pub physical_device_info: PhysicalDeviceInfo,
     pub queues: VkQueues,
+
+    // Every physical device that was considered, including the one that was chosen
+    pub physical_device_reports: Vec<VkPhysicalDeviceReport>,
 }
 
 impl VkDevice {
@@ -323,13 +711,17 @@ impl VkDevice {
 
     pub fn new(
         instance: &VkInstance,
-        window: &dyn Window,
+        window: Option<&dyn Window>,
         physical_device_type_priority: &[PhysicalDeviceType],
+        device_requirements: &VkDeviceRequirements,
+        physical_device_selection: &PhysicalDeviceSelection,
     ) -> Result<Self, VkCreateDeviceError> {
         // Get the surface, needed to select the best queue family
-        let surface = window
-            .create_vulkan_surface(&instance.entry, &instance.instance)
-            .expect("Could not create vulkan surface");
+        let surface = window.map(|window| {
+            window
+                .create_vulkan_surface(&instance.entry, &instance.instance)
+                .expect("Could not create vulkan surface")
+        });
 
         let surface_loader = match &instance.entry {
             VkEntry::Dynamic(entry) => khr::Surface::new(entry, &instance.instance),
@@ -337,19 +729,69 @@ impl VkDevice {
             VkEntry::Static(entry) => khr::Surface::new(entry, &instance.instance),
         };
 
+        let result = Self::new_with_surface(
+            instance,
+            surface,
+            surface_loader.clone(),
+            physical_device_type_priority,
+            device_requirements,
+            physical_device_selection,
+        );
+
+        // On success the surface is owned by the device, otherwise nothing else will destroy it
+        if result.is_err() {
+            if let Some(surface) = surface {
+                unsafe {
+                    surface_loader.destroy_surface(surface, None);
+                }
+            }
+        }
+
+        result
+    }
+
+    // Everything after creating the surface. Anything created here is destroyed before returning
+    // an error, except for the surface.
+    fn new_with_surface(
+        instance: &VkInstance,
+        surface: Option<ash::vk::SurfaceKHR>,
+        surface_loader: ash::extensions::khr::Surface,
+        physical_device_type_priority: &[PhysicalDeviceType],
+        device_requirements: &VkDeviceRequirements,
+        physical_device_selection: &PhysicalDeviceSelection,
+    ) -> Result<Self, VkCreateDeviceError> {
         // Pick a physical device
-        let (physical_device, physical_device_info) = Self::choose_physical_device(
-            &instance.instance,
+        let (physical_devices, mut physical_device_reports) = Self::query_physical_device_reports(
+            instance,
             &surface_loader,
             surface,
             physical_device_type_priority,
+            device_requirements,
         )?;
 
+        let chosen_index =
+            Self::choose_physical_device(&mut physical_device_reports, physical_device_selection);
+        let chosen_index = match chosen_index {
+            Some(chosen_index) => chosen_index,
+            None => {
+                return Err(VkCreateDeviceError::NoSuitableDevice(
+                    physical_device_reports,
+                ));
+            }
+        };
+
+        let physical_device = physical_devices[chosen_index];
+        let physical_device_info = physical_device_reports[chosen_index].info.clone();
+        info!(
+            "Chose physical device '{}' (index {})",
+            physical_device_info.device_name, chosen_index
+        );
+
         // Create a logical device
         let (logical_device, queues) = Self::create_logical_device(
             &instance.instance,
             physical_device,
-            &physical_device_info.queue_family_indices,
+            &physical_device_info,
         )?;
 
         let allocator_create_info = vk_mem::AllocatorCreateInfo {
@@ -362,12 +804,14 @@ impl VkDevice {
             heap_size_limits: Default::default(),
         };
 
-        let allocator = vk_mem::Allocator::new(&allocator_create_info)?;
-
-        let _memory_properties = unsafe {
-            instance
-                .instance
-                .get_physical_device_memory_properties(physical_device)
+        let allocator = match vk_mem::Allocator::new(&allocator_create_info) {
+            Ok(allocator) => allocator,
+            Err(e) => {
+                unsafe {
+                    logical_device.destroy_device(None);
+                }
+                return Err(e.into());
+            }
         };
 
         let device_context = VkDeviceContext::new(
@@ -379,6 +823,7 @@ impl VkDevice {
             physical_device,
             physical_device_info.clone(),
             queues.clone(),
+            instance.debug_utils.clone(),
         );
 
         Ok(VkDevice {
@@ -388,48 +833,94 @@ impl VkDevice {
             physical_device,
             physical_device_info,
             queues,
+            physical_device_reports,
         })
     }
 
-    fn choose_physical_device(
-        instance: &ash::Instance,
+    /// Describes every physical device and whether it meets the given requirements. No surface is
+    /// involved, so support for presenting is not checked.
+    pub fn enumerate_physical_devices(
+        instance: &VkInstance,
+        physical_device_type_priority: &[PhysicalDeviceType],
+        device_requirements: &VkDeviceRequirements,
+    ) -> VkResult<Vec<VkPhysicalDeviceReport>> {
+        let surface_loader = match &instance.entry {
+            VkEntry::Dynamic(entry) => khr::Surface::new(entry, &instance.instance),
+            #[cfg(feature = "static-vulkan")]
+            VkEntry::Static(entry) => khr::Surface::new(entry, &instance.instance),
+        };
+
+        let (_, reports) = Self::query_physical_device_reports(
+            instance,
+            &surface_loader,
+            None,
+            physical_device_type_priority,
+            device_requirements,
+        )?;
+        Ok(reports)
+    }
+
+    // Returns the physical device handles along with their reports, in the same order
+    fn query_physical_device_reports(
+        instance: &VkInstance,
         surface_loader: &ash::extensions::khr::Surface,
-        surface: ash::vk::SurfaceKHR,
+        surface: Option<ash::vk::SurfaceKHR>,
         physical_device_type_priority: &[PhysicalDeviceType],
-    ) -> VkResult<(ash::vk::PhysicalDevice, PhysicalDeviceInfo)> {
-        let physical_devices = unsafe { instance.enumerate_physical_devices()? };
+        device_requirements: &VkDeviceRequirements,
+    ) -> VkResult<(Vec<vk::PhysicalDevice>, Vec<VkPhysicalDeviceReport>)> {
+        info!(
+            "Preferred device types: {:?}",
+            physical_device_type_priority
+        );
 
-        if physical_devices.is_empty() {
-            panic!("Could not find a physical device");
-        }
+        let physical_devices = unsafe { instance.instance.enumerate_physical_devices()? };
 
-        let mut best_physical_device = None;
-        let mut best_physical_device_info = None;
-        let mut best_physical_device_score = -1;
-        // let mut best_physical_device_queue_family_indices = None;
-        for physical_device in physical_devices {
-            let result = Self::query_physical_device_info(
+        let mut reports = Vec::with_capacity(physical_devices.len());
+        for (index, &physical_device) in physical_devices.iter().enumerate() {
+            let (info, unsuitable_reason) = Self::query_physical_device_info(
                 instance,
                 physical_device,
                 surface_loader,
                 surface,
                 physical_device_type_priority,
-            );
+                device_requirements,
+            )?;
+
+            reports.push(VkPhysicalDeviceReport {
+                index,
+                info,
+                unsuitable_reason,
+            });
+        }
 
-            if let Some(physical_device_info) = result? {
-                if physical_device_info.score > best_physical_device_score {
-                    best_physical_device = Some(physical_device);
-                    best_physical_device_score = physical_device_info.score;
-                    best_physical_device_info = Some(physical_device_info);
-                }
+        Ok((physical_devices, reports))
+    }
+
+    // Returns the index of the best suitable device that matches the selection. Suitable devices
+    // that don't match are marked as not selected.
+    fn choose_physical_device(
+        reports: &mut [VkPhysicalDeviceReport],
+        physical_device_selection: &PhysicalDeviceSelection,
+    ) -> Option<usize> {
+        let mut best_index = None;
+        let mut best_score = -1;
+        for (index, report) in reports.iter_mut().enumerate() {
+            if !report.is_suitable() {
+                continue;
             }
-        }
 
-        //TODO: Return an error
-        let physical_device = best_physical_device.expect("Could not find suitable device");
-        let physical_device_info = best_physical_device_info.unwrap();
+            if !physical_device_selection.matches(report) {
+                report.unsuitable_reason = Some(VkUnsuitableDeviceReason::NotSelected);
+                continue;
+            }
+
+            if report.info.score > best_score {
+                best_score = report.info.score;
+                best_index = Some(index);
+            }
+        }
 
-        Ok((physical_device, physical_device_info))
+        best_index
     }
 
     fn vk_version_to_string(version: u32) -> String {
@@ -441,20 +932,17 @@ impl VkDevice {
         )
     }
 
+    // Describes the device. Also returns why the device is unsuitable if it can't be used
     fn query_physical_device_info(
-        instance: &ash::Instance,
+        instance: &VkInstance,
         device: ash::vk::PhysicalDevice,
         surface_loader: &ash::extensions::khr::Surface,
-        surface: ash::vk::SurfaceKHR,
+        surface: Option<ash::vk::SurfaceKHR>,
         physical_device_type_priority: &[PhysicalDeviceType],
-    ) -> VkResult<Option<PhysicalDeviceInfo>> {
-        info!(
-            "Preferred device types: {:?}",
-            physical_device_type_priority
-        );
-
+        device_requirements: &VkDeviceRequirements,
+    ) -> VkResult<(PhysicalDeviceInfo, Option<VkUnsuitableDeviceReason>)> {
         let properties: ash::vk::PhysicalDeviceProperties =
-            unsafe { instance.get_physical_device_properties(device) };
+            unsafe { instance.instance.get_physical_device_properties(device) };
         let device_name = unsafe {
             CStr::from_ptr(properties.device_name.as_ptr())
                 .to_str()
@@ -462,33 +950,114 @@ impl VkDevice {
                 .to_string()
         };
 
-        //TODO: Check that the extensions we want to use are supported
-        let extensions: Vec<ash::vk::ExtensionProperties> =
-            unsafe { instance.enumerate_device_extension_properties(device)? };
+        let extensions: Vec<ash::vk::ExtensionProperties> = unsafe {
+            instance
+                .instance
+                .enumerate_device_extension_properties(device)?
+        };
         let features: vk::PhysicalDeviceFeatures =
-            unsafe { instance.get_physical_device_features(device) };
+            unsafe { instance.instance.get_physical_device_features(device) };
+        let memory_properties = unsafe {
+            instance
+                .instance
+                .get_physical_device_memory_properties(device)
+        };
+        let queue_family_properties = unsafe {
+            instance
+                .instance
+                .get_physical_device_queue_family_properties(device)
+        };
+        let format_properties = REPORTED_FORMATS
+            .iter()
+            .map(|format| {
+                let properties = unsafe {
+                    instance
+                        .instance
+                        .get_physical_device_format_properties(device, *format)
+                };
+                (*format, properties)
+            })
+            .collect();
+
+        let api_version = properties.api_version.min(instance.api_version);
+        let supported_features =
+            Self::query_supported_features(&instance.instance, device, api_version, features);
 
         let queue_family_indices =
-            Self::find_queue_families(instance, device, surface_loader, surface)?;
-        if let Some(queue_family_indices) = queue_family_indices {
-            // Determine the index of the device_type within physical_device_type_priority
-            let index = physical_device_type_priority
+            Self::find_queue_families(&queue_family_properties, device, surface_loader, surface)?;
+
+        let is_extension_supported = |name: &CStr| {
+            extensions
                 .iter()
-                .map(|x| x.to_vk())
-                .position(|x| x == properties.device_type);
+                .any(|x| unsafe { CStr::from_ptr(x.extension_name.as_ptr()) } == name)
+        };
+
+        let mut required_extensions = device_requirements.required_extensions.clone();
+        if surface.is_some() {
+            required_extensions.push(khr::Swapchain::name().to_owned());
+        }
 
-            // Convert it to a score
-            let rank = if let Some(index) = index {
-                // It's in the list, return a value between 1..n
-                physical_device_type_priority.len() - index
-            } else {
-                // Not in the list, return a zero
-                0
-            } as i32;
+        let missing_extensions: Vec<_> = required_extensions
+            .iter()
+            .filter(|name| !is_extension_supported(name))
+            .map(|name| name.to_string_lossy().into_owned())
+            .collect();
+        let missing_features = device_requirements
+            .required_features
+            .difference(&supported_features)
+            .names();
+
+        let mut enabled_extensions = required_extensions;
+        for name in &device_requirements.optional_extensions {
+            if is_extension_supported(name) && !enabled_extensions.contains(name) {
+                enabled_extensions.push(name.clone());
+            }
+        }
 
-            let mut score = 0;
-            score += rank * 100;
+        let enabled_features = device_requirements.required_features.union(
+            &device_requirements
+                .optional_features
+                .intersection(&supported_features),
+        );
 
+        // Determine the index of the device_type within physical_device_type_priority
+        let index = physical_device_type_priority
+            .iter()
+            .map(|x| x.to_vk())
+            .position(|x| x == properties.device_type);
+
+        // Convert it to a score
+        let rank = if let Some(index) = index {
+            // It's in the list, return a value between 1..n
+            physical_device_type_priority.len() - index
+        } else {
+            // Not in the list, return a zero
+            0
+        } as i32;
+
+        let mut score = 0;
+        score += rank * 100;
+
+        let unsuitable_reason = if queue_family_indices.is_none() {
+            Some(VkUnsuitableDeviceReason::MissingQueueFamilies)
+        } else if !missing_extensions.is_empty() || !missing_features.is_empty() {
+            Some(VkUnsuitableDeviceReason::MissingRequirements {
+                extensions: missing_extensions,
+                features: missing_features,
+            })
+        } else {
+            None
+        };
+
+        if let Some(unsuitable_reason) = &unsuitable_reason {
+            info!(
+                "Found unsuitable device '{}' API: {} DriverVersion: {} {}",
+                device_name,
+                Self::vk_version_to_string(properties.api_version),
+                Self::vk_version_to_string(properties.driver_version),
+                unsuitable_reason
+            );
+        } else {
             info!(
                 "Found suitable device '{}' API: {} DriverVersion: {} Score = {}",
                 device_name,
@@ -496,42 +1065,76 @@ impl VkDevice {
                 Self::vk_version_to_string(properties.driver_version),
                 score
             );
+        }
+        trace!("{:#?}", properties);
+
+        let info = PhysicalDeviceInfo {
+            score,
+            queue_family_indices: queue_family_indices.unwrap_or_default(),
+            device_name,
+            properties,
+            features,
+            extension_properties: extensions,
+            memory_properties,
+            queue_family_properties,
+            format_properties,
+            api_version,
+            supported_features,
+            enabled_extensions,
+            enabled_features,
+        };
+
+        Ok((info, unsuitable_reason))
+    }
 
-            let result = PhysicalDeviceInfo {
-                score,
-                queue_family_indices,
-                properties,
-                extension_properties: extensions,
+    // The Vulkan 1.1/1.2 feature structs can only be queried on a Vulkan 1.2 device
+    fn query_supported_features(
+        instance: &ash::Instance,
+        physical_device: ash::vk::PhysicalDevice,
+        api_version: u32,
+        features: vk::PhysicalDeviceFeatures,
+    ) -> VkDeviceFeatures {
+        if api_version < vk::make_version(1, 2, 0) {
+            return VkDeviceFeatures {
                 features,
+                ..Default::default()
             };
+        }
 
-            trace!("{:#?}", properties);
-            Ok(Some(result))
-        } else {
-            info!(
-                "Found unsuitable device '{}' API: {} DriverVersion: {} could not find queue families",
-                device_name,
-                Self::vk_version_to_string(properties.api_version),
-                Self::vk_version_to_string(properties.driver_version)
-            );
-            trace!("{:#?}", properties);
-            Ok(None)
+        let mut vulkan_11_features = vk::PhysicalDeviceVulkan11Features::default();
+        let mut vulkan_12_features = vk::PhysicalDeviceVulkan12Features::default();
+        let mut features2 = vk::PhysicalDeviceFeatures2::builder()
+            .push_next(&mut vulkan_11_features)
+            .push_next(&mut vulkan_12_features);
+
+        unsafe {
+            instance.get_physical_device_features2(physical_device, &mut features2);
+        }
+
+        let features = features2.features;
+
+        // Don't keep pointers into this stack frame
+        vulkan_11_features.p_next = std::ptr::null_mut();
+        vulkan_12_features.p_next = std::ptr::null_mut();
+
+        VkDeviceFeatures {
+            features,
+            vulkan_11_features,
+            vulkan_12_features,
         }
     }
 
     fn find_queue_families(
-        instance: &ash::Instance,
+        queue_families: &[ash::vk::QueueFamilyProperties],
         physical_device: ash::vk::PhysicalDevice,
         surface_loader: &ash::extensions::khr::Surface,
-        surface: ash::vk::SurfaceKHR,
+        surface: Option<ash::vk::SurfaceKHR>,
     ) -> VkResult<Option<VkQueueFamilyIndices>> {
-        let queue_families: Vec<ash::vk::QueueFamilyProperties> =
-            unsafe { instance.get_physical_device_queue_family_properties(physical_device) };
-
         let mut graphics_queue_family_index = None;
         let mut present_queue_family_index = None;
         let mut transfer_queue_family_index = None;
         let mut transfer_queue_family_is_dedicated = false;
+        let mut compute_queue_family_index = None;
 
         info!("Available queue families:");
         for (queue_family_index, queue_family) in queue_families.iter().enumerate() {
@@ -546,12 +1149,19 @@ impl VkDevice {
                 == ash::vk::QueueFlags::GRAPHICS;
             let supports_transfer = queue_family.queue_flags & ash::vk::QueueFlags::TRANSFER
                 == ash::vk::QueueFlags::TRANSFER;
-            let supports_present = unsafe {
-                surface_loader.get_physical_device_surface_support(
-                    physical_device,
-                    queue_family_index,
-                    surface,
-                )?
+            let supports_compute = queue_family.queue_flags & ash::vk::QueueFlags::COMPUTE
+                == ash::vk::QueueFlags::COMPUTE;
+            // Without a surface nothing is presented. Treating graphics queue families as able to
+            // present makes the logic below pick the same family for both.
+            let supports_present = match surface {
+                Some(surface) => unsafe {
+                    surface_loader.get_physical_device_surface_support(
+                        physical_device,
+                        queue_family_index,
+                        surface,
+                    )?
+                },
+                None => supports_graphics,
             };
 
             // Remember the first graphics queue family we saw...
@@ -584,6 +1194,11 @@ impl VkDevice {
                 // Otherwise accept the first queue that supports transfers that is NOT the graphics queue
                 transfer_queue_family_index = Some(queue_family_index);
             }
+
+            // Use the first queue family that supports compute but not graphics for async compute
+            if !supports_graphics && supports_compute && compute_queue_family_index.is_none() {
+                compute_queue_family_index = Some(queue_family_index);
+            }
         }
 
         // If we didn't find a transfer queue family != graphics queue family, settle for using the
@@ -592,24 +1207,40 @@ impl VkDevice {
             transfer_queue_family_index = graphics_queue_family_index;
         }
 
+        // If there is no dedicated compute queue family, compute work goes to the graphics queue
+        // family. Vulkan only requires that some queue family supports both graphics and compute,
+        // not the one we picked, so check it. Without compute support the device is not usable.
+        if compute_queue_family_index.is_none() {
+            compute_queue_family_index = graphics_queue_family_index.filter(|&index| {
+                queue_families[index as usize].queue_flags & ash::vk::QueueFlags::COMPUTE
+                    == ash::vk::QueueFlags::COMPUTE
+            });
+        }
+
         info!(
-            "Graphics QF: {:?}  Present QF: {:?}  Transfer QF: {:?}",
-            graphics_queue_family_index, present_queue_family_index, transfer_queue_family_index
+            "Graphics QF: {:?}  Present QF: {:?}  Transfer QF: {:?}  Compute QF: {:?}",
+            graphics_queue_family_index,
+            present_queue_family_index,
+            transfer_queue_family_index,
+            compute_queue_family_index
         );
 
         if let (
             Some(graphics_queue_family_index),
             Some(present_queue_family_index),
             Some(transfer_queue_family_index),
+            Some(compute_queue_family_index),
         ) = (
             graphics_queue_family_index,
             present_queue_family_index,
             transfer_queue_family_index,
+            compute_queue_family_index,
         ) {
             Ok(Some(VkQueueFamilyIndices {
                 graphics_queue_family_index,
                 present_queue_family_index,
                 transfer_queue_family_index,
+                compute_queue_family_index,
             }))
         } else {
             Ok(None)
@@ -619,19 +1250,23 @@ impl VkDevice {
     fn create_logical_device(
         instance: &ash::Instance,
         physical_device: ash::vk::PhysicalDevice,
-        queue_family_indices: &VkQueueFamilyIndices,
+        physical_device_info: &PhysicalDeviceInfo,
     ) -> VkResult<(ash::Device, VkQueues)> {
         //TODO: Ideally we would set up validation layers for the logical device too.
+        let queue_family_indices = &physical_device_info.queue_family_indices;
 
-        let device_extension_names_raw = [khr::Swapchain::name().as_ptr()];
+        let device_extension_names_raw: Vec<_> = physical_device_info
+            .enabled_extensions
+            .iter()
+            .map(|name| name.as_ptr())
+            .collect();
 
-        // Features enabled here by default are supported very widely (only unsupported devices on
-        // vulkan.gpuinfo.org are SwiftShader, a software renderer.
-        let features = vk::PhysicalDeviceFeatures::builder()
-            .sampler_anisotropy(true)
-            .sample_rate_shading(true)
-            // Used for debug drawing lines/points
-            .fill_mode_non_solid(true);
+        let enabled_features = &physical_device_info.enabled_features;
+        let mut vulkan_11_features = enabled_features.vulkan_11_features;
+        let mut vulkan_12_features = enabled_features.vulkan_12_features;
+        let mut features2 = vk::PhysicalDeviceFeatures2::builder()
+            .features(enabled_features.features)
+            .build();
 
         let priorities = [1.0];
 
@@ -639,6 +1274,7 @@ impl VkDevice {
         queue_families_to_create.insert(queue_family_indices.graphics_queue_family_index);
         queue_families_to_create.insert(queue_family_indices.present_queue_family_index);
         queue_families_to_create.insert(queue_family_indices.transfer_queue_family_index);
+        queue_families_to_create.insert(queue_family_indices.compute_queue_family_index);
 
         let queue_infos: Vec<_> = queue_families_to_create
             .iter()
@@ -650,27 +1286,42 @@ impl VkDevice {
             })
             .collect();
 
-        let device_create_info = vk::DeviceCreateInfo::builder()
+        let mut device_create_info = vk::DeviceCreateInfo::builder()
             .queue_create_infos(&queue_infos)
-            .enabled_extension_names(&device_extension_names_raw)
-            .enabled_features(&features);
+            .enabled_extension_names(&device_extension_names_raw);
+
+        // Vulkan 1.1/1.2 features must be passed through a feature chain, and when using a chain
+        // the core features must be in it too
+        if enabled_features.uses_vulkan_12_structs() {
+            device_create_info = device_create_info
+                .push_next(&mut features2)
+                .push_next(&mut vulkan_11_features)
+                .push_next(&mut vulkan_12_features);
+        } else {
+            device_create_info = device_create_info.enabled_features(&enabled_features.features);
+        }
 
         let device: ash::Device =
             unsafe { instance.create_device(physical_device, &device_create_info, None)? };
 
-        let graphics_queue =
-            unsafe { device.get_device_queue(queue_family_indices.graphics_queue_family_index, 0) };
-
-        let present_queue =
-            unsafe { device.get_device_queue(queue_family_indices.present_queue_family_index, 0) };
-
-        let transfer_queue =
-            unsafe { device.get_device_queue(queue_family_indices.transfer_queue_family_index, 0) };
+        // Only one queue is created per family, so queues of the same family are the same queue
+        // and must share a lock
+        let mut queues_by_family = std::collections::HashMap::new();
+        let mut get_queue = |queue_family_index: u32| {
+            queues_by_family
+                .entry(queue_family_index)
+                .or_insert_with(|| {
+                    let queue = unsafe { device.get_device_queue(queue_family_index, 0) };
+                    Arc::new(Mutex::new(queue))
+                })
+                .clone()
+        };
 
         let queues = VkQueues {
-            graphics_queue: Arc::new(Mutex::new(graphics_queue)),
-            present_queue: Arc::new(Mutex::new(present_queue)),
-            transfer_queue: Arc::new(Mutex::new(transfer_queue)),
+            graphics_queue: get_queue(queue_family_indices.graphics_queue_family_index),
+            present_queue: get_queue(queue_family_indices.present_queue_family_index),
+            transfer_queue: get_queue(queue_family_indices.transfer_queue_family_index),
+            compute_queue: get_queue(queue_family_indices.compute_queue_family_index),
         };
 
         Ok((device, queues))
@@ -682,7 +1333,9 @@ impl Drop for VkDevice {
         trace!("destroying VkDevice");
         unsafe {
             self.device_context.destroy();
-            self.surface_loader.destroy_surface(self.surface, None);
+            if let Some(surface) = self.surface {
+                self.surface_loader.destroy_surface(surface, None);
+            }
         }
 
         trace!("destroyed VkDevice");
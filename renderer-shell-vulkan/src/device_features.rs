@@ -0,0 +1,322 @@
+use ash::vk;
+use std::ffi::CString;
+
+/// A set of device features, including the ones that can only be enabled through the Vulkan 1.1
+/// and 1.2 feature structs. The structs are used as sets of flags, their `p_next` pointers are
+/// never set.
+#[derive(Copy, Clone, Debug, Default)]
+pub struct VkDeviceFeatures {
+    pub features: vk::PhysicalDeviceFeatures,
+    pub vulkan_11_features: vk::PhysicalDeviceVulkan11Features,
+    pub vulkan_12_features: vk::PhysicalDeviceVulkan12Features,
+}
+
+// The only pointers are the p_next of the 1.1/1.2 feature structs, which are always null
+unsafe impl Send for VkDeviceFeatures {}
+unsafe impl Sync for VkDeviceFeatures {}
+
+impl VkDeviceFeatures {
+    /// The features that used to always be enabled. They are supported very widely (the only
+    /// unsupported devices on vulkan.gpuinfo.org are SwiftShader, a software renderer)
+    pub fn default_required() -> Self {
+        let mut required = VkDeviceFeatures::default();
+        required.features.sampler_anisotropy = vk::TRUE;
+        required.features.sample_rate_shading = vk::TRUE;
+        // Used for debug drawing lines/points
+        required.features.fill_mode_non_solid = vk::TRUE;
+        required
+    }
+
+    /// Features that are enabled if supported unless overridden. Block-compressed textures are
+    /// supported by almost all desktop GPUs but few mobile ones. Cube map arrays are needed to view
+    /// image assets with more than one cube.
+    pub fn default_optional() -> Self {
+        let mut optional = VkDeviceFeatures::default();
+        optional.features.texture_compression_bc = vk::TRUE;
+        optional.features.image_cube_array = vk::TRUE;
+        optional
+    }
+
+    /// True if no feature is set
+    pub fn is_empty(&self) -> bool {
+        self.flags().iter().all(|x| *x == vk::FALSE)
+    }
+
+    /// True if any Vulkan 1.1 or 1.2 feature is set. These require a Vulkan 1.2 device.
+    pub fn uses_vulkan_12_structs(&self) -> bool {
+        let mut core_features_only = *self;
+        core_features_only.features = vk::PhysicalDeviceFeatures::default();
+        !core_features_only.is_empty()
+    }
+
+    /// Features that are set in both
+    pub fn intersection(
+        &self,
+        other: &VkDeviceFeatures,
+    ) -> VkDeviceFeatures {
+        self.combine(other, |a, b| a && b)
+    }
+
+    /// Features that are set in either
+    pub fn union(
+        &self,
+        other: &VkDeviceFeatures,
+    ) -> VkDeviceFeatures {
+        self.combine(other, |a, b| a || b)
+    }
+
+    /// Features that are set in self but not in other
+    pub fn difference(
+        &self,
+        other: &VkDeviceFeatures,
+    ) -> VkDeviceFeatures {
+        self.combine(other, |a, b| a && !b)
+    }
+
+    /// Names of the features that are set, i.e. "sampler_anisotropy"
+    pub fn names(&self) -> Vec<String> {
+        Self::flag_names()
+            .iter()
+            .zip(self.flags())
+            .filter(|(_, flag)| *flag != vk::FALSE)
+            .map(|(name, _)| name.to_string())
+            .collect()
+    }
+
+    fn combine<F: Fn(bool, bool) -> bool>(
+        &self,
+        other: &VkDeviceFeatures,
+        f: F,
+    ) -> VkDeviceFeatures {
+        let mut result = VkDeviceFeatures::default();
+        let lhs = self.flags();
+        let rhs = other.flags();
+        for (i, flag) in result.flags_mut().into_iter().enumerate() {
+            *flag = if f(lhs[i] != vk::FALSE, rhs[i] != vk::FALSE) {
+                vk::TRUE
+            } else {
+                vk::FALSE
+            };
+        }
+
+        result
+    }
+}
+
+// Lists every feature flag of the feature structs, in declaration order. The 1.1/1.2 structs also
+// have s_type and p_next, which are not features.
+macro_rules! feature_flags {
+    ($($features_struct:ident { $($flag:ident,)* })*) => {
+        impl VkDeviceFeatures {
+            fn flags(&self) -> Vec<vk::Bool32> {
+                vec![$($(self.$features_struct.$flag,)*)*]
+            }
+
+            fn flags_mut(&mut self) -> Vec<&mut vk::Bool32> {
+                vec![$($(&mut self.$features_struct.$flag,)*)*]
+            }
+
+            fn flag_names() -> &'static [&'static str] {
+                &[$($(stringify!($flag),)*)*]
+            }
+        }
+    };
+}
+
+feature_flags! {
+    features {
+        robust_buffer_access,
+        full_draw_index_uint32,
+        image_cube_array,
+        independent_blend,
+        geometry_shader,
+        tessellation_shader,
+        sample_rate_shading,
+        dual_src_blend,
+        logic_op,
+        multi_draw_indirect,
+        draw_indirect_first_instance,
+        depth_clamp,
+        depth_bias_clamp,
+        fill_mode_non_solid,
+        depth_bounds,
+        wide_lines,
+        large_points,
+        alpha_to_one,
+        multi_viewport,
+        sampler_anisotropy,
+        texture_compression_etc2,
+        texture_compression_astc_ldr,
+        texture_compression_bc,
+        occlusion_query_precise,
+        pipeline_statistics_query,
+        vertex_pipeline_stores_and_atomics,
+        fragment_stores_and_atomics,
+        shader_tessellation_and_geometry_point_size,
+        shader_image_gather_extended,
+        shader_storage_image_extended_formats,
+        shader_storage_image_multisample,
+        shader_storage_image_read_without_format,
+        shader_storage_image_write_without_format,
+        shader_uniform_buffer_array_dynamic_indexing,
+        shader_sampled_image_array_dynamic_indexing,
+        shader_storage_buffer_array_dynamic_indexing,
+        shader_storage_image_array_dynamic_indexing,
+        shader_clip_distance,
+        shader_cull_distance,
+        shader_float64,
+        shader_int64,
+        shader_int16,
+        shader_resource_residency,
+        shader_resource_min_lod,
+        sparse_binding,
+        sparse_residency_buffer,
+        sparse_residency_image2_d,
+        sparse_residency_image3_d,
+        sparse_residency2_samples,
+        sparse_residency4_samples,
+        sparse_residency8_samples,
+        sparse_residency16_samples,
+        sparse_residency_aliased,
+        variable_multisample_rate,
+        inherited_queries,
+    }
+    vulkan_11_features {
+        storage_buffer16_bit_access,
+        uniform_and_storage_buffer16_bit_access,
+        storage_push_constant16,
+        storage_input_output16,
+        multiview,
+        multiview_geometry_shader,
+        multiview_tessellation_shader,
+        variable_pointers_storage_buffer,
+        variable_pointers,
+        protected_memory,
+        sampler_ycbcr_conversion,
+        shader_draw_parameters,
+    }
+    vulkan_12_features {
+        sampler_mirror_clamp_to_edge,
+        draw_indirect_count,
+        storage_buffer8_bit_access,
+        uniform_and_storage_buffer8_bit_access,
+        storage_push_constant8,
+        shader_buffer_int64_atomics,
+        shader_shared_int64_atomics,
+        shader_float16,
+        shader_int8,
+        descriptor_indexing,
+        shader_input_attachment_array_dynamic_indexing,
+        shader_uniform_texel_buffer_array_dynamic_indexing,
+        shader_storage_texel_buffer_array_dynamic_indexing,
+        shader_uniform_buffer_array_non_uniform_indexing,
+        shader_sampled_image_array_non_uniform_indexing,
+        shader_storage_buffer_array_non_uniform_indexing,
+        shader_storage_image_array_non_uniform_indexing,
+        shader_input_attachment_array_non_uniform_indexing,
+        shader_uniform_texel_buffer_array_non_uniform_indexing,
+        shader_storage_texel_buffer_array_non_uniform_indexing,
+        descriptor_binding_uniform_buffer_update_after_bind,
+        descriptor_binding_sampled_image_update_after_bind,
+        descriptor_binding_storage_image_update_after_bind,
+        descriptor_binding_storage_buffer_update_after_bind,
+        descriptor_binding_uniform_texel_buffer_update_after_bind,
+        descriptor_binding_storage_texel_buffer_update_after_bind,
+        descriptor_binding_update_unused_while_pending,
+        descriptor_binding_partially_bound,
+        descriptor_binding_variable_descriptor_count,
+        runtime_descriptor_array,
+        sampler_filter_minmax,
+        scalar_block_layout,
+        imageless_framebuffer,
+        uniform_buffer_standard_layout,
+        shader_subgroup_extended_types,
+        separate_depth_stencil_layouts,
+        host_query_reset,
+        timeline_semaphore,
+        buffer_device_address,
+        buffer_device_address_capture_replay,
+        buffer_device_address_multi_device,
+        vulkan_memory_model,
+        vulkan_memory_model_device_scope,
+        vulkan_memory_model_availability_visibility_chains,
+        shader_output_viewport_index,
+        shader_output_layer,
+        subgroup_broadcast_dynamic_id,
+    }
+}
+
+/// Device extensions and features to enable. Devices that don't support everything that is
+/// required are rejected when picking a physical device. Optional extensions and features are
+/// enabled if the device supports them. Use `VkDeviceContext::enabled_extensions()` and
+/// `VkDeviceContext::enabled_features()` to see what was actually enabled.
+///
+/// The swapchain extension is always required when creating a device for a window.
+#[derive(Clone, Default)]
+pub struct VkDeviceRequirements {
+    pub required_extensions: Vec<CString>,
+    pub optional_extensions: Vec<CString>,
+    pub required_features: VkDeviceFeatures,
+    pub optional_features: VkDeviceFeatures,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_feature_set_operations() {
+        let required = VkDeviceFeatures::default_required();
+
+        let mut supported = VkDeviceFeatures::default();
+        supported.features.sampler_anisotropy = vk::TRUE;
+        supported.features.fill_mode_non_solid = vk::TRUE;
+        supported.vulkan_12_features.descriptor_indexing = vk::TRUE;
+
+        assert_eq!(
+            required.difference(&supported).names(),
+            vec!["sample_rate_shading".to_string()]
+        );
+        assert!(!required.uses_vulkan_12_structs());
+        assert!(supported.uses_vulkan_12_structs());
+
+        let mut optional = VkDeviceFeatures::default();
+        optional.vulkan_12_features.descriptor_indexing = vk::TRUE;
+        optional.vulkan_11_features.multiview = vk::TRUE;
+
+        let enabled = required.union(&optional.intersection(&supported));
+        assert_eq!(
+            enabled.names(),
+            vec![
+                "sample_rate_shading".to_string(),
+                "fill_mode_non_solid".to_string(),
+                "sampler_anisotropy".to_string(),
+                "descriptor_indexing".to_string(),
+            ]
+        );
+        assert!(VkDeviceFeatures::default().is_empty());
+        assert!(!enabled.is_empty());
+    }
+
+    #[test]
+    fn test_each_flag_maps_to_its_name() {
+        let flag_count = VkDeviceFeatures::flag_names().len();
+        assert_eq!(VkDeviceFeatures::default().flags().len(), flag_count);
+
+        for i in 0..flag_count {
+            let mut features = VkDeviceFeatures::default();
+            *features.flags_mut().remove(i) = vk::TRUE;
+            assert_eq!(
+                features.names(),
+                vec![VkDeviceFeatures::flag_names()[i].to_string()]
+            );
+        }
+
+        let mut features = VkDeviceFeatures::default();
+        features.vulkan_12_features.subgroup_broadcast_dynamic_id = vk::TRUE;
+        assert_eq!(
+            features.names(),
+            vec!["subgroup_broadcast_dynamic_id".to_string()]
+        );
+    }
+}
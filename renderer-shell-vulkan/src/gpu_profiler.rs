@@ -0,0 +1,644 @@
+use ash::vk;
+use ash::prelude::VkResult;
+use ash::version::DeviceV1_0;
+
+use crate::VkDeviceContext;
+
+/// GPU timing of a scope recorded with `VkGpuProfiler`. Times are relative to the start of the
+/// frame.
+#[derive(Clone, Debug)]
+pub struct VkGpuProfileScope {
+    pub name: String,
+    pub begin_ms: f32,
+    pub end_ms: f32,
+    pub children: Vec<VkGpuProfileScope>,
+}
+
+impl VkGpuProfileScope {
+    pub fn duration_ms(&self) -> f32 {
+        self.end_ms - self.begin_ms
+    }
+}
+
+/// GPU timing of a whole frame recorded with `VkGpuProfiler`
+#[derive(Clone, Debug)]
+pub struct VkGpuProfileFrame {
+    // Counts calls to VkGpuProfiler::begin_frame
+    pub frame_number: u64,
+    pub duration_ms: f32,
+
+    // Top-level scopes, in the order they were begun
+    pub scopes: Vec<VkGpuProfileScope>,
+}
+
+// Query 0 is written when the frame begins and query 1 when it ends
+const FRAME_BEGIN_QUERY: u32 = 0;
+const FRAME_END_QUERY: u32 = 1;
+const FIRST_SCOPE_QUERY: u32 = 2;
+
+struct RecordedScope {
+    name: String,
+    parent: Option<usize>,
+    begin_query: u32,
+    end_query: Option<u32>,
+}
+
+struct ProfilerFrame {
+    query_pool: vk::QueryPool,
+
+    // Command buffers returned by begin_frame/begin_scope/etc. These are re-recorded every time
+    // this frame slot is reused
+    command_pool: vk::CommandPool,
+    command_buffers: Vec<vk::CommandBuffer>,
+    next_command_buffer: usize,
+
+    scopes: Vec<RecordedScope>,
+    next_query: u32,
+    frame_number: u64,
+
+    // True if the frame was ended and its results have not been read yet
+    pending_results: bool,
+}
+
+/// Measures how long work takes on the GPU using timestamp queries. A query pool is kept per frame
+/// in flight. Results are read when a frame's pool is reused (at which point the GPU is known to be
+/// done with it), so reading never stalls, and `latest_results()` lags a few frames behind.
+///
+/// Scopes can be nested and should be written into the command buffers the caller is recording
+/// with `cmd_begin_scope`/`cmd_end_scope`. Work in command buffers that are prerecorded once and
+/// reused can only be measured with `begin_scope`/`end_scope`, which return small command buffers
+/// to submit around it. Each of those is an extra submitted command buffer, so don't use them for
+/// anything else. `begin_frame` returns a command buffer that must be submitted before anything
+/// else in the frame and `end_frame` one that must be submitted after.
+///
+/// If the graphics queue family doesn't support timestamps, nothing is measured and no results
+/// are produced, but the returned command buffers are still valid to submit.
+pub struct VkGpuProfiler {
+    device_context: VkDeviceContext,
+
+    // Nanoseconds per timestamp tick
+    timestamp_period: f32,
+    timestamp_mask: u64,
+    enabled: bool,
+
+    max_queries_per_frame: u32,
+    frames: Vec<ProfilerFrame>,
+    current_frame: Option<usize>,
+    scope_stack: Vec<Option<usize>>,
+    frame_count: u64,
+    warned_out_of_queries: bool,
+
+    latest_results: Option<VkGpuProfileFrame>,
+}
+
+impl VkGpuProfiler {
+    /// `frames_in_flight` is the number of frames that can be recorded before the GPU finishes
+    /// the first one, i.e. the number of distinct `frame_index` values passed to `begin_frame`.
+    /// Each scope uses two queries.
+    pub fn new(
+        device_context: &VkDeviceContext,
+        frames_in_flight: usize,
+        max_scopes_per_frame: u32,
+    ) -> VkResult<Self> {
+        let queue_family_index = device_context
+            .queue_family_indices()
+            .graphics_queue_family_index;
+        let timestamp_valid_bits = device_context
+            .physical_device_info()
+            .queue_family_properties[queue_family_index as usize]
+            .timestamp_valid_bits;
+        let timestamp_period = device_context.limits().timestamp_period;
+
+        let enabled = timestamp_valid_bits > 0;
+        if !enabled {
+            log::warn!("The graphics queue does not support timestamps, GPU profiling is disabled");
+        }
+
+        let timestamp_mask = if timestamp_valid_bits >= 64 {
+            !0
+        } else {
+            (1u64 << timestamp_valid_bits) - 1
+        };
+
+        let max_queries_per_frame = FIRST_SCOPE_QUERY + max_scopes_per_frame * 2;
+
+        let mut frames = Vec::with_capacity(frames_in_flight);
+        for _ in 0..frames_in_flight {
+            frames.push(Self::create_frame(
+                device_context.device(),
+                queue_family_index,
+                max_queries_per_frame,
+            )?);
+        }
+
+        Ok(VkGpuProfiler {
+            device_context: device_context.clone(),
+            timestamp_period,
+            timestamp_mask,
+            enabled,
+            max_queries_per_frame,
+            frames,
+            current_frame: None,
+            scope_stack: Default::default(),
+            frame_count: 0,
+            warned_out_of_queries: false,
+            latest_results: None,
+        })
+    }
+
+    fn create_frame(
+        logical_device: &ash::Device,
+        queue_family_index: u32,
+        max_queries_per_frame: u32,
+    ) -> VkResult<ProfilerFrame> {
+        let query_pool_create_info = vk::QueryPoolCreateInfo::builder()
+            .query_type(vk::QueryType::TIMESTAMP)
+            .query_count(max_queries_per_frame);
+        let query_pool =
+            unsafe { logical_device.create_query_pool(&query_pool_create_info, None)? };
+
+        let pool_create_info = vk::CommandPoolCreateInfo::builder()
+            .flags(vk::CommandPoolCreateFlags::RESET_COMMAND_BUFFER)
+            .queue_family_index(queue_family_index);
+        let command_pool = unsafe { logical_device.create_command_pool(&pool_create_info, None)? };
+
+        Ok(ProfilerFrame {
+            query_pool,
+            command_pool,
+            command_buffers: Default::default(),
+            next_command_buffer: 0,
+            scopes: Default::default(),
+            next_query: 0,
+            frame_number: 0,
+            pending_results: false,
+        })
+    }
+
+    /// The most recent frame that finished on the GPU
+    pub fn latest_results(&self) -> Option<&VkGpuProfileFrame> {
+        self.latest_results.as_ref()
+    }
+
+    /// Start recording a frame. The GPU must be finished with the previous frame that used the
+    /// same `frame_index`. The returned command buffer must be submitted first. If the previous
+    /// frame was never ended (i.e. recording failed partway through), it is discarded.
+    pub fn begin_frame(
+        &mut self,
+        frame_index: usize,
+    ) -> VkResult<vk::CommandBuffer> {
+        if let Some(abandoned_frame) = self.current_frame.take() {
+            log::debug!("GPU profiler frame was not ended, discarding it");
+            self.frames[abandoned_frame].pending_results = false;
+            self.scope_stack.clear();
+        }
+
+        self.read_results(frame_index)?;
+
+        let frame = &mut self.frames[frame_index];
+        frame.scopes.clear();
+        frame.next_command_buffer = 0;
+        frame.next_query = FIRST_SCOPE_QUERY;
+        frame.frame_number = self.frame_count;
+        self.frame_count += 1;
+        self.current_frame = Some(frame_index);
+
+        let command_buffer = self.begin_marker_command_buffer()?;
+        if self.enabled {
+            let frame = &self.frames[frame_index];
+            unsafe {
+                let device = self.device_context.device();
+                device.cmd_reset_query_pool(
+                    command_buffer,
+                    frame.query_pool,
+                    0,
+                    self.max_queries_per_frame,
+                );
+                device.cmd_write_timestamp(
+                    command_buffer,
+                    vk::PipelineStageFlags::TOP_OF_PIPE,
+                    frame.query_pool,
+                    FRAME_BEGIN_QUERY,
+                );
+            }
+        }
+
+        self.end_marker_command_buffer(command_buffer)
+    }
+
+    /// Finish recording the frame. Open scopes are ended. The returned command buffer must be
+    /// submitted last.
+    pub fn end_frame(&mut self) -> VkResult<vk::CommandBuffer> {
+        let frame_index = self
+            .current_frame
+            .expect("end_frame called without begin_frame");
+
+        let command_buffer = self.begin_marker_command_buffer()?;
+        while !self.scope_stack.is_empty() {
+            self.cmd_end_scope(command_buffer);
+        }
+
+        if self.enabled {
+            unsafe {
+                self.device_context.device().cmd_write_timestamp(
+                    command_buffer,
+                    vk::PipelineStageFlags::BOTTOM_OF_PIPE,
+                    self.frames[frame_index].query_pool,
+                    FRAME_END_QUERY,
+                );
+            }
+        }
+
+        self.frames[frame_index].pending_results = true;
+        self.current_frame = None;
+        self.end_marker_command_buffer(command_buffer)
+    }
+
+    /// Begin a scope in a command buffer the caller is recording. Scopes must be ended in the
+    /// reverse order they were begun, and the command buffers must be submitted in the order the
+    /// scopes were recorded.
+    pub fn cmd_begin_scope(
+        &mut self,
+        command_buffer: vk::CommandBuffer,
+        name: &str,
+    ) {
+        let frame_index = self
+            .current_frame
+            .expect("cmd_begin_scope called outside of a frame");
+        let frame = &mut self.frames[frame_index];
+
+        // Each measured scope that is still open will need a query when it ends
+        let open_scope_count = self.scope_stack.iter().filter(|x| x.is_some()).count() as u32;
+        let begin_query = if self.enabled {
+            allocate_begin_query(
+                &mut frame.next_query,
+                open_scope_count,
+                self.max_queries_per_frame,
+            )
+        } else {
+            None
+        };
+
+        let begin_query = if let Some(begin_query) = begin_query {
+            begin_query
+        } else {
+            if self.enabled && !self.warned_out_of_queries {
+                log::warn!(
+                    "GPU profiler ran out of queries, scope {} is not measured",
+                    name
+                );
+                self.warned_out_of_queries = true;
+            }
+
+            self.scope_stack.push(None);
+            return;
+        };
+
+        let parent = self.scope_stack.last().cloned().flatten();
+        frame.scopes.push(RecordedScope {
+            name: name.to_string(),
+            parent,
+            begin_query,
+            end_query: None,
+        });
+        self.scope_stack.push(Some(frame.scopes.len() - 1));
+
+        unsafe {
+            self.device_context.device().cmd_write_timestamp(
+                command_buffer,
+                vk::PipelineStageFlags::TOP_OF_PIPE,
+                frame.query_pool,
+                begin_query,
+            );
+        }
+    }
+
+    /// End the most recently begun scope in a command buffer the caller is recording
+    pub fn cmd_end_scope(
+        &mut self,
+        command_buffer: vk::CommandBuffer,
+    ) {
+        let frame_index = self
+            .current_frame
+            .expect("cmd_end_scope called outside of a frame");
+        let scope_index = self
+            .scope_stack
+            .pop()
+            .expect("cmd_end_scope called without a matching cmd_begin_scope");
+
+        if let Some(scope_index) = scope_index {
+            let frame = &mut self.frames[frame_index];
+            let end_query = frame.next_query;
+            frame.next_query += 1;
+            frame.scopes[scope_index].end_query = Some(end_query);
+
+            unsafe {
+                self.device_context.device().cmd_write_timestamp(
+                    command_buffer,
+                    vk::PipelineStageFlags::BOTTOM_OF_PIPE,
+                    frame.query_pool,
+                    end_query,
+                );
+            }
+        }
+    }
+
+    /// Begin a scope around command buffers that were prerecorded and can't contain the scope.
+    /// Returns a command buffer to submit before the work being measured. Prefer
+    /// `cmd_begin_scope` for command buffers that are recorded every frame.
+    pub fn begin_scope(
+        &mut self,
+        name: &str,
+    ) -> VkResult<vk::CommandBuffer> {
+        let command_buffer = self.begin_marker_command_buffer()?;
+        self.cmd_begin_scope(command_buffer, name);
+        self.end_marker_command_buffer(command_buffer)
+    }
+
+    /// End the most recently begun scope. Returns a command buffer to submit after the work being
+    /// measured.
+    pub fn end_scope(&mut self) -> VkResult<vk::CommandBuffer> {
+        let command_buffer = self.begin_marker_command_buffer()?;
+        self.cmd_end_scope(command_buffer);
+        self.end_marker_command_buffer(command_buffer)
+    }
+
+    fn begin_marker_command_buffer(&mut self) -> VkResult<vk::CommandBuffer> {
+        let frame_index = self.current_frame.expect("Not recording a frame");
+        let frame = &mut self.frames[frame_index];
+        let device = self.device_context.device();
+
+        if frame.next_command_buffer == frame.command_buffers.len() {
+            let command_buffer_allocate_info = vk::CommandBufferAllocateInfo::builder()
+                .command_buffer_count(1)
+                .command_pool(frame.command_pool)
+                .level(vk::CommandBufferLevel::PRIMARY);
+
+            let command_buffer =
+                unsafe { device.allocate_command_buffers(&command_buffer_allocate_info)? }[0];
+            frame.command_buffers.push(command_buffer);
+        }
+
+        let command_buffer = frame.command_buffers[frame.next_command_buffer];
+        frame.next_command_buffer += 1;
+
+        // Implicitly resets the command buffer
+        let command_buffer_begin_info = vk::CommandBufferBeginInfo::builder()
+            .flags(vk::CommandBufferUsageFlags::ONE_TIME_SUBMIT);
+        unsafe {
+            device.begin_command_buffer(command_buffer, &command_buffer_begin_info)?;
+        }
+
+        Ok(command_buffer)
+    }
+
+    fn end_marker_command_buffer(
+        &self,
+        command_buffer: vk::CommandBuffer,
+    ) -> VkResult<vk::CommandBuffer> {
+        unsafe {
+            self.device_context
+                .device()
+                .end_command_buffer(command_buffer)?;
+        }
+
+        Ok(command_buffer)
+    }
+
+    // Reads the timestamps of the frame that last used this frame_index, if there is one
+    fn read_results(
+        &mut self,
+        frame_index: usize,
+    ) -> VkResult<()> {
+        let frame = &mut self.frames[frame_index];
+        if !frame.pending_results || !self.enabled {
+            return Ok(());
+        }
+
+        frame.pending_results = false;
+
+        let mut timestamps = vec![0u64; frame.next_query as usize];
+        let result = unsafe {
+            self.device_context.device().get_query_pool_results(
+                frame.query_pool,
+                0,
+                frame.next_query,
+                &mut timestamps,
+                vk::QueryResultFlags::TYPE_64,
+            )
+        };
+
+        match result {
+            Ok(()) => {}
+            // The frame was probably never submitted
+            Err(vk::Result::NOT_READY) => {
+                log::debug!(
+                    "GPU profiler results for frame {} not ready",
+                    frame.frame_number
+                );
+                return Ok(());
+            }
+            Err(e) => return Err(e),
+        }
+
+        self.latest_results = Some(build_profile_frame(
+            frame.frame_number,
+            &frame.scopes,
+            &timestamps,
+            self.timestamp_mask,
+            self.timestamp_period,
+        ));
+
+        Ok(())
+    }
+}
+
+impl Drop for VkGpuProfiler {
+    fn drop(&mut self) {
+        log::trace!("destroying VkGpuProfiler");
+
+        unsafe {
+            let device = self.device_context.device();
+            for frame in &self.frames {
+                device.destroy_query_pool(frame.query_pool, None);
+                device.destroy_command_pool(frame.command_pool, None);
+            }
+        }
+
+        log::trace!("destroyed VkGpuProfiler");
+    }
+}
+
+// Returns the begin query of a new scope, or None if there's no room for both of its queries plus
+// the end queries still owed to the open scopes enclosing it
+fn allocate_begin_query(
+    next_query: &mut u32,
+    open_scope_count: u32,
+    max_queries_per_frame: u32,
+) -> Option<u32> {
+    if *next_query + 2 + open_scope_count > max_queries_per_frame {
+        return None;
+    }
+
+    let begin_query = *next_query;
+    *next_query += 1;
+    Some(begin_query)
+}
+
+fn build_profile_frame(
+    frame_number: u64,
+    scopes: &[RecordedScope],
+    timestamps: &[u64],
+    timestamp_mask: u64,
+    timestamp_period: f32,
+) -> VkGpuProfileFrame {
+    let frame_begin = timestamps[FRAME_BEGIN_QUERY as usize];
+
+    // Timestamps may wrap around if fewer than 64 bits are valid
+    let to_ms = |query: u32| {
+        let ticks = timestamps[query as usize].wrapping_sub(frame_begin) & timestamp_mask;
+        (ticks as f64 * timestamp_period as f64 / 1_000_000.0) as f32
+    };
+
+    VkGpuProfileFrame {
+        frame_number,
+        duration_ms: to_ms(FRAME_END_QUERY),
+        scopes: build_scope_tree(scopes, None, &to_ms),
+    }
+}
+
+fn build_scope_tree<F: Fn(u32) -> f32>(
+    scopes: &[RecordedScope],
+    parent: Option<usize>,
+    to_ms: &F,
+) -> Vec<VkGpuProfileScope> {
+    scopes
+        .iter()
+        .enumerate()
+        .filter(|(_, scope)| scope.parent == parent)
+        .map(|(index, scope)| {
+            let begin_ms = to_ms(scope.begin_query);
+            VkGpuProfileScope {
+                name: scope.name.clone(),
+                begin_ms,
+                // Scopes are always ended by end_frame
+                end_ms: scope.end_query.map(to_ms).unwrap_or(begin_ms),
+                children: build_scope_tree(scopes, Some(index), to_ms),
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn scope(
+        name: &str,
+        parent: Option<usize>,
+        begin_query: u32,
+        end_query: u32,
+    ) -> RecordedScope {
+        RecordedScope {
+            name: name.to_string(),
+            parent,
+            begin_query,
+            end_query: Some(end_query),
+        }
+    }
+
+    #[test]
+    fn test_allocate_begin_query_nested_at_limit() {
+        // Room for two scopes. Nest four, the inner two must not be measured
+        let max_queries_per_frame = FIRST_SCOPE_QUERY + 2 * 2;
+        let mut next_query = FIRST_SCOPE_QUERY;
+        let mut scope_stack: Vec<Option<u32>> = vec![];
+        for _ in 0..4 {
+            let open_scope_count = scope_stack.iter().filter(|x| x.is_some()).count();
+            scope_stack.push(allocate_begin_query(
+                &mut next_query,
+                open_scope_count as u32,
+                max_queries_per_frame,
+            ));
+        }
+
+        assert_eq!(scope_stack, vec![Some(2), Some(3), None, None]);
+
+        // Ending the scopes uses the remaining queries, as cmd_end_scope does
+        let mut end_queries = vec![];
+        while let Some(begin_query) = scope_stack.pop() {
+            if begin_query.is_some() {
+                end_queries.push(next_query);
+                next_query += 1;
+            }
+        }
+
+        assert_eq!(end_queries, vec![4, 5]);
+        assert_eq!(next_query, max_queries_per_frame);
+    }
+
+    #[test]
+    fn test_allocate_begin_query_sequential() {
+        // Scopes that aren't nested can use every query
+        let max_queries_per_frame = FIRST_SCOPE_QUERY + 2 * 2;
+        let mut next_query = FIRST_SCOPE_QUERY;
+        for expected_begin_query in &[2, 4] {
+            assert_eq!(
+                allocate_begin_query(&mut next_query, 0, max_queries_per_frame),
+                Some(*expected_begin_query)
+            );
+
+            // End the scope
+            next_query += 1;
+        }
+
+        assert_eq!(
+            allocate_begin_query(&mut next_query, 0, max_queries_per_frame),
+            None
+        );
+    }
+
+    #[test]
+    fn test_build_profile_frame() {
+        // opaque, then bloom containing extract and blur
+        let scopes = vec![
+            scope("opaque", None, 2, 3),
+            scope("bloom", None, 4, 9),
+            scope("extract", Some(1), 5, 6),
+            scope("blur", Some(1), 7, 8),
+        ];
+
+        // 1 tick = 1000ns, so 1000 ticks = 1ms
+        let timestamps = [
+            10_000, 20_000, 10_000, 13_000, 13_000, 13_000, 14_000, 14_000, 19_000, 19_000,
+        ];
+
+        let frame = build_profile_frame(7, &scopes, &timestamps, !0, 1000.0);
+        assert_eq!(frame.frame_number, 7);
+        assert_eq!(frame.duration_ms, 10.0);
+        assert_eq!(frame.scopes.len(), 2);
+        assert_eq!(frame.scopes[0].name, "opaque");
+        assert_eq!(frame.scopes[0].duration_ms(), 3.0);
+        assert!(frame.scopes[0].children.is_empty());
+
+        let bloom = &frame.scopes[1];
+        assert_eq!(bloom.begin_ms, 3.0);
+        assert_eq!(bloom.duration_ms(), 6.0);
+        assert_eq!(bloom.children.len(), 2);
+        assert_eq!(bloom.children[0].name, "extract");
+        assert_eq!(bloom.children[0].duration_ms(), 1.0);
+        assert_eq!(bloom.children[1].name, "blur");
+        assert_eq!(bloom.children[1].duration_ms(), 5.0);
+    }
+
+    #[test]
+    fn test_build_profile_frame_wraps() {
+        // Only 8 valid bits, and the counter wraps during the frame
+        let scopes = vec![scope("pass", None, 2, 3)];
+        let timestamps = [250, 4, 252, 2];
+
+        let frame = build_profile_frame(0, &scopes, &timestamps, 0xFF, 1_000_000.0);
+        assert_eq!(frame.duration_ms, 10.0);
+        assert_eq!(frame.scopes[0].begin_ms, 2.0);
+        assert_eq!(frame.scopes[0].duration_ms(), 6.0);
+    }
+}
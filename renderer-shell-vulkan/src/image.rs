@@ -16,6 +16,8 @@ pub struct VkImage {
     pub format: vk::Format,
     pub tiling: vk::ImageTiling,
     pub mip_level_count: u32,
+    pub array_layer_count: u32,
+    pub create_flags: vk::ImageCreateFlags,
     pub allocation_info: vk_mem::AllocationInfo,
     pub raw: Option<VkImageRaw>,
 }
@@ -42,6 +44,8 @@ impl VkImage {
         tiling: vk::ImageTiling,
         samples: vk::SampleCountFlags,
         mip_level_count: u32,
+        array_layer_count: u32,
+        create_flags: vk::ImageCreateFlags,
         required_property_flags: vk::MemoryPropertyFlags,
     ) -> VkResult<Self> {
         let allocation_create_info = vk_mem::AllocationCreateInfo {
@@ -55,10 +59,11 @@ impl VkImage {
         };
 
         let image_create_info = vk::ImageCreateInfo::builder()
+            .flags(create_flags)
             .image_type(vk::ImageType::TYPE_2D)
             .extent(extent)
             .mip_levels(mip_level_count)
-            .array_layers(1)
+            .array_layers(array_layer_count)
             .format(format)
             .tiling(tiling)
             .initial_layout(vk::ImageLayout::UNDEFINED)
@@ -80,6 +85,8 @@ impl VkImage {
             format,
             tiling,
             mip_level_count,
+            array_layer_count,
+            create_flags,
             allocation_info,
             raw: Some(raw),
         })
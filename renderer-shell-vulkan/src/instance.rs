@@ -1,4 +1,4 @@
-use std::ffi::CString;
+use std::ffi::{CStr, CString};
 
 pub use ash::version::{DeviceV1_0, EntryV1_0, InstanceV1_0};
 use ash::vk;
@@ -8,13 +8,21 @@ use super::VkEntry;
 use super::Window;
 use super::debug_reporter;
 use super::VkDebugReporter;
-use ash::extensions::ext::DebugReport;
+use ash::extensions::ext::{DebugReport, DebugUtils};
 
 /// Create one of these at startup. It never gets lost/destroyed.
 pub struct VkInstance {
     pub entry: VkEntry,
     pub instance: ash::Instance,
     pub debug_reporter: Option<VkDebugReporter>,
+
+    // Loader for VK_EXT_debug_utils, used to name objects and label command buffers. None if the
+    // instance doesn't support the extension
+    pub debug_utils: Option<DebugUtils>,
+
+    // The API version the instance was created with. Device functionality newer than this can't be
+    // used, even if the device supports it
+    pub api_version: u32,
 }
 
 #[derive(Debug)]
@@ -57,10 +65,11 @@ impl From<vk::Result> for VkCreateInstanceError {
 }
 
 impl VkInstance {
-    /// Creates a vulkan instance.
+    /// Creates a vulkan instance. If no window is provided, surface extensions are not enabled and
+    /// the instance can only be used headless.
     pub fn new(
         entry: VkEntry,
-        window: &dyn Window,
+        window: Option<&dyn Window>,
         app_name: &CString,
         validation_layer_debug_report_flags: vk::DebugReportFlagsEXT,
     ) -> Result<VkInstance, VkCreateInstanceError> {
@@ -117,9 +126,19 @@ impl VkInstance {
             .collect();
 
         // Determine what extensions to use
-        let mut extension_names_raw = window.extension_names();
-
-        if !validation_layer_debug_report_flags.is_empty() {
+        let mut extension_names_raw = window
+            .map(|window| window.extension_names())
+            .unwrap_or_default();
+
+        // Debug utils is enabled whenever it's available, even without validation, so that object
+        // names and labels show up in tools like RenderDoc
+        let debug_utils_supported = extensions.iter().any(|extension| {
+            unsafe { CStr::from_ptr(extension.extension_name.as_ptr()) } == DebugUtils::name()
+        });
+
+        if debug_utils_supported {
+            extension_names_raw.push(DebugUtils::name().as_ptr())
+        } else if !validation_layer_debug_report_flags.is_empty() {
             extension_names_raw.push(DebugReport::name().as_ptr())
         }
 
@@ -132,21 +151,60 @@ impl VkInstance {
         info!("Creating vulkan instance");
         let instance: ash::Instance = unsafe { entry.create_instance(&create_info, None)? };
 
+        let debug_utils = if debug_utils_supported {
+            Some(DebugUtils::new(&entry, &instance))
+        } else {
+            None
+        };
+
         // Setup the debug callback for the validation layer
-        let debug_reporter = if !validation_layer_debug_report_flags.is_empty() {
+        let debug_reporter = if validation_layer_debug_report_flags.is_empty() {
+            None
+        } else if let Some(debug_utils) = &debug_utils {
+            Some(Self::setup_vulkan_debug_utils_messenger(
+                debug_utils,
+                validation_layer_debug_report_flags,
+            )?)
+        } else {
             Some(Self::setup_vulkan_debug_callback(
                 &entry,
                 &instance,
                 validation_layer_debug_report_flags,
             )?)
-        } else {
-            None
         };
 
         Ok(VkInstance {
             entry,
             instance,
             debug_reporter,
+            debug_utils,
+            api_version,
+        })
+    }
+
+    /// This is used to setup a debug messenger for logging validation errors
+    fn setup_vulkan_debug_utils_messenger(
+        debug_utils: &DebugUtils,
+        debug_report_flags: vk::DebugReportFlagsEXT,
+    ) -> VkResult<VkDebugReporter> {
+        info!("Seting up vulkan debug messenger");
+        let debug_info = vk::DebugUtilsMessengerCreateInfoEXT::builder()
+            .message_severity(debug_reporter::debug_report_flags_to_severity(
+                debug_report_flags,
+            ))
+            .message_type(
+                vk::DebugUtilsMessageTypeFlagsEXT::GENERAL
+                    | vk::DebugUtilsMessageTypeFlagsEXT::VALIDATION
+                    | vk::DebugUtilsMessageTypeFlagsEXT::PERFORMANCE,
+            )
+            .pfn_user_callback(Some(debug_reporter::vulkan_debug_utils_callback));
+
+        let debug_messenger =
+            unsafe { debug_utils.create_debug_utils_messenger(&debug_info, None)? };
+
+        Ok(VkDebugReporter::DebugUtils {
+            debug_utils_loader: debug_utils.clone(),
+            debug_messenger,
         })
     }
 
@@ -165,7 +223,7 @@ impl VkInstance {
         let debug_callback =
             unsafe { debug_report_loader.create_debug_report_callback(&debug_info, None)? };
 
-        Ok(VkDebugReporter {
+        Ok(VkDebugReporter::DebugReport {
             debug_report_loader,
             debug_callback,
         })
@@ -29,9 +29,18 @@ pub use instance::VkCreateInstanceError;
 mod device;
 pub use device::VkDevice;
 pub use device::VkDeviceContext;
+pub use device::VkDebugLabelScope;
 pub use device::VkQueueFamilyIndices;
 pub use device::VkQueues;
 pub use device::VkCreateDeviceError;
+pub use device::PhysicalDeviceInfo;
+pub use device::PhysicalDeviceSelection;
+pub use device::VkPhysicalDeviceReport;
+pub use device::VkUnsuitableDeviceReason;
+
+mod device_features;
+pub use device_features::VkDeviceFeatures;
+pub use device_features::VkDeviceRequirements;
 
 mod swapchain;
 pub use swapchain::VkSwapchain;
@@ -53,6 +62,19 @@ pub use upload::VkUpload;
 pub use upload::VkTransferUploadState;
 pub use upload::VkTransferUpload;
 
+mod queue_ownership;
+pub use queue_ownership::VkQueueOwnershipTransfer;
+
+mod gpu_profiler;
+pub use gpu_profiler::VkGpuProfiler;
+pub use gpu_profiler::VkGpuProfileFrame;
+pub use gpu_profiler::VkGpuProfileScope;
+
+mod memory_budget;
+pub use memory_budget::VkMemoryHeapBudget;
+pub use memory_budget::VkMemoryHeapUsage;
+pub use memory_budget::VkMemoryUsage;
+
 mod debug_reporter;
 pub use debug_reporter::VkDebugReporter;
 
@@ -67,6 +89,10 @@ pub use surface::VkSurface;
 pub use surface::VkSurfaceSwapchainLifetimeListener;
 pub use surface::FrameInFlight;
 
+mod offscreen;
+pub use offscreen::VkOffscreenTarget;
+pub use offscreen::OffscreenFrameInFlight;
+
 mod coordinates;
 pub use coordinates::Size;
 pub use coordinates::LogicalSize;
@@ -0,0 +1,104 @@
+use ash::vk;
+use crate::VkDeviceContext;
+
+/// From VK_EXT_memory_budget, see `VkDeviceContext::memory_heap_budgets()`
+#[derive(Copy, Clone, Debug)]
+pub struct VkMemoryHeapBudget {
+    /// How much memory in the heap the process can use before allocations may fail or perform
+    /// poorly. This changes at runtime based on what other processes are doing.
+    pub budget: vk::DeviceSize,
+
+    /// How much memory in the heap the process is using
+    pub usage: vk::DeviceSize,
+}
+
+/// Memory usage of a single memory heap
+#[derive(Clone, Debug)]
+pub struct VkMemoryHeapUsage {
+    pub heap_index: u32,
+    pub flags: vk::MemoryHeapFlags,
+    pub size: vk::DeviceSize,
+
+    /// Bytes used by vk-mem allocations in this heap
+    pub allocated_bytes: vk::DeviceSize,
+
+    /// Bytes of device memory vk-mem has allocated in this heap. This includes unused space in
+    /// its memory blocks, so it is at least `allocated_bytes`
+    pub block_bytes: vk::DeviceSize,
+
+    /// None if VK_EXT_memory_budget is not enabled
+    pub budget: Option<VkMemoryHeapBudget>,
+}
+
+impl VkMemoryHeapUsage {
+    pub fn is_device_local(&self) -> bool {
+        self.flags.contains(vk::MemoryHeapFlags::DEVICE_LOCAL)
+    }
+
+    /// True if VK_EXT_memory_budget reports the heap using more than its budget
+    pub fn is_over_budget(&self) -> bool {
+        self.budget
+            .map(|budget| budget.usage > budget.budget)
+            .unwrap_or(false)
+    }
+}
+
+/// Memory usage of every memory heap of a device
+#[derive(Clone, Debug, Default)]
+pub struct VkMemoryUsage {
+    pub heaps: Vec<VkMemoryHeapUsage>,
+}
+
+impl VkMemoryUsage {
+    /// Combines vk-mem statistics with VK_EXT_memory_budget (if it's enabled). Calculating the
+    /// vk-mem statistics walks every memory block, so this shouldn't be called more than about
+    /// once per frame.
+    pub fn query(device_context: &VkDeviceContext) -> Self {
+        let memory_properties = &device_context.physical_device_info().memory_properties;
+        let budgets = device_context.memory_heap_budgets();
+
+        let stats = match device_context.allocator().calculate_stats() {
+            Ok(stats) => Some(stats),
+            Err(e) => {
+                log::warn!("Failed to calculate vk-mem statistics: {:?}", e);
+                None
+            }
+        };
+
+        let heaps = (0..memory_properties.memory_heap_count as usize)
+            .map(|heap_index| {
+                let heap = &memory_properties.memory_heaps[heap_index];
+                let (allocated_bytes, block_bytes) = stats
+                    .as_ref()
+                    .map(|stats| {
+                        let heap_stats = &stats.memoryHeap[heap_index];
+                        (
+                            heap_stats.usedBytes,
+                            heap_stats.usedBytes + heap_stats.unusedBytes,
+                        )
+                    })
+                    .unwrap_or((0, 0));
+
+                VkMemoryHeapUsage {
+                    heap_index: heap_index as u32,
+                    flags: heap.flags,
+                    size: heap.size,
+                    allocated_bytes,
+                    block_bytes,
+                    budget: budgets.as_ref().map(|budgets| budgets[heap_index]),
+                }
+            })
+            .collect();
+
+        VkMemoryUsage { heaps }
+    }
+
+    /// Bytes used by vk-mem allocations in all heaps
+    pub fn allocated_bytes(&self) -> vk::DeviceSize {
+        self.heaps.iter().map(|heap| heap.allocated_bytes).sum()
+    }
+
+    pub fn is_over_budget(&self) -> bool {
+        self.heaps.iter().any(|heap| heap.is_over_budget())
+    }
+}
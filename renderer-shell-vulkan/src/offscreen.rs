@@ -0,0 +1,219 @@
+use ash::version::DeviceV1_0;
+use ash::prelude::VkResult;
+
+use std::mem::ManuallyDrop;
+use ash::vk;
+
+use super::VkSwapchain;
+use super::SwapchainInfo;
+use super::RenderpassAttachmentImage;
+use super::MAX_FRAMES_IN_FLIGHT;
+
+use crate::{VkContext, VkDeviceContext, VkImage, MsaaLevel};
+
+/// A frame being rendered into a `VkOffscreenTarget`. This is the headless equivalent of
+/// `FrameInFlight`.
+pub struct OffscreenFrameInFlight {
+    device_context: VkDeviceContext,
+
+    // Stands in for the swapchain image index
+    present_index: u32,
+
+    image: vk::Image,
+    in_flight_fence: vk::Fence,
+}
+
+impl OffscreenFrameInFlight {
+    // Index of the image being rendered into. Can be used to index into per-frame resources
+    pub fn present_index(&self) -> u32 {
+        self.present_index
+    }
+
+    pub fn image(&self) -> vk::Image {
+        self.image
+    }
+
+    // Submit the given command buffers. There's nothing to present, the rendered image is left in
+    // the target. Dropping the frame without submitting is fine.
+    pub fn submit(
+        self,
+        command_buffers: &[vk::CommandBuffer],
+    ) -> VkResult<()> {
+        let submit_info = [vk::SubmitInfo::builder()
+            .command_buffers(command_buffers)
+            .build()];
+
+        // The fence is reset here rather than when acquiring so that a frame that was never
+        // submitted doesn't leave it unsignaled
+        unsafe {
+            let device = self.device_context.device();
+            device.reset_fences(&[self.in_flight_fence])?;
+
+            let queue = self.device_context.queues().graphics_queue.lock().unwrap();
+            device.queue_submit(*queue, &submit_info, self.in_flight_fence)
+        }
+    }
+}
+
+/// Images to render into in place of a swapchain, for use with a headless `VkContext`. This has
+/// the same color/depth attachments as a `VkSwapchain` and describes itself with the same
+/// `SwapchainInfo`, so code that builds renderpasses for a swapchain can be reused. One image is
+/// created per frame in flight and they are used round-robin.
+pub struct VkOffscreenTarget {
+    pub device_context: VkDeviceContext,
+
+    pub swapchain_info: SwapchainInfo,
+
+    // Stand-ins for the swapchain images, one per MAX_FRAMES_IN_FLIGHT
+    pub images: Vec<ManuallyDrop<VkImage>>,
+    pub image_views: Vec<vk::ImageView>,
+
+    pub color_format: vk::Format,
+    pub color_attachment: RenderpassAttachmentImage,
+
+    pub depth_format: vk::Format,
+    pub depth_attachment: RenderpassAttachmentImage,
+
+    // One per MAX_FRAMES_IN_FLIGHT
+    pub in_flight_fences: Vec<vk::Fence>,
+
+    sync_frame_index: usize,
+}
+
+impl VkOffscreenTarget {
+    /// Create a target of the given size. `format` is the format of the images that would
+    /// otherwise be the swapchain images, for example `R8G8B8A8_SRGB`. The MSAA level is chosen
+    /// from the context's priority list as it would be for a swapchain.
+    pub fn new(
+        context: &VkContext,
+        extents: vk::Extent2D,
+        format: vk::Format,
+    ) -> VkResult<VkOffscreenTarget> {
+        let device_context = context.device_context();
+
+        let msaa_level =
+            VkSwapchain::choose_msaa_level(device_context.limits(), context.msaa_level_priority());
+        log::debug!("MSAA level: {:?}", msaa_level);
+
+        let color_format = VkSwapchain::choose_color_format(device_context);
+        log::debug!("Color format: {:?}", color_format);
+
+        let depth_format = VkSwapchain::choose_depth_format(device_context);
+        log::debug!("Depth format: {:?}", depth_format);
+
+        let swapchain_info = SwapchainInfo {
+            surface_format: vk::SurfaceFormatKHR {
+                format,
+                color_space: vk::ColorSpaceKHR::SRGB_NONLINEAR,
+            },
+            // Nothing is presented, but FIFO is what a swapchain is guaranteed to support
+            present_mode: vk::PresentModeKHR::FIFO,
+            extents,
+            image_count: MAX_FRAMES_IN_FLIGHT,
+            msaa_level,
+            color_format,
+            depth_format,
+        };
+
+        let mut images = Vec::with_capacity(MAX_FRAMES_IN_FLIGHT);
+        let mut image_views = Vec::with_capacity(MAX_FRAMES_IN_FLIGHT);
+        for _ in 0..MAX_FRAMES_IN_FLIGHT {
+            let (image, image_view) = RenderpassAttachmentImage::create_image_and_view(
+                device_context,
+                &swapchain_info,
+                format,
+                vk::ImageAspectFlags::COLOR,
+                vk::ImageUsageFlags::COLOR_ATTACHMENT
+                    | vk::ImageUsageFlags::SAMPLED
+                    | vk::ImageUsageFlags::TRANSFER_SRC,
+                MsaaLevel::Sample1,
+            )?;
+
+            images.push(image);
+            image_views.push(image_view);
+        }
+
+        let color_attachment =
+            VkSwapchain::create_color_attachment(device_context, &swapchain_info)?;
+        let depth_attachment =
+            VkSwapchain::create_depth_attachment(device_context, &swapchain_info)?;
+
+        let in_flight_fences = VkSwapchain::allocate_fences_per_frame(device_context)?;
+
+        Ok(VkOffscreenTarget {
+            device_context: device_context.clone(),
+            swapchain_info,
+            images,
+            image_views,
+            color_format,
+            color_attachment,
+            depth_format,
+            depth_attachment,
+            in_flight_fences,
+            sync_frame_index: 0,
+        })
+    }
+
+    /// Waits until the GPU is done with the next image and returns a frame that renders into it
+    pub fn acquire_next_frame(&mut self) -> VkResult<OffscreenFrameInFlight> {
+        let present_index = self.sync_frame_index;
+        self.sync_frame_index = (self.sync_frame_index + 1) % MAX_FRAMES_IN_FLIGHT;
+
+        let frame_fence = self.in_flight_fences[present_index];
+
+        // Wait if the GPU is already processing too many frames
+        unsafe {
+            //TODO: Dont lock up forever (don't use u64::MAX)
+            self.device_context
+                .device()
+                .wait_for_fences(&[frame_fence], true, std::u64::MAX)?;
+        }
+
+        Ok(OffscreenFrameInFlight {
+            device_context: self.device_context.clone(),
+            present_index: present_index as u32,
+            image: self.images[present_index].image(),
+            in_flight_fence: frame_fence,
+        })
+    }
+
+    /// Blocks until all frames submitted to this target have finished rendering
+    pub fn wait_until_idle(&self) -> VkResult<()> {
+        unsafe {
+            self.device_context.device().wait_for_fences(
+                &self.in_flight_fences,
+                true,
+                std::u64::MAX,
+            )
+        }
+    }
+}
+
+impl Drop for VkOffscreenTarget {
+    fn drop(&mut self) {
+        trace!("destroying VkOffscreenTarget");
+
+        // Don't destroy anything the GPU may still be using
+        let _ = self.wait_until_idle();
+
+        unsafe {
+            let device = self.device_context.device();
+            for &fence in self.in_flight_fences.iter() {
+                device.destroy_fence(fence, None);
+            }
+
+            for &image_view in self.image_views.iter() {
+                device.destroy_image_view(image_view, None);
+            }
+
+            for image in self.images.iter_mut() {
+                ManuallyDrop::drop(image);
+            }
+
+            self.color_attachment.destroy(&self.device_context);
+            self.depth_attachment.destroy(&self.device_context);
+        }
+
+        trace!("destroyed VkOffscreenTarget");
+    }
+}
@@ -0,0 +1,573 @@
+use ash::vk;
+use ash::version::DeviceV1_0;
+
+use crate::VkQueueFamilyIndices;
+
+/// Records the barriers needed to hand a buffer or image from one queue family to another, for
+/// example from the async compute queue to the graphics queue. The release barriers are recorded
+/// into a command buffer submitted to the source queue and the acquire barriers into a command
+/// buffer submitted to the destination queue. The destination submit must wait on a semaphore
+/// signaled by the source submit (with the wait stage included in the acquire's `dst_stage`).
+///
+/// If both queue families are the same, no ownership transfer is needed. Releases record nothing
+/// and acquires only record a barrier if an image layout transition is needed. This allows the
+/// same code to run whether or not the device has async compute.
+#[derive(Copy, Clone, Debug)]
+pub struct VkQueueOwnershipTransfer {
+    pub src_queue_family_index: u32,
+    pub dst_queue_family_index: u32,
+}
+
+impl VkQueueOwnershipTransfer {
+    pub fn new(
+        src_queue_family_index: u32,
+        dst_queue_family_index: u32,
+    ) -> Self {
+        VkQueueOwnershipTransfer {
+            src_queue_family_index,
+            dst_queue_family_index,
+        }
+    }
+
+    pub fn compute_to_graphics(queue_family_indices: &VkQueueFamilyIndices) -> Self {
+        Self::new(
+            queue_family_indices.compute_queue_family_index,
+            queue_family_indices.graphics_queue_family_index,
+        )
+    }
+
+    pub fn graphics_to_compute(queue_family_indices: &VkQueueFamilyIndices) -> Self {
+        Self::new(
+            queue_family_indices.graphics_queue_family_index,
+            queue_family_indices.compute_queue_family_index,
+        )
+    }
+
+    /// False if both queue families are the same
+    pub fn is_required(&self) -> bool {
+        self.src_queue_family_index != self.dst_queue_family_index
+    }
+
+    /// Record on the source queue, after the last use of the buffer
+    pub fn cmd_release_buffer(
+        &self,
+        logical_device: &ash::Device,
+        command_buffer: vk::CommandBuffer,
+        buffer: vk::Buffer,
+        src_access_mask: vk::AccessFlags,
+        src_stage: vk::PipelineStageFlags,
+    ) {
+        if let Some((src_stage, dst_stage, buffer_barrier)) =
+            self.release_buffer_barrier(buffer, src_access_mask, src_stage)
+        {
+            Self::cmd_pipeline_barrier(
+                logical_device,
+                command_buffer,
+                src_stage,
+                dst_stage,
+                &[buffer_barrier],
+                &[],
+            );
+        }
+    }
+
+    /// Record on the destination queue, before the first use of the buffer
+    pub fn cmd_acquire_buffer(
+        &self,
+        logical_device: &ash::Device,
+        command_buffer: vk::CommandBuffer,
+        buffer: vk::Buffer,
+        dst_access_mask: vk::AccessFlags,
+        dst_stage: vk::PipelineStageFlags,
+    ) {
+        if let Some((src_stage, dst_stage, buffer_barrier)) =
+            self.acquire_buffer_barrier(buffer, dst_access_mask, dst_stage)
+        {
+            Self::cmd_pipeline_barrier(
+                logical_device,
+                command_buffer,
+                src_stage,
+                dst_stage,
+                &[buffer_barrier],
+                &[],
+            );
+        }
+    }
+
+    /// Record on the source queue, after the last use of the image. The layouts must match the
+    /// ones passed to `cmd_acquire_image`.
+    #[allow(clippy::too_many_arguments)]
+    pub fn cmd_release_image(
+        &self,
+        logical_device: &ash::Device,
+        command_buffer: vk::CommandBuffer,
+        image: vk::Image,
+        subresource_range: vk::ImageSubresourceRange,
+        old_layout: vk::ImageLayout,
+        new_layout: vk::ImageLayout,
+        src_access_mask: vk::AccessFlags,
+        src_stage: vk::PipelineStageFlags,
+    ) {
+        if let Some((src_stage, dst_stage, image_barrier)) = self.release_image_barrier(
+            image,
+            subresource_range,
+            old_layout,
+            new_layout,
+            src_access_mask,
+            src_stage,
+        ) {
+            Self::cmd_pipeline_barrier(
+                logical_device,
+                command_buffer,
+                src_stage,
+                dst_stage,
+                &[],
+                &[image_barrier],
+            );
+        }
+    }
+
+    /// Record on the destination queue, before the first use of the image. The layouts must match
+    /// the ones passed to `cmd_release_image`.
+    #[allow(clippy::too_many_arguments)]
+    pub fn cmd_acquire_image(
+        &self,
+        logical_device: &ash::Device,
+        command_buffer: vk::CommandBuffer,
+        image: vk::Image,
+        subresource_range: vk::ImageSubresourceRange,
+        old_layout: vk::ImageLayout,
+        new_layout: vk::ImageLayout,
+        dst_access_mask: vk::AccessFlags,
+        dst_stage: vk::PipelineStageFlags,
+    ) {
+        if let Some((src_stage, dst_stage, image_barrier)) = self.acquire_image_barrier(
+            image,
+            subresource_range,
+            old_layout,
+            new_layout,
+            dst_access_mask,
+            dst_stage,
+        ) {
+            Self::cmd_pipeline_barrier(
+                logical_device,
+                command_buffer,
+                src_stage,
+                dst_stage,
+                &[],
+                &[image_barrier],
+            );
+        }
+    }
+
+    // The barriers below are returned with the (src, dst) stages to record them with, or None if
+    // nothing needs to be recorded
+
+    fn release_buffer_barrier(
+        &self,
+        buffer: vk::Buffer,
+        src_access_mask: vk::AccessFlags,
+        src_stage: vk::PipelineStageFlags,
+    ) -> Option<(
+        vk::PipelineStageFlags,
+        vk::PipelineStageFlags,
+        vk::BufferMemoryBarrier,
+    )> {
+        if !self.is_required() {
+            return None;
+        }
+
+        // dst access and stage are ignored for a release
+        let buffer_barrier = self.buffer_barrier(buffer, src_access_mask, vk::AccessFlags::empty());
+        Some((
+            src_stage,
+            vk::PipelineStageFlags::BOTTOM_OF_PIPE,
+            buffer_barrier,
+        ))
+    }
+
+    fn acquire_buffer_barrier(
+        &self,
+        buffer: vk::Buffer,
+        dst_access_mask: vk::AccessFlags,
+        dst_stage: vk::PipelineStageFlags,
+    ) -> Option<(
+        vk::PipelineStageFlags,
+        vk::PipelineStageFlags,
+        vk::BufferMemoryBarrier,
+    )> {
+        // With a single queue family, the semaphore wait is all the synchronization needed
+        if !self.is_required() {
+            return None;
+        }
+
+        // src access and stage are ignored for an acquire
+        let buffer_barrier = self.buffer_barrier(buffer, vk::AccessFlags::empty(), dst_access_mask);
+        Some((
+            vk::PipelineStageFlags::TOP_OF_PIPE,
+            dst_stage,
+            buffer_barrier,
+        ))
+    }
+
+    fn release_image_barrier(
+        &self,
+        image: vk::Image,
+        subresource_range: vk::ImageSubresourceRange,
+        old_layout: vk::ImageLayout,
+        new_layout: vk::ImageLayout,
+        src_access_mask: vk::AccessFlags,
+        src_stage: vk::PipelineStageFlags,
+    ) -> Option<(
+        vk::PipelineStageFlags,
+        vk::PipelineStageFlags,
+        vk::ImageMemoryBarrier,
+    )> {
+        if !self.is_required() {
+            return None;
+        }
+
+        let image_barrier = self.image_barrier(
+            image,
+            subresource_range,
+            old_layout,
+            new_layout,
+            src_access_mask,
+            vk::AccessFlags::empty(),
+            self.src_queue_family_index,
+            self.dst_queue_family_index,
+        );
+        Some((
+            src_stage,
+            vk::PipelineStageFlags::BOTTOM_OF_PIPE,
+            image_barrier,
+        ))
+    }
+
+    fn acquire_image_barrier(
+        &self,
+        image: vk::Image,
+        subresource_range: vk::ImageSubresourceRange,
+        old_layout: vk::ImageLayout,
+        new_layout: vk::ImageLayout,
+        dst_access_mask: vk::AccessFlags,
+        dst_stage: vk::PipelineStageFlags,
+    ) -> Option<(
+        vk::PipelineStageFlags,
+        vk::PipelineStageFlags,
+        vk::ImageMemoryBarrier,
+    )> {
+        let (src_stage, src_queue_family_index, dst_queue_family_index) = if self.is_required() {
+            (
+                vk::PipelineStageFlags::TOP_OF_PIPE,
+                self.src_queue_family_index,
+                self.dst_queue_family_index,
+            )
+        } else if old_layout != new_layout {
+            // Nothing was released, so this is a plain layout transition. Using dst_stage as the
+            // src stage chains it after the semaphore wait.
+            (
+                dst_stage,
+                vk::QUEUE_FAMILY_IGNORED,
+                vk::QUEUE_FAMILY_IGNORED,
+            )
+        } else {
+            return None;
+        };
+
+        let image_barrier = self.image_barrier(
+            image,
+            subresource_range,
+            old_layout,
+            new_layout,
+            vk::AccessFlags::empty(),
+            dst_access_mask,
+            src_queue_family_index,
+            dst_queue_family_index,
+        );
+        Some((src_stage, dst_stage, image_barrier))
+    }
+
+    fn buffer_barrier(
+        &self,
+        buffer: vk::Buffer,
+        src_access_mask: vk::AccessFlags,
+        dst_access_mask: vk::AccessFlags,
+    ) -> vk::BufferMemoryBarrier {
+        vk::BufferMemoryBarrier::builder()
+            .src_access_mask(src_access_mask)
+            .dst_access_mask(dst_access_mask)
+            .src_queue_family_index(self.src_queue_family_index)
+            .dst_queue_family_index(self.dst_queue_family_index)
+            .buffer(buffer)
+            .offset(0)
+            .size(vk::WHOLE_SIZE)
+            .build()
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn image_barrier(
+        &self,
+        image: vk::Image,
+        subresource_range: vk::ImageSubresourceRange,
+        old_layout: vk::ImageLayout,
+        new_layout: vk::ImageLayout,
+        src_access_mask: vk::AccessFlags,
+        dst_access_mask: vk::AccessFlags,
+        src_queue_family_index: u32,
+        dst_queue_family_index: u32,
+    ) -> vk::ImageMemoryBarrier {
+        vk::ImageMemoryBarrier::builder()
+            .src_access_mask(src_access_mask)
+            .dst_access_mask(dst_access_mask)
+            .old_layout(old_layout)
+            .new_layout(new_layout)
+            .src_queue_family_index(src_queue_family_index)
+            .dst_queue_family_index(dst_queue_family_index)
+            .image(image)
+            .subresource_range(subresource_range)
+            .build()
+    }
+
+    fn cmd_pipeline_barrier(
+        logical_device: &ash::Device,
+        command_buffer: vk::CommandBuffer,
+        src_stage: vk::PipelineStageFlags,
+        dst_stage: vk::PipelineStageFlags,
+        buffer_barriers: &[vk::BufferMemoryBarrier],
+        image_barriers: &[vk::ImageMemoryBarrier],
+    ) {
+        unsafe {
+            logical_device.cmd_pipeline_barrier(
+                command_buffer,
+                src_stage,
+                dst_stage,
+                vk::DependencyFlags::empty(),
+                &[],
+                buffer_barriers,
+                image_barriers,
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ash::vk::Handle;
+
+    const COMPUTE_QUEUE_FAMILY_INDEX: u32 = 1;
+    const GRAPHICS_QUEUE_FAMILY_INDEX: u32 = 0;
+
+    fn test_subresource_range() -> vk::ImageSubresourceRange {
+        vk::ImageSubresourceRange {
+            aspect_mask: vk::ImageAspectFlags::COLOR,
+            base_mip_level: 0,
+            level_count: 1,
+            base_array_layer: 0,
+            layer_count: 1,
+        }
+    }
+
+    fn transfer(async_compute: bool) -> VkQueueOwnershipTransfer {
+        let compute_queue_family_index = if async_compute {
+            COMPUTE_QUEUE_FAMILY_INDEX
+        } else {
+            GRAPHICS_QUEUE_FAMILY_INDEX
+        };
+
+        VkQueueOwnershipTransfer::compute_to_graphics(&VkQueueFamilyIndices {
+            graphics_queue_family_index: GRAPHICS_QUEUE_FAMILY_INDEX,
+            present_queue_family_index: GRAPHICS_QUEUE_FAMILY_INDEX,
+            transfer_queue_family_index: GRAPHICS_QUEUE_FAMILY_INDEX,
+            compute_queue_family_index,
+        })
+    }
+
+    #[test]
+    fn test_queue_family_directions() {
+        let queue_family_indices = VkQueueFamilyIndices {
+            graphics_queue_family_index: GRAPHICS_QUEUE_FAMILY_INDEX,
+            present_queue_family_index: GRAPHICS_QUEUE_FAMILY_INDEX,
+            transfer_queue_family_index: GRAPHICS_QUEUE_FAMILY_INDEX,
+            compute_queue_family_index: COMPUTE_QUEUE_FAMILY_INDEX,
+        };
+
+        let to_graphics = VkQueueOwnershipTransfer::compute_to_graphics(&queue_family_indices);
+        assert_eq!(
+            to_graphics.src_queue_family_index,
+            COMPUTE_QUEUE_FAMILY_INDEX
+        );
+        assert_eq!(
+            to_graphics.dst_queue_family_index,
+            GRAPHICS_QUEUE_FAMILY_INDEX
+        );
+        assert!(to_graphics.is_required());
+
+        let to_compute = VkQueueOwnershipTransfer::graphics_to_compute(&queue_family_indices);
+        assert_eq!(
+            to_compute.src_queue_family_index,
+            GRAPHICS_QUEUE_FAMILY_INDEX
+        );
+        assert_eq!(
+            to_compute.dst_queue_family_index,
+            COMPUTE_QUEUE_FAMILY_INDEX
+        );
+        assert!(to_compute.is_required());
+
+        assert!(!transfer(false).is_required());
+    }
+
+    #[test]
+    fn test_buffer_transfer() {
+        let transfer = transfer(true);
+        let buffer = vk::Buffer::from_raw(1);
+
+        let (src_stage, dst_stage, barrier) = transfer
+            .release_buffer_barrier(
+                buffer,
+                vk::AccessFlags::SHADER_WRITE,
+                vk::PipelineStageFlags::COMPUTE_SHADER,
+            )
+            .unwrap();
+        assert_eq!(src_stage, vk::PipelineStageFlags::COMPUTE_SHADER);
+        assert_eq!(dst_stage, vk::PipelineStageFlags::BOTTOM_OF_PIPE);
+        assert_eq!(barrier.buffer, buffer);
+        assert_eq!(barrier.src_access_mask, vk::AccessFlags::SHADER_WRITE);
+        assert_eq!(barrier.dst_access_mask, vk::AccessFlags::empty());
+        assert_eq!(barrier.src_queue_family_index, COMPUTE_QUEUE_FAMILY_INDEX);
+        assert_eq!(barrier.dst_queue_family_index, GRAPHICS_QUEUE_FAMILY_INDEX);
+        assert_eq!(barrier.size, vk::WHOLE_SIZE);
+
+        let (src_stage, dst_stage, barrier) = transfer
+            .acquire_buffer_barrier(
+                buffer,
+                vk::AccessFlags::VERTEX_ATTRIBUTE_READ,
+                vk::PipelineStageFlags::VERTEX_INPUT,
+            )
+            .unwrap();
+        assert_eq!(src_stage, vk::PipelineStageFlags::TOP_OF_PIPE);
+        assert_eq!(dst_stage, vk::PipelineStageFlags::VERTEX_INPUT);
+        assert_eq!(barrier.src_access_mask, vk::AccessFlags::empty());
+        assert_eq!(
+            barrier.dst_access_mask,
+            vk::AccessFlags::VERTEX_ATTRIBUTE_READ
+        );
+        assert_eq!(barrier.src_queue_family_index, COMPUTE_QUEUE_FAMILY_INDEX);
+        assert_eq!(barrier.dst_queue_family_index, GRAPHICS_QUEUE_FAMILY_INDEX);
+    }
+
+    #[test]
+    fn test_buffer_transfer_same_queue_family() {
+        let transfer = transfer(false);
+        let buffer = vk::Buffer::from_raw(1);
+
+        assert!(transfer
+            .release_buffer_barrier(
+                buffer,
+                vk::AccessFlags::SHADER_WRITE,
+                vk::PipelineStageFlags::COMPUTE_SHADER,
+            )
+            .is_none());
+        assert!(transfer
+            .acquire_buffer_barrier(
+                buffer,
+                vk::AccessFlags::VERTEX_ATTRIBUTE_READ,
+                vk::PipelineStageFlags::VERTEX_INPUT,
+            )
+            .is_none());
+    }
+
+    #[test]
+    fn test_image_transfer() {
+        let transfer = transfer(true);
+        let image = vk::Image::from_raw(1);
+
+        let (src_stage, dst_stage, release) = transfer
+            .release_image_barrier(
+                image,
+                test_subresource_range(),
+                vk::ImageLayout::GENERAL,
+                vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+                vk::AccessFlags::SHADER_WRITE,
+                vk::PipelineStageFlags::COMPUTE_SHADER,
+            )
+            .unwrap();
+        assert_eq!(src_stage, vk::PipelineStageFlags::COMPUTE_SHADER);
+        assert_eq!(dst_stage, vk::PipelineStageFlags::BOTTOM_OF_PIPE);
+        assert_eq!(release.image, image);
+        assert_eq!(release.src_access_mask, vk::AccessFlags::SHADER_WRITE);
+        assert_eq!(release.dst_access_mask, vk::AccessFlags::empty());
+
+        let (src_stage, dst_stage, acquire) = transfer
+            .acquire_image_barrier(
+                image,
+                test_subresource_range(),
+                vk::ImageLayout::GENERAL,
+                vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+                vk::AccessFlags::SHADER_READ,
+                vk::PipelineStageFlags::FRAGMENT_SHADER,
+            )
+            .unwrap();
+        assert_eq!(src_stage, vk::PipelineStageFlags::TOP_OF_PIPE);
+        assert_eq!(dst_stage, vk::PipelineStageFlags::FRAGMENT_SHADER);
+        assert_eq!(acquire.src_access_mask, vk::AccessFlags::empty());
+        assert_eq!(acquire.dst_access_mask, vk::AccessFlags::SHADER_READ);
+
+        // Both halves of the transfer must describe the same layout transition and queue families
+        for barrier in &[release, acquire] {
+            assert_eq!(barrier.old_layout, vk::ImageLayout::GENERAL);
+            assert_eq!(
+                barrier.new_layout,
+                vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL
+            );
+            assert_eq!(barrier.src_queue_family_index, COMPUTE_QUEUE_FAMILY_INDEX);
+            assert_eq!(barrier.dst_queue_family_index, GRAPHICS_QUEUE_FAMILY_INDEX);
+        }
+    }
+
+    #[test]
+    fn test_image_transfer_same_queue_family() {
+        let transfer = transfer(false);
+        let image = vk::Image::from_raw(1);
+
+        assert!(transfer
+            .release_image_barrier(
+                image,
+                test_subresource_range(),
+                vk::ImageLayout::GENERAL,
+                vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+                vk::AccessFlags::SHADER_WRITE,
+                vk::PipelineStageFlags::COMPUTE_SHADER,
+            )
+            .is_none());
+
+        // The layout transition still has to happen, as a plain barrier after the semaphore wait
+        let (src_stage, dst_stage, acquire) = transfer
+            .acquire_image_barrier(
+                image,
+                test_subresource_range(),
+                vk::ImageLayout::GENERAL,
+                vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+                vk::AccessFlags::SHADER_READ,
+                vk::PipelineStageFlags::FRAGMENT_SHADER,
+            )
+            .unwrap();
+        assert_eq!(src_stage, vk::PipelineStageFlags::FRAGMENT_SHADER);
+        assert_eq!(dst_stage, vk::PipelineStageFlags::FRAGMENT_SHADER);
+        assert_eq!(acquire.src_queue_family_index, vk::QUEUE_FAMILY_IGNORED);
+        assert_eq!(acquire.dst_queue_family_index, vk::QUEUE_FAMILY_IGNORED);
+
+        // Without a layout change there is nothing to record
+        assert!(transfer
+            .acquire_image_barrier(
+                image,
+                test_subresource_range(),
+                vk::ImageLayout::GENERAL,
+                vk::ImageLayout::GENERAL,
+                vk::AccessFlags::SHADER_READ,
+                vk::PipelineStageFlags::FRAGMENT_SHADER,
+            )
+            .is_none());
+    }
+}
@@ -100,6 +100,8 @@ impl RenderpassAttachmentImage {
             vk::ImageTiling::OPTIMAL,
             msaa_level.into(),
             1,
+            1,
+            vk::ImageCreateFlags::empty(),
             vk::MemoryPropertyFlags::DEVICE_LOCAL,
         )?;
 
@@ -153,7 +155,7 @@ impl RenderpassAttachmentImage {
         self.resolved_image_view
     }
 
-    fn destroy(
+    pub(crate) fn destroy(
         &mut self,
         device_context: &VkDeviceContext,
     ) {
@@ -211,7 +213,9 @@ impl VkSwapchain {
             Self::query_swapchain_support(
                 device_context.physical_device(),
                 device_context.surface_loader(),
-                device_context.surface(),
+                device_context
+                    .surface()
+                    .expect("A swapchain can't be created for a headless device"),
             )?;
 
         let surface_format = Self::choose_swapchain_format(&available_formats);
@@ -264,33 +268,8 @@ impl VkSwapchain {
             &swapchain_images,
         )?;
 
-        let color_attachment = RenderpassAttachmentImage::new(
-            device_context,
-            &swapchain_info,
-            color_format,
-            vk::ImageAspectFlags::COLOR,
-            // the msaa image won't actually be sampled, but it's being passed from the debug renderpass to the
-            // composite renderpass with layout ShaderReadOnlyOptimal for the non-msaa case. If msaa is enabled
-            // it will get resolved to the resolved image and we will sample that. If msaa is off, we don't even
-            // create an msaa image
-            vk::ImageUsageFlags::COLOR_ATTACHMENT
-                | vk::ImageUsageFlags::SAMPLED
-                | vk::ImageUsageFlags::TRANSFER_SRC,
-            vk::ImageUsageFlags::COLOR_ATTACHMENT
-                | vk::ImageUsageFlags::SAMPLED
-                | vk::ImageUsageFlags::TRANSFER_DST,
-            msaa_level,
-        )?;
-
-        let depth_attachment = RenderpassAttachmentImage::new(
-            device_context,
-            &swapchain_info,
-            depth_format,
-            vk::ImageAspectFlags::DEPTH,
-            vk::ImageUsageFlags::DEPTH_STENCIL_ATTACHMENT,
-            vk::ImageUsageFlags::DEPTH_STENCIL_ATTACHMENT,
-            msaa_level,
-        )?;
+        let color_attachment = Self::create_color_attachment(device_context, &swapchain_info)?;
+        let depth_attachment = Self::create_depth_attachment(device_context, &swapchain_info)?;
 
         let image_available_semaphores = Self::allocate_semaphores_per_frame(&device_context)?;
         let render_finished_semaphores = Self::allocate_semaphores_per_frame(&device_context)?;
@@ -313,6 +292,44 @@ impl VkSwapchain {
         })
     }
 
+    pub(crate) fn create_color_attachment(
+        device_context: &VkDeviceContext,
+        swapchain_info: &SwapchainInfo,
+    ) -> VkResult<RenderpassAttachmentImage> {
+        RenderpassAttachmentImage::new(
+            device_context,
+            swapchain_info,
+            swapchain_info.color_format,
+            vk::ImageAspectFlags::COLOR,
+            // the msaa image won't actually be sampled, but it's being passed from the debug renderpass to the
+            // composite renderpass with layout ShaderReadOnlyOptimal for the non-msaa case. If msaa is enabled
+            // it will get resolved to the resolved image and we will sample that. If msaa is off, we don't even
+            // create an msaa image
+            vk::ImageUsageFlags::COLOR_ATTACHMENT
+                | vk::ImageUsageFlags::SAMPLED
+                | vk::ImageUsageFlags::TRANSFER_SRC,
+            vk::ImageUsageFlags::COLOR_ATTACHMENT
+                | vk::ImageUsageFlags::SAMPLED
+                | vk::ImageUsageFlags::TRANSFER_DST,
+            swapchain_info.msaa_level,
+        )
+    }
+
+    pub(crate) fn create_depth_attachment(
+        device_context: &VkDeviceContext,
+        swapchain_info: &SwapchainInfo,
+    ) -> VkResult<RenderpassAttachmentImage> {
+        RenderpassAttachmentImage::new(
+            device_context,
+            swapchain_info,
+            swapchain_info.depth_format,
+            vk::ImageAspectFlags::DEPTH,
+            vk::ImageUsageFlags::DEPTH_STENCIL_ATTACHMENT,
+            vk::ImageUsageFlags::DEPTH_STENCIL_ATTACHMENT,
+            swapchain_info.msaa_level,
+        )
+    }
+
     fn query_swapchain_support(
         physical_device: ash::vk::PhysicalDevice,
         surface_loader: &ash::extensions::khr::Surface,
@@ -459,7 +476,7 @@ impl VkSwapchain {
             khr::Swapchain::new(device_context.instance(), device_context.device());
 
         let mut swapchain_create_info = vk::SwapchainCreateInfoKHR::builder()
-            .surface(device_context.surface())
+            .surface(device_context.surface().unwrap())
             .min_image_count(min_image_count)
             .image_format(surface_format.format)
             .image_color_space(surface_format.color_space)
@@ -542,7 +559,7 @@ impl VkSwapchain {
         Ok(image_views)
     }
 
-    fn choose_msaa_level(
+    pub(crate) fn choose_msaa_level(
         limits: &vk::PhysicalDeviceLimits,
         msaa_level_priority: &[MsaaLevel],
     ) -> MsaaLevel {
@@ -593,7 +610,7 @@ impl VkSwapchain {
         None
     }
 
-    fn choose_color_format(device_context: &VkDeviceContext) -> vk::Format {
+    pub(crate) fn choose_color_format(device_context: &VkDeviceContext) -> vk::Format {
         let format = Self::find_supported_format(
             device_context.instance(),
             device_context.physical_device(),
@@ -610,7 +627,7 @@ impl VkSwapchain {
         format
     }
 
-    fn choose_depth_format(device_context: &VkDeviceContext) -> vk::Format {
+    pub(crate) fn choose_depth_format(device_context: &VkDeviceContext) -> vk::Format {
         let format = Self::find_supported_format(
             device_context.instance(),
             device_context.physical_device(),
@@ -646,7 +663,9 @@ impl VkSwapchain {
         Ok(semaphores)
     }
 
-    fn allocate_fences_per_frame(device_context: &VkDeviceContext) -> VkResult<Vec<vk::Fence>> {
+    pub(crate) fn allocate_fences_per_frame(
+        device_context: &VkDeviceContext
+    ) -> VkResult<Vec<vk::Fence>> {
         let mut fences = Vec::with_capacity(MAX_FRAMES_IN_FLIGHT);
         for _ in 0..MAX_FRAMES_IN_FLIGHT {
             let fence_create_info =
@@ -0,0 +1,100 @@
+use glam::{Mat4, Vec3};
+
+/// An axis-aligned bounding box in world space
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Aabb {
+    pub min: Vec3,
+    pub max: Vec3,
+}
+
+impl Aabb {
+    pub fn new(
+        min: Vec3,
+        max: Vec3,
+    ) -> Self {
+        debug_assert!(min.x() <= max.x() && min.y() <= max.y() && min.z() <= max.z());
+        Aabb { min, max }
+    }
+
+    pub fn from_center_half_extents(
+        center: Vec3,
+        half_extents: Vec3,
+    ) -> Self {
+        Aabb::new(center - half_extents, center + half_extents)
+    }
+
+    pub fn center(&self) -> Vec3 {
+        (self.min + self.max) * 0.5
+    }
+
+    pub fn half_extents(&self) -> Vec3 {
+        (self.max - self.min) * 0.5
+    }
+
+    pub fn union(
+        &self,
+        other: &Aabb,
+    ) -> Aabb {
+        Aabb {
+            min: self.min.min(other.min),
+            max: self.max.max(other.max),
+        }
+    }
+
+    pub fn grow_to_include(
+        &self,
+        point: Vec3,
+    ) -> Aabb {
+        Aabb {
+            min: self.min.min(point),
+            max: self.max.max(point),
+        }
+    }
+
+    pub fn surface_area(&self) -> f32 {
+        let size = self.max - self.min;
+        2.0 * (size.x() * size.y() + size.y() * size.z() + size.z() * size.x())
+    }
+
+    pub fn corners(&self) -> [Vec3; 8] {
+        let (min, max) = (self.min, self.max);
+        [
+            Vec3::new(min.x(), min.y(), min.z()),
+            Vec3::new(max.x(), min.y(), min.z()),
+            Vec3::new(min.x(), max.y(), min.z()),
+            Vec3::new(max.x(), max.y(), min.z()),
+            Vec3::new(min.x(), min.y(), max.z()),
+            Vec3::new(max.x(), min.y(), max.z()),
+            Vec3::new(min.x(), max.y(), max.z()),
+            Vec3::new(max.x(), max.y(), max.z()),
+        ]
+    }
+
+    /// The AABB enclosing this AABB after it is transformed by the given matrix
+    pub fn transform(
+        &self,
+        transform: Mat4,
+    ) -> Aabb {
+        let corners = self.corners();
+        let first = transform.transform_point3(corners[0]);
+        corners[1..]
+            .iter()
+            .fold(Aabb::new(first, first), |aabb, corner| {
+                aabb.grow_to_include(transform.transform_point3(*corner))
+            })
+    }
+
+    pub fn bounding_sphere(&self) -> BoundingSphere {
+        BoundingSphere {
+            center: self.center(),
+            radius: self.half_extents().length(),
+        }
+    }
+}
+
+/// A sphere in world space. Cheaper to test than an AABB, but usually a looser fit
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct BoundingSphere {
+    pub center: Vec3,
+    pub radius: f32,
+}
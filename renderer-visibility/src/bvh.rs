@@ -0,0 +1,771 @@
+//! A bounding volume hierarchy for culling large numbers of objects that rarely move.
+//!
+//! The tree is built top-down with a binned surface area heuristic (SAH). Nodes are stored in a
+//! flat vec in pre-order, so children always have a higher index than their parent. This lets
+//! a refit walk the nodes in reverse without recursion.
+//!
+//! Insertion and removal are batched:
+//! * Inserted primitives go into a pending list that is tested linearly until there are enough of
+//!   them to justify a rebuild
+//! * Removed primitives are tombstoned and the tree is refit. Once enough tombstones accumulate,
+//!   the tree is rebuilt
+
+use std::hash::Hash;
+use fnv::FnvHashMap;
+use glam::Vec3;
+use renderer_nodes::{GenericRenderNodeHandle, VisibilityResult};
+use crate::{Aabb, BoundingSphere, Frustum, LodCalculator, LodScreenSizeThresholds, OcclusionBuffer};
+
+const SAH_BIN_COUNT: usize = 12;
+
+/// Cost of visiting an interior node, relative to the cost of testing a single primitive
+const SAH_TRAVERSAL_COST: f32 = 1.0;
+
+/// Leaves at or below this size are never split further
+const MAX_LEAF_PRIMITIVE_COUNT: usize = 4;
+
+/// Don't rebuild the tree for pending insertions until there are at least this many
+const MIN_PENDING_PRIMITIVES_BEFORE_REBUILD: usize = 64;
+
+/// Don't rebuild the tree for tombstoned primitives until there are at least this many
+const MIN_REMOVED_PRIMITIVES_BEFORE_REBUILD: usize = 64;
+
+pub struct BvhPrimitive<K> {
+    pub key: K,
+    pub handle: GenericRenderNodeHandle,
+    pub aabb: Aabb,
+    pub bounding_sphere: BoundingSphere,
+    pub lod_thresholds: LodScreenSizeThresholds,
+}
+
+#[derive(Copy, Clone, Debug)]
+struct BvhNode {
+    // None if every primitive under this node has been removed
+    bounds: Option<Aabb>,
+
+    // For interior nodes, the index of the left child (the right child immediately follows it).
+    // For leaves, the first index into primitive_indices
+    left_or_first: u32,
+
+    // Non-zero for leaves
+    primitive_count: u32,
+}
+
+impl BvhNode {
+    fn is_leaf(&self) -> bool {
+        self.primitive_count > 0
+    }
+}
+
+struct SahSplit {
+    axis: usize,
+    position: f32,
+    cost: f32,
+}
+
+pub struct Bvh<K> {
+    // Removed primitives are left as None until the next rebuild
+    primitives: Vec<Option<BvhPrimitive<K>>>,
+
+    // Leaves reference contiguous ranges of this list
+    primitive_indices: Vec<u32>,
+    nodes: Vec<BvhNode>,
+
+    // Primitives that have been inserted but are not in the tree yet
+    pending_primitives: Vec<u32>,
+
+    // Primitives that have been removed but are still referenced by the tree
+    removed_primitive_count: usize,
+
+    key_to_primitive: FnvHashMap<K, u32>,
+}
+
+impl<K> Default for Bvh<K>
+where
+    K: Copy + Eq + Hash,
+{
+    fn default() -> Self {
+        Bvh {
+            primitives: Default::default(),
+            primitive_indices: Default::default(),
+            nodes: Default::default(),
+            pending_primitives: Default::default(),
+            removed_primitive_count: 0,
+            key_to_primitive: Default::default(),
+        }
+    }
+}
+
+impl<K> Bvh<K>
+where
+    K: Copy + Eq + Hash,
+{
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Number of primitives that have been inserted and not removed
+    pub fn primitive_count(&self) -> usize {
+        self.key_to_primitive.len()
+    }
+
+    pub fn node_count(&self) -> usize {
+        self.nodes.len()
+    }
+
+    pub fn pending_primitive_count(&self) -> usize {
+        self.pending_primitives.len()
+    }
+
+    /// Add primitives. They are immediately visible to queries, but may not be placed in the tree
+    /// until a later rebuild.
+    pub fn insert_batch<I: IntoIterator<Item = BvhPrimitive<K>>>(
+        &mut self,
+        primitives: I,
+    ) {
+        for primitive in primitives {
+            let primitive_index = self.primitives.len() as u32;
+            let previous = self.key_to_primitive.insert(primitive.key, primitive_index);
+            assert!(previous.is_none(), "key was inserted into the bvh twice");
+
+            self.primitives.push(Some(primitive));
+            self.pending_primitives.push(primitive_index);
+        }
+
+        let rebuild_threshold =
+            MIN_PENDING_PRIMITIVES_BEFORE_REBUILD.max(self.primitive_indices.len() / 8);
+        if self.pending_primitives.len() > rebuild_threshold {
+            self.rebuild();
+        }
+    }
+
+    /// Remove primitives. It is fatal to remove a key that is not in the tree.
+    pub fn remove_batch<I: IntoIterator<Item = K>>(
+        &mut self,
+        keys: I,
+    ) {
+        let mut removed_from_tree = false;
+        for key in keys {
+            let primitive_index = self
+                .key_to_primitive
+                .remove(&key)
+                .expect("tried to remove a key that is not in the bvh");
+            self.primitives[primitive_index as usize] = None;
+
+            if let Some(pending_index) = self
+                .pending_primitives
+                .iter()
+                .position(|x| *x == primitive_index)
+            {
+                self.pending_primitives.swap_remove(pending_index);
+            } else {
+                self.removed_primitive_count += 1;
+                removed_from_tree = true;
+            }
+        }
+
+        let rebuild_threshold =
+            MIN_REMOVED_PRIMITIVES_BEFORE_REBUILD.max(self.primitive_indices.len() / 4);
+        if self.removed_primitive_count > rebuild_threshold {
+            self.rebuild();
+        } else if removed_from_tree {
+            self.refit();
+        }
+    }
+
+    /// Change the bounds of a primitive. The tree is not updated until `refit` or `rebuild` is
+    /// called, so batch up all changes and refit once.
+    pub fn update_bounds(
+        &mut self,
+        key: K,
+        aabb: Aabb,
+    ) {
+        let primitive_index = self.key_to_primitive[&key];
+        let primitive = self.primitives[primitive_index as usize].as_mut().unwrap();
+        primitive.aabb = aabb;
+        primitive.bounding_sphere = aabb.bounding_sphere();
+    }
+
+    /// Recalculate node bounds bottom-up without changing the tree's topology. This is much
+    /// cheaper than a rebuild, but the tree quality degrades if primitives move far.
+    pub fn refit(&mut self) {
+        for node_index in (0..self.nodes.len()).rev() {
+            let node = self.nodes[node_index];
+            let bounds = if node.is_leaf() {
+                let first = node.left_or_first as usize;
+                let last = first + node.primitive_count as usize;
+                self.primitive_indices[first..last]
+                    .iter()
+                    .filter_map(|primitive_index| {
+                        self.primitives[*primitive_index as usize].as_ref()
+                    })
+                    .fold(None, |bounds, primitive| {
+                        union_optional(bounds, Some(primitive.aabb))
+                    })
+            } else {
+                let left = self.nodes[node.left_or_first as usize].bounds;
+                let right = self.nodes[node.left_or_first as usize + 1].bounds;
+                union_optional(left, right)
+            };
+
+            self.nodes[node_index].bounds = bounds;
+        }
+    }
+
+    /// Rebuild the whole tree, including pending primitives, and compact out removed primitives
+    pub fn rebuild(&mut self) {
+        let primitives: Vec<_> = self.primitives.drain(..).filter(|x| x.is_some()).collect();
+        self.primitives = primitives;
+
+        self.key_to_primitive.clear();
+        for (primitive_index, primitive) in self.primitives.iter().enumerate() {
+            self.key_to_primitive
+                .insert(primitive.as_ref().unwrap().key, primitive_index as u32);
+        }
+
+        self.pending_primitives.clear();
+        self.removed_primitive_count = 0;
+        self.primitive_indices = (0..self.primitives.len() as u32).collect();
+        self.nodes.clear();
+
+        if self.primitives.is_empty() {
+            return;
+        }
+
+        let centroids: Vec<Vec3> = self
+            .primitives
+            .iter()
+            .map(|primitive| primitive.as_ref().unwrap().aabb.center())
+            .collect();
+
+        self.nodes.push(BvhNode {
+            bounds: None,
+            left_or_first: 0,
+            primitive_count: self.primitives.len() as u32,
+        });
+
+        let mut node_stack = vec![0];
+        while let Some(node_index) = node_stack.pop() {
+            if let Some(left_child_index) = self.subdivide(node_index, &centroids) {
+                node_stack.push(left_child_index);
+                node_stack.push(left_child_index + 1);
+            }
+        }
+
+        log::trace!(
+            "Rebuilt bvh with {} primitives and {} nodes",
+            self.primitives.len(),
+            self.nodes.len()
+        );
+    }
+
+    // Calculates the node's bounds and splits it if the SAH says it's worthwhile. Returns the
+    // index of the left child if the node was split.
+    fn subdivide(
+        &mut self,
+        node_index: usize,
+        centroids: &[Vec3],
+    ) -> Option<usize> {
+        let first = self.nodes[node_index].left_or_first as usize;
+        let count = self.nodes[node_index].primitive_count as usize;
+
+        let mut bounds = self.primitive(self.primitive_indices[first]).aabb;
+        let first_centroid = centroids[self.primitive_indices[first] as usize];
+        let mut centroid_bounds = Aabb {
+            min: first_centroid,
+            max: first_centroid,
+        };
+
+        for primitive_index in &self.primitive_indices[first..first + count] {
+            bounds = bounds.union(&self.primitive(*primitive_index).aabb);
+            centroid_bounds = centroid_bounds.grow_to_include(centroids[*primitive_index as usize]);
+        }
+
+        self.nodes[node_index].bounds = Some(bounds);
+
+        if count <= MAX_LEAF_PRIMITIVE_COUNT {
+            return None;
+        }
+
+        let parent_area = bounds.surface_area();
+        let split = self.find_sah_split(first, count, parent_area, &centroid_bounds, centroids)?;
+
+        // Intersection cost of each primitive is 1, scaled by the area like the split cost
+        let leaf_cost = count as f32 * parent_area;
+        if split.cost >= leaf_cost {
+            return None;
+        }
+
+        // Partition the primitives in place on either side of the split plane
+        let mut i = first;
+        let mut j = first + count;
+        while i < j {
+            let centroid = centroids[self.primitive_indices[i] as usize];
+            if axis_value(centroid, split.axis) < split.position {
+                i += 1;
+            } else {
+                j -= 1;
+                self.primitive_indices.swap(i, j);
+            }
+        }
+
+        let left_count = i - first;
+        if left_count == 0 || left_count == count {
+            return None;
+        }
+
+        let left_child_index = self.nodes.len();
+        self.nodes.push(BvhNode {
+            bounds: None,
+            left_or_first: first as u32,
+            primitive_count: left_count as u32,
+        });
+        self.nodes.push(BvhNode {
+            bounds: None,
+            left_or_first: (first + left_count) as u32,
+            primitive_count: (count - left_count) as u32,
+        });
+
+        let node = &mut self.nodes[node_index];
+        node.left_or_first = left_child_index as u32;
+        node.primitive_count = 0;
+
+        Some(left_child_index)
+    }
+
+    // Bins primitives by centroid along each axis and returns the cheapest split. The returned
+    // cost is not normalized by the parent's surface area.
+    fn find_sah_split(
+        &self,
+        first: usize,
+        count: usize,
+        parent_area: f32,
+        centroid_bounds: &Aabb,
+        centroids: &[Vec3],
+    ) -> Option<SahSplit> {
+        let mut best_split: Option<SahSplit> = None;
+
+        for axis in 0..3 {
+            let axis_min = axis_value(centroid_bounds.min, axis);
+            let axis_extent = axis_value(centroid_bounds.max, axis) - axis_min;
+            if axis_extent <= 0.0 {
+                continue;
+            }
+
+            let mut bin_bounds: [Option<Aabb>; SAH_BIN_COUNT] = [None; SAH_BIN_COUNT];
+            let mut bin_counts = [0usize; SAH_BIN_COUNT];
+
+            let bin_scale = SAH_BIN_COUNT as f32 / axis_extent;
+            for primitive_index in &self.primitive_indices[first..first + count] {
+                let centroid = axis_value(centroids[*primitive_index as usize], axis);
+                let bin = (((centroid - axis_min) * bin_scale) as usize).min(SAH_BIN_COUNT - 1);
+                let aabb = self.primitive(*primitive_index).aabb;
+
+                bin_counts[bin] += 1;
+                bin_bounds[bin] = union_optional(bin_bounds[bin], Some(aabb));
+            }
+
+            // Sweep from the right to get the area/count of everything right of each split plane
+            let mut right_areas = [0.0; SAH_BIN_COUNT];
+            let mut right_counts = [0usize; SAH_BIN_COUNT];
+            let mut accumulated_bounds: Option<Aabb> = None;
+            let mut accumulated_count = 0;
+            for bin in (1..SAH_BIN_COUNT).rev() {
+                accumulated_bounds = union_optional(accumulated_bounds, bin_bounds[bin]);
+                accumulated_count += bin_counts[bin];
+                right_areas[bin] = accumulated_bounds.map_or(0.0, |bounds| bounds.surface_area());
+                right_counts[bin] = accumulated_count;
+            }
+
+            // Sweep from the left, evaluating the split plane after each bin
+            let mut accumulated_bounds: Option<Aabb> = None;
+            let mut accumulated_count = 0;
+            for bin in 0..SAH_BIN_COUNT - 1 {
+                accumulated_bounds = union_optional(accumulated_bounds, bin_bounds[bin]);
+                accumulated_count += bin_counts[bin];
+
+                let right_count = right_counts[bin + 1];
+                if accumulated_count == 0 || right_count == 0 {
+                    continue;
+                }
+
+                let left_area = accumulated_bounds.map_or(0.0, |bounds| bounds.surface_area());
+                let cost = SAH_TRAVERSAL_COST * parent_area
+                    + left_area * accumulated_count as f32
+                    + right_areas[bin + 1] * right_count as f32;
+
+                let is_best_split = match &best_split {
+                    Some(best_split) => cost < best_split.cost,
+                    None => true,
+                };
+
+                if is_best_split {
+                    best_split = Some(SahSplit {
+                        axis,
+                        position: axis_min + axis_extent * (bin + 1) as f32 / SAH_BIN_COUNT as f32,
+                        cost,
+                    });
+                }
+            }
+        }
+
+        best_split
+    }
+
+    fn primitive(
+        &self,
+        primitive_index: u32,
+    ) -> &BvhPrimitive<K> {
+        self.primitives[primitive_index as usize].as_ref().unwrap()
+    }
+
+    /// Find primitives in the frustum. If an occlusion buffer is given, nodes and primitives it
+    /// hides are skipped too.
+    pub fn query_frustum(
+        &self,
+        frustum: &Frustum,
+        lod_calculator: &LodCalculator,
+        occlusion_buffer: Option<&OcclusionBuffer>,
+    ) -> VisibilityResult {
+        let is_visible = |aabb: &Aabb| match occlusion_buffer {
+            Some(occlusion_buffer) => occlusion_buffer.is_aabb_visible(aabb),
+            None => true,
+        };
+
+        let mut result = VisibilityResult::default();
+
+        if !self.nodes.is_empty() {
+            // If a node is entirely inside the frustum, so is everything under it
+            let mut node_stack = vec![(0, false)];
+            while let Some((node_index, parent_fully_inside)) = node_stack.pop() {
+                let node = &self.nodes[node_index as usize];
+                let bounds = match &node.bounds {
+                    Some(bounds) => bounds,
+                    None => continue,
+                };
+
+                let fully_inside = if parent_fully_inside {
+                    true
+                } else if frustum.intersects_aabb(bounds) {
+                    frustum.contains_aabb(bounds)
+                } else {
+                    continue;
+                };
+
+                if !is_visible(bounds) {
+                    continue;
+                }
+
+                if node.is_leaf() {
+                    let first = node.left_or_first as usize;
+                    let last = first + node.primitive_count as usize;
+                    for primitive_index in &self.primitive_indices[first..last] {
+                        if let Some(primitive) = &self.primitives[*primitive_index as usize] {
+                            if (fully_inside
+                                || frustum
+                                    .intersects_bounds(&primitive.aabb, &primitive.bounding_sphere))
+                                && is_visible(&primitive.aabb)
+                            {
+                                result.visible_nodes.push(lod_calculator.visible_node(
+                                    primitive.handle,
+                                    &primitive.bounding_sphere,
+                                    &primitive.lod_thresholds,
+                                ));
+                            }
+                        }
+                    }
+                } else {
+                    node_stack.push((node.left_or_first, fully_inside));
+                    node_stack.push((node.left_or_first + 1, fully_inside));
+                }
+            }
+        }
+
+        for primitive_index in &self.pending_primitives {
+            let primitive = self.primitive(*primitive_index);
+            if frustum.intersects_bounds(&primitive.aabb, &primitive.bounding_sphere)
+                && is_visible(&primitive.aabb)
+            {
+                result.visible_nodes.push(lod_calculator.visible_node(
+                    primitive.handle,
+                    &primitive.bounding_sphere,
+                    &primitive.lod_thresholds,
+                ));
+            }
+        }
+
+        result
+    }
+}
+
+fn axis_value(
+    v: Vec3,
+    axis: usize,
+) -> f32 {
+    match axis {
+        0 => v.x(),
+        1 => v.y(),
+        _ => v.z(),
+    }
+}
+
+fn union_optional(
+    a: Option<Aabb>,
+    b: Option<Aabb>,
+) -> Option<Aabb> {
+    match (a, b) {
+        (Some(a), Some(b)) => Some(a.union(&b)),
+        (a, b) => a.or(b),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use glam::Mat4;
+    use crate::OccluderMesh;
+    use rand::{Rng, SeedableRng};
+    use rand::rngs::StdRng;
+
+    fn random_aabb(rng: &mut StdRng) -> Aabb {
+        let center = Vec3::new(
+            rng.gen_range(-100.0, 100.0),
+            rng.gen_range(-100.0, 100.0),
+            rng.gen_range(-100.0, 100.0),
+        );
+        let half_extents = Vec3::new(
+            rng.gen_range(0.0, 5.0),
+            rng.gen_range(0.0, 5.0),
+            rng.gen_range(0.0, 5.0),
+        );
+        Aabb::from_center_half_extents(center, half_extents)
+    }
+
+    fn random_view(rng: &mut StdRng) -> (Frustum, LodCalculator) {
+        let eye = Vec3::new(
+            rng.gen_range(-120.0, 120.0),
+            rng.gen_range(-120.0, 120.0),
+            rng.gen_range(-120.0, 120.0),
+        );
+        let target = Vec3::new(
+            rng.gen_range(-50.0, 50.0),
+            rng.gen_range(-50.0, 50.0),
+            rng.gen_range(-50.0, 50.0),
+        );
+        let view = Mat4::look_at_rh(eye, target, Vec3::unit_z());
+        let proj = Mat4::perspective_rh_gl(
+            rng.gen_range(0.3, 2.0),
+            rng.gen_range(0.5, 2.0),
+            0.1,
+            rng.gen_range(10.0, 300.0),
+        );
+        (
+            Frustum::from_view_projection(proj * view),
+            LodCalculator::new(eye, proj),
+        )
+    }
+
+    fn random_primitive(
+        rng: &mut StdRng,
+        key: u32,
+    ) -> BvhPrimitive<u32> {
+        let aabb = random_aabb(rng);
+        BvhPrimitive {
+            key,
+            handle: GenericRenderNodeHandle::new(0, key),
+            aabb,
+            bounding_sphere: aabb.bounding_sphere(),
+            lod_thresholds: Default::default(),
+        }
+    }
+
+    fn sorted_indices(result: &VisibilityResult) -> Vec<u32> {
+        let mut indices: Vec<_> = result
+            .visible_nodes
+            .iter()
+            .map(|visible_node| visible_node.handle.render_node_index())
+            .collect();
+        indices.sort();
+        indices
+    }
+
+    fn brute_force(
+        aabbs: &FnvHashMap<u32, Aabb>,
+        frustum: &Frustum,
+    ) -> Vec<u32> {
+        let mut indices: Vec<_> = aabbs
+            .iter()
+            .filter(|(_, aabb)| frustum.intersects_bounds(aabb, &aabb.bounding_sphere()))
+            .map(|(key, _)| *key)
+            .collect();
+        indices.sort();
+        indices
+    }
+
+    fn assert_matches_brute_force(
+        rng: &mut StdRng,
+        bvh: &Bvh<u32>,
+        aabbs: &FnvHashMap<u32, Aabb>,
+    ) {
+        for _ in 0..50 {
+            let (frustum, lod_calculator) = random_view(rng);
+            assert_eq!(
+                brute_force(aabbs, &frustum),
+                sorted_indices(&bvh.query_frustum(&frustum, &lod_calculator, None))
+            );
+        }
+    }
+
+    #[test]
+    fn test_empty() {
+        let bvh = Bvh::<u32>::new();
+        let mut rng = StdRng::seed_from_u64(0);
+        let (frustum, lod_calculator) = random_view(&mut rng);
+        assert!(bvh
+            .query_frustum(&frustum, &lod_calculator, None)
+            .visible_nodes
+            .is_empty());
+    }
+
+    #[test]
+    fn test_random_scene_matches_brute_force() {
+        let mut rng = StdRng::seed_from_u64(1);
+        for _ in 0..10 {
+            let mut bvh = Bvh::new();
+            let mut aabbs = FnvHashMap::default();
+            let primitive_count = rng.gen_range(1, 2000);
+            let primitives: Vec<_> = (0..primitive_count)
+                .map(|key| random_primitive(&mut rng, key))
+                .collect();
+            for primitive in &primitives {
+                aabbs.insert(primitive.key, primitive.aabb);
+            }
+
+            bvh.insert_batch(primitives);
+            bvh.rebuild();
+            assert_eq!(0, bvh.pending_primitive_count());
+            assert_matches_brute_force(&mut rng, &bvh, &aabbs);
+        }
+    }
+
+    #[test]
+    fn test_pending_insertions_match_brute_force() {
+        let mut rng = StdRng::seed_from_u64(2);
+        let mut bvh = Bvh::new();
+        let mut aabbs = FnvHashMap::default();
+
+        // Small batches stay pending for a while, then trigger rebuilds
+        let mut next_key = 0;
+        for _ in 0..50 {
+            let primitives: Vec<_> = (0..rng.gen_range(1, 40))
+                .map(|_| {
+                    next_key += 1;
+                    random_primitive(&mut rng, next_key)
+                })
+                .collect();
+            for primitive in &primitives {
+                aabbs.insert(primitive.key, primitive.aabb);
+            }
+
+            bvh.insert_batch(primitives);
+            assert_eq!(aabbs.len(), bvh.primitive_count());
+            assert_matches_brute_force(&mut rng, &bvh, &aabbs);
+        }
+    }
+
+    #[test]
+    fn test_batched_removal_matches_brute_force() {
+        let mut rng = StdRng::seed_from_u64(3);
+        let mut bvh = Bvh::new();
+        let mut aabbs = FnvHashMap::default();
+
+        let primitives: Vec<_> = (0..3000)
+            .map(|key| random_primitive(&mut rng, key))
+            .collect();
+        for primitive in &primitives {
+            aabbs.insert(primitive.key, primitive.aabb);
+        }
+        bvh.insert_batch(primitives);
+
+        // Remove in batches of varying size so that both the refit and rebuild paths are hit
+        while !aabbs.is_empty() {
+            let batch_size = rng.gen_range(1, 300).min(aabbs.len());
+            let keys: Vec<u32> = aabbs.keys().take(batch_size).cloned().collect();
+            for key in &keys {
+                aabbs.remove(key);
+            }
+
+            bvh.remove_batch(keys);
+            assert_eq!(aabbs.len(), bvh.primitive_count());
+            assert_matches_brute_force(&mut rng, &bvh, &aabbs);
+        }
+    }
+
+    #[test]
+    fn test_refit_matches_brute_force() {
+        let mut rng = StdRng::seed_from_u64(4);
+        let mut bvh = Bvh::new();
+        let mut aabbs = FnvHashMap::default();
+
+        let primitives: Vec<_> = (0..1000)
+            .map(|key| random_primitive(&mut rng, key))
+            .collect();
+        for primitive in &primitives {
+            aabbs.insert(primitive.key, primitive.aabb);
+        }
+        bvh.insert_batch(primitives);
+        bvh.rebuild();
+
+        for key in 0..1000 {
+            if rng.gen_bool(0.3) {
+                let aabb = random_aabb(&mut rng);
+                aabbs.insert(key, aabb);
+                bvh.update_bounds(key, aabb);
+            }
+        }
+
+        bvh.refit();
+        assert_matches_brute_force(&mut rng, &bvh, &aabbs);
+    }
+
+    #[test]
+    fn test_occlusion_matches_brute_force() {
+        let mut rng = StdRng::seed_from_u64(5);
+        let mut bvh = Bvh::new();
+        let primitives: Vec<_> = (0..2000)
+            .map(|key| random_primitive(&mut rng, key))
+            .collect();
+        let aabbs: Vec<_> = primitives
+            .iter()
+            .map(|primitive| (primitive.key, primitive.aabb))
+            .collect();
+        bvh.insert_batch(primitives);
+        bvh.rebuild();
+
+        let eye = Vec3::new(0.0, 0.0, 200.0);
+        let view = Mat4::look_at_rh(eye, Vec3::zero(), Vec3::unit_y());
+        let proj = Mat4::perspective_rh_gl(1.0, 1.5, 0.1, 500.0);
+        let frustum = Frustum::from_view_projection(proj * view);
+        let lod_calculator = LodCalculator::new(eye, proj);
+
+        let mut occlusion_buffer = OcclusionBuffer::new(Default::default(), proj * view);
+        let wall = Aabb::new(Vec3::new(-60.0, -40.0, 110.0), Vec3::new(40.0, 60.0, 112.0));
+        occlusion_buffer.rasterize_occluder(&OccluderMesh::from_aabb(&wall), Mat4::identity());
+        occlusion_buffer.build_hierarchy();
+
+        let mut expected: Vec<_> = aabbs
+            .iter()
+            .filter(|(_, aabb)| {
+                frustum.intersects_bounds(aabb, &aabb.bounding_sphere())
+                    && occlusion_buffer.is_aabb_visible(aabb)
+            })
+            .map(|(key, _)| *key)
+            .collect();
+        expected.sort();
+
+        let unoccluded = bvh.query_frustum(&frustum, &lod_calculator, None);
+        let occluded = bvh.query_frustum(&frustum, &lod_calculator, Some(&occlusion_buffer));
+        assert!(occluded.visible_nodes.len() < unoccluded.visible_nodes.len());
+        assert_eq!(expected, sorted_indices(&occluded));
+    }
+}
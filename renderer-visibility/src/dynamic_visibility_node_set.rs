@@ -1,4 +1,4 @@
-use renderer_base::slab::RawSlab;
+use renderer_base::slab::{RawSlab, RawSlabKey};
 use renderer_nodes::RenderView;
 use renderer_nodes::VisibilityResult;
 use crate::*;
@@ -6,36 +6,94 @@ use crate::*;
 #[derive(Default)]
 pub struct DynamicVisibilityNodeSet {
     dynamic_aabb: RawSlab<DynamicAabbVisibilityNode>,
+    octree: LooseOctree<RawSlabKey<DynamicAabbVisibilityNode>>,
 }
 
 impl DynamicVisibilityNodeSet {
+    pub fn new(octree_config: LooseOctreeConfig) -> Self {
+        DynamicVisibilityNodeSet {
+            dynamic_aabb: Default::default(),
+            octree: LooseOctree::new(octree_config),
+        }
+    }
+
     pub fn register_dynamic_aabb(
         &mut self,
         node: DynamicAabbVisibilityNode,
     ) -> DynamicAabbVisibilityNodeHandle {
-        //TODO: Insert into spatial structure?
-        DynamicAabbVisibilityNodeHandle(self.dynamic_aabb.allocate(node))
+        let handle = node.handle;
+        let aabb = node.aabb;
+        let bounding_sphere = node.bounding_sphere;
+        let lod_thresholds = node.lod_thresholds;
+
+        let key = self.dynamic_aabb.allocate(node);
+        self.octree.insert(LooseOctreeObject {
+            key,
+            handle,
+            aabb,
+            bounding_sphere,
+            lod_thresholds,
+        });
+
+        DynamicAabbVisibilityNodeHandle(key)
     }
 
     pub fn unregister_dynamic_aabb(
         &mut self,
         handle: DynamicAabbVisibilityNodeHandle,
     ) {
-        //TODO: Remove from spatial structure?
+        self.octree.remove(handle.0);
         self.dynamic_aabb.free(handle.0);
     }
 
+    /// Update the bounds of a node. Small moves that keep the node in the same octree cell only
+    /// overwrite the bounds.
+    pub fn move_dynamic_aabb(
+        &mut self,
+        handle: DynamicAabbVisibilityNodeHandle,
+        aabb: Aabb,
+    ) {
+        // A panic here means the handle was already unregistered
+        self.dynamic_aabb.get_mut(handle.0).unwrap().set_aabb(aabb);
+        self.octree.move_object(handle.0, aabb);
+    }
+
+    pub fn octree_stats(&self) -> LooseOctreeStats {
+        self.octree.stats()
+    }
+
     pub fn calculate_dynamic_visibility(
         &self,
         view: &RenderView,
     ) -> VisibilityResult {
         log::trace!("Calculate dynamic visibility for {}", view.debug_name());
-        let mut result = VisibilityResult::default();
+        self.do_calculate_dynamic_visibility(view, None)
+    }
 
-        for (_, aabb) in self.dynamic_aabb.iter() {
-            log::trace!("push dynamic visibility object {:?}", aabb.handle);
-            result.handles.push(aabb.handle);
-        }
+    /// Like `calculate_dynamic_visibility`, but also skips nodes hidden in the occlusion buffer.
+    /// The occlusion buffer must have been built for the same view.
+    pub fn calculate_dynamic_visibility_with_occlusion(
+        &self,
+        view: &RenderView,
+        occlusion_buffer: &OcclusionBuffer,
+    ) -> VisibilityResult {
+        log::trace!(
+            "Calculate dynamic visibility with occlusion for {}",
+            view.debug_name()
+        );
+        self.do_calculate_dynamic_visibility(view, Some(occlusion_buffer))
+    }
+
+    fn do_calculate_dynamic_visibility(
+        &self,
+        view: &RenderView,
+        occlusion_buffer: Option<&OcclusionBuffer>,
+    ) -> VisibilityResult {
+        let frustum = Frustum::from_view(view);
+        let lod_calculator = LodCalculator::from_view(view);
+        let result = self
+            .octree
+            .query_frustum(&frustum, &lod_calculator, occlusion_buffer);
 
         //TODO: Could consider sorting lists of handles by type/key to get linear memory access
         result
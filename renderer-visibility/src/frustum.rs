@@ -0,0 +1,169 @@
+use glam::{Mat4, Vec3, Vec4};
+use renderer_nodes::RenderView;
+use crate::{Aabb, BoundingSphere};
+
+/// A plane where points p satisfying `normal.dot(p) + d >= 0` are on the inside
+#[derive(Copy, Clone, Debug)]
+pub struct Plane {
+    pub normal: Vec3,
+    pub d: f32,
+}
+
+impl Plane {
+    fn from_vec4_normalized(v: Vec4) -> Self {
+        let normal = v.truncate();
+        let inv_length = 1.0 / normal.length();
+        Plane {
+            normal: normal * inv_length,
+            d: v.w() * inv_length,
+        }
+    }
+
+    pub fn signed_distance(
+        &self,
+        point: Vec3,
+    ) -> f32 {
+        self.normal.dot(point) + self.d
+    }
+}
+
+/// Six inward-facing planes extracted from a view-projection matrix
+#[derive(Copy, Clone, Debug)]
+pub struct Frustum {
+    // left, right, bottom, top, near, far
+    planes: [Plane; 6],
+}
+
+impl Frustum {
+    /// Extract planes from a view-projection matrix (i.e. `proj * view` in glam's column-major
+    /// convention). Assumes GL-style -w..w clip depth, which is conservative for 0..w projections.
+    pub fn from_view_projection(view_proj: Mat4) -> Self {
+        // The planes are sums/differences of the matrix rows
+        let rows = view_proj.transpose();
+        let r0 = rows.x_axis();
+        let r1 = rows.y_axis();
+        let r2 = rows.z_axis();
+        let r3 = rows.w_axis();
+
+        Frustum {
+            planes: [
+                Plane::from_vec4_normalized(r3 + r0),
+                Plane::from_vec4_normalized(r3 - r0),
+                Plane::from_vec4_normalized(r3 + r1),
+                Plane::from_vec4_normalized(r3 - r1),
+                Plane::from_vec4_normalized(r3 + r2),
+                Plane::from_vec4_normalized(r3 - r2),
+            ],
+        }
+    }
+
+    pub fn from_view(view: &RenderView) -> Self {
+        Self::from_view_projection(view.projection_matrix() * view.view_matrix())
+    }
+
+    pub fn planes(&self) -> &[Plane; 6] {
+        &self.planes
+    }
+
+    pub fn intersects_sphere(
+        &self,
+        sphere: &BoundingSphere,
+    ) -> bool {
+        self.planes
+            .iter()
+            .all(|plane| plane.signed_distance(sphere.center) >= -sphere.radius)
+    }
+
+    pub fn intersects_aabb(
+        &self,
+        aabb: &Aabb,
+    ) -> bool {
+        let center = aabb.center();
+        let half_extents = aabb.half_extents();
+        self.planes.iter().all(|plane| {
+            // Projected "radius" of the box onto the plane normal
+            let radius = plane.normal.abs().dot(half_extents);
+            plane.signed_distance(center) >= -radius
+        })
+    }
+
+    /// True if the AABB is entirely on the inside of every plane. Used to accept whole subtrees
+    /// of a spatial structure without testing each element.
+    pub fn contains_aabb(
+        &self,
+        aabb: &Aabb,
+    ) -> bool {
+        let center = aabb.center();
+        let half_extents = aabb.half_extents();
+        self.planes.iter().all(|plane| {
+            let radius = plane.normal.abs().dot(half_extents);
+            plane.signed_distance(center) >= radius
+        })
+    }
+
+    /// Sphere test first since it's cheaper and rejects most far-away objects, then the AABB for
+    /// a tighter fit
+    pub fn intersects_bounds(
+        &self,
+        aabb: &Aabb,
+        sphere: &BoundingSphere,
+    ) -> bool {
+        self.intersects_sphere(sphere) && self.intersects_aabb(aabb)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_frustum() -> Frustum {
+        // Looking down -Z from the origin
+        let view = Mat4::look_at_rh(Vec3::zero(), Vec3::new(0.0, 0.0, -1.0), Vec3::unit_y());
+        let proj = Mat4::perspective_rh_gl(std::f32::consts::FRAC_PI_2, 1.0, 0.1, 100.0);
+        Frustum::from_view_projection(proj * view)
+    }
+
+    fn unit_box(center: Vec3) -> Aabb {
+        Aabb::from_center_half_extents(center, Vec3::new(0.5, 0.5, 0.5))
+    }
+
+    #[test]
+    fn test_aabb_inside() {
+        let frustum = test_frustum();
+        let aabb = unit_box(Vec3::new(0.0, 0.0, -10.0));
+        assert!(frustum.intersects_bounds(&aabb, &aabb.bounding_sphere()));
+    }
+
+    #[test]
+    fn test_aabb_behind_camera() {
+        let frustum = test_frustum();
+        let aabb = unit_box(Vec3::new(0.0, 0.0, 10.0));
+        assert!(!frustum.intersects_bounds(&aabb, &aabb.bounding_sphere()));
+    }
+
+    #[test]
+    fn test_aabb_beyond_far_plane() {
+        let frustum = test_frustum();
+        let aabb = unit_box(Vec3::new(0.0, 0.0, -200.0));
+        assert!(!frustum.intersects_bounds(&aabb, &aabb.bounding_sphere()));
+    }
+
+    #[test]
+    fn test_aabb_outside_side_planes() {
+        let frustum = test_frustum();
+
+        // 90 degree fov, so at distance 10 the frustum is 20 units wide
+        let aabb = unit_box(Vec3::new(12.0, 0.0, -10.0));
+        assert!(!frustum.intersects_bounds(&aabb, &aabb.bounding_sphere()));
+
+        let aabb = unit_box(Vec3::new(0.0, -12.0, -10.0));
+        assert!(!frustum.intersects_bounds(&aabb, &aabb.bounding_sphere()));
+    }
+
+    #[test]
+    fn test_aabb_straddling_plane() {
+        let frustum = test_frustum();
+        let aabb = Aabb::new(Vec3::new(5.0, -1.0, -11.0), Vec3::new(15.0, 1.0, -9.0));
+        assert!(frustum.intersects_bounds(&aabb, &aabb.bounding_sphere()));
+    }
+}
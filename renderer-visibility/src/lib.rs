@@ -1,3 +1,31 @@
+mod bounding_volumes;
+pub use bounding_volumes::Aabb;
+pub use bounding_volumes::BoundingSphere;
+
+mod frustum;
+pub use frustum::Frustum;
+pub use frustum::Plane;
+
+mod lod;
+pub use lod::LodScreenSizeThresholds;
+pub use lod::LodCalculator;
+pub use lod::MAX_LOD_COUNT;
+
+mod bvh;
+pub use bvh::Bvh;
+pub use bvh::BvhPrimitive;
+
+mod loose_octree;
+pub use loose_octree::LooseOctree;
+pub use loose_octree::LooseOctreeConfig;
+pub use loose_octree::LooseOctreeObject;
+pub use loose_octree::LooseOctreeStats;
+
+mod occlusion_buffer;
+pub use occlusion_buffer::OcclusionBuffer;
+pub use occlusion_buffer::OcclusionBufferConfig;
+pub use occlusion_buffer::OccluderMesh;
+
 mod visibility_nodes;
 pub use visibility_nodes::*;
 
@@ -7,4 +35,8 @@ pub use static_visibility_node_set::StaticVisibilityNodeSet;
 mod dynamic_visibility_node_set;
 pub use dynamic_visibility_node_set::DynamicVisibilityNodeSet;
 
+mod occluder_set;
+pub use occluder_set::OccluderSet;
+
 pub use renderer_nodes::VisibilityResult;
+pub use renderer_nodes::VisibleNode;
@@ -0,0 +1,190 @@
+use glam::{Mat4, Vec3};
+use renderer_nodes::{RenderView, GenericRenderNodeHandle, VisibleNode, LodIndex};
+use crate::BoundingSphere;
+
+pub const MAX_LOD_COUNT: usize = 4;
+
+/// Screen sizes at which a node switches to the next LOD, in descending order. Screen size is
+/// the projected diameter of the node's bounding sphere as a fraction of the viewport height.
+///
+/// A node larger than the first threshold uses LOD 0, a node between the first and second
+/// thresholds uses LOD 1, and so on. With no thresholds, LOD 0 is always used.
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct LodScreenSizeThresholds {
+    thresholds: [f32; MAX_LOD_COUNT - 1],
+    threshold_count: u8,
+}
+
+impl LodScreenSizeThresholds {
+    pub fn new(thresholds: &[f32]) -> Self {
+        assert!(thresholds.len() < MAX_LOD_COUNT);
+        debug_assert!(thresholds.windows(2).all(|pair| pair[0] >= pair[1]));
+
+        let mut result = LodScreenSizeThresholds::default();
+        result.thresholds[..thresholds.len()].copy_from_slice(thresholds);
+        result.threshold_count = thresholds.len() as u8;
+        result
+    }
+
+    pub fn thresholds(&self) -> &[f32] {
+        &self.thresholds[..self.threshold_count as usize]
+    }
+
+    pub fn lod_count(&self) -> u32 {
+        self.threshold_count as u32 + 1
+    }
+
+    pub fn select_lod(
+        &self,
+        screen_size: f32,
+    ) -> LodIndex {
+        self.thresholds()
+            .iter()
+            .take_while(|threshold| screen_size < **threshold)
+            .count() as LodIndex
+    }
+}
+
+/// Per-view state for calculating the distance and LOD of visible nodes
+#[derive(Copy, Clone, Debug)]
+pub struct LodCalculator {
+    eye_position: Vec3,
+
+    // cot(fov_y / 2) for perspective projections, 2 / height for orthographic projections
+    projection_scale: f32,
+    is_orthographic: bool,
+}
+
+impl LodCalculator {
+    pub fn new(
+        eye_position: Vec3,
+        projection: Mat4,
+    ) -> Self {
+        // abs() because projections are sometimes flipped on Y
+        let projection_scale = projection.y_axis().y().abs();
+
+        // Perspective projections put -z into w, orthographic projections leave w alone
+        let is_orthographic = projection.w_axis().w() == 1.0;
+
+        LodCalculator {
+            eye_position,
+            projection_scale,
+            is_orthographic,
+        }
+    }
+
+    pub fn from_view(view: &RenderView) -> Self {
+        Self::new(view.eye_position(), view.projection_matrix())
+    }
+
+    pub fn distance_from_camera(
+        &self,
+        sphere: &BoundingSphere,
+    ) -> f32 {
+        (sphere.center - self.eye_position).length()
+    }
+
+    /// Projected diameter of the sphere as a fraction of the viewport height
+    pub fn screen_size(
+        &self,
+        sphere: &BoundingSphere,
+        distance_from_camera: f32,
+    ) -> f32 {
+        if self.is_orthographic {
+            sphere.radius * self.projection_scale
+        } else if distance_from_camera <= sphere.radius {
+            // The camera is inside the sphere, so it fills the screen
+            std::f32::MAX
+        } else {
+            sphere.radius * self.projection_scale / distance_from_camera
+        }
+    }
+
+    pub fn visible_node(
+        &self,
+        handle: GenericRenderNodeHandle,
+        sphere: &BoundingSphere,
+        lod_thresholds: &LodScreenSizeThresholds,
+    ) -> VisibleNode {
+        let distance_from_camera = self.distance_from_camera(sphere);
+        let screen_size = self.screen_size(sphere, distance_from_camera);
+        VisibleNode {
+            handle,
+            distance_from_camera,
+            lod_index: lod_thresholds.select_lod(screen_size),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_select_lod() {
+        let thresholds = LodScreenSizeThresholds::new(&[0.5, 0.2, 0.05]);
+        assert_eq!(4, thresholds.lod_count());
+        assert_eq!(0, thresholds.select_lod(std::f32::MAX));
+        assert_eq!(0, thresholds.select_lod(0.6));
+        assert_eq!(1, thresholds.select_lod(0.3));
+        assert_eq!(2, thresholds.select_lod(0.1));
+        assert_eq!(3, thresholds.select_lod(0.01));
+    }
+
+    #[test]
+    fn test_no_thresholds_is_always_lod_0() {
+        let thresholds = LodScreenSizeThresholds::default();
+        assert_eq!(1, thresholds.lod_count());
+        assert_eq!(0, thresholds.select_lod(0.0));
+    }
+
+    #[test]
+    fn test_perspective_lod_falls_off_with_distance() {
+        let projection = Mat4::perspective_rh_gl(std::f32::consts::FRAC_PI_2, 1.0, 0.1, 1000.0);
+        let lod_calculator = LodCalculator::new(Vec3::zero(), projection);
+        let thresholds = LodScreenSizeThresholds::new(&[0.5, 0.1]);
+
+        let sphere_at = |distance: f32| BoundingSphere {
+            center: Vec3::new(0.0, 0.0, -distance),
+            radius: 1.0,
+        };
+
+        // 90 degree fov, so the screen size of a unit sphere is 1 / distance
+        let near = lod_calculator.visible_node(
+            GenericRenderNodeHandle::new(0, 0),
+            &sphere_at(1.5),
+            &thresholds,
+        );
+        assert_eq!(0, near.lod_index);
+        assert!((near.distance_from_camera - 1.5).abs() < 0.0001);
+
+        let middle = lod_calculator.visible_node(
+            GenericRenderNodeHandle::new(0, 0),
+            &sphere_at(5.0),
+            &thresholds,
+        );
+        assert_eq!(1, middle.lod_index);
+
+        let far = lod_calculator.visible_node(
+            GenericRenderNodeHandle::new(0, 0),
+            &sphere_at(50.0),
+            &thresholds,
+        );
+        assert_eq!(2, far.lod_index);
+    }
+
+    #[test]
+    fn test_camera_inside_sphere_is_lod_0() {
+        let projection = Mat4::perspective_rh_gl(std::f32::consts::FRAC_PI_2, 1.0, 0.1, 1000.0);
+        let lod_calculator = LodCalculator::new(Vec3::zero(), projection);
+        let thresholds = LodScreenSizeThresholds::new(&[0.5, 0.1]);
+        let sphere = BoundingSphere {
+            center: Vec3::new(0.0, 0.0, -1.0),
+            radius: 2.0,
+        };
+
+        let visible_node =
+            lod_calculator.visible_node(GenericRenderNodeHandle::new(0, 0), &sphere, &thresholds);
+        assert_eq!(0, visible_node.lod_index);
+    }
+}
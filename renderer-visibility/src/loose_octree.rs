@@ -0,0 +1,711 @@
+//! A loose octree for culling objects that move frequently.
+//!
+//! Each cell's bounds are expanded to twice the size of the cell ("loose" bounds). An object is
+//! stored in the deepest cell where its largest half-extent fits in half the cell's size, picked
+//! by the object's center. That choice can be made directly from the object's bounds, without
+//! walking the tree. An object that stays in the same cell when it moves only needs its bounds
+//! updated. An object that changes cells is unlinked and relinked in O(depth).
+//!
+//! Cells are created on demand and empty leaf cells are pruned when objects leave them. Objects
+//! whose center is outside the root cell, or that are too big for the root, are kept in a
+//! separate list and tested individually.
+
+use std::hash::Hash;
+use fnv::FnvHashMap;
+use glam::Vec3;
+use renderer_nodes::{GenericRenderNodeHandle, VisibilityResult};
+use crate::{Aabb, BoundingSphere, Frustum, LodCalculator, LodScreenSizeThresholds, OcclusionBuffer};
+
+#[derive(Copy, Clone, Debug)]
+pub struct LooseOctreeConfig {
+    pub center: Vec3,
+
+    /// Half the size of the root cell along each axis
+    pub half_size: f32,
+
+    /// Depth of the smallest cells. The root is depth 0.
+    pub max_depth: u32,
+}
+
+impl Default for LooseOctreeConfig {
+    fn default() -> Self {
+        LooseOctreeConfig {
+            center: Vec3::zero(),
+            half_size: 512.0,
+            max_depth: 8,
+        }
+    }
+}
+
+/// Snapshot of how objects are distributed through the tree, intended for debug UI
+#[derive(Clone, Debug, Default)]
+pub struct LooseOctreeStats {
+    pub cell_count: usize,
+    pub occupied_cell_count: usize,
+    pub object_count: usize,
+
+    /// Objects whose center is outside the root cell
+    pub outside_root_object_count: usize,
+
+    /// Deepest cell that currently exists
+    pub max_depth: u32,
+    pub max_objects_per_cell: usize,
+
+    // Indexed by depth
+    pub cells_per_depth: Vec<usize>,
+    pub objects_per_depth: Vec<usize>,
+}
+
+impl LooseOctreeStats {
+    pub fn average_objects_per_occupied_cell(&self) -> f32 {
+        if self.occupied_cell_count == 0 {
+            0.0
+        } else {
+            (self.object_count - self.outside_root_object_count) as f32
+                / self.occupied_cell_count as f32
+        }
+    }
+}
+
+pub struct LooseOctreeObject<K> {
+    pub key: K,
+    pub handle: GenericRenderNodeHandle,
+    pub aabb: Aabb,
+    pub bounding_sphere: BoundingSphere,
+    pub lod_thresholds: LodScreenSizeThresholds,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+enum ObjectLocation {
+    Cell { cell_index: u32, object_index: u32 },
+    OutsideRoot { object_index: u32 },
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+struct CellCoordinate {
+    depth: u32,
+    x: u32,
+    y: u32,
+    z: u32,
+}
+
+impl CellCoordinate {
+    // Which of the parent's 8 children contains the cell with this coordinate at the given depth
+    fn child_slot_at_depth(
+        &self,
+        depth: u32,
+    ) -> usize {
+        let shift = self.depth - depth;
+        let x = (self.x >> shift) & 1;
+        let y = (self.y >> shift) & 1;
+        let z = (self.z >> shift) & 1;
+        (x | y << 1 | z << 2) as usize
+    }
+}
+
+struct OctreeCell<K> {
+    coordinate: CellCoordinate,
+    loose_bounds: Aabb,
+    parent: Option<u32>,
+    children: [Option<u32>; 8],
+    objects: Vec<LooseOctreeObject<K>>,
+}
+
+impl<K> OctreeCell<K> {
+    fn has_children(&self) -> bool {
+        self.children.iter().any(|child| child.is_some())
+    }
+}
+
+pub struct LooseOctree<K> {
+    config: LooseOctreeConfig,
+
+    // Cell 0 is always the root. Freed cells are None and their index is put in the free list
+    cells: Vec<Option<OctreeCell<K>>>,
+    free_cells: Vec<u32>,
+
+    outside_root_objects: Vec<LooseOctreeObject<K>>,
+    object_locations: FnvHashMap<K, ObjectLocation>,
+}
+
+impl<K> Default for LooseOctree<K>
+where
+    K: Copy + Eq + Hash,
+{
+    fn default() -> Self {
+        LooseOctree::new(LooseOctreeConfig::default())
+    }
+}
+
+impl<K> LooseOctree<K>
+where
+    K: Copy + Eq + Hash,
+{
+    pub fn new(config: LooseOctreeConfig) -> Self {
+        let root_coordinate = CellCoordinate {
+            depth: 0,
+            x: 0,
+            y: 0,
+            z: 0,
+        };
+
+        let root = OctreeCell {
+            coordinate: root_coordinate,
+            loose_bounds: Self::loose_bounds(&config, root_coordinate),
+            parent: None,
+            children: [None; 8],
+            objects: Default::default(),
+        };
+
+        LooseOctree {
+            config,
+            cells: vec![Some(root)],
+            free_cells: Default::default(),
+            outside_root_objects: Default::default(),
+            object_locations: Default::default(),
+        }
+    }
+
+    pub fn config(&self) -> &LooseOctreeConfig {
+        &self.config
+    }
+
+    pub fn object_count(&self) -> usize {
+        self.object_locations.len()
+    }
+
+    pub fn insert(
+        &mut self,
+        object: LooseOctreeObject<K>,
+    ) {
+        let key = object.key;
+        let location = match self.cell_coordinate_for(&object.aabb) {
+            Some(coordinate) => {
+                let cell_index = self.find_or_create_cell(coordinate);
+                let cell = self.cell_mut(cell_index);
+                cell.objects.push(object);
+                ObjectLocation::Cell {
+                    cell_index,
+                    object_index: cell.objects.len() as u32 - 1,
+                }
+            }
+            None => {
+                self.outside_root_objects.push(object);
+                ObjectLocation::OutsideRoot {
+                    object_index: self.outside_root_objects.len() as u32 - 1,
+                }
+            }
+        };
+
+        let previous = self.object_locations.insert(key, location);
+        assert!(previous.is_none(), "key was inserted into the octree twice");
+    }
+
+    /// Remove an object. It is fatal to remove a key that is not in the tree.
+    pub fn remove(
+        &mut self,
+        key: K,
+    ) -> LooseOctreeObject<K> {
+        let location = self
+            .object_locations
+            .remove(&key)
+            .expect("tried to remove a key that is not in the octree");
+        self.unlink_object(location)
+    }
+
+    /// Update an object's bounds. If the object still belongs in the same cell, this only
+    /// overwrites its bounds.
+    pub fn move_object(
+        &mut self,
+        key: K,
+        aabb: Aabb,
+    ) {
+        let location = self.object_locations[&key];
+        let new_coordinate = self.cell_coordinate_for(&aabb);
+
+        let stays_in_place = match (location, new_coordinate) {
+            (ObjectLocation::Cell { cell_index, .. }, Some(new_coordinate)) => {
+                self.cell(cell_index).coordinate == new_coordinate
+            }
+            (ObjectLocation::OutsideRoot { .. }, None) => true,
+            _ => false,
+        };
+
+        if stays_in_place {
+            let object = match location {
+                ObjectLocation::Cell {
+                    cell_index,
+                    object_index,
+                } => &mut self.cell_mut(cell_index).objects[object_index as usize],
+                ObjectLocation::OutsideRoot { object_index } => {
+                    &mut self.outside_root_objects[object_index as usize]
+                }
+            };
+
+            object.aabb = aabb;
+            object.bounding_sphere = aabb.bounding_sphere();
+        } else {
+            let mut object = self.remove(key);
+            object.aabb = aabb;
+            object.bounding_sphere = aabb.bounding_sphere();
+            self.insert(object);
+        }
+    }
+
+    /// Find objects in the frustum. If an occlusion buffer is given, cells and objects it hides
+    /// are skipped too.
+    pub fn query_frustum(
+        &self,
+        frustum: &Frustum,
+        lod_calculator: &LodCalculator,
+        occlusion_buffer: Option<&OcclusionBuffer>,
+    ) -> VisibilityResult {
+        let is_visible = |aabb: &Aabb| match occlusion_buffer {
+            Some(occlusion_buffer) => occlusion_buffer.is_aabb_visible(aabb),
+            None => true,
+        };
+
+        let mut result = VisibilityResult::default();
+
+        // If a cell is entirely inside the frustum, so is everything under it
+        let mut cell_stack = vec![(0, false)];
+        while let Some((cell_index, parent_fully_inside)) = cell_stack.pop() {
+            let cell = self.cell(cell_index);
+
+            let fully_inside = if parent_fully_inside {
+                true
+            } else if frustum.intersects_aabb(&cell.loose_bounds) {
+                frustum.contains_aabb(&cell.loose_bounds)
+            } else {
+                continue;
+            };
+
+            if !is_visible(&cell.loose_bounds) {
+                continue;
+            }
+
+            for object in &cell.objects {
+                if (fully_inside
+                    || frustum.intersects_bounds(&object.aabb, &object.bounding_sphere))
+                    && is_visible(&object.aabb)
+                {
+                    result.visible_nodes.push(lod_calculator.visible_node(
+                        object.handle,
+                        &object.bounding_sphere,
+                        &object.lod_thresholds,
+                    ));
+                }
+            }
+
+            for child in cell.children.iter().filter_map(|x| *x) {
+                cell_stack.push((child, fully_inside));
+            }
+        }
+
+        for object in &self.outside_root_objects {
+            if frustum.intersects_bounds(&object.aabb, &object.bounding_sphere)
+                && is_visible(&object.aabb)
+            {
+                result.visible_nodes.push(lod_calculator.visible_node(
+                    object.handle,
+                    &object.bounding_sphere,
+                    &object.lod_thresholds,
+                ));
+            }
+        }
+
+        result
+    }
+
+    pub fn stats(&self) -> LooseOctreeStats {
+        let depth_count = self.config.max_depth as usize + 1;
+        let mut stats = LooseOctreeStats {
+            object_count: self.object_locations.len(),
+            outside_root_object_count: self.outside_root_objects.len(),
+            cells_per_depth: vec![0; depth_count],
+            objects_per_depth: vec![0; depth_count],
+            ..Default::default()
+        };
+
+        for cell in self.cells.iter().filter_map(|x| x.as_ref()) {
+            let depth = cell.coordinate.depth;
+            stats.cell_count += 1;
+            stats.max_depth = stats.max_depth.max(depth);
+            stats.cells_per_depth[depth as usize] += 1;
+            stats.objects_per_depth[depth as usize] += cell.objects.len();
+            stats.max_objects_per_cell = stats.max_objects_per_cell.max(cell.objects.len());
+            if !cell.objects.is_empty() {
+                stats.occupied_cell_count += 1;
+            }
+        }
+
+        stats
+    }
+
+    fn cell(
+        &self,
+        cell_index: u32,
+    ) -> &OctreeCell<K> {
+        self.cells[cell_index as usize].as_ref().unwrap()
+    }
+
+    fn cell_mut(
+        &mut self,
+        cell_index: u32,
+    ) -> &mut OctreeCell<K> {
+        self.cells[cell_index as usize].as_mut().unwrap()
+    }
+
+    fn cell_size_at_depth(
+        config: &LooseOctreeConfig,
+        depth: u32,
+    ) -> f32 {
+        (config.half_size * 2.0) / (1 << depth) as f32
+    }
+
+    // Loose bounds are twice the size of the cell, centered on the cell
+    fn loose_bounds(
+        config: &LooseOctreeConfig,
+        coordinate: CellCoordinate,
+    ) -> Aabb {
+        let cell_size = Self::cell_size_at_depth(config, coordinate.depth);
+        let root_min = config.center - Vec3::splat(config.half_size);
+        let cell_center = root_min
+            + Vec3::new(
+                (coordinate.x as f32 + 0.5) * cell_size,
+                (coordinate.y as f32 + 0.5) * cell_size,
+                (coordinate.z as f32 + 0.5) * cell_size,
+            );
+        Aabb::from_center_half_extents(cell_center, Vec3::splat(cell_size))
+    }
+
+    // Returns None if the object's center is outside the root cell
+    fn cell_coordinate_for(
+        &self,
+        aabb: &Aabb,
+    ) -> Option<CellCoordinate> {
+        let center = aabb.center();
+        let half_extents = aabb.half_extents();
+        let max_half_extent = half_extents.x().max(half_extents.y()).max(half_extents.z());
+
+        let root_min = self.config.center - Vec3::splat(self.config.half_size);
+        let relative_center = center - root_min;
+        let root_size = self.config.half_size * 2.0;
+        let is_inside_root = [
+            relative_center.x(),
+            relative_center.y(),
+            relative_center.z(),
+        ]
+        .iter()
+        .all(|x| *x >= 0.0 && *x <= root_size);
+
+        // Objects too big for even the root's loose bounds are also treated as outside the root
+        if !is_inside_root || max_half_extent > self.config.half_size {
+            return None;
+        }
+
+        // Pick the deepest level where the object's extent fits within the loose margin (half
+        // the cell size) on every side
+        let mut depth = 0;
+        while depth < self.config.max_depth
+            && max_half_extent <= Self::cell_size_at_depth(&self.config, depth + 1) * 0.5
+        {
+            depth += 1;
+        }
+
+        let cell_size = Self::cell_size_at_depth(&self.config, depth);
+        let max_coordinate = (1 << depth) - 1;
+        let to_cell = |x: f32| ((x / cell_size) as u32).min(max_coordinate);
+
+        Some(CellCoordinate {
+            depth,
+            x: to_cell(relative_center.x()),
+            y: to_cell(relative_center.y()),
+            z: to_cell(relative_center.z()),
+        })
+    }
+
+    fn find_or_create_cell(
+        &mut self,
+        coordinate: CellCoordinate,
+    ) -> u32 {
+        let mut cell_index = 0;
+        for depth in 1..=coordinate.depth {
+            let slot = coordinate.child_slot_at_depth(depth);
+            cell_index = match self.cell(cell_index).children[slot] {
+                Some(child_index) => child_index,
+                None => {
+                    let child_coordinate = CellCoordinate {
+                        depth,
+                        x: coordinate.x >> (coordinate.depth - depth),
+                        y: coordinate.y >> (coordinate.depth - depth),
+                        z: coordinate.z >> (coordinate.depth - depth),
+                    };
+
+                    let child_index = self.allocate_cell(OctreeCell {
+                        coordinate: child_coordinate,
+                        loose_bounds: Self::loose_bounds(&self.config, child_coordinate),
+                        parent: Some(cell_index),
+                        children: [None; 8],
+                        objects: Default::default(),
+                    });
+
+                    self.cell_mut(cell_index).children[slot] = Some(child_index);
+                    child_index
+                }
+            };
+        }
+
+        cell_index
+    }
+
+    fn allocate_cell(
+        &mut self,
+        cell: OctreeCell<K>,
+    ) -> u32 {
+        if let Some(cell_index) = self.free_cells.pop() {
+            self.cells[cell_index as usize] = Some(cell);
+            cell_index
+        } else {
+            self.cells.push(Some(cell));
+            self.cells.len() as u32 - 1
+        }
+    }
+
+    // Removes the object from its cell or from the outside list and fixes up the location of the
+    // object that gets swapped into its place
+    fn unlink_object(
+        &mut self,
+        location: ObjectLocation,
+    ) -> LooseOctreeObject<K> {
+        match location {
+            ObjectLocation::Cell {
+                cell_index,
+                object_index,
+            } => {
+                let cell = self.cell_mut(cell_index);
+                let object = cell.objects.swap_remove(object_index as usize);
+                if let Some(moved_object) = cell.objects.get(object_index as usize) {
+                    let moved_key = moved_object.key;
+                    self.object_locations.insert(moved_key, location);
+                }
+
+                self.prune_cell(cell_index);
+                object
+            }
+            ObjectLocation::OutsideRoot { object_index } => {
+                let object = self.outside_root_objects.swap_remove(object_index as usize);
+                if let Some(moved_object) = self.outside_root_objects.get(object_index as usize) {
+                    self.object_locations.insert(moved_object.key, location);
+                }
+
+                object
+            }
+        }
+    }
+
+    // Free the cell and any ancestors that are left empty. The root is never freed.
+    fn prune_cell(
+        &mut self,
+        mut cell_index: u32,
+    ) {
+        loop {
+            let cell = self.cell(cell_index);
+            if !cell.objects.is_empty() || cell.has_children() {
+                return;
+            }
+
+            let parent_index = match cell.parent {
+                Some(parent_index) => parent_index,
+                None => return,
+            };
+
+            let slot = cell.coordinate.child_slot_at_depth(cell.coordinate.depth);
+            self.cell_mut(parent_index).children[slot] = None;
+            self.cells[cell_index as usize] = None;
+            self.free_cells.push(cell_index);
+
+            cell_index = parent_index;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use glam::Mat4;
+    use rand::{Rng, SeedableRng};
+    use rand::rngs::StdRng;
+
+    fn random_vec3(
+        rng: &mut StdRng,
+        range: f32,
+    ) -> Vec3 {
+        Vec3::new(
+            rng.gen_range(-range, range),
+            rng.gen_range(-range, range),
+            rng.gen_range(-range, range),
+        )
+    }
+
+    fn random_aabb(rng: &mut StdRng) -> Aabb {
+        // Some objects are placed outside the root, and some are bigger than it
+        let center = random_vec3(rng, 600.0);
+        let max_half_extent = if rng.gen_bool(0.01) { 600.0 } else { 20.0 };
+        let half_extents = random_vec3(rng, max_half_extent).abs();
+        Aabb::from_center_half_extents(center, half_extents)
+    }
+
+    fn random_view(rng: &mut StdRng) -> (Frustum, LodCalculator) {
+        let eye = random_vec3(rng, 600.0);
+        let target = random_vec3(rng, 100.0);
+        let view = Mat4::look_at_rh(eye, target, Vec3::unit_z());
+        let proj = Mat4::perspective_rh_gl(
+            rng.gen_range(0.3, 2.0),
+            rng.gen_range(0.5, 2.0),
+            0.1,
+            rng.gen_range(10.0, 1000.0),
+        );
+        (
+            Frustum::from_view_projection(proj * view),
+            LodCalculator::new(eye, proj),
+        )
+    }
+
+    fn random_object(
+        rng: &mut StdRng,
+        key: u32,
+    ) -> LooseOctreeObject<u32> {
+        let aabb = random_aabb(rng);
+        LooseOctreeObject {
+            key,
+            handle: GenericRenderNodeHandle::new(0, key),
+            aabb,
+            bounding_sphere: aabb.bounding_sphere(),
+            lod_thresholds: Default::default(),
+        }
+    }
+
+    fn assert_matches_brute_force(
+        rng: &mut StdRng,
+        octree: &LooseOctree<u32>,
+        aabbs: &FnvHashMap<u32, Aabb>,
+    ) {
+        for _ in 0..20 {
+            let (frustum, lod_calculator) = random_view(rng);
+
+            let mut expected: Vec<_> = aabbs
+                .iter()
+                .filter(|(_, aabb)| frustum.intersects_bounds(aabb, &aabb.bounding_sphere()))
+                .map(|(key, _)| *key)
+                .collect();
+            expected.sort();
+
+            let mut actual: Vec<_> = octree
+                .query_frustum(&frustum, &lod_calculator, None)
+                .visible_nodes
+                .iter()
+                .map(|visible_node| visible_node.handle.render_node_index())
+                .collect();
+            actual.sort();
+
+            assert_eq!(expected, actual);
+        }
+    }
+
+    #[test]
+    fn test_random_scene_matches_brute_force() {
+        let mut rng = StdRng::seed_from_u64(0);
+        let mut octree = LooseOctree::default();
+        let mut aabbs = FnvHashMap::default();
+
+        for key in 0..2000 {
+            let object = random_object(&mut rng, key);
+            aabbs.insert(key, object.aabb);
+            octree.insert(object);
+        }
+
+        assert_eq!(2000, octree.object_count());
+        assert_matches_brute_force(&mut rng, &octree, &aabbs);
+    }
+
+    #[test]
+    fn test_moves_match_brute_force() {
+        let mut rng = StdRng::seed_from_u64(1);
+        let mut octree = LooseOctree::default();
+        let mut aabbs = FnvHashMap::default();
+
+        for key in 0..1000 {
+            let object = random_object(&mut rng, key);
+            aabbs.insert(key, object.aabb);
+            octree.insert(object);
+        }
+
+        for _ in 0..10 {
+            for key in 0..1000 {
+                let aabb = aabbs[&key];
+                let new_aabb = if rng.gen_bool(0.9) {
+                    // Small move, usually within the same cell
+                    let offset = random_vec3(&mut rng, 0.5);
+                    Aabb::new(aabb.min + offset, aabb.max + offset)
+                } else {
+                    random_aabb(&mut rng)
+                };
+
+                aabbs.insert(key, new_aabb);
+                octree.move_object(key, new_aabb);
+            }
+
+            assert_eq!(1000, octree.object_count());
+            assert_matches_brute_force(&mut rng, &octree, &aabbs);
+        }
+    }
+
+    #[test]
+    fn test_remove_prunes_cells() {
+        let mut rng = StdRng::seed_from_u64(2);
+        let mut octree = LooseOctree::default();
+        let mut aabbs = FnvHashMap::default();
+
+        for key in 0..1000 {
+            let object = random_object(&mut rng, key);
+            aabbs.insert(key, object.aabb);
+            octree.insert(object);
+        }
+
+        for key in 0..500 {
+            octree.remove(key);
+            aabbs.remove(&key);
+        }
+
+        assert_matches_brute_force(&mut rng, &octree, &aabbs);
+
+        for key in 500..1000 {
+            octree.remove(key);
+        }
+
+        // Only the root should be left
+        let stats = octree.stats();
+        assert_eq!(0, stats.object_count);
+        assert_eq!(1, stats.cell_count);
+        assert_eq!(0, stats.max_depth);
+    }
+
+    #[test]
+    fn test_small_objects_go_deep() {
+        let mut octree = LooseOctree::default();
+        let aabb = Aabb::from_center_half_extents(Vec3::new(1.0, 2.0, 3.0), Vec3::splat(0.5));
+        octree.insert(LooseOctreeObject {
+            key: 0,
+            handle: GenericRenderNodeHandle::new(0, 0),
+            aabb,
+            bounding_sphere: aabb.bounding_sphere(),
+            lod_thresholds: Default::default(),
+        });
+
+        let stats = octree.stats();
+        assert_eq!(octree.config().max_depth, stats.max_depth);
+        assert_eq!(stats.max_depth as usize + 1, stats.cell_count);
+        assert_eq!(1, stats.objects_per_depth[stats.max_depth as usize]);
+    }
+}
@@ -0,0 +1,148 @@
+use glam::Mat4;
+use renderer_base::slab::RawSlab;
+use renderer_nodes::RenderView;
+use crate::*;
+
+/// Meshes that hide whatever is behind them. Each view rasterizes the occluders in its frustum
+/// into an `OcclusionBuffer`, which can then be passed to the visibility node sets.
+#[derive(Default)]
+pub struct OccluderSet {
+    occluders: RawSlab<OccluderNode>,
+    config: OcclusionBufferConfig,
+}
+
+impl OccluderSet {
+    pub fn new(config: OcclusionBufferConfig) -> Self {
+        OccluderSet {
+            occluders: Default::default(),
+            config,
+        }
+    }
+
+    pub fn register_occluder(
+        &mut self,
+        node: OccluderNode,
+    ) -> OccluderHandle {
+        OccluderHandle(self.occluders.allocate(node))
+    }
+
+    pub fn unregister_occluder(
+        &mut self,
+        handle: OccluderHandle,
+    ) {
+        self.occluders.free(handle.0);
+    }
+
+    pub fn set_occluder_transform(
+        &mut self,
+        handle: OccluderHandle,
+        world_transform: Mat4,
+    ) {
+        // A panic here means the handle was already unregistered
+        self.occluders.get_mut(handle.0).unwrap().world_transform = world_transform;
+    }
+
+    pub fn occluder_count(&self) -> usize {
+        self.occluders.allocated_count()
+    }
+
+    pub fn build_occlusion_buffer(
+        &self,
+        view: &RenderView,
+    ) -> OcclusionBuffer {
+        log::trace!("Build occlusion buffer for {}", view.debug_name());
+        let frustum = Frustum::from_view(view);
+        let mut occlusion_buffer = OcclusionBuffer::from_view(self.config, view);
+
+        for (_, occluder) in self.occluders.iter() {
+            let world_aabb = occluder
+                .mesh
+                .local_aabb()
+                .transform(occluder.world_transform);
+            if frustum.intersects_aabb(&world_aabb) {
+                occlusion_buffer.rasterize_occluder(&occluder.mesh, occluder.world_transform);
+            }
+        }
+
+        occlusion_buffer.build_hierarchy();
+        occlusion_buffer
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use glam::Vec3;
+    use renderer_nodes::RenderPhaseMaskBuilder;
+    use std::sync::Arc;
+
+    fn test_view() -> RenderView {
+        // Looking down -Z from the origin
+        let view = Mat4::look_at_rh(Vec3::zero(), Vec3::new(0.0, 0.0, -1.0), Vec3::unit_y());
+        let proj = Mat4::perspective_rh_gl(std::f32::consts::FRAC_PI_2, 2.0, 0.1, 1000.0);
+        RenderView::new(
+            0,
+            Vec3::zero(),
+            view,
+            proj,
+            RenderPhaseMaskBuilder::default().build(),
+            "test".to_string(),
+        )
+    }
+
+    fn wall_occluder(world_transform: Mat4) -> OccluderNode {
+        let mesh = OccluderMesh::from_aabb(&Aabb::new(
+            Vec3::new(-2.0, -2.0, -1.0),
+            Vec3::new(2.0, 2.0, 0.0),
+        ));
+        OccluderNode {
+            mesh: Arc::new(mesh),
+            world_transform,
+        }
+    }
+
+    fn box_at(center: Vec3) -> Aabb {
+        Aabb::from_center_half_extents(center, Vec3::splat(1.0))
+    }
+
+    #[test]
+    fn test_empty_set_occludes_nothing() {
+        let occluder_set = OccluderSet::default();
+        let occlusion_buffer = occluder_set.build_occlusion_buffer(&test_view());
+        assert!(occlusion_buffer.is_aabb_visible(&box_at(Vec3::new(0.0, 0.0, -50.0))));
+    }
+
+    #[test]
+    fn test_registered_occluder_hides_objects_behind_it() {
+        let mut occluder_set = OccluderSet::default();
+        occluder_set.register_occluder(wall_occluder(Mat4::from_translation(Vec3::new(
+            0.0, 0.0, -5.0,
+        ))));
+
+        let occlusion_buffer = occluder_set.build_occlusion_buffer(&test_view());
+        assert!(!occlusion_buffer.is_aabb_visible(&box_at(Vec3::new(0.0, 0.0, -20.0))));
+        assert!(occlusion_buffer.is_aabb_visible(&box_at(Vec3::new(0.0, 0.0, -3.0))));
+        assert!(occlusion_buffer.is_aabb_visible(&box_at(Vec3::new(15.0, 0.0, -20.0))));
+    }
+
+    #[test]
+    fn test_moved_and_unregistered_occluders() {
+        let mut occluder_set = OccluderSet::default();
+        let handle = occluder_set.register_occluder(wall_occluder(Mat4::from_translation(
+            Vec3::new(0.0, 0.0, -5.0),
+        )));
+
+        // Move the wall behind the camera, it should no longer hide anything
+        occluder_set
+            .set_occluder_transform(handle, Mat4::from_translation(Vec3::new(0.0, 0.0, 10.0)));
+        let occlusion_buffer = occluder_set.build_occlusion_buffer(&test_view());
+        assert!(occlusion_buffer.is_aabb_visible(&box_at(Vec3::new(0.0, 0.0, -20.0))));
+
+        occluder_set
+            .set_occluder_transform(handle, Mat4::from_translation(Vec3::new(0.0, 0.0, -5.0)));
+        occluder_set.unregister_occluder(handle);
+        assert_eq!(occluder_set.occluder_count(), 0);
+        let occlusion_buffer = occluder_set.build_occlusion_buffer(&test_view());
+        assert!(occlusion_buffer.is_aabb_visible(&box_at(Vec3::new(0.0, 0.0, -20.0))));
+    }
+}
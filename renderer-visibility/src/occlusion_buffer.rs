@@ -0,0 +1,512 @@
+use glam::{Mat4, Vec3, Vec4};
+use renderer_nodes::RenderView;
+use crate::Aabb;
+
+#[derive(Copy, Clone, Debug)]
+pub struct OcclusionBufferConfig {
+    pub width: u32,
+    pub height: u32,
+}
+
+impl Default for OcclusionBufferConfig {
+    fn default() -> Self {
+        OcclusionBufferConfig {
+            width: 256,
+            height: 128,
+        }
+    }
+}
+
+/// A triangle mesh used only for occlusion. These should be simple, and must be entirely
+/// contained by the visible geometry they stand in for or they will hide things that should be
+/// visible.
+#[derive(Clone, Debug)]
+pub struct OccluderMesh {
+    positions: Vec<Vec3>,
+    indices: Vec<u32>,
+    local_aabb: Aabb,
+}
+
+impl OccluderMesh {
+    pub fn new(
+        positions: Vec<Vec3>,
+        indices: Vec<u32>,
+    ) -> Self {
+        assert!(!positions.is_empty());
+        assert_eq!(indices.len() % 3, 0);
+        debug_assert!(indices
+            .iter()
+            .all(|index| (*index as usize) < positions.len()));
+
+        let local_aabb = positions[1..]
+            .iter()
+            .fold(Aabb::new(positions[0], positions[0]), |aabb, position| {
+                aabb.grow_to_include(*position)
+            });
+
+        OccluderMesh {
+            positions,
+            indices,
+            local_aabb,
+        }
+    }
+
+    /// A closed box filling the given AABB
+    pub fn from_aabb(aabb: &Aabb) -> Self {
+        #[rustfmt::skip]
+        let indices = vec![
+            0, 1, 3, 0, 3, 2, // -z
+            4, 6, 7, 4, 7, 5, // +z
+            0, 4, 5, 0, 5, 1, // -y
+            2, 3, 7, 2, 7, 6, // +y
+            0, 2, 6, 0, 6, 4, // -x
+            1, 5, 7, 1, 7, 3, // +x
+        ];
+
+        OccluderMesh::new(aabb.corners().to_vec(), indices)
+    }
+
+    pub fn positions(&self) -> &[Vec3] {
+        &self.positions
+    }
+
+    pub fn indices(&self) -> &[u32] {
+        &self.indices
+    }
+
+    pub fn local_aabb(&self) -> &Aabb {
+        &self.local_aabb
+    }
+}
+
+/// A low-resolution CPU depth buffer that occluder meshes are rasterized into for a single view,
+/// and a hierarchical-Z pyramid built from it that AABBs are tested against.
+///
+/// Depth is stored as 0 (near) to 1 (far). Each hierarchical-Z texel holds the farthest depth of
+/// the texels under it, so a box whose nearest point is behind that depth is hidden.
+/// Rasterization samples at pixel centers and the results are the same on every platform.
+pub struct OcclusionBuffer {
+    width: u32,
+    height: u32,
+    view_proj: Mat4,
+
+    // Level 0 is full resolution, each following level is half the size (rounded up)
+    levels: Vec<Vec<f32>>,
+    level_sizes: Vec<(u32, u32)>,
+    hierarchy_is_built: bool,
+}
+
+impl OcclusionBuffer {
+    pub fn new(
+        config: OcclusionBufferConfig,
+        view_proj: Mat4,
+    ) -> Self {
+        assert!(config.width > 0 && config.height > 0);
+
+        let mut level_sizes = vec![(config.width, config.height)];
+        let (mut width, mut height) = (config.width, config.height);
+        while width > 1 || height > 1 {
+            width = (width + 1) / 2;
+            height = (height + 1) / 2;
+            level_sizes.push((width, height));
+        }
+
+        let levels = level_sizes
+            .iter()
+            .map(|(width, height)| vec![1.0; (width * height) as usize])
+            .collect();
+
+        OcclusionBuffer {
+            width: config.width,
+            height: config.height,
+            view_proj,
+            levels,
+            level_sizes,
+            hierarchy_is_built: false,
+        }
+    }
+
+    pub fn from_view(
+        config: OcclusionBufferConfig,
+        view: &RenderView,
+    ) -> Self {
+        Self::new(config, view.projection_matrix() * view.view_matrix())
+    }
+
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    pub fn view_proj(&self) -> Mat4 {
+        self.view_proj
+    }
+
+    /// The full resolution depth buffer, row by row
+    pub fn depth(&self) -> &[f32] {
+        &self.levels[0]
+    }
+
+    /// Reset to the far plane so that nothing is occluded
+    pub fn clear(&mut self) {
+        for level in &mut self.levels {
+            for depth in level.iter_mut() {
+                *depth = 1.0;
+            }
+        }
+
+        self.hierarchy_is_built = false;
+    }
+
+    pub fn rasterize_occluder(
+        &mut self,
+        mesh: &OccluderMesh,
+        world_transform: Mat4,
+    ) {
+        let transform = self.view_proj * world_transform;
+        let clip_positions: Vec<Vec4> = mesh
+            .positions
+            .iter()
+            .map(|position| transform * position.extend(1.0))
+            .collect();
+
+        for triangle in mesh.indices.chunks_exact(3) {
+            self.rasterize_triangle([
+                clip_positions[triangle[0] as usize],
+                clip_positions[triangle[1] as usize],
+                clip_positions[triangle[2] as usize],
+            ]);
+        }
+
+        self.hierarchy_is_built = false;
+    }
+
+    /// Must be called after rasterizing occluders and before testing AABBs
+    pub fn build_hierarchy(&mut self) {
+        for level_index in 1..self.levels.len() {
+            let (src_width, src_height) = self.level_sizes[level_index - 1];
+            let (dst_width, dst_height) = self.level_sizes[level_index];
+            let (src_levels, dst_levels) = self.levels.split_at_mut(level_index);
+            let src = &src_levels[level_index - 1];
+            let dst = &mut dst_levels[0];
+
+            for y in 0..dst_height {
+                let y0 = y * 2;
+                let y1 = (y0 + 1).min(src_height - 1);
+                for x in 0..dst_width {
+                    let x0 = x * 2;
+                    let x1 = (x0 + 1).min(src_width - 1);
+                    let depth = src[(y0 * src_width + x0) as usize]
+                        .max(src[(y0 * src_width + x1) as usize])
+                        .max(src[(y1 * src_width + x0) as usize])
+                        .max(src[(y1 * src_width + x1) as usize]);
+                    dst[(y * dst_width + x) as usize] = depth;
+                }
+            }
+        }
+
+        self.hierarchy_is_built = true;
+    }
+
+    /// Returns false only if the AABB is entirely hidden behind rasterized occluders. AABBs that
+    /// cross the near plane or fall outside the buffer are considered visible.
+    pub fn is_aabb_visible(
+        &self,
+        aabb: &Aabb,
+    ) -> bool {
+        debug_assert!(self.hierarchy_is_built);
+
+        let mut min_x = std::f32::MAX;
+        let mut min_y = std::f32::MAX;
+        let mut max_x = std::f32::MIN;
+        let mut max_y = std::f32::MIN;
+        let mut min_depth = std::f32::MAX;
+        for corner in &aabb.corners() {
+            let clip = self.view_proj * corner.extend(1.0);
+            if clip.z() < -clip.w() || clip.w() <= 0.0 {
+                return true;
+            }
+
+            let (x, y, depth) = self.clip_to_screen(clip);
+            min_x = min_x.min(x);
+            min_y = min_y.min(y);
+            max_x = max_x.max(x);
+            max_y = max_y.max(y);
+            min_depth = min_depth.min(depth);
+        }
+
+        if max_x < 0.0 || max_y < 0.0 || min_x >= self.width as f32 || min_y >= self.height as f32 {
+            return true;
+        }
+
+        let x0 = (min_x.max(0.0) as u32).min(self.width - 1);
+        let y0 = (min_y.max(0.0) as u32).min(self.height - 1);
+        let x1 = (max_x.max(0.0) as u32).min(self.width - 1);
+        let y1 = (max_y.max(0.0) as u32).min(self.height - 1);
+
+        // Use the finest level where the rect is at most 4x4 texels
+        let mut level_index = 0;
+        while level_index + 1 < self.levels.len()
+            && ((x1 >> level_index) - (x0 >> level_index) >= 4
+                || (y1 >> level_index) - (y0 >> level_index) >= 4)
+        {
+            level_index += 1;
+        }
+
+        let level = &self.levels[level_index];
+        let (level_width, _) = self.level_sizes[level_index];
+        let mut max_occluder_depth: f32 = 0.0;
+        for y in (y0 >> level_index)..=(y1 >> level_index) {
+            for x in (x0 >> level_index)..=(x1 >> level_index) {
+                max_occluder_depth = max_occluder_depth.max(level[(y * level_width + x) as usize]);
+            }
+        }
+
+        min_depth <= max_occluder_depth
+    }
+
+    fn clip_to_screen(
+        &self,
+        clip: Vec4,
+    ) -> (f32, f32, f32) {
+        let inv_w = 1.0 / clip.w();
+        (
+            (clip.x() * inv_w * 0.5 + 0.5) * self.width as f32,
+            (clip.y() * inv_w * 0.5 + 0.5) * self.height as f32,
+            (clip.z() * inv_w * 0.5 + 0.5).max(0.0).min(1.0),
+        )
+    }
+
+    fn rasterize_triangle(
+        &mut self,
+        vertices: [Vec4; 3],
+    ) {
+        // Clip against the near plane (z >= -w), which can turn the triangle into a quad
+        let mut clipped = [Vec4::zero(); 4];
+        let mut clipped_count = 0;
+        for i in 0..3 {
+            let current = vertices[i];
+            let next = vertices[(i + 1) % 3];
+            let current_distance = current.z() + current.w();
+            let next_distance = next.z() + next.w();
+
+            if current_distance >= 0.0 {
+                clipped[clipped_count] = current;
+                clipped_count += 1;
+            }
+
+            if (current_distance >= 0.0) != (next_distance >= 0.0) {
+                let t = current_distance / (current_distance - next_distance);
+                clipped[clipped_count] = current + (next - current) * t;
+                clipped_count += 1;
+            }
+        }
+
+        if clipped_count < 3 {
+            return;
+        }
+
+        let mut screen = [(0.0, 0.0, 0.0); 4];
+        for i in 0..clipped_count {
+            screen[i] = self.clip_to_screen(clipped[i]);
+        }
+
+        for i in 1..clipped_count - 1 {
+            self.rasterize_screen_triangle(screen[0], screen[i], screen[i + 1]);
+        }
+    }
+
+    fn rasterize_screen_triangle(
+        &mut self,
+        v0: (f32, f32, f32),
+        mut v1: (f32, f32, f32),
+        mut v2: (f32, f32, f32),
+    ) {
+        let mut area = edge_function(v0, v1, v2.0, v2.1);
+        if area == 0.0 {
+            return;
+        }
+
+        // Occluders are double-sided, so flip back faces to a consistent winding
+        if area < 0.0 {
+            std::mem::swap(&mut v1, &mut v2);
+            area = -area;
+        }
+
+        let min_x = v0.0.min(v1.0).min(v2.0).max(0.0);
+        let min_y = v0.1.min(v1.1).min(v2.1).max(0.0);
+        let max_x = v0.0.max(v1.0).max(v2.0).min(self.width as f32);
+        let max_y = v0.1.max(v1.1).max(v2.1).min(self.height as f32);
+        if min_x >= max_x || min_y >= max_y {
+            return;
+        }
+
+        let x0 = min_x as u32;
+        let y0 = min_y as u32;
+        let x1 = (max_x.ceil() as u32).min(self.width);
+        let y1 = (max_y.ceil() as u32).min(self.height);
+
+        // A top-left style fill rule keeps pixels on edges shared by two triangles from being skipped
+        let bias0 = top_left_bias(v1, v2);
+        let bias1 = top_left_bias(v2, v0);
+        let bias2 = top_left_bias(v0, v1);
+
+        let inv_area = 1.0 / area;
+        let depth = &mut self.levels[0];
+        for y in y0..y1 {
+            let sample_y = y as f32 + 0.5;
+            for x in x0..x1 {
+                let sample_x = x as f32 + 0.5;
+                let w0 = edge_function(v1, v2, sample_x, sample_y);
+                let w1 = edge_function(v2, v0, sample_x, sample_y);
+                let w2 = edge_function(v0, v1, sample_x, sample_y);
+                if w0 + bias0 <= 0.0 || w1 + bias1 <= 0.0 || w2 + bias2 <= 0.0 {
+                    continue;
+                }
+
+                // z/w is linear in screen space, so it doesn't need perspective correction
+                let sample_depth = (w0 * v0.2 + w1 * v1.2 + w2 * v2.2) * inv_area;
+                let texel = &mut depth[(y * self.width + x) as usize];
+                *texel = texel.min(sample_depth);
+            }
+        }
+    }
+}
+
+fn edge_function(
+    a: (f32, f32, f32),
+    b: (f32, f32, f32),
+    x: f32,
+    y: f32,
+) -> f32 {
+    (b.0 - a.0) * (y - a.1) - (b.1 - a.1) * (x - a.0)
+}
+
+// Pixels exactly on an edge belong to only one of the two triangles sharing it, since the edge
+// runs in opposite directions in each of them
+fn top_left_bias(
+    a: (f32, f32, f32),
+    b: (f32, f32, f32),
+) -> f32 {
+    let is_top = a.1 == b.1 && b.0 < a.0;
+    let is_left = b.1 > a.1;
+    if is_top || is_left {
+        f32::MIN_POSITIVE
+    } else {
+        0.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_view_proj() -> Mat4 {
+        let view = Mat4::look_at_rh(Vec3::zero(), Vec3::new(0.0, 0.0, -1.0), Vec3::unit_y());
+        let proj = Mat4::perspective_rh_gl(std::f32::consts::FRAC_PI_2, 2.0, 0.1, 1000.0);
+        proj * view
+    }
+
+    fn wall_at(distance: f32) -> OccluderMesh {
+        OccluderMesh::from_aabb(&Aabb::new(
+            Vec3::new(-2.0, -2.0, -distance - 1.0),
+            Vec3::new(2.0, 2.0, -distance),
+        ))
+    }
+
+    fn box_at(
+        center: Vec3,
+        half_extent: f32,
+    ) -> Aabb {
+        Aabb::from_center_half_extents(center, Vec3::splat(half_extent))
+    }
+
+    fn occlusion_buffer_with(occluders: &[OccluderMesh]) -> OcclusionBuffer {
+        let mut occlusion_buffer =
+            OcclusionBuffer::new(OcclusionBufferConfig::default(), test_view_proj());
+        for occluder in occluders {
+            occlusion_buffer.rasterize_occluder(occluder, Mat4::identity());
+        }
+        occlusion_buffer.build_hierarchy();
+        occlusion_buffer
+    }
+
+    #[test]
+    fn test_empty_buffer_occludes_nothing() {
+        let occlusion_buffer = occlusion_buffer_with(&[]);
+        assert!(occlusion_buffer.is_aabb_visible(&box_at(Vec3::new(0.0, 0.0, -50.0), 1.0)));
+        assert!(occlusion_buffer.is_aabb_visible(&box_at(Vec3::new(0.0, 0.0, -999.0), 0.1)));
+    }
+
+    #[test]
+    fn test_box_behind_wall_is_occluded() {
+        let occlusion_buffer = occlusion_buffer_with(&[wall_at(5.0)]);
+        assert!(!occlusion_buffer.is_aabb_visible(&box_at(Vec3::new(0.0, 0.0, -20.0), 1.0)));
+        assert!(!occlusion_buffer.is_aabb_visible(&box_at(Vec3::new(1.0, -1.0, -100.0), 5.0)));
+    }
+
+    #[test]
+    fn test_box_in_front_of_wall_is_visible() {
+        let occlusion_buffer = occlusion_buffer_with(&[wall_at(5.0)]);
+        assert!(occlusion_buffer.is_aabb_visible(&box_at(Vec3::new(0.0, 0.0, -3.0), 1.0)));
+
+        // Pokes through the front of the wall
+        assert!(occlusion_buffer.is_aabb_visible(&box_at(Vec3::new(0.0, 0.0, -7.0), 3.0)));
+    }
+
+    #[test]
+    fn test_box_beside_wall_is_visible() {
+        let occlusion_buffer = occlusion_buffer_with(&[wall_at(5.0)]);
+
+        // The wall hides -40..40 on x and y at z = -100
+        assert!(occlusion_buffer.is_aabb_visible(&box_at(Vec3::new(45.0, 0.0, -100.0), 10.0)));
+        assert!(occlusion_buffer.is_aabb_visible(&box_at(Vec3::new(0.0, 50.0, -100.0), 1.0)));
+    }
+
+    #[test]
+    fn test_box_crossing_near_plane_is_visible() {
+        let occlusion_buffer = occlusion_buffer_with(&[wall_at(5.0)]);
+        assert!(occlusion_buffer.is_aabb_visible(&box_at(Vec3::zero(), 1.0)));
+    }
+
+    #[test]
+    fn test_occluder_crossing_near_plane_is_clipped() {
+        // A floor that starts behind the camera and runs far into the distance
+        let floor = OccluderMesh::new(
+            vec![
+                Vec3::new(-100.0, -1.0, 10.0),
+                Vec3::new(100.0, -1.0, 10.0),
+                Vec3::new(-100.0, -1.0, -500.0),
+                Vec3::new(100.0, -1.0, -500.0),
+            ],
+            vec![0, 1, 3, 0, 3, 2],
+        );
+        let occlusion_buffer = occlusion_buffer_with(&[floor]);
+
+        assert!(!occlusion_buffer.is_aabb_visible(&box_at(Vec3::new(0.0, -10.0, -20.0), 1.0)));
+        assert!(occlusion_buffer.is_aabb_visible(&box_at(Vec3::new(0.0, 0.0, -20.0), 0.5)));
+    }
+
+    #[test]
+    fn test_clear() {
+        let mut occlusion_buffer = occlusion_buffer_with(&[wall_at(5.0)]);
+        occlusion_buffer.clear();
+        occlusion_buffer.build_hierarchy();
+        assert!(occlusion_buffer.is_aabb_visible(&box_at(Vec3::new(0.0, 0.0, -20.0), 1.0)));
+        assert!(occlusion_buffer.depth().iter().all(|depth| *depth == 1.0));
+    }
+
+    #[test]
+    fn test_rasterization_is_deterministic() {
+        let occluders = [
+            wall_at(5.0),
+            OccluderMesh::from_aabb(&box_at(Vec3::new(3.0, 2.0, -4.0), 1.5)),
+        ];
+        let a = occlusion_buffer_with(&occluders);
+        let b = occlusion_buffer_with(&occluders);
+        assert_eq!(a.depth(), b.depth());
+    }
+}
@@ -1,4 +1,4 @@
-use renderer_base::slab::RawSlab;
+use renderer_base::slab::{RawSlab, RawSlabKey};
 use renderer_nodes::RenderView;
 use renderer_nodes::VisibilityResult;
 use crate::*;
@@ -6,6 +6,7 @@ use crate::*;
 #[derive(Default)]
 pub struct StaticVisibilityNodeSet {
     static_aabb: RawSlab<StaticAabbVisibilityNode>,
+    bvh: Bvh<RawSlabKey<StaticAabbVisibilityNode>>,
 }
 
 impl StaticVisibilityNodeSet {
@@ -13,16 +14,47 @@ impl StaticVisibilityNodeSet {
         &mut self,
         node: StaticAabbVisibilityNode,
     ) -> StaticAabbVisibilityNodeHandle {
-        //TODO: Insert into spatial structure?
-        StaticAabbVisibilityNodeHandle(self.static_aabb.allocate(node))
+        let handle = self.allocate_static_aabb(node);
+        let primitive = self.bvh_primitive(handle.0);
+        self.bvh.insert_batch(std::iter::once(primitive));
+        handle
+    }
+
+    /// Prefer this to registering nodes one at a time when loading many nodes, since the BVH is
+    /// updated once for the whole batch
+    pub fn register_static_aabbs(
+        &mut self,
+        nodes: Vec<StaticAabbVisibilityNode>,
+    ) -> Vec<StaticAabbVisibilityNodeHandle> {
+        let handles: Vec<_> = nodes
+            .into_iter()
+            .map(|node| self.allocate_static_aabb(node))
+            .collect();
+
+        let primitives: Vec<_> = handles
+            .iter()
+            .map(|handle| self.bvh_primitive(handle.0))
+            .collect();
+        self.bvh.insert_batch(primitives);
+
+        handles
     }
 
     pub fn unregister_static_aabb(
         &mut self,
         handle: StaticAabbVisibilityNodeHandle,
     ) {
-        //TODO: Remove from spatial structure?
-        self.static_aabb.free(handle.0);
+        self.unregister_static_aabbs(&[handle]);
+    }
+
+    pub fn unregister_static_aabbs(
+        &mut self,
+        handles: &[StaticAabbVisibilityNodeHandle],
+    ) {
+        self.bvh.remove_batch(handles.iter().map(|handle| handle.0));
+        for handle in handles {
+            self.static_aabb.free(handle.0);
+        }
     }
 
     pub fn calculate_static_visibility(
@@ -30,14 +62,56 @@ impl StaticVisibilityNodeSet {
         view: &RenderView,
     ) -> VisibilityResult {
         log::trace!("Calculate static visibility for {}", view.debug_name());
-        let mut result = VisibilityResult::default();
+        self.do_calculate_static_visibility(view, None)
+    }
 
-        for (_, aabb) in self.static_aabb.iter() {
-            log::trace!("push static visibility object {:?}", aabb.handle);
-            result.handles.push(aabb.handle);
-        }
+    /// Like `calculate_static_visibility`, but also skips nodes hidden in the occlusion buffer.
+    /// The occlusion buffer must have been built for the same view.
+    pub fn calculate_static_visibility_with_occlusion(
+        &self,
+        view: &RenderView,
+        occlusion_buffer: &OcclusionBuffer,
+    ) -> VisibilityResult {
+        log::trace!(
+            "Calculate static visibility with occlusion for {}",
+            view.debug_name()
+        );
+        self.do_calculate_static_visibility(view, Some(occlusion_buffer))
+    }
+
+    fn do_calculate_static_visibility(
+        &self,
+        view: &RenderView,
+        occlusion_buffer: Option<&OcclusionBuffer>,
+    ) -> VisibilityResult {
+        let frustum = Frustum::from_view(view);
+        let lod_calculator = LodCalculator::from_view(view);
+        let result = self
+            .bvh
+            .query_frustum(&frustum, &lod_calculator, occlusion_buffer);
 
         //TODO: Could consider sorting lists of handles by type/key to get linear memory access
         result
     }
+
+    fn allocate_static_aabb(
+        &mut self,
+        node: StaticAabbVisibilityNode,
+    ) -> StaticAabbVisibilityNodeHandle {
+        StaticAabbVisibilityNodeHandle(self.static_aabb.allocate(node))
+    }
+
+    fn bvh_primitive(
+        &self,
+        key: RawSlabKey<StaticAabbVisibilityNode>,
+    ) -> BvhPrimitive<RawSlabKey<StaticAabbVisibilityNode>> {
+        let node = self.static_aabb.get(key).unwrap();
+        BvhPrimitive {
+            key,
+            handle: node.handle,
+            aabb: node.aabb,
+            bounding_sphere: node.bounding_sphere,
+            lod_thresholds: node.lod_thresholds,
+        }
+    }
 }
@@ -1,9 +1,37 @@
+use std::sync::Arc;
+use glam::Mat4;
 use renderer_base::slab::RawSlabKey;
 use renderer_nodes::GenericRenderNodeHandle;
+use crate::{Aabb, BoundingSphere, LodScreenSizeThresholds, OccluderMesh};
 
 ////////////////// StaticAabb VisibilityNode //////////////////
 pub struct StaticAabbVisibilityNode {
     pub handle: GenericRenderNodeHandle,
+    pub aabb: Aabb,
+    pub bounding_sphere: BoundingSphere,
+    pub lod_thresholds: LodScreenSizeThresholds,
+}
+
+impl StaticAabbVisibilityNode {
+    pub fn new(
+        handle: GenericRenderNodeHandle,
+        aabb: Aabb,
+    ) -> Self {
+        StaticAabbVisibilityNode {
+            handle,
+            aabb,
+            bounding_sphere: aabb.bounding_sphere(),
+            lod_thresholds: Default::default(),
+        }
+    }
+
+    pub fn with_lod_thresholds(
+        mut self,
+        lod_thresholds: LodScreenSizeThresholds,
+    ) -> Self {
+        self.lod_thresholds = lod_thresholds;
+        self
+    }
 }
 
 #[derive(Copy, Clone)]
@@ -12,7 +40,49 @@ pub struct StaticAabbVisibilityNodeHandle(pub RawSlabKey<StaticAabbVisibilityNod
 ////////////////// DynamicAabb VisibilityNode //////////////////
 pub struct DynamicAabbVisibilityNode {
     pub handle: GenericRenderNodeHandle,
+    pub aabb: Aabb,
+    pub bounding_sphere: BoundingSphere,
+    pub lod_thresholds: LodScreenSizeThresholds,
+}
+
+impl DynamicAabbVisibilityNode {
+    pub fn new(
+        handle: GenericRenderNodeHandle,
+        aabb: Aabb,
+    ) -> Self {
+        DynamicAabbVisibilityNode {
+            handle,
+            aabb,
+            bounding_sphere: aabb.bounding_sphere(),
+            lod_thresholds: Default::default(),
+        }
+    }
+
+    pub fn with_lod_thresholds(
+        mut self,
+        lod_thresholds: LodScreenSizeThresholds,
+    ) -> Self {
+        self.lod_thresholds = lod_thresholds;
+        self
+    }
+
+    pub fn set_aabb(
+        &mut self,
+        aabb: Aabb,
+    ) {
+        self.aabb = aabb;
+        self.bounding_sphere = aabb.bounding_sphere();
+    }
 }
 
 #[derive(Copy, Clone)]
 pub struct DynamicAabbVisibilityNodeHandle(pub RawSlabKey<DynamicAabbVisibilityNode>);
+
+////////////////// Occluder //////////////////
+pub struct OccluderNode {
+    pub mesh: Arc<OccluderMesh>,
+    pub world_transform: Mat4,
+}
+
+#[derive(Copy, Clone)]
+pub struct OccluderHandle(pub RawSlabKey<OccluderNode>);
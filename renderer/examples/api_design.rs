@@ -105,10 +105,10 @@ fn main() {
             //   render node needs the entity.
             // - ALTERNATIVE: Could create an empty entity, create the components, and then add all of them
             demo_render_nodes.register_demo_component_with_handle(|render_node_handle| {
-                let aabb_info = DynamicAabbVisibilityNode {
-                    handle: render_node_handle.into(),
-                    // aabb bounds
-                };
+                let aabb_info = DynamicAabbVisibilityNode::new(
+                    render_node_handle.into(),
+                    Aabb::from_center_half_extents(position, Vec3::new(0.5, 0.5, 0.5)),
+                );
 
                 // User calls functions to register visibility objects
                 // - This is a retained API because presumably we don't want to rebuild spatial structures every frame
@@ -241,11 +241,11 @@ fn main() {
 
         log::trace!(
             "main view static node count: {}",
-            main_view_static_visibility_result.handles.len()
+            main_view_static_visibility_result.visible_nodes.len()
         );
         log::trace!(
             "main view dynamic node count: {}",
-            main_view_dynamic_visibility_result.handles.len()
+            main_view_dynamic_visibility_result.visible_nodes.len()
         );
 
         let demo_render_nodes = resources.get::<DemoRenderNodeSet>().unwrap();
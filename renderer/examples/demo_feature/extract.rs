@@ -9,38 +9,29 @@ use crate::demo_feature::prepare::DemoPrepareJobImpl;
 use renderer_features::PositionComponent;
 
 #[derive(Default)]
-pub struct DemoExtractJobImpl {
-    per_frame_data: Vec<ExtractedDemoData>,
-    per_view_data: Vec<Vec<ExtractedDemoData>>,
-}
+pub struct DemoExtractJobImpl {}
 
 impl DefaultExtractJobImpl<DemoExtractContext, DemoPrepareContext, DemoWriteContext>
     for DemoExtractJobImpl
 {
+    type ExtractedFrameNodeData = ExtractedDemoData;
+    type ExtractedViewNodeData = ExtractedDemoData;
+
     fn extract_begin(
         &mut self,
         _extract_context: &DemoExtractContext,
-        frame_packet: &FramePacket,
-        views: &[&RenderView],
+        _frame_packet: &FramePacket,
+        _views: &[&RenderView],
     ) {
         log::debug!("extract_begin {}", self.feature_debug_name());
-        self.per_frame_data
-            .reserve(frame_packet.frame_node_count(self.feature_index()) as usize);
-
-        self.per_view_data.reserve(views.len());
-        for view in views {
-            self.per_view_data.push(Vec::with_capacity(
-                frame_packet.view_node_count(view, self.feature_index()) as usize,
-            ));
-        }
     }
 
     fn extract_frame_node(
-        &mut self,
+        &self,
         extract_context: &DemoExtractContext,
         frame_node: PerFrameNode,
         frame_node_index: u32,
-    ) {
+    ) -> ExtractedDemoData {
         log::debug!(
             "extract_frame_node {} {}",
             self.feature_debug_name(),
@@ -64,27 +55,27 @@ impl DefaultExtractJobImpl<DemoExtractContext, DemoPrepareContext, DemoWriteCont
             .get_component::<DemoComponent>(demo_render_node.entity)
             .unwrap();
 
-        self.per_frame_data.push(ExtractedDemoData {
+        ExtractedDemoData {
             position: position_component.position,
             alpha: demo_component.alpha,
-        });
+        }
     }
 
     fn extract_view_node(
-        &mut self,
+        &self,
         _extract_context: &DemoExtractContext,
-        view: &RenderView,
+        _view: &RenderView,
         view_node: PerViewNode,
         view_node_index: u32,
-    ) {
+        extracted_frame_node_data: &[ExtractedDemoData],
+    ) -> ExtractedDemoData {
         log::debug!(
             "extract_view_nodes {} {} {:?}",
             self.feature_debug_name(),
             view_node_index,
-            self.per_frame_data[view_node.frame_node_index() as usize]
+            extracted_frame_node_data[view_node.frame_node_index() as usize]
         );
-        let frame_data = self.per_frame_data[view_node.frame_node_index() as usize].clone();
-        self.per_view_data[view.view_index() as usize].push(frame_data);
+        extracted_frame_node_data[view_node.frame_node_index() as usize].clone()
     }
 
     fn extract_view_finalize(
@@ -98,12 +89,14 @@ impl DefaultExtractJobImpl<DemoExtractContext, DemoPrepareContext, DemoWriteCont
     fn extract_frame_finalize(
         self,
         _extract_context: &DemoExtractContext,
+        extracted_frame_node_data: Vec<ExtractedDemoData>,
+        extracted_view_node_data: Vec<Vec<ExtractedDemoData>>,
     ) -> Box<dyn PrepareJob<DemoPrepareContext, DemoWriteContext>> {
         log::debug!("extract_frame_finalize {}", self.feature_debug_name());
 
         let prepare_impl = DemoPrepareJobImpl {
-            per_frame_data: self.per_frame_data,
-            per_view_data: self.per_view_data,
+            per_frame_data: extracted_frame_node_data,
+            per_view_data: extracted_view_node_data,
         };
 
         Box::new(DefaultPrepareJob::new(prepare_impl))
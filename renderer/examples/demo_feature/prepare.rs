@@ -1,7 +1,7 @@
 use renderer_features::phases::draw_transparent::DrawTransparentRenderPhase;
 use renderer_nodes::{
     RenderView, ViewSubmitNodes, FeatureSubmitNodes, FeatureCommandWriter, RenderFeatureIndex,
-    FramePacket, DefaultPrepareJobImpl, PerFrameNode, PerViewNode, RenderFeature,
+    FramePacket, DefaultPrepareJobImpl, PerFrameNode, PerViewNode, RenderFeature, SubmitNodeId,
 };
 use glam::Vec3;
 use crate::demo_feature::{DemoRenderFeature, ExtractedDemoData};
@@ -15,6 +15,9 @@ pub struct DemoPrepareJobImpl {
 }
 
 impl DefaultPrepareJobImpl<DemoPrepareContext, DemoWriteContext> for DemoPrepareJobImpl {
+    type PreparedFrameNodeData = ();
+    type PreparedViewNodeData = ();
+
     fn prepare_begin(
         &mut self,
         _prepare_context: &DemoPrepareContext,
@@ -26,11 +29,10 @@ impl DefaultPrepareJobImpl<DemoPrepareContext, DemoWriteContext> for DemoPrepare
     }
 
     fn prepare_frame_node(
-        &mut self,
+        &self,
         _prepare_context: &DemoPrepareContext,
         _frame_node: PerFrameNode,
         frame_node_index: u32,
-        _submit_nodes: &mut FeatureSubmitNodes,
     ) {
         log::debug!(
             "prepare_frame_node {} {}",
@@ -40,11 +42,13 @@ impl DefaultPrepareJobImpl<DemoPrepareContext, DemoWriteContext> for DemoPrepare
     }
 
     fn prepare_view_node(
-        &mut self,
+        &self,
         _prepare_context: &DemoPrepareContext,
         view: &RenderView,
         view_node: PerViewNode,
         view_node_index: u32,
+        _prepared_frame_node_data: &[()],
+        submit_node_id: SubmitNodeId,
         submit_nodes: &mut ViewSubmitNodes,
     ) {
         log::debug!(
@@ -60,11 +64,11 @@ impl DefaultPrepareJobImpl<DemoPrepareContext, DemoWriteContext> for DemoPrepare
             &self.per_view_data[view.view_index() as usize][view_node_index as usize];
 
         if extracted_data.alpha >= 1.0 {
-            submit_nodes.add_submit_node::<DrawOpaqueRenderPhase>(view_node_index, 0, 0.0);
+            submit_nodes.add_submit_node::<DrawOpaqueRenderPhase>(submit_node_id, 0, 0.0);
         } else {
             let distance_from_camera = Vec3::length(extracted_data.position - view.eye_position());
             submit_nodes.add_submit_node::<DrawTransparentRenderPhase>(
-                view_node_index,
+                submit_node_id,
                 0,
                 distance_from_camera,
             );
@@ -83,6 +87,8 @@ impl DefaultPrepareJobImpl<DemoPrepareContext, DemoWriteContext> for DemoPrepare
     fn prepare_frame_finalize(
         self,
         _prepare_context: &DemoPrepareContext,
+        _prepared_frame_node_data: Vec<()>,
+        _prepared_view_node_data: Vec<()>,
         _submit_nodes: &mut FeatureSubmitNodes,
     ) -> Box<dyn FeatureCommandWriter<DemoWriteContext>> {
         log::debug!("prepare_frame_finalize {}", self.feature_debug_name());